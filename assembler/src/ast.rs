@@ -1,14 +1,63 @@
-use crate::{Mnemonics, Operand};
+use crate::{AssemblerErrorKind, CpuType, Dialect, Mnemonics, Operand};
+use std::{collections::HashSet, fmt};
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct Position(pub usize, pub usize);
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    /// Macro expansion the token was produced by, `0` if it comes from the source directly.
+    /// see [`crate::Parser::expansions`]
+    pub expansion: usize,
+    /// File the token was read from, `0` is the main source. see [`crate::Sources`]
+    pub file: usize,
+    /// Scope (`.proc`, `.scope`) the statement is in, `0` is the global scope. see [`crate::Parser::scopes`]
+    pub scope: usize,
+}
 
-impl PartialEq for Position {
-    fn eq(&self, _: &Self) -> bool {
-        true
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self {
+            line,
+            column,
+            expansion: 0,
+            file: 0,
+            scope: 0,
+        }
     }
 }
 
+/// Range of the source, `end` is exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.line == self.end.line && self.start.column >= self.end.column
+    }
+}
+
+/// An empty span at the position, see [`Span::is_empty`]
+impl From<Position> for Span {
+    fn from(position: Position) -> Self {
+        Self::new(position, position)
+    }
+}
+
+/// A macro expansion, `call_site` is the position of the macro invocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    pub call_site: Position,
+    pub locals: HashSet<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Program(pub Vec<Statement>);
 
@@ -16,6 +65,26 @@ pub struct Program(pub Vec<Statement>);
 pub enum Statement {
     Instruction(Instruction),
     Label(String),
+    Org(u16, Position),
+    Data(Data),
+    Constant(Constant),
+    Segment(Segment),
+    /// `.import name, ...`, symbols of other objects.
+    /// the symbols of `.importzp` (ca65) are in the zero page
+    Import(Vec<String>, bool, Position),
+    /// `.export name, ...`, labels and constants for other objects
+    Export(Vec<String>, Position),
+}
+
+/// `.segment "NAME"`, the following statements are assembled into the segment,
+/// see [`crate::Assembler::assemble_object`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub name: String,
+    /// the labels of the segment are zero page addresses, for the segments named `ZEROPAGE`
+    /// and `.segment "NAME": zeropage`
+    pub zero_page: bool,
+    pub position: Position,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -23,6 +92,11 @@ pub struct Instruction {
     pub opcode: Mnemonics,
     pub operand: Operand,
     pub position: Position,
+    /// a branch written as `JEQ`, `JNE`, ..., assembled as the inverted branch over a `JMP`
+    /// to the target if it is out of the range of the branch
+    pub long: bool,
+    /// the CPU selected at the instruction, see [`CpuType`]
+    pub cpu: CpuType,
 }
 
 impl Instruction {
@@ -31,6 +105,254 @@ impl Instruction {
             opcode,
             operand,
             position,
+            long: false,
+            cpu: CpuType::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Data {
+    pub kind: DataKind,
+    pub position: Position,
+}
+
+impl Data {
+    pub fn new(kind: DataKind, position: Position) -> Self {
+        Self { kind, position }
+    }
+}
+
+/// `NAME = expression`, `NAME .equ expression`, `.equ NAME, expression`
+///
+/// the name is qualified with the scope it is defined in, like labels.
+/// constants can be used before they are defined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constant {
+    pub name: String,
+    pub value: Expression,
+    pub position: Position,
+}
+
+impl Constant {
+    pub fn new(name: String, value: Expression, position: Position) -> Self {
+        Self {
+            name,
+            value,
+            position,
+        }
+    }
+}
+
+/// Data definition directives
+///
+/// - `.byte`, `dcb`: 8-bit values
+/// - `.word`: 16-bit values (little endian)
+/// - `.text`, `.asciiz`: string literal (`.asciiz` is terminated with `0x00`)
+/// - `.incbin "file"[, offset[, length]]`: contents of a binary file, stored as [`DataKind::Text`]
+/// - `.fill count, value`: `count` bytes of `value`
+/// - `.res count`: reserves `count` bytes without emitting them
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataKind {
+    Byte(Vec<Expression>),
+    Word(Vec<Expression>),
+    Text(Vec<u8>),
+    Fill(Expression, Expression),
+    Res(Expression),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(i32),
+    Identifier(String),
+    /// `*`, the address of the current statement
+    CurrentAddress,
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Evaluates the expression.
+    ///
+    /// `current_address` is the value of `*` (not available outside of the assembler),
+    /// `resolve` returns the value of an identifier.
+    pub fn evaluate(
+        &self,
+        current_address: Option<i32>,
+        resolve: &dyn Fn(&str) -> Option<i32>,
+    ) -> Result<i32, AssemblerErrorKind> {
+        Ok(match self {
+            Expression::Number(n) => *n,
+            Expression::Identifier(identifier) => match resolve(identifier) {
+                Some(value) => value,
+                None => return Err(AssemblerErrorKind::InvalidLabel(identifier.clone())),
+            },
+            Expression::CurrentAddress => match current_address {
+                Some(address) => address,
+                None => return Err(AssemblerErrorKind::InvalidOperand("*".to_string())),
+            },
+            Expression::Unary(operator, expression) => {
+                let value = expression.evaluate(current_address, resolve)?;
+                match operator {
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::Not => !value,
+                    UnaryOperator::Low => value & 0xFF,
+                    UnaryOperator::High => (value >> 8) & 0xFF,
+                    UnaryOperator::Bank => (value >> 16) & 0xFF,
+                    UnaryOperator::LogicalNot => (value == 0) as i32,
+                }
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(current_address, resolve)?;
+                let right = right.evaluate(current_address, resolve)?;
+                match operator {
+                    BinaryOperator::Add => left.wrapping_add(right),
+                    BinaryOperator::Sub => left.wrapping_sub(right),
+                    BinaryOperator::Mul => left.wrapping_mul(right),
+                    BinaryOperator::Div => match left.checked_div(right) {
+                        Some(value) => value,
+                        None => return Err(AssemblerErrorKind::DivisionByZero),
+                    },
+                    BinaryOperator::Mod => match left.checked_rem(right) {
+                        Some(value) => value,
+                        None => return Err(AssemblerErrorKind::DivisionByZero),
+                    },
+                    BinaryOperator::And => left & right,
+                    BinaryOperator::Or => left | right,
+                    BinaryOperator::Xor => left ^ right,
+                    BinaryOperator::Shl => left.wrapping_shl(right as u32),
+                    BinaryOperator::Shr => left.wrapping_shr(right as u32),
+                    BinaryOperator::Equal => (left == right) as i32,
+                    BinaryOperator::NotEqual => (left != right) as i32,
+                    BinaryOperator::Less => (left < right) as i32,
+                    BinaryOperator::Greater => (left > right) as i32,
+                    BinaryOperator::LessEqual => (left <= right) as i32,
+                    BinaryOperator::GreaterEqual => (left >= right) as i32,
+                    BinaryOperator::LogicalAnd => (left != 0 && right != 0) as i32,
+                    BinaryOperator::LogicalOr => (left != 0 || right != 0) as i32,
+                    BinaryOperator::LogicalXor => ((left != 0) != (right != 0)) as i32,
+                }
+            }
+        })
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Number(n) => write!(f, "{n}"),
+            Expression::Identifier(s) => write!(f, "{s}"),
+            Expression::CurrentAddress => write!(f, "*"),
+            Expression::Unary(operator, expression) => write!(f, "{operator}{expression}"),
+            Expression::Binary(operator, left, right) => write!(f, "({left} {operator} {right})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    /// `<`, low byte
+    Low,
+    /// `>`, high byte
+    High,
+    /// `^` (ca65), bits 16-23
+    Bank,
+    /// `!`, `1` if the value is `0`, `0` otherwise
+    LogicalNot,
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOperator::Negate => write!(f, "-"),
+            UnaryOperator::Not => write!(f, "~"),
+            UnaryOperator::Low => write!(f, "<"),
+            UnaryOperator::High => write!(f, ">"),
+            UnaryOperator::Bank => write!(f, "^"),
+            UnaryOperator::LogicalNot => write!(f, "!"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// `.mod` (ca65)
+    Mod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    LogicalAnd,
+    LogicalOr,
+    /// `.xor` (ca65)
+    LogicalXor,
+}
+
+impl BinaryOperator {
+    /// Precedence of the operator in the dialect, the operators of a higher one bind tighter.
+    /// in ca65 `&` and `^` are multiplicative and `|` is additive.
+    pub fn precedence(&self, dialect: Dialect) -> u8 {
+        use BinaryOperator::*;
+
+        match dialect {
+            Dialect::Native => match self {
+                LogicalOr => 0,
+                LogicalAnd | LogicalXor => 1,
+                Or => 2,
+                Xor => 3,
+                And => 4,
+                Equal | NotEqual => 5,
+                Less | Greater | LessEqual | GreaterEqual => 6,
+                Shl | Shr => 7,
+                Add | Sub => 8,
+                Mul | Div | Mod => 9,
+            },
+            Dialect::Ca65 => match self {
+                LogicalOr => 0,
+                LogicalAnd | LogicalXor => 1,
+                Equal | NotEqual | Less | Greater | LessEqual | GreaterEqual => 2,
+                Add | Sub | Or => 3,
+                Mul | Div | Mod | And | Xor | Shl | Shr => 4,
+            },
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryOperator::Add => write!(f, "+"),
+            BinaryOperator::Sub => write!(f, "-"),
+            BinaryOperator::Mul => write!(f, "*"),
+            BinaryOperator::Div => write!(f, "/"),
+            BinaryOperator::Mod => write!(f, ".mod"),
+            BinaryOperator::And => write!(f, "&"),
+            BinaryOperator::Or => write!(f, "|"),
+            BinaryOperator::Xor => write!(f, "^"),
+            BinaryOperator::Shl => write!(f, "<<"),
+            BinaryOperator::Shr => write!(f, ">>"),
+            BinaryOperator::Equal => write!(f, "=="),
+            BinaryOperator::NotEqual => write!(f, "!="),
+            BinaryOperator::Less => write!(f, "<"),
+            BinaryOperator::Greater => write!(f, ">"),
+            BinaryOperator::LessEqual => write!(f, "<="),
+            BinaryOperator::GreaterEqual => write!(f, ">="),
+            BinaryOperator::LogicalAnd => write!(f, "&&"),
+            BinaryOperator::LogicalOr => write!(f, "||"),
+            BinaryOperator::LogicalXor => write!(f, ".xor"),
         }
     }
 }
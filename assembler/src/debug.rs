@@ -0,0 +1,332 @@
+use crate::{Chunk, Position};
+use std::{collections::HashMap, fmt::Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Label,
+    Constant,
+}
+
+/// A label or constant of an assembled source, `name` is qualified with its scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: i32,
+    pub kind: SymbolKind,
+    /// where the symbol is defined
+    pub position: Position,
+}
+
+/// Source position of the `size` bytes at `address`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineInfo {
+    pub address: u16,
+    pub size: usize,
+    pub position: Position,
+}
+
+/// Assembled source with its debug information, see [`crate::Assembler::build`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    /// address of `bytes`, see [`crate::chunks_to_image`]
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub chunks: Vec<Chunk>,
+    /// labels and constants, sorted by name
+    pub symbols: Vec<Symbol>,
+    /// every instruction and data directive, in source order
+    pub lines: Vec<LineInfo>,
+    /// names of the source files, see [`Position::file`].
+    /// the main source's name is empty, set it before writing the debug files.
+    pub files: Vec<String>,
+}
+
+impl Assembly {
+    /// Name of a label at `address`.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.kind == SymbolKind::Label && symbol.value == address as i32)
+            .map(|symbol| symbol.name.as_str())
+    }
+
+    /// Source line of the instruction or data that contains `address`.
+    pub fn line(&self, address: u16) -> Option<&LineInfo> {
+        self.lines.iter().find(|line| {
+            (line.address as usize..line.address as usize + line.size).contains(&(address as usize))
+        })
+    }
+
+    /// Labels in the VICE monitor format (`al C:8000 .start`), load them with `ll "file"`.
+    /// characters VICE does not accept in labels (`::`, `.`) are replaced with `_`.
+    pub fn vice_labels(&self) -> String {
+        let mut output = String::new();
+        for symbol in self.symbols.iter().filter(|s| s.kind == SymbolKind::Label) {
+            let name = symbol
+                .name
+                .replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_");
+            writeln!(output, "al C:{:04X} .{name}", symbol.value).unwrap();
+        }
+
+        output
+    }
+
+    /// Debug information as JSON: the files, the symbols and the source line of every address.
+    pub fn json(&self) -> String {
+        let files = self
+            .files
+            .iter()
+            .map(|name| json_string(name))
+            .collect::<Vec<_>>();
+        let symbols = self
+            .symbols
+            .iter()
+            .map(|symbol| {
+                format!(
+                    r#"{{"name": {}, "value": {}, "kind": "{}", "file": {}, "line": {}, "column": {}}}"#,
+                    json_string(&symbol.name),
+                    symbol.value,
+                    match symbol.kind {
+                        SymbolKind::Label => "label",
+                        SymbolKind::Constant => "constant",
+                    },
+                    symbol.position.file,
+                    symbol.position.line,
+                    symbol.position.column,
+                )
+            })
+            .collect::<Vec<_>>();
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| {
+                format!(
+                    r#"{{"address": {}, "size": {}, "file": {}, "line": {}, "column": {}}}"#,
+                    line.address,
+                    line.size,
+                    line.position.file,
+                    line.position.line,
+                    line.position.column,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "{{\n  \"files\": [{}],\n  \"symbols\": [\n    {}\n  ],\n  \"lines\": [\n    {}\n  ]\n}}\n",
+            files.join(", "),
+            symbols.join(",\n    "),
+            lines.join(",\n    "),
+        )
+    }
+
+    /// Debug information in the format of the cc65 linker (`ld65 --dbgfile`),
+    /// for debuggers and emulators that read it. every chunk is a segment.
+    pub fn ca65_dbg(&self) -> String {
+        let mut output = String::new();
+        let mut spans = Vec::new();
+        // (file, line) of the line records, spans of each line
+        let mut lines = Vec::<((usize, usize), Vec<usize>)>::new();
+        let mut line_ids = HashMap::new();
+        let mut line_id = |lines: &mut Vec<_>, position: Position| {
+            let key = (position.file, position.line);
+            *line_ids.entry(key).or_insert_with(|| {
+                lines.push((key, Vec::new()));
+                lines.len() - 1
+            })
+        };
+
+        for line in &self.lines {
+            let Some(segment) = self.chunks.iter().position(|chunk| {
+                (chunk.origin as usize..chunk.origin as usize + chunk.bytes.len())
+                    .contains(&(line.address as usize))
+            }) else {
+                continue;
+            };
+
+            let id = line_id(&mut lines, line.position);
+            lines[id].1.push(spans.len());
+            spans.push((
+                segment,
+                line.address - self.chunks[segment].origin,
+                line.size,
+            ));
+        }
+        let definitions = self
+            .symbols
+            .iter()
+            .map(|symbol| line_id(&mut lines, symbol.position))
+            .collect::<Vec<_>>();
+
+        let size = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.bytes.len())
+            .sum::<usize>();
+        writeln!(output, "version\tmajor=2,minor=0").unwrap();
+        writeln!(
+            output,
+            "info\tcsym=0,file={},lib=0,line={},mod=1,scope=1,seg={},span={},sym={},type=0",
+            self.files.len(),
+            lines.len(),
+            self.chunks.len(),
+            spans.len(),
+            self.symbols.len(),
+        )
+        .unwrap();
+
+        for (id, name) in self.files.iter().enumerate() {
+            writeln!(
+                output,
+                "file\tid={id},name={},size=0,mtime=0x00000000,mod=0",
+                json_string(name)
+            )
+            .unwrap();
+        }
+        for (id, ((file, line), spans)) in lines.iter().enumerate() {
+            write!(output, "line\tid={id},file={file},line={line}").unwrap();
+            if !spans.is_empty() {
+                let spans = spans.iter().map(usize::to_string).collect::<Vec<_>>();
+                write!(output, ",span={}", spans.join("+")).unwrap();
+            }
+            writeln!(output).unwrap();
+        }
+        writeln!(
+            output,
+            "mod\tid=0,name={},file=0",
+            json_string(self.files.first().map_or("", String::as_str))
+        )
+        .unwrap();
+        for (id, chunk) in self.chunks.iter().enumerate() {
+            writeln!(
+                output,
+                "seg\tid={id},name=\"CODE{id}\",start=0x{:06X},size=0x{:04X},addrsize=absolute,type=rw",
+                chunk.origin,
+                chunk.bytes.len(),
+            )
+            .unwrap();
+        }
+        for (id, (segment, start, size)) in spans.iter().enumerate() {
+            writeln!(
+                output,
+                "span\tid={id},seg={segment},start={start},size={size}"
+            )
+            .unwrap();
+        }
+        writeln!(output, "scope\tid=0,name=\"\",mod=0,size={size}").unwrap();
+        for (id, (symbol, definition)) in self.symbols.iter().zip(definitions).enumerate() {
+            writeln!(
+                output,
+                "sym\tid={id},name={},addrsize={},scope=0,def={definition},val=0x{:X},type={}",
+                json_string(&symbol.name),
+                match symbol.value {
+                    0..=0xFF => "zeropage",
+                    _ => "absolute",
+                },
+                symbol.value,
+                match symbol.kind {
+                    SymbolKind::Label => "lab",
+                    SymbolKind::Constant => "equ",
+                },
+            )
+            .unwrap();
+        }
+
+        output
+    }
+}
+
+/// `s` as a quoted and escaped string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Assembler, Assembly};
+
+    /// a constant, a scoped label and two chunks, the file name needs escaping
+    fn assembly() -> Assembly {
+        let source = "ZP = $10\nstart: LDA ZP\n.scope s\nloop: BNE loop\n.endscope\n.org $9000\n.byte 1, 2\n";
+        let mut assembly = Assembler::new(source).build().unwrap();
+        assembly.files[0] = "main \"1\".asm".to_string();
+        assembly
+    }
+
+    #[test]
+    fn test_lookup() {
+        let assembly = assembly();
+        assert_eq!(assembly.label(0x8002), Some("s::loop"));
+        // constants are not labels
+        assert_eq!(assembly.label(0x0010), None);
+        assert_eq!(assembly.line(0x9001).unwrap().position.line, 7);
+        // the gap between the chunks
+        assert_eq!(assembly.line(0x8004), None);
+    }
+
+    #[test]
+    fn test_vice_labels() {
+        assert_eq!(
+            assembly().vice_labels(),
+            "al C:8002 .s__loop\nal C:8000 .start\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            assembly().json(),
+            r#"{
+  "files": ["main \"1\".asm"],
+  "symbols": [
+    {"name": "ZP", "value": 16, "kind": "constant", "file": 0, "line": 1, "column": 1},
+    {"name": "s::loop", "value": 32770, "kind": "label", "file": 0, "line": 4, "column": 1},
+    {"name": "start", "value": 32768, "kind": "label", "file": 0, "line": 2, "column": 1}
+  ],
+  "lines": [
+    {"address": 32768, "size": 2, "file": 0, "line": 2, "column": 8},
+    {"address": 32770, "size": 2, "file": 0, "line": 4, "column": 7},
+    {"address": 36864, "size": 2, "file": 0, "line": 7, "column": 1}
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_ca65_dbg() {
+        // every chunk is a segment, the line of a constant has no span
+        assert_eq!(
+            assembly().ca65_dbg(),
+            "version\tmajor=2,minor=0
+info\tcsym=0,file=1,lib=0,line=4,mod=1,scope=1,seg=2,span=3,sym=3,type=0
+file\tid=0,name=\"main \\\"1\\\".asm\",size=0,mtime=0x00000000,mod=0
+line\tid=0,file=0,line=2,span=0
+line\tid=1,file=0,line=4,span=1
+line\tid=2,file=0,line=7,span=2
+line\tid=3,file=0,line=1
+mod\tid=0,name=\"main \\\"1\\\".asm\",file=0
+seg\tid=0,name=\"CODE0\",start=0x008000,size=0x0004,addrsize=absolute,type=rw
+seg\tid=1,name=\"CODE1\",start=0x009000,size=0x0002,addrsize=absolute,type=rw
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=2
+span\tid=2,seg=1,start=0,size=2
+scope\tid=0,name=\"\",mod=0,size=6
+sym\tid=0,name=\"ZP\",addrsize=zeropage,scope=0,def=3,val=0x10,type=equ
+sym\tid=1,name=\"s::loop\",addrsize=absolute,scope=0,def=1,val=0x8002,type=lab
+sym\tid=2,name=\"start\",addrsize=absolute,scope=0,def=0,val=0x8000,type=lab
+"
+        );
+    }
+}
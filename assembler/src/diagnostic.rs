@@ -0,0 +1,258 @@
+use crate::{
+    lexer::Lexer, AssemblerError, AssemblerErrorKind, AssemblerWarning, AssemblerWarningKind,
+    Position, SourceFile, Span, TokenKind,
+};
+
+impl AssemblerError {
+    /// Narrows an empty span at the start of a statement to the statement (without the comment),
+    /// or to the name of an undefined label if it is written in the statement.
+    pub fn with_statement_span(mut self, files: &[SourceFile]) -> Self {
+        let label = match &self.kind {
+            AssemblerErrorKind::InvalidLabel(label) => Some(label.as_str()),
+            AssemblerErrorKind::DuplicateLabel { name, .. } => name.rsplit([':', '.']).next(),
+            AssemblerErrorKind::DeniedWarning(kind) => kind.symbol(),
+            _ => None,
+        };
+        if self.span.is_empty() {
+            *self.span = statement_span(files, self.span.start, label).unwrap_or(*self.span);
+        }
+        self
+    }
+
+    /// Renders the error with the source line it refers to, `files` are the sources (see [`crate::Sources`]).
+    pub fn render(&self, files: &[SourceFile]) -> String {
+        let mut notes = self
+            .frames()
+            .into_iter()
+            .map(|(name, position, count)| match count {
+                1 => format!("in macro {name:?} at {}", location(files, &position)),
+                count => format!(
+                    "in macro {name:?} at {} (×{count})",
+                    location(files, &position)
+                ),
+            })
+            .collect::<Vec<_>>();
+        if let AssemblerErrorKind::DuplicateLabel { previous, .. } = &self.kind {
+            notes.push(format!("first defined at {}", location(files, previous)));
+        }
+
+        render("error", &self.kind.to_string(), *self.span, files, &notes)
+    }
+}
+
+impl AssemblerWarning {
+    /// Renders the warning with the source line it refers to, see [`AssemblerError::render`]
+    pub fn render(&self, files: &[SourceFile]) -> String {
+        render(
+            "warning",
+            &self.kind.to_string(),
+            self.span(files),
+            files,
+            &[],
+        )
+    }
+
+    /// Span of the statement the warning refers to, or of the name of the unused label or constant.
+    pub fn span(&self, files: &[SourceFile]) -> Span {
+        statement_span(files, self.position, self.kind.symbol()).unwrap_or(self.position.into())
+    }
+}
+
+impl AssemblerWarningKind {
+    /// Name of the unused label or constant, as written in its definition.
+    fn symbol(&self) -> Option<&str> {
+        match self {
+            AssemblerWarningKind::UnusedLabel(name)
+            | AssemblerWarningKind::UnusedConstant(name) => name.rsplit(':').next(),
+            _ => None,
+        }
+    }
+}
+
+/// `name:line:column`, the main source is `<source>` if it has no name.
+fn location(files: &[SourceFile], position: &Position) -> String {
+    let name = match files.get(position.file) {
+        Some(file) if !file.name.is_empty() => file.name.as_str(),
+        _ => "<source>",
+    };
+
+    format!("{name}:{}:{}", position.line, position.column)
+}
+
+fn line<'a>(files: &'a [SourceFile], position: &Position) -> Option<&'a str> {
+    files
+        .get(position.file)?
+        .source
+        .lines()
+        .nth(position.line.checked_sub(1)?)
+}
+
+/// ```text
+/// error: Invalid label: foo
+///  --> main.asm:3:9
+///   |
+/// 3 |     LDA foo
+///   |         ^^^
+///   = in macro "bar" at main.asm:7:5
+/// ```
+fn render(
+    level: &str,
+    message: &str,
+    span: Span,
+    files: &[SourceFile],
+    notes: &[String],
+) -> String {
+    let start = span.start;
+    let gutter = " ".repeat(start.line.to_string().len());
+    let mut output = format!("{level}: {message}\n");
+    // errors of the linker are not in a source
+    if start.line > 0 {
+        output.push_str(&format!("{gutter}--> {}\n", location(files, &start)));
+    }
+
+    if let Some(text) = line(files, &start) {
+        let column = start.column.max(1);
+        // tabs are kept, so that the underline lines up with the text
+        let indent = text
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let end = match span.end.line == start.line {
+            true => span.end.column,
+            false => text.chars().count() + 1,
+        };
+        let underline = "^".repeat(end.saturating_sub(column).max(1));
+
+        output.push_str(&format!("{gutter} |\n{} | {text}\n", start.line));
+        output.push_str(&format!("{gutter} | {indent}{underline}\n"));
+    }
+
+    for note in notes {
+        output.push_str(&format!("{gutter} = {note}\n"));
+    }
+
+    output
+}
+
+/// Span of the tokens of the statement at `position`, or of the identifier `label` if the statement has it.
+fn statement_span(files: &[SourceFile], position: Position, label: Option<&str>) -> Option<Span> {
+    let text = line(files, &position)?;
+    let offset = text.char_indices().nth(position.column.checked_sub(1)?)?.0;
+
+    // positions of the lexer are relative to the column of the statement
+    let absolute = |relative: Position| Position {
+        line: position.line,
+        column: position.column + relative.column - 1,
+        ..position
+    };
+
+    let mut lexer = Lexer::new(&text[offset..]);
+    let mut tokens = Vec::new();
+    while let Ok(token) = lexer.next_token() {
+        if matches!(
+            token.kind,
+            TokenKind::Newline | TokenKind::Comment | TokenKind::EOF
+        ) {
+            break;
+        }
+        tokens.push(token);
+    }
+
+    if let Some(token) = label.and_then(|label| {
+        tokens
+            .iter()
+            .find(|token| token.kind == TokenKind::Identifier(label))
+    }) {
+        return Some(Span::new(absolute(token.position), absolute(token.end)));
+    }
+
+    Some(Span::new(
+        absolute(tokens.first()?.position),
+        absolute(tokens.last()?.end),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(source: &str) -> Vec<SourceFile> {
+        vec![SourceFile {
+            name: "main.asm".to_string(),
+            source: source.to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_render_error() {
+        // the span is narrowed to the label, the underline keeps the tabs of the line
+        let files = files("\tLDA foo ; comment\n");
+        let error = AssemblerError::new(
+            AssemblerErrorKind::InvalidLabel("foo".to_string()),
+            Position::new(1, 2),
+        )
+        .with_statement_span(&files);
+        assert_eq!(
+            error.render(&files),
+            "error: Invalid label: foo\n --> main.asm:1:6\n  |\n1 | \tLDA foo ; comment\n  | \t    ^^^\n"
+        );
+
+        // a statement without the label, the comment is not underlined
+        let error = AssemblerError::new(
+            AssemblerErrorKind::InvalidLabel("bar".to_string()),
+            Position::new(1, 2),
+        )
+        .with_statement_span(&files);
+        assert!(error.render(&files).ends_with("\n  | \t^^^^^^^\n"));
+
+        // a span over more lines is underlined to the end of its first line
+        let files = self::files("LDA (x +\n1),Y");
+        let error = AssemblerError::new(
+            AssemblerErrorKind::InvalidLabel("x".to_string()),
+            Span::new(Position::new(1, 6), Position::new(2, 2)),
+        );
+        assert!(error
+            .render(&files)
+            .ends_with("1 | LDA (x +\n  |      ^^^\n"));
+    }
+
+    #[test]
+    fn test_render_without_source() {
+        // errors of the linker have no position
+        let error = AssemblerError::new(
+            AssemblerErrorKind::DuplicateExport("main".to_string()),
+            Position::default(),
+        );
+        assert_eq!(
+            error.render(&[]),
+            "error: Symbol main is exported more than once\n"
+        );
+
+        // a position after the end of the source, without a name
+        let files = [SourceFile {
+            name: String::new(),
+            source: "NOP".to_string(),
+        }];
+        let error = AssemblerError::new(AssemblerErrorKind::UnexpectedToken2, Position::new(3, 1));
+        assert_eq!(
+            error.render(&files),
+            "error: Unexpected token\n --> <source>:3:1\n"
+        );
+    }
+
+    #[test]
+    fn test_render_warning() {
+        // the gutter is as wide as the line number, the name of the unused label without its scope
+        let files = files(&("NOP\n".repeat(9) + "unused: NOP\n"));
+        let warning = AssemblerWarning::new(
+            AssemblerWarningKind::UnusedLabel("s::unused".to_string()),
+            Position::new(10, 1),
+        );
+        assert_eq!(
+            warning.render(&files),
+            "warning: label `s::unused` is never used\n  --> main.asm:10:1\n   |\n10 | unused: NOP\n   | ^^^^^^\n"
+        );
+        assert_eq!(warning.span(&files).end.column, 7);
+    }
+}
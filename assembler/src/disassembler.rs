@@ -0,0 +1,428 @@
+use crate::{opcodes, AccumulatorSyntax, AddressingMode, CpuType, Mnemonics, Opcode};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Addresses of the NMI, reset and IRQ vectors, the entry points of a disassembly without any.
+pub const VECTORS: [u16; 3] = [0xFFFA, 0xFFFC, 0xFFFE];
+
+/// Data bytes written in a `.byte` directive, longer data continues in the next lines.
+const LINE_BYTES: usize = 8;
+
+/// Options of [`disassemble_flow`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DisassemblyOptions {
+    /// addresses the code is followed from. if empty, the addresses in the vectors (see [`VECTORS`]),
+    /// or the origin if the image does not contain the vectors
+    pub entries: Vec<u16>,
+    /// the opcodes are decoded for it
+    pub cpu: CpuType,
+    pub accumulator: AccumulatorSyntax,
+}
+
+/// Whether a byte was reached as an instruction, see [`disassemble_flow`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteKind {
+    Code,
+    Data,
+}
+
+/// An instruction, or data bytes of a `.byte` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassemblyLine {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// `L8012` if the address is the target of a jump, a branch or an operand
+    pub label: Option<String>,
+    /// the instruction or the directive
+    pub text: String,
+}
+
+/// Disassembly of an image, see [`disassemble_flow`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disassembly {
+    pub origin: u16,
+    /// kind of every byte of the image
+    pub kinds: Vec<ByteKind>,
+    /// addresses that have a label
+    pub labels: BTreeSet<u16>,
+    pub lines: Vec<DisassemblyLine>,
+}
+
+impl Disassembly {
+    /// Source of the disassembly, it assembles to the same bytes at the same addresses
+    /// for the CPU of the disassembly (see [`DisassemblyOptions::cpu`]).
+    pub fn source(&self) -> String {
+        let mut source = format!("        .org ${:04X}\n", self.origin);
+        for line in &self.lines {
+            let label = line
+                .label
+                .as_ref()
+                .map(|label| format!("{label}:"))
+                .unwrap_or_default();
+            source += &format!("{label:<8}{}\n", line.text);
+        }
+
+        source
+    }
+}
+
+/// Name of the label at the address, `L8012`.
+pub fn label_name(address: u16) -> String {
+    format!("L{address:04X}")
+}
+
+/// Disassembles the image at `origin` by following the code from the entry points (recursive traversal).
+///
+/// branches are followed both ways, `JSR` is assumed to return and the flow ends at `JMP`, `BRA`,
+/// `RTS`, `RTI`, `BRK` and the instructions that stop the CPU. jumps through a pointer are not followed.
+/// bytes that are never reached, or that would decode to an invalid opcode, are data (`.byte`),
+/// like the instructions of an opcode that another opcode of the CPU assembles to (the duplicates of the 6502X),
+/// the vectors at the end of the image are written as `.word` directives.
+/// the targets of branches and jumps, and the absolute operands and pointers in the image, get labels,
+/// branches to other targets (outside of the image, in the middle of an instruction) are written as `*+n`.
+pub fn disassemble_flow(origin: u16, bytes: &[u8], options: &DisassemblyOptions) -> Disassembly {
+    let offset = |address: u16| {
+        (address as usize)
+            .checked_sub(origin as usize)
+            .filter(|&offset| offset < bytes.len())
+    };
+    let word = |address: u16| {
+        let low = bytes[offset(address)?];
+        let high = bytes[offset(address.wrapping_add(1))?];
+        Some(u16::from_le_bytes([low, high]))
+    };
+
+    let mut pending = options.entries.clone();
+    if pending.is_empty() {
+        pending = VECTORS.iter().filter_map(|&vector| word(vector)).collect();
+    }
+    if pending.is_empty() {
+        pending.push(origin);
+    }
+    let mut labels = pending.iter().copied().collect::<BTreeSet<_>>();
+    let mut kinds = vec![ByteKind::Data; bytes.len()];
+    // by offset
+    let mut instructions = BTreeMap::<usize, &Opcode>::new();
+
+    while let Some(mut address) = pending.pop() {
+        while let Some(start) = offset(address) {
+            let Some(opcode) = opcodes::decode(bytes[start], options.cpu) else {
+                break;
+            };
+            let end = start + opcode.size();
+            // followed already, or the middle of another instruction
+            if end > bytes.len() || kinds[start..end].contains(&ByteKind::Code) {
+                break;
+            }
+            kinds[start..end].fill(ByteKind::Code);
+            instructions.insert(start, opcode);
+
+            let next = address.wrapping_add(opcode.size() as u16);
+            let operand = &bytes[start + 1..end];
+            if let Some(target) = flow_target(opcode, operand, next) {
+                labels.insert(target);
+                pending.push(target);
+            }
+            if ends_flow(opcode) {
+                break;
+            }
+            address = next;
+        }
+    }
+
+    // absolute operands and pointers in the image, zero page addresses keep their numbers
+    // so that they are not assembled with the zero page modes
+    for (&start, opcode) in &instructions {
+        use AddressingMode::*;
+        if let (ABS | ABX | ABY | IND | IAX, [low, high]) =
+            (opcode.mode, &bytes[start + 1..start + opcode.size()])
+        {
+            let address = u16::from_le_bytes([*low, *high]);
+            if address > 0xFF {
+                labels.insert(address);
+            }
+        }
+    }
+    // labels are written before a line, not in the middle of an instruction
+    labels.retain(|&address| {
+        offset(address).is_some_and(|offset| {
+            instructions.contains_key(&offset) || kinds[offset] == ByteKind::Data
+        })
+    });
+
+    let address_text = |address: u16| match labels.contains(&address) {
+        true => label_name(address),
+        false => format!("${address:04X}"),
+    };
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let address = origin.wrapping_add(start as u16);
+        let (end, text) = match instructions.get(&start) {
+            // an opcode the instruction is not assembled to (`NOP $5C` of the 6502X), see [`opcodes::encode`]
+            Some(opcode)
+                if opcodes::encode(opcode.mnemonic, opcode.mode, options.cpu) != Some(*opcode) =>
+            {
+                let end = start + opcode.size();
+                (end, data_text(&bytes[start..end]))
+            }
+            Some(opcode) => {
+                let end = start + opcode.size();
+                let operand = &bytes[start + 1..end];
+                let next = address.wrapping_add(opcode.size() as u16);
+                let target = match opcode.mode {
+                    // a number would be read as an absolute address, `*` is the address of the branch
+                    AddressingMode::REL | AddressingMode::ZPR => {
+                        match branch_target(opcode, operand, next) {
+                            target if labels.contains(&target) => label_name(target),
+                            target => match target.wrapping_sub(address) as i16 {
+                                distance @ 0.. => format!("*+{distance}"),
+                                distance => format!("*{distance}"),
+                            },
+                        }
+                    }
+                    AddressingMode::ABS | AddressingMode::ABX | AddressingMode::ABY => {
+                        let word = u16::from_le_bytes([operand[0], operand[1]]);
+                        match word {
+                            // forced absolute, see `OperandWidth`
+                            0..=0xFF => format!("a:${word:04X}"),
+                            _ => address_text(word),
+                        }
+                    }
+                    _ => address_text(u16::from_le_bytes([
+                        operand.first().copied().unwrap_or_default(),
+                        operand.last().copied().unwrap_or_default(),
+                    ])),
+                };
+
+                let text = instruction_text(opcode, operand, options.accumulator, Some(&target));
+                (end, text)
+            }
+            // the addresses in the vectors
+            None if address == VECTORS[0]
+                && bytes.len() - start == 6
+                && (start..bytes.len()).all(|offset| kinds[offset] == ByteKind::Data)
+                && !(VECTORS[0] + 1..=0xFFFF).any(|address| labels.contains(&address)) =>
+            {
+                let vectors = VECTORS.map(|vector| address_text(word(vector).unwrap()));
+                (bytes.len(), format!(".word {}", vectors.join(", ")))
+            }
+            None => {
+                // until the next instruction, label or the vectors
+                let mut end = start + 1;
+                while end < bytes.len()
+                    && end - start < LINE_BYTES
+                    && kinds[end] == ByteKind::Data
+                    && !labels.contains(&origin.wrapping_add(end as u16))
+                    && origin.wrapping_add(end as u16) != VECTORS[0]
+                {
+                    end += 1;
+                }
+                (end, data_text(&bytes[start..end]))
+            }
+        };
+
+        lines.push(DisassemblyLine {
+            address,
+            bytes: bytes[start..end].to_vec(),
+            label: labels.contains(&address).then(|| label_name(address)),
+            text,
+        });
+        start = end;
+    }
+
+    Disassembly {
+        origin,
+        kinds,
+        labels,
+        lines,
+    }
+}
+
+/// `.byte $12, $34` directive of the bytes.
+fn data_text(bytes: &[u8]) -> String {
+    let data = bytes
+        .iter()
+        .map(|byte| format!("${byte:02X}"))
+        .collect::<Vec<_>>();
+
+    format!(".byte {}", data.join(", "))
+}
+
+/// Target of a branch, `next` is the address after the instruction.
+fn branch_target(opcode: &Opcode, operand: &[u8], next: u16) -> u16 {
+    let offset = match opcode.mode {
+        // `BBR0 zp,target`
+        AddressingMode::ZPR => operand[1],
+        _ => operand[0],
+    };
+    next.wrapping_add(offset as i8 as u16)
+}
+
+/// Address the flow continues at besides the next instruction: the target of a branch,
+/// of `JSR` or of `JMP`. jumps through a pointer have no target.
+fn flow_target(opcode: &Opcode, operand: &[u8], next: u16) -> Option<u16> {
+    match (opcode.mnemonic, opcode.mode) {
+        (_, AddressingMode::REL | AddressingMode::ZPR) => {
+            Some(branch_target(opcode, operand, next))
+        }
+        (Mnemonics::JSR | Mnemonics::JMP, AddressingMode::ABS) => {
+            Some(u16::from_le_bytes([operand[0], operand[1]]))
+        }
+        _ => None,
+    }
+}
+
+/// Whether the instruction after it is not reached from it.
+fn ends_flow(opcode: &Opcode) -> bool {
+    use Mnemonics::*;
+    matches!(opcode.mnemonic, JMP | BRA | RTS | RTI | BRK | STP | JAM)
+}
+
+/// Text of a decoded instruction, the accumulator mode is written in the `accumulator` syntax.
+/// `target` is the operand of the absolute modes and the target of branches (a label or an address),
+/// without it the bytes of the operand are written.
+pub(crate) fn instruction_text(
+    opcode: &Opcode,
+    operand: &[u8],
+    accumulator: AccumulatorSyntax,
+    target: Option<&str>,
+) -> String {
+    let byte = operand.first().copied().unwrap_or_default();
+    let word = u16::from_le_bytes([byte, operand.last().copied().unwrap_or_default()]);
+    let address = target.map_or_else(|| format!("${word:04X}"), str::to_string);
+    let branch = target.map_or_else(|| format!("${byte:02X}"), str::to_string);
+
+    let mnemonic = opcode.mnemonic;
+    match opcode.mode {
+        AddressingMode::ACC if accumulator == AccumulatorSyntax::Explicit => {
+            format!("{mnemonic:?} A")
+        }
+        AddressingMode::IMP | AddressingMode::ACC => format!("{mnemonic:?}"),
+        AddressingMode::IMM => format!("{mnemonic:?} #${byte:02X}"),
+        AddressingMode::ZPG => format!("{mnemonic:?} ${byte:02X}"),
+        AddressingMode::REL => format!("{mnemonic:?} {branch}"),
+        AddressingMode::ZPX => format!("{mnemonic:?} ${byte:02X},X"),
+        AddressingMode::ZPY => format!("{mnemonic:?} ${byte:02X},Y"),
+        AddressingMode::ABS => format!("{mnemonic:?} {address}"),
+        AddressingMode::ABX => format!("{mnemonic:?} {address},X"),
+        AddressingMode::ABY => format!("{mnemonic:?} {address},Y"),
+        AddressingMode::IND => format!("{mnemonic:?} ({address})"),
+        AddressingMode::IDX => format!("{mnemonic:?} (${byte:02X},X)"),
+        AddressingMode::IDY => format!("{mnemonic:?} (${byte:02X}),Y"),
+        AddressingMode::IZP => format!("{mnemonic:?} (${byte:02X})"),
+        AddressingMode::IAX => format!("{mnemonic:?} ({address},X)"),
+        AddressingMode::ZPR => {
+            let branch = target.map_or_else(|| format!("${:02X}", operand[1]), str::to_string);
+            format!("{mnemonic:?} ${byte:02X},{branch}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(origin: u16, bytes: &[u8], entries: &[u16]) -> String {
+        let options = DisassemblyOptions {
+            entries: entries.to_vec(),
+            ..Default::default()
+        };
+        disassemble_flow(origin, bytes, &options).source()
+    }
+
+    #[test]
+    fn test_data() {
+        // jumps through a pointer are not followed, the bytes after them are data in lines of 8
+        let bytes = [0x6C, 0x00, 0x02, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(
+            source(0x8000, &bytes, &[]),
+            "        .org $8000
+L8000:  JMP ($0200)
+        .byte $01, $02, $03, $04, $05, $06, $07, $08
+        .byte $09, $0A
+"
+        );
+
+        // an operand in the data gets a label that starts a line, an invalid opcode is data
+        let bytes = [0xAD, 0x05, 0x80, 0x60, 0x01, 0x02, 0x03];
+        assert_eq!(
+            source(0x8000, &bytes, &[0x8000, 0x8006]),
+            "        .org $8000
+L8000:  LDA L8005
+        RTS
+        .byte $01
+L8005:  .byte $02
+L8006:  .byte $03
+"
+        );
+    }
+
+    #[test]
+    fn test_operands() {
+        // branches outside of the image and into an instruction are relative,
+        // absolute operands in the zero page keep their width
+        let bytes = [0xD0, 0x10, 0xF0, 0xFF, 0xAD, 0x10, 0x00, 0xA5, 0x12, 0x60];
+        assert_eq!(
+            source(0x8000, &bytes, &[]),
+            "        .org $8000
+L8000:  BNE *+18
+        BEQ *+1
+        LDA a:$0010
+        LDA $12
+        RTS
+"
+        );
+
+        // `JSR` returns, `BRK` ends the flow
+        let bytes = [0x20, 0x05, 0x80, 0x00, 0xEA, 0x60];
+        let disassembly = disassemble_flow(0x8000, &bytes, &DisassemblyOptions::default());
+        assert_eq!(
+            disassembly.kinds,
+            [
+                ByteKind::Code,
+                ByteKind::Code,
+                ByteKind::Code,
+                ByteKind::Code,
+                ByteKind::Data,
+                ByteKind::Code
+            ]
+        );
+        assert_eq!(disassembly.labels, BTreeSet::from([0x8000, 0x8005]));
+    }
+
+    #[test]
+    fn test_vectors() {
+        // the entry points are the addresses in the vectors
+        let mut bytes = vec![0xEA; 0x10];
+        bytes[0] = 0x40;
+        bytes[1] = 0x60;
+        bytes[0x0A..].copy_from_slice(&[0xF0, 0xFF, 0xF1, 0xFF, 0xF0, 0xFF]);
+        assert_eq!(
+            source(0xFFF0, &bytes, &[]),
+            "        .org $FFF0
+LFFF0:  RTI
+LFFF1:  RTS
+        .byte $EA, $EA, $EA, $EA, $EA, $EA, $EA, $EA
+        .word LFFF0, LFFF1, LFFF0
+"
+        );
+    }
+
+    #[test]
+    fn test_instruction_text() {
+        let text = |byte, operand: &[u8]| {
+            let opcode = opcodes::decode(byte, CpuType::Cmos65C02).unwrap();
+            instruction_text(opcode, operand, AccumulatorSyntax::Explicit, None)
+        };
+        assert_eq!(text(0x0A, &[]), "ASL A");
+        assert_eq!(text(0xA9, &[0x01]), "LDA #$01");
+        assert_eq!(text(0xB6, &[0x12]), "LDX $12,Y");
+        assert_eq!(text(0xBD, &[0x34, 0x12]), "LDA $1234,X");
+        assert_eq!(text(0x7C, &[0x34, 0x12]), "JMP ($1234,X)");
+        assert_eq!(text(0xA1, &[0x12]), "LDA ($12,X)");
+        assert_eq!(text(0xB2, &[0x12]), "LDA ($12)");
+        assert_eq!(text(0xD0, &[0xFE]), "BNE $FE");
+        assert_eq!(text(0x0F, &[0x12, 0xFD]), "BBR0 $12,$FD");
+        assert_eq!(label_name(0x00FF), "L00FF");
+    }
+}
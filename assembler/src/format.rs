@@ -0,0 +1,617 @@
+use crate::{
+    is_label_definition, lexer::Lexer, AccumulatorSyntax, AddressingMode, CpuType, Dialect,
+    Expression, Instruction, Mnemonics, NumberType, Operand, OperandData, OperandWidth, Parser,
+    Program, Statement, Token, TokenKind,
+};
+
+/// Case of the mnemonics and registers of the instructions, see [`FormatOptions`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MnemonicCase {
+    /// `LDA $00,X`
+    #[default]
+    Upper,
+    /// `lda $00,x`
+    Lower,
+}
+
+impl MnemonicCase {
+    pub const ALL: [MnemonicCase; 2] = [MnemonicCase::Upper, MnemonicCase::Lower];
+
+    /// Name of the case on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MnemonicCase::Upper => "upper",
+            MnemonicCase::Lower => "lower",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|case| case.name() == name)
+    }
+}
+
+/// Radix of the number literals in the operands of the instructions, the data directives
+/// (`.byte`, `.word`) and the constant definitions, see [`FormatOptions`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Radix {
+    /// `$0F`, `$0200`
+    #[default]
+    Hexadecimal,
+    /// `15`, `512`
+    Decimal,
+    /// `%00001111`, `%0000001000000000`
+    Binary,
+}
+
+impl Radix {
+    pub const ALL: [Radix; 3] = [Radix::Hexadecimal, Radix::Decimal, Radix::Binary];
+
+    /// Name of the radix on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Radix::Hexadecimal => "hex",
+            Radix::Decimal => "dec",
+            Radix::Binary => "bin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|radix| radix.name() == name)
+    }
+
+    /// The number in the radix, 8-bit numbers with two hexadecimal or eight binary digits.
+    fn number(&self, number: u16) -> String {
+        match (self, u8::try_from(number)) {
+            (Radix::Hexadecimal, Ok(number)) => format!("${number:02X}"),
+            (Radix::Hexadecimal, Err(_)) => format!("${number:04X}"),
+            (Radix::Decimal, _) => number.to_string(),
+            (Radix::Binary, Ok(number)) => format!("%{number:08b}"),
+            (Radix::Binary, Err(_)) => format!("%{number:016b}"),
+        }
+    }
+}
+
+/// Layout of the source formatted by [`format_source`], columns start at `0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    pub mnemonic_case: MnemonicCase,
+    /// column of the labels and the constant definitions (`NAME = value`)
+    pub label_column: usize,
+    /// column of the instructions, directives and macro invocations,
+    /// they follow a longer label after a space
+    pub mnemonic_column: usize,
+    pub operand_column: usize,
+    /// column of the comments after a statement, comments on a line of their own
+    /// stay at the start of the line or are indented to the mnemonic column
+    pub comment_column: usize,
+    /// radix of the number literals of the instruction operands, the data directives and the constants
+    pub radix: Radix,
+    pub accumulator: AccumulatorSyntax,
+    /// dialect and CPU at the start of the source, `.dialect` and `.setcpu` switch them
+    pub dialect: Dialect,
+    pub cpu: CpuType,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            mnemonic_case: MnemonicCase::default(),
+            label_column: 0,
+            mnemonic_column: 8,
+            operand_column: 12,
+            comment_column: 32,
+            radix: Radix::default(),
+            accumulator: AccumulatorSyntax::default(),
+            dialect: Dialect::default(),
+            cpu: CpuType::default(),
+        }
+    }
+}
+
+/// Directives of data, their number literals are written in the radix of the options.
+const DATA_DIRECTIVES: [&str; 8] = [
+    "byte",
+    "byt",
+    "word",
+    "addr",
+    "dbyt",
+    "lobytes",
+    "hibytes",
+    "bankbytes",
+];
+
+/// Formats the source line by line, comments and blank lines are kept.
+///
+/// the instructions are printed from their syntax tree, with the mnemonic case and the radix
+/// of the options. an operand is kept as written if it would not parse to the same operand,
+/// like operands with characters (`'A'`), cheap local or anonymous labels and ca65 functions,
+/// only its registers (`A`, `,X`, `,Y`) get the mnemonic case.
+/// the number literals of the data directives and the constant definitions are written in the radix,
+/// the rest of them is kept. other directives and macro invocations are aligned but not changed,
+/// lines that do not lex are kept as they are.
+pub fn format_source(source: &str, options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        options,
+        dialect: options.dialect,
+        cpu: options.cpu,
+    };
+
+    let mut lines = source
+        .split('\n')
+        .map(|line| formatter.line(line))
+        .collect::<Vec<_>>();
+    // the newline at the end of the source is kept, not an empty line
+    if source.ends_with('\n') {
+        lines.pop();
+        lines.push(String::new());
+    }
+
+    lines.join("\n")
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    dialect: Dialect,
+    cpu: CpuType,
+}
+
+impl Formatter<'_> {
+    fn line(&mut self, line: &str) -> String {
+        let Some(mut tokens) = self.tokens(line) else {
+            return line.trim_end().to_string();
+        };
+
+        let comment = match tokens.last() {
+            Some(token) if token.kind == TokenKind::Comment => {
+                let column = token.position.column;
+                tokens.pop();
+                Some((line[offset(line, column)..].trim_end(), column))
+            }
+            _ => None,
+        };
+
+        let mut formatted = String::new();
+        let mut statement = tokens.as_slice();
+        match statement {
+            [] => {
+                if let Some((comment, column)) = comment {
+                    // a comment at the start of the line is not indented
+                    if column > 1 {
+                        pad(&mut formatted, self.options.mnemonic_column);
+                    }
+                    formatted.push_str(comment);
+                }
+                return formatted;
+            }
+            [label, colon, rest @ ..] if is_label_definition(label, colon) => {
+                pad(&mut formatted, self.options.label_column);
+                formatted.push_str(text(line, label, colon));
+                statement = rest;
+            }
+            [Token {
+                kind: TokenKind::Colon,
+                ..
+            }, rest @ ..] => {
+                // anonymous label
+                pad(&mut formatted, self.options.label_column);
+                formatted.push(':');
+                statement = rest;
+            }
+            _ => {}
+        }
+
+        if let [first, .., last] | [first @ last] = statement {
+            let text = text(line, first, last);
+            match statement {
+                [Token {
+                    kind: TokenKind::Identifier(_),
+                    ..
+                }, Token {
+                    kind: TokenKind::Equal,
+                    ..
+                }, ..]
+                | [Token {
+                    kind: TokenKind::Define,
+                    ..
+                }, ..] => {
+                    pad(&mut formatted, self.options.label_column);
+                    formatted.push_str(&self.numbers(line, statement));
+                }
+                [Token {
+                    kind: TokenKind::Identifier(_),
+                    ..
+                }, Token {
+                    kind: TokenKind::Directive(directive),
+                    ..
+                }, ..]
+                    if directive.eq_ignore_ascii_case("equ") =>
+                {
+                    pad(&mut formatted, self.options.label_column);
+                    formatted.push_str(&self.numbers(line, statement));
+                }
+                [Token {
+                    kind: TokenKind::Identifier(_),
+                    ..
+                }, ..] => match self.instruction(text, line, statement) {
+                    Some((mnemonic, operand)) => {
+                        self.statement(&mut formatted, &mnemonic, &operand);
+                    }
+                    None => self.verbatim_statement(&mut formatted, line, statement),
+                },
+                [Token {
+                    kind: TokenKind::Directive(directive),
+                    ..
+                }, arguments @ ..]
+                    if DATA_DIRECTIVES
+                        .iter()
+                        .any(|data| directive.eq_ignore_ascii_case(data)) =>
+                {
+                    let name = &line[offset(line, statement[0].position.column)
+                        ..offset(line, statement[0].end.column)];
+                    self.statement(&mut formatted, name, &self.numbers(line, arguments));
+                }
+                [Token {
+                    kind: TokenKind::Directive(_),
+                    ..
+                }, ..] => {
+                    self.switch(text);
+                    self.verbatim_statement(&mut formatted, line, statement);
+                }
+                _ => {
+                    pad(&mut formatted, self.options.mnemonic_column);
+                    formatted.push_str(text);
+                }
+            }
+        }
+
+        if let Some((comment, _)) = comment {
+            pad(&mut formatted, self.options.comment_column);
+            formatted.push_str(comment);
+        }
+
+        formatted
+    }
+
+    /// Tokens of the line until the end, `None` if the line does not lex.
+    fn tokens<'a>(&self, line: &'a str) -> Option<Vec<Token<'a>>> {
+        let mut lexer = Lexer::new(line);
+        lexer.dialect = self.dialect;
+
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token().ok()?;
+            if token.kind == TokenKind::EOF {
+                return Some(tokens);
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn parser<'a>(&self, text: &'a str) -> Parser<'a> {
+        let mut lexer = Lexer::new(text);
+        lexer.dialect = self.dialect;
+        let mut parser = Parser::new(lexer);
+        parser.cpu = self.cpu;
+
+        parser
+    }
+
+    /// Follows `.dialect`, `.setcpu` and the CPU directives of ca65 (`.pc02`).
+    fn switch(&mut self, directive: &str) {
+        let mut parser = self.parser(directive);
+        if parser.parse().is_ok() {
+            self.cpu = parser.cpu;
+            self.dialect = parser.lexer.dialect;
+        }
+    }
+
+    /// Mnemonic and operand of the instruction `statement`, printed from its syntax tree if it parses back
+    /// to the same instruction, or with the operand as written otherwise.
+    fn instruction(
+        &self,
+        statement: &str,
+        line: &str,
+        tokens: &[Token],
+    ) -> Option<(String, String)> {
+        let instruction = self.parse_instruction(statement)?;
+        let mnemonic = self.case(&match instruction.long {
+            true => format!("J{}", &instruction.opcode.to_string()[1..]),
+            false => instruction.opcode.to_string(),
+        });
+
+        let operand = self
+            .operand(&instruction.operand, &tokens[1..])
+            .filter(|operand| {
+                let reparsed = self.parse_instruction(&format!("{mnemonic} {operand}"));
+                reparsed.is_some_and(|reparsed| {
+                    (reparsed.opcode, &reparsed.operand, reparsed.long)
+                        == (instruction.opcode, &instruction.operand, instruction.long)
+                })
+            })
+            .unwrap_or_else(|| self.registers(line, &tokens[1..]));
+
+        Some((mnemonic, operand))
+    }
+
+    /// The instruction `text`, its number literals in one radix to compare it with another one.
+    fn parse_instruction(&self, text: &str) -> Option<Instruction> {
+        let mut instruction = match self.parser(text).parse() {
+            Ok(Program(statements)) => match statements.as_slice() {
+                [Statement::Instruction(instruction)] => instruction.clone(),
+                _ => return None,
+            },
+            Err(_) => return None,
+        };
+        if let Some(OperandData::Number(number)) = &mut instruction.operand.value {
+            *number = match *number {
+                NumberType::Decimal8(n) => NumberType::Hexadecimal8(n),
+                NumberType::Decimal16(n) => NumberType::Hexadecimal16(n),
+                number => number,
+            };
+        }
+
+        Some(instruction)
+    }
+
+    /// Text of the operand in the options, `None` if it is written with tokens the syntax tree
+    /// does not keep (characters, directives).
+    fn operand(&self, operand: &Operand, tokens: &[Token]) -> Option<String> {
+        if tokens.iter().any(|token| {
+            matches!(
+                token.kind,
+                TokenKind::Char(_) | TokenKind::String(_) | TokenKind::Directive(_)
+            )
+        }) {
+            return None;
+        }
+
+        let value = match &operand.value {
+            Some(OperandData::Number(number)) => self.options.radix.number(match *number {
+                NumberType::Decimal8(n) | NumberType::Hexadecimal8(n) => n as u16,
+                NumberType::Decimal16(n) | NumberType::Hexadecimal16(n) => n,
+            }),
+            Some(OperandData::Expression(expression)) => self.expression(expression, 0)?,
+            None => String::new(),
+        };
+        let value = match operand.width {
+            OperandWidth::Auto => value,
+            OperandWidth::Absolute => format!("a:{value}"),
+            OperandWidth::ZeroPage => format!("z:{value}"),
+        };
+        let x = self.case("X");
+        let y = self.case("Y");
+
+        use AddressingMode::*;
+        Some(match operand.addressing_mode {
+            IMP => String::new(),
+            ACC => match self.options.accumulator {
+                AccumulatorSyntax::Implied => String::new(),
+                AccumulatorSyntax::Explicit => self.case("A"),
+            },
+            IMM => format!("#{value}"),
+            ZPG | ABS | REL => value,
+            ZPX | ABX => format!("{value},{x}"),
+            ZPY | ABY => format!("{value},{y}"),
+            IND | IZP => format!("({value})"),
+            IDX | IAX => format!("({value},{x})"),
+            IDY => format!("({value}),{y}"),
+            ZPR => format!("{value},{}", self.expression(operand.target.as_ref()?, 0)?),
+        })
+    }
+
+    /// The expression with the parentheses the precedence of the dialect needs,
+    /// `None` for the labels the parser renamed (cheap local and anonymous labels).
+    fn expression(&self, expression: &Expression, precedence: u8) -> Option<String> {
+        Some(match expression {
+            Expression::Number(number) => self.options.radix.number(*number as u16),
+            Expression::Identifier(name) if name.starts_with(['.', ':']) => return None,
+            Expression::Identifier(name) => name.clone(),
+            Expression::CurrentAddress => "*".to_string(),
+            Expression::Unary(operator, operand) => {
+                format!("{operator}{}", self.expression(operand, u8::MAX)?)
+            }
+            Expression::Binary(operator, left, right) => {
+                let operator_precedence = operator.precedence(self.dialect);
+                let text = format!(
+                    "{} {operator} {}",
+                    self.expression(left, operator_precedence)?,
+                    self.expression(right, operator_precedence + 1)?
+                );
+                match operator_precedence < precedence {
+                    true => format!("({text})"),
+                    false => text,
+                }
+            }
+        })
+    }
+
+    fn case(&self, text: &str) -> String {
+        match self.options.mnemonic_case {
+            MnemonicCase::Upper => text.to_uppercase(),
+            MnemonicCase::Lower => text.to_lowercase(),
+        }
+    }
+
+    fn statement(&self, formatted: &mut String, mnemonic: &str, operand: &str) {
+        pad(formatted, self.options.mnemonic_column);
+        formatted.push_str(mnemonic);
+        if !operand.is_empty() {
+            pad(formatted, self.options.operand_column);
+            formatted.push_str(operand);
+        }
+    }
+
+    /// Text of the tokens with their number literals in the radix of the options, the other tokens
+    /// and the spaces between them are kept as written.
+    fn numbers(&self, line: &str, tokens: &[Token]) -> String {
+        rewrite(line, tokens, |_, token| match token.kind {
+            TokenKind::Decimal(n) | TokenKind::Hexadecimal(n) | TokenKind::Binary(n) => {
+                Some(self.options.radix.number(n))
+            }
+            _ => None,
+        })
+    }
+
+    /// Text of the operand tokens of an instruction with the accumulator (`A`) and the index
+    /// registers (`,X`, `,Y`) in the case of the options, the rest is kept as written.
+    fn registers(&self, line: &str, tokens: &[Token]) -> String {
+        rewrite(line, tokens, |index, token| match token.kind {
+            TokenKind::Identifier(name)
+                if (name.eq_ignore_ascii_case("x") || name.eq_ignore_ascii_case("y"))
+                    && index > 0
+                    && tokens[index - 1].kind == TokenKind::Comma =>
+            {
+                Some(self.case(name))
+            }
+            TokenKind::Identifier(name) if name.eq_ignore_ascii_case("a") && tokens.len() == 1 => {
+                Some(self.case(name))
+            }
+            _ => None,
+        })
+    }
+
+    /// Directive, macro invocation or instruction that does not parse on its own line (`BNE :-`),
+    /// its arguments as written at the operand column.
+    fn verbatim_statement(&self, formatted: &mut String, line: &str, tokens: &[Token]) {
+        let (name, operand) = match tokens[0].kind {
+            TokenKind::Identifier(name) if Mnemonics::from_name(name).is_some() => {
+                (self.case(name), self.registers(line, &tokens[1..]))
+            }
+            _ => {
+                let operand = match tokens {
+                    [_, first, .., last] | [_, first @ last] => text(line, first, last),
+                    _ => "",
+                };
+                (
+                    text(line, &tokens[0], &tokens[0]).to_string(),
+                    operand.to_string(),
+                )
+            }
+        };
+        self.statement(formatted, &name, &operand);
+    }
+}
+
+/// Text of the tokens with the ones `replace` returns a text for (by index and token) replaced,
+/// the other tokens and the spaces between them are kept as written.
+fn rewrite(
+    line: &str,
+    tokens: &[Token],
+    replace: impl Fn(usize, &Token) -> Option<String>,
+) -> String {
+    let (Some(first), Some(last)) = (tokens.first(), tokens.last()) else {
+        return String::new();
+    };
+
+    let mut rewritten = String::new();
+    let mut start = offset(line, first.position.column);
+    for (index, token) in tokens.iter().enumerate() {
+        let Some(replacement) = replace(index, token) else {
+            continue;
+        };
+        rewritten.push_str(&line[start..offset(line, token.position.column)]);
+        rewritten.push_str(&replacement);
+        start = offset(line, token.end.column);
+    }
+    rewritten.push_str(&line[start..offset(line, last.end.column)]);
+
+    rewritten
+}
+
+/// Pads the line with spaces to the column, or with a space if it is already there.
+fn pad(formatted: &mut String, column: usize) {
+    let length = formatted.chars().count();
+    if length < column {
+        formatted.extend(std::iter::repeat_n(' ', column - length));
+    } else if length > 0 {
+        formatted.push(' ');
+    }
+}
+
+/// Byte offset of the column (from `1`) of the line.
+fn offset(line: &str, column: usize) -> usize {
+    line.char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(line.len(), |(offset, _)| offset)
+}
+
+/// Text of the line from the start of `first` to the end of `last`.
+fn text<'a>(line: &'a str, first: &Token, last: &Token) -> &'a str {
+    &line[offset(line, first.position.column)..offset(line, last.end.column)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(source, &FormatOptions::default())
+    }
+
+    #[test]
+    fn test_names() {
+        for case in MnemonicCase::ALL {
+            assert_eq!(MnemonicCase::from_name(case.name()), Some(case));
+        }
+        for radix in Radix::ALL {
+            assert_eq!(Radix::from_name(radix.name()), Some(radix));
+        }
+        assert_eq!(Radix::from_name("oct"), None);
+    }
+
+    #[test]
+    fn test_radix() {
+        let numbers = |radix: Radix| [0x0F, 0x0200].map(|number| radix.number(number));
+        assert_eq!(numbers(Radix::Hexadecimal), ["$0F", "$0200"]);
+        assert_eq!(numbers(Radix::Decimal), ["15", "512"]);
+        assert_eq!(numbers(Radix::Binary), ["%00001111", "%0000001000000000"]);
+    }
+
+    #[test]
+    fn test_operands() {
+        let cases = [
+            // the parentheses the precedence needs
+            ("LDA #(1+2)*3", "LDA #($01 + $02) * $03"),
+            ("LDA 1+(2*3)", "LDA $01 + $02 * $03"),
+            ("LDA 10-(2-1)", "LDA $0A - ($02 - $01)"),
+            ("BNE *+4", "BNE * + $04"),
+            // the addressing modes and widths
+            ("JMP ($1234)", "JMP ($1234)"),
+            ("lda ($10,x)", "LDA ($10,X)"),
+            ("LDA ($10),y", "LDA ($10),Y"),
+            ("LDA a:$10", "LDA a:$10"),
+            ("STA z:ptr,x", "STA z:ptr,X"),
+        ];
+        for (source, formatted) in cases {
+            assert_eq!(format(source), format!("        {formatted}"), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let cases = [
+            // a longer label is followed by a space
+            ("verylonglabel: NOP", "verylonglabel: NOP"),
+            (":  BNE :-", ":       BNE :-"),
+            // comments on a line of their own
+            ("   ; indented", "        ; indented"),
+            ("; start", "; start"),
+            // constants at the label column, with the numbers in the radix
+            ("X .equ 10", "X .equ $0A"),
+            ("SCREEN=$0400 ; s", "SCREEN=$0400                    ; s"),
+            // directives are aligned
+            ("  .org $8000", "        .org $8000"),
+            // a line that does not lex is kept
+            ("LDA \"open", "LDA \"open"),
+        ];
+        for (source, formatted) in cases {
+            assert_eq!(format(source), formatted, "{source}");
+        }
+
+        // macro invocations are aligned, their arguments are kept
+        assert_eq!(
+            format(".macro m\n m 1,2\n .endm\n"),
+            "        .macro m\n        m   1,2\n        .endm\n"
+        );
+    }
+}
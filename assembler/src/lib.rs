@@ -1,34 +1,144 @@
-/*
-TODO
-
-define 파서에서 다 처리하기
-=> OperandData::Ident -> OperandData::Label
-*/
-
 mod ast;
+mod debug;
+mod diagnostic;
+mod disassembler;
+mod format;
 mod instruction;
+mod linker;
+mod lint;
+mod listing;
+mod object;
+mod output;
 mod parser;
+mod source;
 mod tokenizer;
 
 pub use ast::*;
+pub use debug::*;
+pub use disassembler::*;
+pub use format::*;
 pub use instruction::*;
+pub use linker::*;
+pub use lint::*;
+pub use listing::*;
+pub use object::*;
+pub use output::*;
 pub use parser::*;
+pub use source::*;
 pub use tokenizer::*;
 
-use std::{collections::HashMap, fmt};
-use tokenizer::lexer::Lexer;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AssemblerErrorKind {
     IllegalCharacter(char),
     InvalidNumber,
-    UnexpectedToken { expected: String, found: String },
+    UnexpectedToken {
+        expected: String,
+        found: String,
+    },
     UnexpectedToken2,
     InvalidOperand(String),
     InvalidLabel(String),
+    /// a label defined again, the position of its first definition
+    DuplicateLabel {
+        name: String,
+        previous: Position,
+    },
     InvalidInstruction(String, AddressingMode),
     InvalidMnemonic(String),
+    /// an instruction of another CPU than the selected one, see [`CpuType`]
+    UnavailableInstruction {
+        mnemonic: String,
+        cpu: CpuType,
+    },
+    /// `INC A` and `DEC A` on a CPU without the accumulator mode of the CMOS CPUs
+    UnavailableAccumulatorMode {
+        mnemonic: String,
+        cpu: CpuType,
+    },
     InvalidOpcode(u8),
+    InvalidDirective(String),
+    UnterminatedString,
+    InvalidEscape(char),
+    InvalidCharLiteral(String),
+    OutOfRange(i32),
+    DivisionByZero,
+    UnterminatedMacro(String),
+    NestedMacro,
+    MacroArguments {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    MacroRecursion(String),
+    UnmatchedConditional(String),
+    UnterminatedConditional,
+    FileNotFound(String),
+    FileError(String),
+    IncludeRecursion(String),
+    UnmatchedScope(String),
+    UnterminatedScope(String),
+    UnterminatedRepeat,
+    /// a directive or function of ca65 that the ca65 dialect does not support, see [`Dialect::Ca65`]
+    Unsupported(String),
+    /// distance of the target from the end of the branch
+    BranchOutOfRange(i32),
+    /// an address that a previous `.org` block assembled already
+    OverlappingOrigin(u16),
+    /// bytes past `$FFFF`
+    AddressOverflow,
+    /// a warning of a lint set to [`LintLevel::Deny`]
+    DeniedWarning(AssemblerWarningKind),
+    /// invalid record of a program file, see [`OutputFormat::read`]
+    InvalidRecord(String),
+    /// a directive of object files in a program assembled to its addresses, see [`Assembler::assemble_object`]
+    ObjectOnly(String),
+    /// a directive that sets addresses in an object file or with a layout
+    AbsoluteOnly(String),
+    /// a segment that the layout does not place, see [`Assembler::set_layout`]
+    UnplacedSegment(String),
+    /// an expression of an object file that can not be computed from the addresses of the segments
+    NotRelocatable(String),
+    /// a branch to another segment or an import, the target
+    SegmentBranch(String),
+    InvalidExport(String),
+    /// invalid record of an object file, see [`Object::read`]
+    InvalidObject(String),
+    /// see [`LinkerConfig::parse`]
+    InvalidConfig(String),
+    /// a segment of an object that the linker config does not place
+    UnknownSegment {
+        segment: String,
+        object: String,
+    },
+    /// a segment whose `start` is before the end of the previous segment or after its memory area
+    SegmentOverlap {
+        segment: String,
+        address: u16,
+    },
+    /// the segments of a memory area are `bytes` bytes larger than the area
+    SegmentOverflow {
+        segment: String,
+        memory: String,
+        bytes: usize,
+    },
+    DuplicateExport(String),
+    UnresolvedImport {
+        name: String,
+        object: String,
+    },
+    /// bytes in a segment of `type = bss` or `type = zp`
+    BssData(String),
+    /// a relocated value that does not fit into its bytes
+    RelocationOutOfRange {
+        target: String,
+        value: i32,
+    },
 }
 
 impl fmt::Display for AssemblerErrorKind {
@@ -40,321 +150,3366 @@ impl fmt::Display for AssemblerErrorKind {
             AssemblerErrorKind::UnexpectedToken2 => write!(f, "Unexpected token"),
             AssemblerErrorKind::InvalidOperand(operand) => write!(f, "Invalid operand: {operand}"),
             AssemblerErrorKind::InvalidLabel(label) => write!(f, "Invalid label: {label}",),
+            AssemblerErrorKind::DuplicateLabel { name, previous } => write!(f, "Duplicate label: {name} (first defined at line {})", previous.line),
             AssemblerErrorKind::InvalidInstruction(mnemonic, addressing_mode) => write!(f, "Invalid instruction: mnemonic {mnemonic:?} does not support {addressing_mode:?} addressing mode"),
             AssemblerErrorKind::InvalidMnemonic(mnemonic) => write!(f, "Invalid mnemonic: {mnemonic:?}"),
+            AssemblerErrorKind::UnavailableInstruction { mnemonic, cpu } => write!(f, "Instruction {mnemonic} is not available on the {cpu}, select another CPU with .setcpu"),
+            AssemblerErrorKind::UnavailableAccumulatorMode { mnemonic, cpu } => write!(f, "Accumulator mode of {mnemonic} ({mnemonic} A) is not available on the {cpu}, it needs a CMOS CPU (65SC02, 65C02, W65C02), select one with .setcpu"),
             AssemblerErrorKind::InvalidOpcode(opcode) => write!(f, "Invalid opcode: {opcode:?}"),
+            AssemblerErrorKind::InvalidDirective(directive) => write!(f, "Invalid directive: .{directive}"),
+            AssemblerErrorKind::UnterminatedString => write!(f, "Unterminated string"),
+            AssemblerErrorKind::InvalidEscape(c) => write!(f, "Invalid escape sequence: \\{c}"),
+            AssemblerErrorKind::InvalidCharLiteral(literal) => {
+                write!(f, "Invalid character literal: '{literal}', it must be a single byte")
+            }
+            AssemblerErrorKind::OutOfRange(value) => write!(f, "Value out of range: {value}"),
+            AssemblerErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            AssemblerErrorKind::UnterminatedMacro(name) => write!(f, "Unterminated macro: {name:?} has no .endm"),
+            AssemblerErrorKind::NestedMacro => write!(f, "Macro definitions can not be nested"),
+            AssemblerErrorKind::MacroArguments { name, expected, found } => write!(f, "Invalid macro arguments: {name:?} expects {expected} arguments, found {found}"),
+            AssemblerErrorKind::UnmatchedConditional(directive) => write!(f, "Unmatched conditional: .{directive} without .if"),
+            AssemblerErrorKind::UnterminatedConditional => write!(f, "Unterminated conditional: .if has no .endif"),
+            AssemblerErrorKind::MacroRecursion(name) => write!(f, "Macro recursion limit reached while expanding {name:?}"),
+            AssemblerErrorKind::FileNotFound(path) => write!(f, "File not found: {path:?}"),
+            AssemblerErrorKind::FileError(message) => write!(f, "Can not read file: {message}"),
+            AssemblerErrorKind::IncludeRecursion(name) => write!(f, "Recursive include of {name:?}"),
+            AssemblerErrorKind::UnmatchedScope(directive) => write!(f, "Unmatched scope: .{directive} without .{}", &directive[3..]),
+            AssemblerErrorKind::UnterminatedScope(directive) => write!(f, "Unterminated scope: .{directive} has no .end{directive}"),
+            AssemblerErrorKind::UnterminatedRepeat => write!(f, "Unterminated repeat: .repeat has no .endrep"),
+            AssemblerErrorKind::Unsupported(feature) => write!(f, "Unsupported ca65 feature: {feature}"),
+            AssemblerErrorKind::OverlappingOrigin(address) => write!(f, "Address ${address:04X} is assembled twice, the .org blocks overlap"),
+            AssemblerErrorKind::AddressOverflow => write!(f, "Address out of range: the program passes $FFFF"),
+            AssemblerErrorKind::BranchOutOfRange(distance) => write!(f, "Branch out of range: the target is {distance} bytes away, branches reach -128 to 127 (use a long branch like JEQ)"),
+            AssemblerErrorKind::DeniedWarning(kind) => write!(f, "Denied warning: {kind} [{}]", kind.lint().name()),
+            AssemblerErrorKind::InvalidRecord(message) => write!(f, "Invalid record: {message}"),
+            AssemblerErrorKind::ObjectOnly(directive) => write!(f, "Directive .{directive} can only be used in object files or with a layout"),
+            AssemblerErrorKind::AbsoluteOnly(directive) => write!(f, "Directive .{directive} can not be used in object files or with a layout, the linker config places the segments"),
+            AssemblerErrorKind::UnplacedSegment(segment) => write!(f, "Segment {segment} is not in the layout"),
+            AssemblerErrorKind::NotRelocatable(expression) => write!(f, "Expression can not be relocated: {expression}, only `symbol + constant`, `symbol - constant`, `<symbol`, `>symbol` and differences of symbols in the same segment can"),
+            AssemblerErrorKind::SegmentBranch(target) => write!(f, "Branch to {target} in another segment or object (use a long branch like JEQ)"),
+            AssemblerErrorKind::InvalidExport(name) => write!(f, "Invalid export: {name} is not a label or constant of the object"),
+            AssemblerErrorKind::InvalidObject(message) => write!(f, "Invalid object file: {message}"),
+            AssemblerErrorKind::InvalidConfig(message) => write!(f, "Invalid linker config: {message}"),
+            AssemblerErrorKind::UnknownSegment { segment, object } => write!(f, "Segment {segment} of {object:?} is not in the linker config"),
+            AssemblerErrorKind::SegmentOverlap { segment, address } => write!(f, "Segment {segment} can not start at ${address:04X}, it overlaps the previous segment or is outside of its memory area"),
+            AssemblerErrorKind::SegmentOverflow { segment, memory, bytes } => write!(f, "Segment {segment} overflows memory area {memory} by {bytes} byte{}", if *bytes == 1 { "" } else { "s" }),
+            AssemblerErrorKind::DuplicateExport(name) => write!(f, "Symbol {name} is exported more than once"),
+            AssemblerErrorKind::UnresolvedImport { name, object } => write!(f, "Unresolved import {name} of {object:?}"),
+            AssemblerErrorKind::BssData(segment) => write!(f, "Segment {segment} is bss or zero page, it can only reserve bytes (.res)"),
+            AssemblerErrorKind::RelocationOutOfRange { target, value } => write!(f, "Relocated value out of range: {target} is {value} there"),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AssemblerError {
     pub kind: AssemblerErrorKind,
-    pub position: Position,
+    /// source of the error, empty if only the start is known (the start of a statement).
+    /// boxed like the fields below, so that results stay small
+    pub span: Box<Span>,
+    /// macro invocations the error was expanded from, innermost first
+    pub backtrace: Box<[(String, Position)]>,
+    /// names of the source files, see [`Position::file`]
+    pub files: Box<[String]>,
 }
 
 impl AssemblerError {
-    pub fn new(kind: AssemblerErrorKind, position: Position) -> Self {
-        Self { kind, position }
+    pub fn new(kind: AssemblerErrorKind, span: impl Into<Span>) -> Self {
+        Self {
+            kind,
+            span: Box::new(span.into()),
+            backtrace: Box::default(),
+            files: Box::default(),
+        }
+    }
+
+    /// Resolves the macro invocations the error position was expanded from.
+    pub fn with_backtrace(mut self, expansions: &[Expansion]) -> Self {
+        let mut backtrace = Vec::new();
+        let mut expansion = self.span.start.expansion;
+        while let Some(Expansion {
+            name, call_site, ..
+        }) = expansion.checked_sub(1).and_then(|i| expansions.get(i))
+        {
+            backtrace.push((name.clone(), *call_site));
+            expansion = call_site.expansion;
+        }
+
+        self.backtrace = backtrace.into_boxed_slice();
+        self
+    }
+
+    /// The backtrace with the repeated invocations of a recursive macro collapsed, and their number.
+    pub(crate) fn frames(&self) -> Vec<(&str, Position, usize)> {
+        let mut frames = Vec::<(&str, Position, usize)>::new();
+        for (name, position) in self.backtrace.iter() {
+            match frames.last_mut() {
+                // the invocations are in different expansions of the macro
+                Some((last, at, count))
+                    if last == name
+                        && (at.file, at.line, at.column)
+                            == (position.file, position.line, position.column) =>
+                {
+                    *count += 1
+                }
+                _ => frames.push((name, *position, 1)),
+            }
+        }
+
+        frames
+    }
+
+    /// Names the source files, so that positions in included files can be displayed.
+    pub fn with_files(mut self, sources: &Sources) -> Self {
+        self.files = sources.names().into_boxed_slice();
+        self
+    }
+
+    fn fmt_position(&self, f: &mut fmt::Formatter<'_>, position: &Position) -> fmt::Result {
+        // errors of the linker are not in a source
+        if position.line == 0 {
+            return Ok(());
+        }
+        write!(f, " at line {}, column {}", position.line, position.column)?;
+        match self.files.get(position.file) {
+            Some(name) if position.file > 0 => write!(f, " in {name:?}"),
+            _ => Ok(()),
+        }
     }
 }
 
 impl fmt::Display for AssemblerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.kind)?;
-        let Position(line, column) = self.position;
-        write!(f, " at line {}, column {}", line, column)?;
+        self.fmt_position(f, &self.span.start)?;
+        for (name, position, count) in self.frames() {
+            write!(f, ", in macro {name:?}")?;
+            self.fmt_position(f, &position)?;
+            if count > 1 {
+                write!(f, " (×{count})")?;
+            }
+        }
         Ok(())
     }
 }
 
 pub type AssemblerResult<T> = Result<T, AssemblerError>;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssemblerWarningKind {
+    /// `define NAME value`, the name of the constant
+    DeprecatedDefine(String),
+    /// `define NAME #value` or `define NAME address,X`, the name of the operand
+    DeprecatedDefineOperand(String),
+    UnusedLabel(String),
+    UnusedConstant(String),
+    /// absolute addressing of an address that zero page addressing could reach
+    ZeroPage(u16),
+    /// `JMP ($xxFF)`, the address of the pointer
+    JmpIndirectPageWrap(u16),
+    Unreachable,
+    BranchToSelf,
+    /// a write into an assembled instruction, the address written to
+    SelfModifying(u16),
+}
+
+impl fmt::Display for AssemblerWarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssemblerWarningKind::DeprecatedDefine(name) => {
+                write!(f, "`define` is deprecated, use `{name} = value` instead")
+            }
+            AssemblerWarningKind::DeprecatedDefineOperand(name) => write!(
+                f,
+                "`define` with an addressing mode is deprecated, use `{name} = value` and write `#` or the index register on the instruction"
+            ),
+            AssemblerWarningKind::UnusedLabel(name) => write!(f, "label `{name}` is never used"),
+            AssemblerWarningKind::UnusedConstant(name) => {
+                write!(f, "constant `{name}` is never used")
+            }
+            AssemblerWarningKind::ZeroPage(address) => write!(
+                f,
+                "absolute addressing of ${address:04X}, zero page addressing is shorter and faster"
+            ),
+            AssemblerWarningKind::JmpIndirectPageWrap(address) => write!(
+                f,
+                "`JMP (${address:04X})` reads the high byte from ${:04X} on the NMOS 6502",
+                address & 0xFF00
+            ),
+            AssemblerWarningKind::Unreachable => write!(f, "unreachable instruction"),
+            AssemblerWarningKind::BranchToSelf => write!(f, "branch to itself"),
+            AssemblerWarningKind::SelfModifying(address) => {
+                write!(
+                    f,
+                    "write to ${address:04X}, which is an assembled instruction"
+                )
+            }
+        }
+    }
+}
+
+/// A problem that does not stop the source from being assembled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblerWarning {
+    pub kind: AssemblerWarningKind,
+    pub position: Position,
+}
+
+impl AssemblerWarning {
+    pub fn new(kind: AssemblerWarningKind, position: Position) -> Self {
+        Self { kind, position }
+    }
+}
+
+impl fmt::Display for AssemblerWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Warning: {} at line {}, column {}",
+            self.kind, self.position.line, self.position.column
+        )
+    }
+}
+
+/// Address the code is placed at when the source has no `.org` directive.
+pub const DEFAULT_ORIGIN: u16 = 0x8000;
+
+/// A contiguous block of assembled bytes, placed at `origin`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn new(origin: u16) -> Self {
+        Self {
+            origin,
+            bytes: Vec::new(),
+        }
+    }
+}
+
+/// Merges the chunks into a single image, returns the lowest origin and the image.
+/// gaps between the chunks are filled with `0x00`.
+pub fn chunks_to_image(chunks: &[Chunk]) -> (u16, Vec<u8>) {
+    let start = match chunks.iter().map(|chunk| chunk.origin as usize).min() {
+        Some(start) => start,
+        None => return (DEFAULT_ORIGIN, Vec::new()),
+    };
+    let end = chunks
+        .iter()
+        .map(|chunk| chunk.origin as usize + chunk.bytes.len())
+        .max()
+        .unwrap_or(start);
+
+    let mut bytes = vec![0; end - start];
+    for chunk in chunks {
+        let offset = chunk.origin as usize - start;
+        bytes[offset..offset + chunk.bytes.len()].copy_from_slice(&chunk.bytes);
+    }
+
+    (start as u16, bytes)
+}
+
+/// Address a loaded program starts at: the reset vector (`$FFFC`) if the chunks set it,
+/// or else the origin of the first chunk.
+pub fn entry_point(chunks: &[Chunk]) -> Option<u16> {
+    let byte = |address: usize| {
+        chunks.iter().find_map(|chunk| {
+            let offset = address.checked_sub(chunk.origin as usize)?;
+            chunk.bytes.get(offset).copied()
+        })
+    };
+
+    match (byte(0xFFFC), byte(0xFFFD)) {
+        (Some(low), Some(high)) => Some(u16::from_le_bytes([low, high])),
+        _ => chunks.first().map(|chunk| chunk.origin),
+    }
+}
+
+/// Pushes the current chunk (if it is not empty) and starts a new one at `origin`.
+fn next_chunk(chunks: &mut Vec<Chunk>, chunk: &mut Chunk, origin: u16) {
+    let chunk = std::mem::replace(chunk, Chunk::new(origin));
+    if !chunk.bytes.is_empty() {
+        chunks.push(chunk);
+    }
+}
+
 pub struct Assembler<'a> {
     pub source: &'a str,
     pointer: usize,
     labels: HashMap<String, u16>,
+    /// values of the constants (`NAME = expression`), by qualified name
+    constants: HashMap<String, i32>,
+    symbols: HashMap<String, i32>,
+    resolver: Option<Box<dyn FileResolver>>,
+    /// see [`Parser::scopes`]
+    scopes: Vec<String>,
+    /// errors of the last assembled source, the first one is returned
+    pub errors: Vec<AssemblerError>,
+    /// warnings of the last assembled source
+    pub warnings: Vec<AssemblerWarning>,
+    /// sources of the last assembly, see [`Assembler::diagnostics`]
+    pub files: Vec<SourceFile>,
+    /// levels of the lints that do not warn, see [`Assembler::set_lint`]
+    lints: HashMap<Lint, LintLevel>,
+    /// qualified names of the labels and constants referenced so far
+    used: RefCell<HashSet<String>>,
+    /// see [`Assembler::set_long_branches`]
+    long_branches: bool,
+    /// segments of the object being assembled, `None` if the source is assembled to its addresses.
+    /// see [`Assembler::assemble_object`]
+    object: Option<ObjectState>,
+    /// see [`Assembler::set_layout`]
+    layout: Option<Layout>,
+    /// see [`Assembler::set_dialect`]
+    dialect: Dialect,
+    /// see [`Assembler::set_cpu`]
+    cpu: CpuType,
+    /// listing of the last assembled source
+    pub listing: Listing,
 }
 
 impl<'a> Assembler<'a> {
     pub fn new(source: &'a str) -> Self {
         Self {
             source,
-            pointer: 0,
+            pointer: DEFAULT_ORIGIN as usize,
             labels: HashMap::new(),
+            constants: HashMap::new(),
+            symbols: HashMap::new(),
+            resolver: None,
+            scopes: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            files: Vec::new(),
+            lints: HashMap::new(),
+            used: RefCell::default(),
+            long_branches: false,
+            object: None,
+            layout: None,
+            dialect: Dialect::default(),
+            cpu: CpuType::default(),
+            listing: Listing::default(),
         }
     }
 
-    pub fn assemble(&mut self) -> AssemblerResult<Vec<u8>> {
-        let lexer = Lexer::new(self.source);
-        let mut parser = Parser::new(lexer);
-        let p = parser.parse()?;
+    /// Defines a symbol before assembling, like `-D NAME=VALUE` on the command line.
+    /// symbols can be used in expressions and are visible to `.if`/`.ifdef`.
+    pub fn define_symbol(&mut self, name: &str, value: i32) -> &mut Self {
+        self.symbols.insert(name.to_string(), value);
+        self
+    }
 
-        let mut bytes = Vec::new();
+    /// Sets the resolver for `.include` and `.incbin`, without one every included file is not found.
+    pub fn set_resolver(&mut self, resolver: impl FileResolver + 'static) -> &mut Self {
+        self.resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Sets how the warnings of `lint` are reported, every lint warns by default.
+    /// warnings can also be suppressed per line with a `; nowarn` comment, see [`Lint`]
+    pub fn set_lint(&mut self, lint: Lint, level: LintLevel) -> &mut Self {
+        self.lints.insert(lint, level);
+        self
+    }
 
-        for statement in p.0.clone() {
-            self.preprocess_statement(statement);
+    /// Sets the level of every lint, [`LintLevel::Deny`] turns every warning into an error.
+    pub fn set_lints(&mut self, level: LintLevel) -> &mut Self {
+        for lint in Lint::ALL {
+            self.set_lint(lint, level);
         }
+        self
+    }
 
-        self.pointer = 0;
+    /// Assembles every branch like a long branch (`BEQ` like `JEQ`, see [`Instruction::long`]),
+    /// branches to targets out of range become the inverted branch over a `JMP`.
+    pub fn set_long_branches(&mut self, long_branches: bool) -> &mut Self {
+        self.long_branches = long_branches;
+        self
+    }
 
-        for statement in p.0 {
-            if let Statement::Instruction(instruction) = statement {
-                bytes.extend(self.assemble_instruction(instruction)?)
+    /// Sets the syntax of the source, included files start in the dialect of the including file.
+    /// a file can switch the dialect of its following lines with `.dialect ca65` or `.dialect native`.
+    pub fn set_dialect(&mut self, dialect: Dialect) -> &mut Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Sets the CPU the source is assembled for, the source can select another one with `.setcpu "65C02"`.
+    pub fn set_cpu(&mut self, cpu: CpuType) -> &mut Self {
+        self.cpu = cpu;
+        self
+    }
+
+    /// Places the segments of the source (`.segment "NAME"`) like the linker, without objects.
+    ///
+    /// every segment has its own location counter, the statements before the first `.segment`
+    /// are in [`DEFAULT_SEGMENT`]. segments of `type = bss` and `type = zp` only reserve bytes (`.res`),
+    /// and memory areas with a `fill` value are filled. `.org` can not be used.
+    pub fn set_layout(&mut self, layout: LinkerConfig) -> &mut Self {
+        self.layout = Some(Layout::new(layout));
+        self
+    }
+
+    /// Assembles the source into a single image that starts at the lowest origin.
+    /// gaps between the chunks are filled with `0x00`.
+    pub fn assemble(&mut self) -> AssemblerResult<Vec<u8>> {
+        Ok(chunks_to_image(&self.assemble_chunks()?).1)
+    }
+
+    /// Assembles the source and keeps its symbols and the source line of every address,
+    /// for debuggers and the debug files (see [`Assembly`]).
+    pub fn build(&mut self) -> AssemblerResult<Assembly> {
+        let chunks = self.assemble_chunks()?;
+        let (origin, bytes) = chunks_to_image(&chunks);
+
+        Ok(Assembly {
+            origin,
+            bytes,
+            chunks,
+            symbols: self.listing.symbols.clone(),
+            lines: self
+                .listing
+                .entries
+                .iter()
+                .filter(|entry| !entry.bytes.is_empty())
+                .map(|entry| LineInfo {
+                    address: entry.address,
+                    size: entry.bytes.len(),
+                    position: entry.position,
+                })
+                .collect(),
+            files: self
+                .listing
+                .files
+                .iter()
+                .map(|file| file.name.clone())
+                .collect(),
+        })
+    }
+
+    /// Every error and warning of the last assembly, rendered with the source lines they refer to.
+    /// set the name of the main source (`files[0]`) first to display it.
+    pub fn diagnostics(&self) -> String {
+        let errors = self.errors.iter().map(|error| error.render(&self.files));
+        let warnings = self
+            .warnings
+            .iter()
+            .map(|warning| warning.render(&self.files));
+
+        errors.chain(warnings).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Assembles the source into chunks, a new chunk is started by every `.org` (`*=`) directive.
+    pub fn assemble_chunks(&mut self) -> AssemblerResult<Vec<Chunk>> {
+        // the resolver is taken out while assembling, the sources borrow it
+        let resolver = self.resolver.take();
+        let result = self.assemble_sources(&Sources::load(self.source, resolver.as_deref()));
+        self.resolver = resolver;
+
+        result
+    }
+
+    /// Assembles the source into a relocatable object for the linker, see [`link`]
+    ///
+    /// the statements are assembled into segments (`.segment "NAME"`, [`DEFAULT_SEGMENT`] before the first one)
+    /// that start at `0`, so `.org` can not be used. `.import` declares the symbols of other objects
+    /// and `.export` the labels and constants for them.
+    pub fn assemble_object(&mut self) -> AssemblerResult<Object> {
+        // the linker places the segments of objects
+        let layout = self.layout.take();
+        self.object = Some(ObjectState::default());
+        let result = self.assemble_chunks();
+        let object = self.object.take().unwrap_or_default();
+        self.layout = layout;
+        result?;
+
+        Ok(Object {
+            name: String::new(),
+            segments: object
+                .segments
+                .into_iter()
+                .filter(|segment| segment.size > 0)
+                .collect(),
+            exports: object.symbols,
+            imports: object.imports,
+        })
+    }
+
+    /// Starts a pass at the address of the first statement,
+    /// objects start every segment at `0` and a layout at the start of [`DEFAULT_SEGMENT`].
+    fn reset_segments(&mut self) {
+        self.pointer = match (&mut self.object, &mut self.layout) {
+            (Some(object), _) => {
+                object.reset();
+                0
             }
+            (None, Some(layout)) => layout.reset(),
+            (None, None) => DEFAULT_ORIGIN as usize,
+        };
+    }
+
+    fn assemble_sources(&mut self, sources: &Sources) -> AssemblerResult<Vec<Chunk>> {
+        let mut parser = Parser::with_sources(sources, self.dialect);
+        parser.symbols = self.symbols.clone();
+        parser.cpu = self.cpu;
+        self.files = sources.files.clone();
+        self.errors.clear();
+
+        // the statements without parse errors are assembled too, to report their errors as well
+        let p = parser.parse_program();
+        self.warnings = std::mem::take(&mut parser.warnings);
+        let parse_errors = std::mem::take(&mut parser.errors);
+        self.scopes = parser.scopes.clone();
+        self.used = RefCell::new(parser.used.take());
+        self.listing = Listing {
+            files: sources.files.clone(),
+            included_at: parser.included_at.clone(),
+            expansions: parser.expansions.clone(),
+            ..Default::default()
+        };
+
+        let chunks = self.assemble_program(p);
+        // deferred constants are reported after the statements they precede
+        self.errors.sort_by_key(|error| {
+            let start = error.span.start;
+            (start.file, start.line, start.column)
+        });
+        // the parse errors are reported first, in the order of the parser
+        self.errors.splice(0..0, parse_errors);
+        if self.errors.is_empty() {
+            self.listing.symbols = self.symbol_table(&parser);
+            self.lint();
         }
+        self.finish(sources, &parser)?;
 
-        Ok(bytes)
+        Ok(chunks)
     }
 
-    fn assemble_instruction(&mut self, instruction: Instruction) -> AssemblerResult<Vec<u8>> {
-        let operand = self.assemble_operand_data(instruction.clone())?;
-        let instruction = Instruction {
-            operand: Operand {
-                addressing_mode: operand.1,
-                ..instruction.operand
-            },
-            ..instruction
-        };
-        let bytes = [instruction_to_byte(instruction.clone())?]
+    /// Applies the lint levels to the warnings and returns the first error, if there is one.
+    fn finish(&mut self, sources: &Sources, parser: &Parser) -> AssemblerResult<()> {
+        self.apply_lints();
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        self.errors = std::mem::take(&mut self.errors)
+            .into_iter()
+            .map(|error| {
+                error
+                    .with_backtrace(&parser.expansions)
+                    .with_files(sources)
+                    .with_statement_span(&sources.files)
+            })
+            .collect();
+
+        Err(self.errors[0].clone())
+    }
+
+    /// Labels and constants of the assembled source, sorted by name.
+    fn symbol_table(&self, parser: &Parser) -> Vec<Symbol> {
+        let labels = self
+            .labels
             .iter()
-            .chain(&operand.0)
-            .copied()
+            .map(|(name, address)| (name, *address as i32, SymbolKind::Label));
+        let constants = self
+            .constants
+            .iter()
+            .map(|(name, value)| (name, *value, SymbolKind::Constant));
+        let mut symbols = labels
+            .chain(constants)
+            // anonymous labels and the labels local to a macro expansion are not listed
+            .filter(|(name, ..)| !name.starts_with(':') && !name.contains('@'))
+            .map(|(name, value, kind)| Symbol {
+                name: name.clone(),
+                value,
+                kind,
+                position: parser.labels.get(name).copied().unwrap_or_default(),
+            })
             .collect::<Vec<_>>();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
 
-        self.pointer += bytes.len();
-
-        Ok(bytes)
+        symbols
     }
 
-    fn preprocess_statement(&mut self, statement: Statement) {
-        match statement {
-            Statement::Instruction(instruction) => {
-                self.pointer += 1;
+    /// Defines the labels and constants of the program,
+    /// returns the segment (see [`ObjectState::segment`], `0` outside of objects) and the address of every statement.
+    fn preprocess(&mut self, p: &Program) -> Vec<(usize, usize)> {
+        self.reset_segments();
+        self.labels.clear();
+        self.constants.clear();
+        self.errors.clear();
+        if let Some(object) = &mut self.object {
+            object.labels.clear();
+            object.constants.clear();
+            object.imports.clear();
+            object.zero_page_imports.clear();
+            object.exports.clear();
+        }
 
-                self.preprocess_operand(instruction);
+        // constants that refer to later labels or constants are evaluated after every label is known
+        let mut deferred = Vec::new();
+        let mut addresses = Vec::new();
+        for statement in p.0.iter().cloned() {
+            let segment = self.object.as_ref().map_or(0, |object| object.segment);
+            addresses.push((segment, self.pointer));
+            if let (
+                Some(layout),
+                Statement::Instruction(Instruction { position, .. })
+                | Statement::Data(Data { position, .. }),
+            ) = (&mut self.layout, &statement)
+            {
+                layout.statement(*position);
             }
-            Statement::Label(label) => {
-                self.labels.insert(label, self.pointer as u16);
+            if let Err(error) = self.preprocess_statement(statement, &mut deferred) {
+                self.report(error);
             }
         }
+        self.define_deferred_constants(deferred);
+        if let Some(layout) = &mut self.layout {
+            layout.end(self.pointer);
+        }
+
+        addresses
     }
 
-    fn preprocess_operand(&mut self, instruction: Instruction) {
-        let Instruction {
-            opcode,
-            operand: Operand { value, .. },
-            ..
-        } = instruction;
+    /// Whether an instruction can use its short form, `None` if it has none:
+    /// zero page addressing for an operand without a forced width that is in the zero page,
+    /// or a branch for a long branch to a target in range.
+    fn fits_short(
+        &mut self,
+        statement: &Statement,
+        (segment, address): (usize, usize),
+    ) -> Option<bool> {
+        let Statement::Instruction(instruction) = statement else {
+            return None;
+        };
+        let Some(OperandData::Expression(expression)) = &instruction.operand.value else {
+            return None;
+        };
 
-        if let Some(value) = value {
-            match value {
-                OperandData::Number(number_type) => match number_type {
-                    NumberType::Decimal8(_) | NumberType::Hexadecimal8(_) => self.pointer += 1,
-                    NumberType::Decimal16(_) | NumberType::Hexadecimal16(_) => self.pointer += 2,
-                },
-                OperandData::Label(_) => match opcode {
-                    Mnemonics::BCC
-                    | Mnemonics::BCS
-                    | Mnemonics::BEQ
-                    | Mnemonics::BMI
-                    | Mnemonics::BNE
-                    | Mnemonics::BPL
-                    | Mnemonics::BVC
-                    | Mnemonics::BVS => self.pointer += 1,
-                    _ => self.pointer += 2,
-                },
+        let zero_page = match instruction.operand.addressing_mode.zero_page() {
+            Some(mode) => {
+                instruction.operand.width == OperandWidth::Auto
+                    && !instruction.opcode.is_branch()
+                    && instruction.opcode.supports(mode, instruction.cpu)
             }
+            None => false,
+        };
+        if !instruction.long && !zero_page {
+            return None;
+        }
+
+        // `*` is the address of the instruction
+        self.pointer = address;
+        if let Some(object) = &mut self.object {
+            object.segment = segment;
         }
+        let position = instruction.position;
+        Some(match (instruction.long, &self.object) {
+            (true, _) => self
+                .branch_distance(expression, position, 2)
+                .is_ok_and(|distance| (-128..=127).contains(&distance)),
+            (false, None) => matches!(self.evaluate(expression, position), Ok(0..=0xFF)),
+            (false, Some(object)) => self
+                .relocatable(expression, position)
+                .is_ok_and(|value| object.is_zero_page(&value)),
+        })
     }
 
-    fn assemble_operand_data(
-        &mut self,
-        instruction: Instruction,
-    ) -> AssemblerResult<(Vec<u8>, AddressingMode)> {
-        let Instruction {
-            opcode,
-            operand: Operand {
-                value,
-                addressing_mode,
-            },
-            position,
-        } = instruction;
+    /// Records an error and continues assembling, so that every error of the source is reported.
+    fn report(&mut self, error: AssemblerError) {
+        if !self
+            .errors
+            .iter()
+            .any(|e| e.span == error.span && e.kind == error.kind)
+        {
+            self.errors.push(error);
+        }
+    }
 
-        let value = if let Some(value) = value {
-            value
-        } else {
-            return Ok((vec![], AddressingMode::IMPACC));
+    /// Assembles the statements, the errors are collected in [`Assembler::errors`].
+    ///
+    /// instructions use their short form if it fits (see [`Assembler::fits_short`]),
+    /// which moves the labels after them. the labels are computed again until no instruction changes
+    /// its size. an instruction that has to be widened again stays long, so this ends.
+    fn assemble_program(&mut self, mut p: Program) -> Vec<Chunk> {
+        if self.long_branches {
+            for statement in &mut p.0 {
+                // number literals are offsets, not targets. `BRA` has no inverted branch
+                if let Statement::Instruction(instruction) = statement {
+                    instruction.long |= instruction.opcode.inverted_branch().is_some()
+                        && instruction
+                            .operand
+                            .value
+                            .as_ref()
+                            .is_some_and(OperandData::is_expression);
+                }
+            }
+        }
+
+        let mut short = vec![false; p.0.len()];
+        let mut widened = vec![false; p.0.len()];
+        let p = loop {
+            let program = with_short_forms(&p, &short);
+            let addresses = self.preprocess(&program);
+
+            // the segments are placed again with their new sizes, which moves their labels
+            let mut changed = match self.layout.as_mut().map(Layout::place) {
+                Some(Ok(changed)) => changed,
+                Some(Err(error)) => {
+                    self.report(error);
+                    false
+                }
+                None => false,
+            };
+            for (i, statement) in p.0.iter().enumerate() {
+                let Some(fits) = self.fits_short(statement, addresses[i]) else {
+                    continue;
+                };
+                widened[i] |= short[i] && !fits;
+                let selected = fits && !widened[i];
+                changed |= selected != short[i];
+                short[i] = selected;
+            }
+
+            if !changed {
+                break program;
+            }
         };
 
-        let mut bytes = Vec::new();
+        self.reset_segments();
+        let mut chunks = Vec::new();
+        let mut chunk = Chunk::new(self.pointer as u16);
 
-        match value {
-            OperandData::Number(number_type) => match number_type {
-                NumberType::Decimal8(value) => bytes.extend(value.to_le_bytes()),
-                NumberType::Decimal16(value) => bytes.extend(value.to_le_bytes()),
-                NumberType::Hexadecimal8(value) => bytes.extend(value.to_le_bytes()),
-                NumberType::Hexadecimal16(value) => bytes.extend(value.to_le_bytes()),
-            },
-            OperandData::Label(label) => match self.labels.get(&label) {
-                Some(address) => match opcode {
-                    Mnemonics::BCC
-                    | Mnemonics::BCS
-                    | Mnemonics::BEQ
-                    | Mnemonics::BMI
-                    | Mnemonics::BNE
-                    | Mnemonics::BPL
-                    | Mnemonics::BVC
-                    | Mnemonics::BVS => {
-                        let relative_address = (*address as i16 - self.pointer as i16 - 2) as u8;
-                        bytes.extend(relative_address.to_le_bytes());
-                    }
-                    _ => {
-                        let absolute_address = *address + 0x8000;
-                        bytes.extend(absolute_address.to_le_bytes());
+        for statement in p.0 {
+            let address = self.pointer;
+            let result = match statement {
+                Statement::Instruction(instruction) => {
+                    let position = instruction.position;
+                    self.assemble_instruction(instruction)
+                        .and_then(|bytes| self.emit(&chunks, &mut chunk, address, bytes, position))
+                }
+                // reported by `preprocess`
+                Statement::Org(..) if self.object.is_some() || self.layout.is_some() => Ok(()),
+                Statement::Org(origin, _) => {
+                    self.pointer = origin as usize;
+                    next_chunk(&mut chunks, &mut chunk, origin);
+                    Ok(())
+                }
+                Statement::Segment(segment) => match (&mut self.object, &mut self.layout) {
+                    (Some(object), _) => {
+                        self.pointer =
+                            object.switch(&segment.name, segment.zero_page, self.pointer);
+                        Ok(())
                     }
+                    (None, Some(layout)) => layout
+                        .switch(&segment.name, segment.position, self.pointer)
+                        .map(|pointer| {
+                            self.pointer = pointer;
+                            next_chunk(&mut chunks, &mut chunk, pointer as u16);
+                        }),
+                    // reported by `preprocess`
+                    (None, None) => Ok(()),
                 },
-                None => {
-                    return Err(AssemblerError::new(
-                        AssemblerErrorKind::InvalidLabel(label),
+                Statement::Data(Data {
+                    kind: DataKind::Res(count),
+                    position,
+                }) => {
+                    self.listing.entries.push(ListingEntry {
                         position,
-                    ))
+                        address: self.pointer as u16,
+                        bytes: Vec::new(),
+                        cycles: None,
+                        cpu: CpuType::default(),
+                    });
+                    self.evaluate_count(&count, position).map(|count| {
+                        self.pointer += count;
+                        next_chunk(&mut chunks, &mut chunk, self.pointer as u16);
+                    })
                 }
-            },
-        }
+                Statement::Data(data) => {
+                    let position = data.position;
+                    self.assemble_data(data)
+                        .and_then(|bytes| self.emit(&chunks, &mut chunk, address, bytes, position))
+                }
+                Statement::Label(_)
+                | Statement::Constant(_)
+                | Statement::Import(..)
+                | Statement::Export(..) => Ok(()),
+            };
 
-        Ok((bytes, addressing_mode))
-    }
-}
+            if let Err(error) = result {
+                self.report(error);
+            }
+        }
 
-/// pointer, bytes, instruction
-pub fn disassemble(bytes: &[u8]) -> AssemblerResult<Vec<(usize, String, String)>> {
-    let mut result = Vec::new();
-    let mut pointer = 0;
+        if let Some(object) = &mut self.object {
+            object.current().size = self.pointer;
+            self.export_symbols();
+        }
 
-    while pointer < bytes.len() {
-        let mut line = String::new();
+        if !chunk.bytes.is_empty() {
+            chunks.push(chunk);
+        }
 
-        let (opcode, addressing_mode) = byte_to_opcode(bytes[pointer])?;
-        let result_pointer = pointer;
-        pointer += 1;
+        match &self.layout {
+            Some(layout) => layout.config.fill(chunks),
+            None => chunks,
+        }
+    }
 
-        match addressing_mode {
-            AddressingMode::IMPACC => {
-                line.push_str(&format!("{:?}", opcode));
-            }
-            AddressingMode::IMM => {
-                let operand = bytes[pointer];
-                pointer += 1;
-                line.push_str(&format!("{:?} #${:02X}", opcode, operand));
-            }
-            AddressingMode::RELZPG => {
-                let operand = bytes[pointer];
-                pointer += 1;
-                line.push_str(&format!("{:?} ${:02X}", opcode, operand));
-            }
-            AddressingMode::ZPX => {
-                let operand = bytes[pointer];
-                pointer += 1;
-                line.push_str(&format!("{:?} ${:02X},X", opcode, operand));
-            }
-            AddressingMode::ZPY => {
-                let operand = bytes[pointer];
-                pointer += 1;
-                line.push_str(&format!("{:?} ${:02X},Y", opcode, operand));
-            }
-            AddressingMode::ABS => {
-                let operand = u16::from_le_bytes([bytes[pointer], bytes[pointer + 1]]);
-                pointer += 2;
-                line.push_str(&format!("{:?} ${:04X}", opcode, operand));
-            }
-            AddressingMode::ABX => {
-                let operand = u16::from_le_bytes([bytes[pointer], bytes[pointer + 1]]);
-                pointer += 2;
-                line.push_str(&format!("{:?} ${:04X},X", opcode, operand));
-            }
-            AddressingMode::ABY => {
-                let operand = u16::from_le_bytes([bytes[pointer], bytes[pointer + 1]]);
-                pointer += 2;
-                line.push_str(&format!("{:?} ${:04X},Y", opcode, operand));
-            }
-            AddressingMode::IND => {
-                let operand = u16::from_le_bytes([bytes[pointer], bytes[pointer + 1]]);
-                pointer += 2;
-                line.push_str(&format!("{:?} (${:04X})", opcode, operand));
+    /// Adds the bytes of the statement at `address` to the chunk, or to the current segment of an object.
+    /// without a layout, the bytes can not overlap the previous chunks (`chunks`) or pass `$FFFF`.
+    fn emit(
+        &mut self,
+        chunks: &[Chunk],
+        chunk: &mut Chunk,
+        address: usize,
+        bytes: Vec<u8>,
+        position: Position,
+    ) -> AssemblerResult<()> {
+        let end = address + bytes.len();
+        let error = match &mut self.object {
+            Some(object) if !bytes.is_empty() => {
+                // after the bytes reserved with `.res`
+                let segment = object.current();
+                segment.bytes.resize(address, 0);
+                segment.bytes.extend(bytes);
+                None
             }
-            AddressingMode::IDX => {
-                let operand = bytes[pointer];
-                pointer += 1;
-                line.push_str(&format!("{:?} (${:02X},X)", opcode, operand));
+            Some(_) => None,
+            // the layout reports the segments that overlap or overflow their memory areas
+            None if self.layout.is_some() || bytes.is_empty() => {
+                chunk.bytes.extend(bytes);
+                None
             }
-            AddressingMode::IDY => {
-                let operand = bytes[pointer];
-                pointer += 1;
-                line.push_str(&format!("{:?} (${:02X}),Y", opcode, operand));
+            None => {
+                // the bytes are kept, the addresses of the following statements do not change
+                chunk.bytes.extend(bytes);
+                let overlap = chunks.iter().find_map(|previous| {
+                    let start = previous.origin as usize;
+                    let overlap = address.max(start);
+                    (overlap < end.min(start + previous.bytes.len())).then_some(overlap)
+                });
+                match (end > 0x10000, overlap) {
+                    (true, _) => Some(AssemblerErrorKind::AddressOverflow),
+                    (false, Some(overlap)) => {
+                        Some(AssemblerErrorKind::OverlappingOrigin(overlap as u16))
+                    }
+                    (false, None) => None,
+                }
             }
-        }
-
-        let bytes = &bytes[result_pointer..pointer]
-            .iter()
-            .map(|b| format!("{b:02X}"))
-            .collect::<Vec<_>>()
-            .join(" ");
-        result.push((result_pointer, format!("{bytes:<8}"), line));
+        };
 
-        if opcode == Mnemonics::BRK {
-            break;
+        match error {
+            Some(kind) => Err(AssemblerError::new(kind, position)),
+            None => Ok(()),
         }
     }
 
-    Ok(result)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Values of the `.export` names of an object, see [`ObjectState::symbols`]
+    fn export_symbols(&mut self) {
+        let exports = self
+            .object
+            .as_ref()
+            .map(|object| object.exports.clone())
+            .unwrap_or_default();
 
-    #[test]
-    fn test_assemble_instruction() {
-        let s = r#"
-LDX #$01
-STX $0000
-"#;
+        for (name, position) in exports {
+            let (value, segment) =
+                match self.relocatable(&Expression::Identifier(name.clone()), position) {
+                    Ok(Relocatable::Absolute(value)) => (value, None),
+                    Ok(Relocatable::Relative(RelocationTarget::Segment(segment), value)) => {
+                        (value, Some(segment))
+                    }
+                    _ => {
+                        self.report(AssemblerError::new(
+                            AssemblerErrorKind::InvalidExport(name),
+                            position,
+                        ));
+                        continue;
+                    }
+                };
 
-        let src = Assembler::new(s).assemble().unwrap();
-        assert_eq!(src, vec![0xA2, 0x01, 0x8E, 0x00, 0x00]);
+            if let Some(object) = &mut self.object {
+                object.symbols.push(ObjectSymbol {
+                    name,
+                    value,
+                    segment,
+                });
+            }
+        }
     }
 
-    #[test]
-    fn test_assemble_label() {
-        let s = r#"
-LDA #$02
-CMP #$01
-BNE FOO
+    fn assemble_instruction(&mut self, instruction: Instruction) -> AssemblerResult<Vec<u8>> {
+        if instruction.long {
+            return self.assemble_long_branch(instruction);
+        }
+
+        let operand = self.assemble_operand_data(instruction.clone())?;
+        let instruction = Instruction {
+            operand: Operand {
+                addressing_mode: operand.1,
+                ..instruction.operand
+            },
+            ..instruction
+        };
+        let opcode = instruction_opcode(&instruction)?;
+        let bytes = [opcode.byte]
+            .iter()
+            .chain(&operand.0)
+            .copied()
+            .collect::<Vec<_>>();
+
+        let zero_page = operand.1.zero_page();
+        // the operands of objects are relocated by the linker
+        if self.object.is_none()
+            && instruction.operand.width == OperandWidth::Auto
+            && zero_page.is_some_and(|mode| instruction.opcode.supports(mode, instruction.cpu))
+            && operand.0[1] == 0
+        {
+            self.warnings.push(AssemblerWarning::new(
+                AssemblerWarningKind::ZeroPage(operand.0[0] as u16),
+                instruction.position,
+            ));
+        }
+
+        self.listing.entries.push(ListingEntry {
+            position: instruction.position,
+            address: self.pointer as u16,
+            bytes: bytes.clone(),
+            cycles: Some((opcode.cycles, opcode.page_cross)),
+            cpu: instruction.cpu,
+        });
+        self.pointer += bytes.len();
+
+        Ok(bytes)
+    }
+
+    /// `JEQ target` is `BNE *+5`, `JMP target`
+    fn assemble_long_branch(&mut self, instruction: Instruction) -> AssemblerResult<Vec<u8>> {
+        let Some(OperandData::Expression(target)) = &instruction.operand.value else {
+            unreachable!("the target of a long branch is an expression");
+        };
+        let target = self.evaluate_word(target, instruction.position, 3)?;
+        let branch = Instruction::new(
+            instruction.opcode.inverted_branch().unwrap(),
+            Operand::new(AddressingMode::REL, None),
+            instruction.position,
+        );
+        let jump = Instruction::new(
+            Mnemonics::JMP,
+            Operand::new(AddressingMode::ABS, None),
+            instruction.position,
+        );
+        let (branch, jump) = (instruction_opcode(&branch)?, instruction_opcode(&jump)?);
+        let mut bytes = vec![branch.byte, 3, jump.byte];
+        bytes.extend(target.to_le_bytes());
+
+        self.listing.entries.push(ListingEntry {
+            position: instruction.position,
+            address: self.pointer as u16,
+            bytes: bytes.clone(),
+            // the inverted branch is not taken, then `JMP`
+            cycles: Some((5, false)),
+            cpu: instruction.cpu,
+        });
+        self.pointer += bytes.len();
+
+        Ok(bytes)
+    }
+
+    fn assemble_data(&mut self, data: Data) -> AssemblerResult<Vec<u8>> {
+        let Data { kind, position } = data;
+        let mut bytes = Vec::new();
+
+        match kind {
+            DataKind::Byte(expressions) => {
+                for expression in expressions {
+                    bytes.push(self.evaluate_byte(&expression, position, bytes.len())?);
+                }
+            }
+            DataKind::Word(expressions) => {
+                for expression in expressions {
+                    let word = self.evaluate_word(&expression, position, bytes.len())?;
+                    bytes.extend(word.to_le_bytes());
+                }
+            }
+            DataKind::Text(text) => bytes.extend(text),
+            DataKind::Fill(count, value) => {
+                let count = self.evaluate_count(&count, position)?;
+                let value = self.evaluate_absolute(&value, position)?;
+                if !(-0x80..=0xFF).contains(&value) {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::OutOfRange(value),
+                        position,
+                    ));
+                }
+                let value = value as u8;
+                bytes.extend(vec![value; count]);
+            }
+            DataKind::Res(_) => unreachable!("`.res` does not emit bytes"),
+        }
+
+        self.listing.entries.push(ListingEntry {
+            position,
+            address: self.pointer as u16,
+            bytes: bytes.clone(),
+            cycles: None,
+            cpu: CpuType::default(),
+        });
+        self.pointer += bytes.len();
+
+        Ok(bytes)
+    }
+
+    /// Value of the label or constant `name` referenced from the scope `scope`, the innermost definition wins.
+    /// symbols are used if there is no such label or constant.
+    fn symbol(&self, name: &str, scope: usize) -> Option<i32> {
+        let scope = self.scopes.get(scope).map_or("", String::as_str);
+
+        qualified_names(scope, name)
+            .into_iter()
+            .find_map(|name| {
+                let value = match self.labels.get(&name) {
+                    Some(address) => *address as i32,
+                    None => *self.constants.get(&name)?,
+                };
+                self.used.borrow_mut().insert(name);
+                Some(value)
+            })
+            .or_else(|| self.symbols.get(name).copied())
+    }
+
+    /// Evaluates the expression with the labels and constants resolved so far.
+    /// `*` is the address of the current statement.
+    pub fn evaluate(&self, expression: &Expression, position: Position) -> AssemblerResult<i32> {
+        expression
+            .evaluate(Some(self.pointer as i32), &|identifier| {
+                self.symbol(identifier, position.scope)
+            })
+            .map_err(|kind| AssemblerError::new(kind, position))
+    }
+
+    /// Value of the label or constant `name` in an object, like [`Assembler::symbol`].
+    /// imports are used if there is no such label or constant, then the symbols.
+    fn relocatable_symbol(&self, name: &str, scope: usize) -> Option<Relocatable> {
+        let object = self.object.as_ref()?;
+        let scope = self.scopes.get(scope).map_or("", String::as_str);
+
+        qualified_names(scope, name)
+            .into_iter()
+            .find_map(|name| {
+                let value = match self.labels.get(&name) {
+                    Some(address) => Relocatable::Relative(
+                        RelocationTarget::Segment(object.labels[&name].clone()),
+                        *address as i32,
+                    ),
+                    None => object.constants.get(&name)?.clone(),
+                };
+                self.used.borrow_mut().insert(name);
+                Some(value)
+            })
+            .or_else(|| {
+                object
+                    .imports
+                    .iter()
+                    .any(|import| import == name)
+                    .then(|| Relocatable::Relative(RelocationTarget::Import(name.to_string()), 0))
+            })
+            .or_else(|| {
+                self.symbols
+                    .get(name)
+                    .map(|value| Relocatable::Absolute(*value))
+            })
+    }
+
+    /// Evaluates the expression in an object, `*` is relative to the current segment.
+    fn relocatable(
+        &self,
+        expression: &Expression,
+        position: Position,
+    ) -> AssemblerResult<Relocatable> {
+        let segment = match &self.object {
+            Some(object) => object.segments[object.segment].name.clone(),
+            None => unreachable!("only objects are relocatable"),
+        };
+        let current_address =
+            Relocatable::Relative(RelocationTarget::Segment(segment), self.pointer as i32);
+
+        expression
+            .evaluate_relocatable(&current_address, &|identifier| {
+                self.relocatable_symbol(identifier, position.scope)
+            })
+            .map_err(|kind| AssemblerError::new(kind, position))
+    }
+
+    /// Evaluates an expression whose value has to be known before linking, like the count of `.res`.
+    fn evaluate_absolute(
+        &self,
+        expression: &Expression,
+        position: Position,
+    ) -> AssemblerResult<i32> {
+        if self.object.is_none() {
+            return self.evaluate(expression, position);
+        }
+
+        match self.relocatable(expression, position)? {
+            Relocatable::Absolute(value) => Ok(value),
+            _ => Err(AssemblerError::new(
+                AssemblerErrorKind::NotRelocatable(expression.to_string()),
+                position,
+            )),
+        }
+    }
+
+    /// Evaluates an operand or data field of `kind` at `offset` from the current statement.
+    /// in an object, a value relative to a segment or an import is `0` and adds a relocation
+    /// of the field (see [`Relocation`]).
+    fn evaluate_field(
+        &mut self,
+        expression: &Expression,
+        position: Position,
+        kind: RelocationKind,
+        offset: usize,
+    ) -> AssemblerResult<i32> {
+        if self.object.is_none() {
+            return self.evaluate(expression, position);
+        }
+
+        let (kind, target, addend) = match self.relocatable(expression, position)? {
+            Relocatable::Absolute(value) => return Ok(value),
+            Relocatable::Relative(target, addend) => (kind, target, addend),
+            Relocatable::Part(part, target, addend) if kind == RelocationKind::Byte => {
+                (part, target, addend)
+            }
+            Relocatable::Part(..) => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::NotRelocatable(expression.to_string()),
+                    position,
+                ))
+            }
+        };
+
+        let offset = self.pointer + offset;
+        if let Some(object) = &mut self.object {
+            object.current().relocations.push(Relocation {
+                offset,
+                kind,
+                target,
+                addend,
+            });
+        }
+
+        Ok(0)
+    }
+
+    /// Distance of the target of the branch at the current statement from the end of the branch
+    /// (`size` bytes), in an object the target has to be in the current segment.
+    fn branch_distance(
+        &self,
+        expression: &Expression,
+        position: Position,
+        size: i32,
+    ) -> AssemblerResult<i32> {
+        let target = match &self.object {
+            None => self.evaluate(expression, position)?,
+            Some(object) => match self.relocatable(expression, position)? {
+                Relocatable::Relative(RelocationTarget::Segment(segment), address)
+                    if segment == object.segments[object.segment].name =>
+                {
+                    address
+                }
+                _ => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::SegmentBranch(expression.to_string()),
+                        position,
+                    ))
+                }
+            },
+        };
+
+        Ok(target - self.pointer as i32 - size)
+    }
+
+    fn define_constant(&mut self, constant: &Constant) -> AssemblerResult<()> {
+        let value = match self.object {
+            Some(_) => {
+                let value = self.relocatable(&constant.value, constant.position)?;
+                let addend = value.addend();
+                if let Some(object) = &mut self.object {
+                    object.constants.insert(constant.name.clone(), value);
+                }
+                addend
+            }
+            None => self.evaluate(&constant.value, constant.position)?,
+        };
+        self.constants.insert(constant.name.clone(), value);
+
+        Ok(())
+    }
+
+    /// Evaluates the constants that could not be evaluated in order (`pointer` is the value of `*`),
+    /// until every constant is defined or none of the rest can be, their errors are reported.
+    fn define_deferred_constants(&mut self, mut deferred: Vec<(Constant, usize)>) {
+        let pointer = self.pointer;
+
+        while !deferred.is_empty() {
+            let count = deferred.len();
+            let mut errors = Vec::new();
+
+            for (constant, pointer) in std::mem::take(&mut deferred) {
+                self.pointer = pointer;
+                if let Err(error) = self.define_constant(&constant) {
+                    errors.push(error);
+                    deferred.push((constant, pointer));
+                }
+            }
+
+            if deferred.len() == count {
+                for error in errors {
+                    self.report(error);
+                }
+                break;
+            }
+        }
+
+        self.pointer = pointer;
+    }
+
+    /// `offset` is the offset of the byte from the current statement, see [`Assembler::evaluate_field`]
+    fn evaluate_byte(
+        &mut self,
+        expression: &Expression,
+        position: Position,
+        offset: usize,
+    ) -> AssemblerResult<u8> {
+        let value = self.evaluate_field(expression, position, RelocationKind::Byte, offset)?;
+        if !(-0x80..=0xFF).contains(&value) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange(value),
+                position,
+            ));
+        }
+
+        Ok(value as u8)
+    }
+
+    fn evaluate_word(
+        &mut self,
+        expression: &Expression,
+        position: Position,
+        offset: usize,
+    ) -> AssemblerResult<u16> {
+        let value = self.evaluate_field(expression, position, RelocationKind::Word, offset)?;
+        if !(-0x8000..=0xFFFF).contains(&value) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange(value),
+                position,
+            ));
+        }
+
+        Ok(value as u16)
+    }
+
+    /// Address of an operand up to `max`, unlike data an address is not negative (`LDA -1` is an error).
+    fn evaluate_address(
+        &mut self,
+        expression: &Expression,
+        position: Position,
+        max: i32,
+    ) -> AssemblerResult<u16> {
+        let kind = match max {
+            0..=0xFF => RelocationKind::Byte,
+            _ => RelocationKind::Word,
+        };
+        let value = self.evaluate_field(expression, position, kind, 1)?;
+        if !(0..=max).contains(&value) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange(value),
+                position,
+            ));
+        }
+
+        Ok(value as u16)
+    }
+
+    fn evaluate_count(
+        &self,
+        expression: &Expression,
+        position: Position,
+    ) -> AssemblerResult<usize> {
+        let value = self.evaluate_absolute(expression, position)?;
+        if !(0..=0x10000).contains(&value) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange(value),
+                position,
+            ));
+        }
+
+        Ok(value as usize)
+    }
+
+    fn preprocess_statement(
+        &mut self,
+        statement: Statement,
+        deferred: &mut Vec<(Constant, usize)>,
+    ) -> AssemblerResult<()> {
+        let emits = match &statement {
+            Statement::Instruction(instruction) => Some(instruction.position),
+            Statement::Data(Data { kind, position }) if !matches!(kind, DataKind::Res(_)) => {
+                Some(*position)
+            }
+            _ => None,
+        };
+        if let (Some(position), Some(segment)) =
+            (emits, self.layout.as_ref().and_then(Layout::is_reserved))
+        {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::BssData(segment.to_string()),
+                position,
+            ));
+        }
+
+        match statement {
+            // the inverted branch and `JMP`
+            Statement::Instruction(instruction) if instruction.long => self.pointer += 5,
+            Statement::Instruction(instruction) => {
+                self.pointer += 1;
+
+                self.preprocess_operand(instruction);
+            }
+            Statement::Label(label) => {
+                if let Some(object) = &mut self.object {
+                    let segment = object.current().name.clone();
+                    object.labels.insert(label.clone(), segment);
+                }
+                self.labels.insert(label, self.pointer as u16);
+            }
+            Statement::Org(_, position) if self.object.is_some() || self.layout.is_some() => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::AbsoluteOnly("org".to_string()),
+                    position,
+                ))
+            }
+            Statement::Org(origin, _) => self.pointer = origin as usize,
+            Statement::Segment(segment) => match (&mut self.object, &mut self.layout) {
+                (Some(object), _) => {
+                    self.pointer = object.switch(&segment.name, segment.zero_page, self.pointer)
+                }
+                (None, Some(layout)) => {
+                    self.pointer = layout.switch(&segment.name, segment.position, self.pointer)?
+                }
+                (None, None) => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::ObjectOnly("segment".to_string()),
+                        segment.position,
+                    ))
+                }
+            },
+            Statement::Import(names, zero_page, position) => match &mut self.object {
+                Some(object) => {
+                    for name in names {
+                        if zero_page {
+                            object.zero_page_imports.insert(name.clone());
+                        }
+                        if !object.imports.contains(&name) {
+                            object.imports.push(name);
+                        }
+                    }
+                }
+                None => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::ObjectOnly("import".to_string()),
+                        position,
+                    ))
+                }
+            },
+            // exports are ignored outside of objects, a source can be assembled both ways
+            Statement::Export(names, position) => {
+                if let Some(object) = &mut self.object {
+                    object
+                        .exports
+                        .extend(names.into_iter().map(|name| (name, position)));
+                }
+            }
+            Statement::Constant(constant) => {
+                if self.define_constant(&constant).is_err() {
+                    deferred.push((constant, self.pointer));
+                }
+            }
+            Statement::Data(Data { kind, position }) => {
+                self.pointer += match kind {
+                    DataKind::Byte(expressions) => expressions.len(),
+                    DataKind::Word(expressions) => expressions.len() * 2,
+                    DataKind::Text(text) => text.len(),
+                    DataKind::Fill(count, _) | DataKind::Res(count) => {
+                        self.evaluate_count(&count, position)?
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn preprocess_operand(&mut self, instruction: Instruction) {
+        let Instruction {
+            operand:
+                Operand {
+                    value,
+                    addressing_mode,
+                    target,
+                    ..
+                },
+            ..
+        } = instruction;
+
+        // the offset of `BBR0 zp,target`
+        if target.is_some() {
+            self.pointer += 1;
+        }
+        if let Some(value) = value {
+            match value {
+                OperandData::Number(number_type) => match number_type {
+                    NumberType::Decimal8(_) | NumberType::Hexadecimal8(_) => self.pointer += 1,
+                    NumberType::Decimal16(_) | NumberType::Hexadecimal16(_) => self.pointer += 2,
+                },
+                OperandData::Expression(_) => match addressing_mode {
+                    AddressingMode::IMM
+                    | AddressingMode::ZPG
+                    | AddressingMode::REL
+                    | AddressingMode::ZPX
+                    | AddressingMode::ZPY
+                    | AddressingMode::IDX
+                    | AddressingMode::IDY
+                    | AddressingMode::IZP
+                    | AddressingMode::ZPR => self.pointer += 1,
+                    _ => self.pointer += 2,
+                },
+            }
+        }
+    }
+
+    fn assemble_operand_data(
+        &mut self,
+        instruction: Instruction,
+    ) -> AssemblerResult<(Vec<u8>, AddressingMode)> {
+        let Instruction {
+            operand:
+                Operand {
+                    value,
+                    addressing_mode,
+                    target,
+                    ..
+                },
+            position,
+            ..
+        } = instruction;
+
+        let value = if let Some(value) = value {
+            value
+        } else {
+            return Ok((vec![], addressing_mode));
+        };
+
+        let mut bytes = Vec::new();
+
+        match value {
+            OperandData::Number(number_type) => match number_type {
+                NumberType::Decimal8(value) => bytes.extend(value.to_le_bytes()),
+                NumberType::Decimal16(value) => bytes.extend(value.to_le_bytes()),
+                NumberType::Hexadecimal8(value) => bytes.extend(value.to_le_bytes()),
+                NumberType::Hexadecimal16(value) => bytes.extend(value.to_le_bytes()),
+            },
+            OperandData::Expression(expression) => match addressing_mode {
+                AddressingMode::REL => {
+                    let distance = self.branch_distance(&expression, position, 2)?;
+                    if !(-128..=127).contains(&distance) {
+                        return Err(AssemblerError::new(
+                            AssemblerErrorKind::BranchOutOfRange(distance),
+                            position,
+                        ));
+                    }
+                    bytes.push(distance as u8);
+                }
+                AddressingMode::IMM => bytes.push(self.evaluate_byte(&expression, position, 1)?),
+                AddressingMode::ZPG
+                | AddressingMode::ZPX
+                | AddressingMode::ZPY
+                | AddressingMode::IDX
+                | AddressingMode::IDY
+                | AddressingMode::IZP
+                | AddressingMode::ZPR => {
+                    let address = self.evaluate_address(&expression, position, 0xFF)?;
+                    bytes.push(address as u8)
+                }
+                _ => {
+                    let address = self.evaluate_address(&expression, position, 0xFFFF)?;
+                    bytes.extend(address.to_le_bytes())
+                }
+            },
+        }
+
+        if let Some(target) = target {
+            let distance = self.branch_distance(&target, position, 3)?;
+            if !(-128..=127).contains(&distance) {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::BranchOutOfRange(distance),
+                    position,
+                ));
+            }
+            bytes.push(distance as u8);
+        }
+
+        Ok((bytes, addressing_mode))
+    }
+}
+
+/// The program with the short form of the instructions selected by `short` (by index),
+/// see [`Assembler::fits_short`]
+fn with_short_forms(p: &Program, short: &[bool]) -> Program {
+    let statements =
+        p.0.iter()
+            .zip(short)
+            .map(|(statement, &short)| match statement {
+                Statement::Instruction(instruction) if short && instruction.long => {
+                    Statement::Instruction(Instruction {
+                        long: false,
+                        ..instruction.clone()
+                    })
+                }
+                Statement::Instruction(instruction) if short => {
+                    let mut instruction = instruction.clone();
+                    let mode = instruction.operand.addressing_mode;
+                    instruction.operand.addressing_mode = mode.zero_page().unwrap_or(mode);
+                    Statement::Instruction(instruction)
+                }
+                statement => statement.clone(),
+            });
+
+    Program(statements.collect())
+}
+
+/// pointer, bytes, instruction. the opcodes are decoded for `cpu`,
+/// the accumulator mode is written in the `accumulator` syntax
+pub fn disassemble(
+    bytes: &[u8],
+    cpu: CpuType,
+    accumulator: AccumulatorSyntax,
+) -> AssemblerResult<Vec<(usize, String, String)>> {
+    let mut result = Vec::new();
+    let mut pointer = 0;
+
+    while pointer < bytes.len() {
+        let opcode = byte_to_opcode(bytes[pointer], cpu)?;
+        let result_pointer = pointer;
+        pointer += 1;
+
+        let operand = &bytes[pointer..pointer + opcode.mode.operand_size()];
+        pointer += operand.len();
+        let mnemonic = opcode.mnemonic;
+        let line = instruction_text(opcode, operand, accumulator, None);
+
+        let bytes = &bytes[result_pointer..pointer]
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        result.push((result_pointer, format!("{bytes:<8}"), line));
+
+        if mnemonic == Mnemonics::BRK {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_instruction() {
+        let s = r#"
+LDX #$01
+STX $0000
+STX a:$00
+"#;
+
+        let src = Assembler::new(s).assemble().unwrap();
+        assert_eq!(src, vec![0xA2, 0x01, 0x86, 0x00, 0x8E, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_assemble_label() {
+        let s = r#"
+LDA #$02
+CMP #$01
+BNE FOO
 LDA #$01
 STA $00
 BRK
 
-FOO:
-    LDA #$01
-    STA $01
-    BRK
-        "#;
+FOO:
+    LDA #$01
+    STA $01
+    BRK
+        "#;
+
+        let src = Assembler::new(s).assemble().unwrap();
+        assert_eq!(
+            src,
+            vec![
+                0xA9, 0x02, // LDA #$02
+                0xC9, 0x01, // CMP #$01
+                0xD0, 0x05, // BNE FOO
+                0xA9, 0x01, // LDA #$01
+                0x85, 0x00, // STA $00
+                0x00, // BRK
+                0xA9, 0x01, // LDA #$01
+                0x85, 0x01, // STA $01
+                0x00, // BRK
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_org() {
+        let s = r#"
+*= $0000
+    .org $E000
+RESET:
+    LDA #$01
+    JMP RESET
+
+.org $FFFC
+    JMP RESET
+"#;
+
+        let chunks = Assembler::new(s).assemble_chunks().unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk {
+                    origin: 0xE000,
+                    bytes: vec![0xA9, 0x01, 0x4C, 0x00, 0xE0],
+                },
+                Chunk {
+                    origin: 0xFFFC,
+                    bytes: vec![0x4C, 0x00, 0xE0],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_org_image() {
+        let s = r#"
+.org $0200
+    BRK
+.org $0203
+    NOP
+"#;
+
+        let src = Assembler::new(s).assemble().unwrap();
+        assert_eq!(src, vec![0x00, 0x00, 0x00, 0xEA]);
+    }
+
+    #[test]
+    fn test_entry_point() {
+        let chunks =
+            Assembler::new(".org $0200\nNOP\n.org $E000\nstart: NOP\n.org $FFFC\n.word start")
+                .assemble_chunks()
+                .unwrap();
+        assert_eq!(entry_point(&chunks), Some(0xE000));
+
+        // the vector split between two chunks
+        let chunks = [
+            Chunk {
+                origin: 0xFFFD,
+                bytes: vec![0x12],
+            },
+            Chunk {
+                origin: 0xFFFC,
+                bytes: vec![0x34],
+            },
+        ];
+        assert_eq!(entry_point(&chunks), Some(0x1234));
+
+        let chunks = Assembler::new(".org $0300\nNOP\n.org $0200\nNOP")
+            .assemble_chunks()
+            .unwrap();
+        assert_eq!(entry_point(&chunks), Some(0x0300));
+        assert_eq!(entry_point(&[]), None);
+    }
+
+    #[test]
+    fn test_assemble_org_errors() {
+        let s = ".org $0200\n    LDA #$01\n    NOP\n.org $0202\n    BRK\n    NOP\n";
+        let mut assembler = Assembler::new(s);
+        let error = assembler.assemble().unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::OverlappingOrigin(0x0202));
+        assert_eq!(error.span.start, Position::new(5, 5));
+        assert_eq!(assembler.errors.len(), 1);
+
+        // an earlier block inside a later one
+        let error = Assembler::new(".org $0300\nNOP\n.org $02FF\n.word 0")
+            .assemble()
+            .unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::OverlappingOrigin(0x0300));
+
+        let error = Assembler::new(".org $FFFE\nJMP $1234")
+            .assemble()
+            .unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::AddressOverflow);
+        let error = Assembler::new(".org $FFFF\n.res 1\nNOP")
+            .assemble()
+            .unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::AddressOverflow);
+        assert_eq!(
+            Assembler::new(".org $FFFE\n.word 0").assemble().unwrap(),
+            vec![0, 0]
+        );
+    }
+
+    #[test]
+    fn test_assemble_data() {
+        let s = r#"
+.org $0200
+TABLE:
+    .byte 1, $02, TABLE >> 8, <VECTORS, -1
+    dcb 3 * 2
+    .word TABLE, $1234
+    .text "A\n"
+    .asciiz "B"
+    .fill 2, $EA
+    .res 2
+VECTORS:
+    .word TABLE + 1
+"#;
+
+        let chunks = Assembler::new(s).assemble_chunks().unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk {
+                    origin: 0x0200,
+                    bytes: vec![
+                        0x01, 0x02, 0x02, 0x12, 0xFF, // .byte
+                        0x06, // dcb
+                        0x00, 0x02, 0x34, 0x12, // .word
+                        0x41, 0x0A, // .text
+                        0x42, 0x00, // .asciiz
+                        0xEA, 0xEA, // .fill
+                    ],
+                },
+                Chunk {
+                    origin: 0x0212,
+                    bytes: vec![0x01, 0x02],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_data_label_size() {
+        let s = r#"
+    JMP START
+MESSAGE:
+    .text "HI"
+START:
+    LDA MESSAGE
+"#;
+
+        let src = Assembler::new(s).assemble().unwrap();
+        assert_eq!(src, vec![0x4C, 0x05, 0x80, 0x48, 0x49, 0xAD, 0x03, 0x80]);
+    }
+
+    #[test]
+    fn test_assemble_data_out_of_range() {
+        assert!(Assembler::new(".byte 256").assemble().is_err());
+        assert!(Assembler::new(".byte UNKNOWN").assemble().is_err());
+    }
+
+    #[test]
+    fn test_assemble_macro() {
+        let s = r#"
+.macro MOVE src, dst
+    LDA src
+    STA dst
+.endm
+
+.macro WAIT count
+    LDX #count
+loop:
+    DEX
+    BNE loop
+.endm
+
+.macro WAIT2
+    WAIT 1
+    WAIT 2
+.endm
+
+.org $0200
+    MOVE $10, $20
+    MOVE #1, ($20,X)
+    WAIT2
+"#;
+
+        let src = Assembler::new(s).assemble().unwrap();
+        assert_eq!(
+            src,
+            vec![
+                0xA5, 0x10, 0x85, 0x20, // MOVE $10, $20
+                0xA9, 0x01, 0x81, 0x20, // MOVE #1, ($20,X)
+                0xA2, 0x01, 0xCA, 0xD0, 0xFD, // WAIT 1
+                0xA2, 0x02, 0xCA, 0xD0, 0xFD, // WAIT 2
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_macro_errors() {
+        let s = ".macro LOOP\n    LOOP\n.endm\nLOOP";
+        let mut assembler = Assembler::new(s);
+        let error = assembler.assemble().unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::MacroRecursion(_)));
+        // the invocations of the recursion are collapsed
+        let frames = error.backtrace.len() - 1;
+        assert!(error.to_string().ends_with(&format!(
+            "in macro \"LOOP\" at line 2, column 5 (×{frames}), in macro \"LOOP\" at line 4, column 1"
+        )));
+        let diagnostics = assembler.diagnostics();
+        assert_eq!(
+            diagnostics.matches("= in macro").count(),
+            2,
+            "{diagnostics}"
+        );
+        assert!(diagnostics.contains(&format!("= in macro \"LOOP\" at <source>:2:5 (×{frames})")));
+
+        let s = ".macro M a\n    NOP\n.endm\nM";
+        let error = Assembler::new(s).assemble().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::MacroArguments {
+                expected: 1,
+                found: 0,
+                ..
+            }
+        ));
+
+        let s = ".macro M\n    NOP\n    FOO\n.endm\n\n    M";
+        let error = Assembler::new(s).assemble().unwrap_err();
+        assert_eq!((error.span.start.line, error.span.start.column), (3, 5));
+        assert_eq!(error.backtrace.len(), 1);
+        assert_eq!(error.backtrace[0].0, "M");
+        assert_eq!(
+            (error.backtrace[0].1.line, error.backtrace[0].1.column),
+            (6, 5)
+        );
+    }
+
+    #[test]
+    fn test_assemble_conditional() {
+        let s = r#"
+.if TARGET == 1
+    LDA #$01
+.elif TARGET == 2
+    LDA #$02
+    .ifdef DEBUG
+        BRK
+    .else
+        NOP
+    .endif
+.else
+    UNKNOWN $1234
+.endif
+.ifndef TARGET
+    .byte 0
+.endif
+"#;
+
+        let src = Assembler::new(s)
+            .define_symbol("TARGET", 2)
+            .assemble()
+            .unwrap();
+        assert_eq!(src, vec![0xA9, 0x02, 0xEA]);
+
+        let src = Assembler::new(s)
+            .define_symbol("TARGET", 2)
+            .define_symbol("DEBUG", 1)
+            .assemble()
+            .unwrap();
+        assert_eq!(src, vec![0xA9, 0x02, 0x00]);
+
+        let src = Assembler::new(s).define_symbol("TARGET", 1).assemble();
+        assert_eq!(src.unwrap(), vec![0xA9, 0x01]);
+
+        assert!(Assembler::new(s)
+            .define_symbol("TARGET", 3)
+            .assemble()
+            .is_err());
+    }
+
+    #[test]
+    fn test_assemble_conditional_errors() {
+        let error = Assembler::new(".if 1\nNOP").assemble().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::UnterminatedConditional
+        ));
+
+        let error = Assembler::new("NOP\n.endif").assemble().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::UnmatchedConditional(_)
+        ));
+    }
+
+    fn files(files: &[(&str, &[u8])]) -> HashMap<String, Vec<u8>> {
+        files
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_assemble_include() {
+        let s = r#"
+.include "vectors.asm"
+    JMP RESET
+.incbin "data.bin", 1, 2
+.incbin "data.bin"
+"#;
+        let resolver = files(&[
+            (
+                "vectors.asm",
+                b".macro INIT\n    LDX #$FF\n.endm\nRESET:\n    INIT",
+            ),
+            ("data.bin", &[0x01, 0x02, 0x03, 0x04]),
+        ]);
+
+        let binary = Assembler::new(s).set_resolver(resolver).assemble().unwrap();
+        assert_eq!(
+            binary,
+            vec![0xA2, 0xFF, 0x4C, 0x00, 0x80, 0x02, 0x03, 0x01, 0x02, 0x03, 0x04]
+        );
+    }
+
+    #[test]
+    fn test_assemble_ca65() {
+        let s = r#"
+SCREEN = $0400
+.define COLOR 5
+.proc main
+    ldx #.lobyte(text)
+@loop:
+    lda text,x
+    beq :+
+    sta SCREEN,x
+    inx
+    bne @loop
+:   lda #COLOR .mod 3
+    .repeat 3, i
+    .byte i * 2
+    .endrep
+    rts
+.endproc
+text: .byte "Hi\", 0
+.addr main
+.dbyt $1234
+.hibytes $1234, $5678
+.res 2, $EA
+.if 1 <> 2 .and .defined(main)
+    .byte 1 + 2 & 3
+.elseif 1
+    .byte $FF
+.endif
+"#;
+        let binary = Assembler::new(s)
+            .set_dialect(Dialect::Ca65)
+            .assemble()
+            .unwrap();
+        assert_eq!(
+            binary,
+            vec![
+                0xA2, 0x13, 0xBD, 0x13, 0x80, 0xF0, 0x06, 0x9D, 0x00, 0x04, 0xE8, 0xD0, 0xF5, 0xA9,
+                0x02, 0x00, 0x02, 0x04, 0x60, b'H', b'i', b'\\', 0x00, 0x00, 0x80, 0x12, 0x34,
+                0x12, 0x56, 0xEA, 0xEA, 0x03
+            ]
+        );
+
+        // the dialect of an included file ends with it
+        let resolver = files(&[("lib.s", b".dialect ca65\nlib: .byte 1 <> 2")]);
+        let binary = Assembler::new(".include \"lib.s\"\n.byte 1 == 2")
+            .set_resolver(resolver)
+            .assemble()
+            .unwrap();
+        assert_eq!(binary, vec![0x01, 0x00]);
+
+        let error = |s: &str| {
+            Assembler::new(s)
+                .set_dialect(Dialect::Ca65)
+                .assemble()
+                .unwrap_err()
+                .kind
+        };
+        let unsupported = |feature: &str| AssemblerErrorKind::Unsupported(feature.to_string());
+        assert_eq!(error(".struct point"), unsupported(".struct"));
+        assert_eq!(error("lda #.strlen(\"a\")"), unsupported(".strlen"));
+        assert_eq!(error(".ifconst 1\n.endif"), unsupported(".ifconst"));
+        assert_eq!(
+            error(".define INC(x) x + 1"),
+            unsupported(".define INC with a value that is not an expression")
+        );
+        assert_eq!(
+            error(".repeat 2\nnop"),
+            AssemblerErrorKind::UnterminatedRepeat
+        );
+        // not assembled, `.endif` ends the unsupported conditional
+        assert_eq!(
+            Assembler::new(".if 0\n.ifref x\n.endif\n.endif\nnop")
+                .set_dialect(Dialect::Ca65)
+                .assemble()
+                .unwrap(),
+            vec![0xEA]
+        );
+    }
+
+    #[test]
+    fn test_assemble_include_errors() {
+        let error = Assembler::new(".include \"missing.asm\"")
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::FileNotFound(_)));
+
+        let resolver = files(&[
+            ("a.asm", b".include \"b.asm\""),
+            ("b.asm", b".include \"a.asm\""),
+        ]);
+        let error = Assembler::new(".include \"a.asm\"")
+            .set_resolver(resolver)
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::IncludeRecursion(_)
+        ));
+
+        let resolver = files(&[("data.bin", &[0x00, 0x01])]);
+        let error = Assembler::new(".incbin \"data.bin\", 1, 2")
+            .set_resolver(resolver)
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::OutOfRange(3)));
+
+        let resolver = files(&[("lib.asm", b"    NOP\n    FOO")]);
+        let error = Assembler::new("\n.include \"lib.asm\"")
+            .set_resolver(resolver)
+            .assemble()
+            .unwrap_err();
+        assert_eq!((error.span.start.line, error.span.start.column), (2, 5));
+        assert_eq!(error.files[error.span.start.file], "lib.asm");
+        assert!(error
+            .to_string()
+            .ends_with("at line 2, column 5 in \"lib.asm\""));
+    }
+
+    #[test]
+    fn test_assemble_local_labels() {
+        let s = r#"
+first:
+    LDA #$03
+.loop:
+    SBC #$01
+    BNE .loop
+second:
+    LDA #$03
+@loop:
+    SBC #$01
+    BNE @loop
+    JMP first.loop
+"#;
+        let binary = Assembler::new(s).assemble().unwrap();
+        assert_eq!(
+            binary,
+            vec![
+                0xA9, 0x03, 0xE9, 0x01, 0xD0, 0xFC, // first
+                0xA9, 0x03, 0xE9, 0x01, 0xD0, 0xFC, // second
+                0x4C, 0x02, 0x80,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_duplicate_labels() {
+        let mut assembler =
+            Assembler::new("start:\n    NOP\n.loop:\n    NOP\nstart:\n.loop:\n    RTS");
+        assembler.assemble().unwrap_err();
+        assembler.files[0].name = "main.asm".to_string();
+
+        let error = &assembler.errors[0];
+        match &error.kind {
+            AssemblerErrorKind::DuplicateLabel { name, previous } => {
+                assert_eq!((name.as_str(), previous.line), ("start", 1));
+            }
+            kind => panic!("unexpected error: {kind}"),
+        }
+        assert_eq!(error.span.start.line, 5);
+        let diagnostics = assembler.diagnostics();
+        assert!(diagnostics.contains("= first defined at main.asm:1:1"));
+        assert!(diagnostics.contains("start.loop (first defined at line 3)"));
+        assert_eq!(
+            error.to_string(),
+            "Duplicate label: start (first defined at line 1) at line 5, column 1"
+        );
+    }
+
+    #[test]
+    fn test_assemble_anonymous_labels() {
+        let s = r#"
+:
+    DEX
+    BNE :-
+    BEQ :++
+:
+    NOP
+:
+    JMP :--
+"#;
+        let binary = Assembler::new(s).assemble().unwrap();
+        assert_eq!(
+            binary,
+            vec![0xCA, 0xD0, 0xFD, 0xF0, 0x01, 0xEA, 0x4C, 0x05, 0x80]
+        );
+
+        let error = Assembler::new("    BNE :-").assemble().unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::InvalidLabel(_)));
+    }
+
+    #[test]
+    fn test_assemble_scopes() {
+        let s = r#"
+.proc clear
+    LDA #$00
+loop:
+    STA $0200
+    JMP loop
+.endproc
+
+.scope sound
+    .scope voice
+    init:
+        NOP
+    .endscope
+    JSR voice::init
+.endscope
+
+loop:
+    JSR clear
+    JSR sound::voice::init
+    JMP clear::loop
+"#;
+        let binary = Assembler::new(s).assemble().unwrap();
+        assert_eq!(
+            binary,
+            vec![
+                0xA9, 0x00, 0x8D, 0x00, 0x02, 0x4C, 0x02, 0x80, // clear
+                0xEA, 0x20, 0x08, 0x80, // sound
+                0x20, 0x00, 0x80, 0x20, 0x08, 0x80, 0x4C, 0x02, 0x80,
+            ]
+        );
+
+        let error = Assembler::new(".scope foo\n.endproc")
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::UnmatchedScope(_)));
+
+        let error = Assembler::new(".proc foo\n    NOP").assemble().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AssemblerErrorKind::UnterminatedScope(_)
+        ));
+    }
+
+    #[test]
+    fn test_assemble_constants() {
+        let s = r#"
+SCREEN = $0200
+ZP .equ $10
+.equ LENGTH, end - start
+start:
+    LDA #LENGTH
+    LDA ZP,X
+    STA SCREEN + 1
+    STA PORT
+end:
+PORT = $6000
+.if ZP == $10
+    NOP
+.endif
+"#;
+        let binary = Assembler::new(s).assemble().unwrap();
+        assert_eq!(
+            binary,
+            vec![
+                0xA9, 0x0A, // LDA #LENGTH
+                0xB5, 0x10, // LDA ZP,X (zero page)
+                0x8D, 0x01, 0x02, // STA SCREEN + 1
+                0x8D, 0x00, 0x60, // STA PORT (forward reference, absolute)
+                0xEA,
+            ]
+        );
+
+        let error = Assembler::new("A1 = B1\nB1 = A1").assemble().unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::InvalidLabel(_)));
+
+        let error = Assembler::new("LDA #VALUE\nVALUE = $1234")
+            .assemble()
+            .unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::OutOfRange(0x1234)));
+    }
+
+    #[test]
+    fn test_assemble_define() {
+        let mut assembler = Assembler::new("define ADDR $0200\nSTA ADDR,Y");
+        assert_eq!(assembler.assemble().unwrap(), vec![0x99, 0x00, 0x02]);
+        assert_eq!(
+            assembler.warnings,
+            vec![AssemblerWarning::new(
+                AssemblerWarningKind::DeprecatedDefine("ADDR".to_string()),
+                Position::new(1, 1),
+            )]
+        );
+
+        // the old forms with an addressing mode still assemble
+        let old = "LDY #$00\ndefine STA_ADDR $0200,Y\ndefine V #$05\nloop:\n    LDA V\n    STA STA_ADDR\n    INY\n    BNE loop";
+        let new = "LDY #$00\nSTA_ADDR = $0200\nV = $05\nloop:\n    LDA #V\n    STA STA_ADDR,Y\n    INY\n    BNE loop";
+        let mut assembler = Assembler::new(old);
+        assert_eq!(
+            assembler.assemble().unwrap(),
+            Assembler::new(new).assemble().unwrap()
+        );
+        assert_eq!(
+            assembler.warnings,
+            [("STA_ADDR", 2), ("V", 3)].map(|(name, line)| AssemblerWarning::new(
+                AssemblerWarningKind::DeprecatedDefineOperand(name.to_string()),
+                Position::new(line, 1),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_assemble_duplicate_constants() {
+        let error = |s: &str| Assembler::new(s).assemble().unwrap_err();
+        for source in [
+            "FOO = 1\nFOO = 2\nLDA #FOO",
+            ".equ FOO, 1\n.equ FOO, 2\nLDA #FOO",
+            "FOO:\nFOO = 2\nJMP FOO",
+        ] {
+            let error = error(source);
+            assert_eq!(
+                error.kind,
+                AssemblerErrorKind::DuplicateLabel {
+                    name: "FOO".to_string(),
+                    previous: Position::new(1, 1),
+                },
+                "{source}"
+            );
+            assert_eq!(error.span.start.line, 2);
+        }
+        assert!(matches!(
+            error("FOO = 1\nFOO:\nNOP").kind,
+            AssemblerErrorKind::DuplicateLabel { .. }
+        ));
+    }
+
+    #[test]
+    fn test_assemble_listing() {
+        let s = r#"; listing
+.macro clear address
+    LDA #$00
+    STA address,X
+.endm
+start:
+    clear $0200
+    BNE start
+.byte 1, 2, 3, 4, 5"#;
+        let mut assembler = Assembler::new(s);
+        assembler.assemble().unwrap();
+        assert_eq!(
+            assembler.listing.to_string(),
+            r#"Line   Addr  Bytes        Cycles  Source
+    1                             ; listing
+    2                             .macro clear address
+    3                                 LDA #$00
+    4                                 STA address,X
+    5                             .endm
+    6                             start:
+    7                                 clear $0200
+    3+ 8000  A9 00        2           LDA #$00
+    4+ 8002  9D 00 02     5           STA address,X
+    8  8005  D0 F9        2*          BNE start
+    9  8007  01 02 03 04          .byte 1, 2, 3, 4, 5
+       800B  05
+
+Symbols:
+start  $8000
+"#
+        );
+    }
+
+    #[test]
+    fn test_assemble_build() {
+        let s = r#"SCREEN = $0200
+start:
+    LDA #$01
+.proc draw
+    STA SCREEN
+    RTS
+.endproc"#;
+        let assembly = Assembler::new(s).build().unwrap();
+        assert_eq!(assembly.origin, 0x8000);
+        assert_eq!(assembly.bytes, vec![0xA9, 0x01, 0x8D, 0x00, 0x02, 0x60]);
+
+        let symbols = assembly
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.value, symbol.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                ("SCREEN", 0x0200, SymbolKind::Constant),
+                ("draw", 0x8002, SymbolKind::Label),
+                ("start", 0x8000, SymbolKind::Label),
+            ]
+        );
+        assert_eq!(assembly.symbols[2].position.line, 2);
+
+        assert_eq!(assembly.label(0x8002), Some("draw"));
+        assert_eq!(assembly.label(0x8003), None);
+        assert_eq!(assembly.line(0x8003).unwrap().position.line, 5);
+        assert_eq!(assembly.line(0x8006), None);
+
+        assert_eq!(
+            assembly.vice_labels(),
+            "al C:8002 .draw\nal C:8000 .start\n"
+        );
+        assert!(assembly
+            .json()
+            .contains(r#"{"address": 32770, "size": 3, "file": 0, "line": 5, "column": 5}"#));
+        assert!(assembly.ca65_dbg().contains(
+            "sym\tid=0,name=\"SCREEN\",addrsize=absolute,scope=0,def=3,val=0x200,type=equ"
+        ));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let mut assembler = Assembler::new("LDA (\nNOP\nLDA #@\nFOO");
+        let error = assembler.assemble().unwrap_err();
+        assert_eq!(error.kind, assembler.errors[0].kind);
+        let positions = assembler
+            .errors
+            .iter()
+            .map(|error| (error.span.start.line, error.span.start.column))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(1, 6), (3, 6), (4, 1)]);
+
+        let mut assembler = Assembler::new("C = later\nJMP nowhere\nLDA missing,X\nNOP");
+        assembler.assemble().unwrap_err();
+        let labels = assembler
+            .errors
+            .iter()
+            .map(|error| match &error.kind {
+                AssemblerErrorKind::InvalidLabel(label) => label.as_str(),
+                kind => panic!("unexpected error: {kind}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["later", "nowhere", "missing"]);
+
+        let span = *assembler.errors[1].span;
+        assert_eq!((span.start.column, span.end.column), (5, 12));
+
+        // the statements after a parse error are assembled, their errors are reported too
+        let mut assembler = Assembler::new("JMP nowhere\nLDA (\nLDA #$80*4\nNOP");
+        let error = assembler.assemble().unwrap_err();
+        assert!(matches!(error.kind, AssemblerErrorKind::InvalidOperand(_)));
+        let errors = assembler
+            .errors
+            .iter()
+            .map(|error| (error.span.start.line, error.kind.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors[1..],
+            [
+                (1, AssemblerErrorKind::InvalidLabel("nowhere".to_string())),
+                (3, AssemblerErrorKind::OutOfRange(0x200)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_diagnostics() {
+        let mut assembler = Assembler::new("NOP\n\tJMP nowhere ; comment\n  define OLD 1");
+        assembler.assemble().unwrap_err();
+        assembler.files[0].name = "main.asm".to_string();
+        assert_eq!(
+            assembler.diagnostics(),
+            r#"error: Invalid label: nowhere
+ --> main.asm:2:6
+  |
+2 | 	JMP nowhere ; comment
+  | 	    ^^^^^^^
+
+warning: `define` is deprecated, use `OLD = value` instead
+ --> main.asm:3:3
+  |
+3 |   define OLD 1
+  |   ^^^^^^^^^^^^
+"#
+        );
+    }
+
+    #[test]
+    fn test_assemble_lints() {
+        let s = r#"UNUSED = 1
+start:
+    LDA $0010
+loop:
+    BNE loop
+    JMP ($02FF)
+    NOP
+patch:
+    STA patch+1
+    JMP start
+    RTS
+"#;
+        let mut assembler = Assembler::new(s);
+        assembler.assemble().unwrap();
+        let warnings = assembler
+            .warnings
+            .iter()
+            .map(|warning| (warning.kind.clone(), warning.position.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    AssemblerWarningKind::UnusedConstant("UNUSED".to_string()),
+                    1
+                ),
+                (AssemblerWarningKind::BranchToSelf, 5),
+                (AssemblerWarningKind::JmpIndirectPageWrap(0x02FF), 6),
+                (AssemblerWarningKind::Unreachable, 7),
+                (AssemblerWarningKind::SelfModifying(0x8009), 9),
+                (AssemblerWarningKind::Unreachable, 11),
+            ]
+        );
+
+        // procedures are entered from outside, their labels are not unused
+        let mut assembler = Assembler::new(".proc outer\n    RTS\n.endproc\n");
+        assembler.assemble().unwrap();
+        assert_eq!(assembler.warnings, vec![]);
+    }
+
+    #[test]
+    fn test_assemble_lint_levels() {
+        let s = "start:\n    BNE start ; nowarn branch-to-self\n    JMP start\n    NOP ; nowarn\n";
+        let mut assembler = Assembler::new(s);
+        assembler.assemble().unwrap();
+        assert_eq!(assembler.warnings, vec![]);
+
+        let s = "UNUSED = 1\nstart:\n    BNE start\n    RTS\n    NOP\n";
+        let mut assembler = Assembler::new(s);
+        assembler
+            .set_lints(LintLevel::Deny)
+            .set_lint(Lint::Unused, LintLevel::Allow);
+        let error = assembler.assemble().unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::DeniedWarning(AssemblerWarningKind::BranchToSelf)
+        );
+        assert_eq!(assembler.errors.len(), 2);
+        assert_eq!(assembler.warnings, vec![]);
+    }
+
+    #[test]
+    fn test_assemble_zero_page() {
+        let s = "LDA ptr\nLDA ptr,Y\nloop: JMP loop\nptr = $10";
+        assert_eq!(
+            Assembler::new(s).assemble().unwrap(),
+            vec![0xA5, 0x10, 0xB9, 0x10, 0x00, 0x4C, 0x05, 0x80]
+        );
+
+        // the labels after `LDA end` move when it is assembled with zero page addressing
+        let s = ".org $F9\nLDA end\nLDA end\nend:";
+        assert_eq!(
+            Assembler::new(s).assemble().unwrap(),
+            vec![0xA5, 0xFD, 0xA5, 0xFD]
+        );
+        let s = ".org $F8\nLDA end\nLDA end\nLDA end\nend:";
+        assert_eq!(
+            Assembler::new(s).assemble().unwrap(),
+            vec![0xAD, 0x01, 0x01, 0xAD, 0x01, 0x01, 0xAD, 0x01, 0x01]
+        );
+
+        let s = "LDA a:ptr\nLDA a:$10\nLDA z:$0010\nLDA z:ptr\nLDA $0010\nptr = $10";
+        let mut assembler = Assembler::new(s);
+        assert_eq!(
+            assembler.assemble().unwrap(),
+            vec![0xAD, 0x10, 0x00, 0xAD, 0x10, 0x00, 0xA5, 0x10, 0xA5, 0x10, 0xA5, 0x10]
+        );
+        assert_eq!(assembler.warnings, vec![]);
+
+        let error = Assembler::new("LDA z:$1234").assemble().unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::OutOfRange(0x1234));
+        let error = Assembler::new("LDA z:ptr,Y\nptr = $10")
+            .assemble()
+            .unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::InvalidInstruction("LDA".to_string(), AddressingMode::ZPY)
+        );
+    }
+
+    #[test]
+    fn test_assemble_zero_page_forward() {
+        // forward references are sized by their value, the modes without a zero page form stay absolute
+        let s = "LDX table,Y\nSTX ptr,Y\nLDA ptr+$F0\nLDA ptr+$F1\nptr = $0F\ntable = $1000";
+        assert_eq!(
+            Assembler::new(s).assemble().unwrap(),
+            vec![0xBE, 0x00, 0x10, 0x96, 0x0F, 0xA5, 0xFF, 0xAD, 0x00, 0x01]
+        );
+        let s = ".setcpu \"65C02\"\nSTZ flag\nLDA (ptr)\nflag = $80\nptr = $10";
+        assert_eq!(
+            Assembler::new(s).assemble().unwrap(),
+            vec![0x64, 0x80, 0xB2, 0x10]
+        );
+
+        // in an object the imports of `.importzp` and the labels of zero page segments are in the zero page
+        let s = ".importzp zp\n.import abs\nLDA zp\nLDA abs\nLDA var\n.segment \"ZEROPAGE\"\nvar: .res 1";
+        let object = Assembler::new(s)
+            .set_dialect(Dialect::Ca65)
+            .assemble_object()
+            .unwrap();
+        assert_eq!(
+            object.segments[0].bytes,
+            vec![0xA5, 0x00, 0xAD, 0x00, 0x00, 0xA5, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_assemble_literals() {
+        let s = "LDA #%0000_1111\nCMP #'A'\nLDX #-1\nLDA 0x1\nLDA $000F,X\n.byte '\\n', ';', -128\n.word 1_000";
+        let mut assembler = Assembler::new(s);
+        assert_eq!(
+            assembler.assemble().unwrap(),
+            vec![
+                0xA9, 0x0F, 0xC9, 0x41, 0xA2, 0xFF, 0xA5, 0x01, 0xB5, 0x0F, 0x0A, 0x3B, 0x80, 0xE8,
+                0x03
+            ]
+        );
+        assert_eq!(assembler.warnings, vec![]);
+
+        let error = Assembler::new("LDA #-129").assemble().unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::OutOfRange(-129));
+        let error = Assembler::new("LDA #'ab'").assemble().unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::InvalidCharLiteral("ab".to_string())
+        );
+    }
 
-        let src = Assembler::new(s).assemble().unwrap();
+    #[test]
+    fn test_assemble_literal_widths() {
+        // the width of a literal is its value, not its digits (`$10` and `$0010` alike), `a:` and `z:` select one.
+        // the instructions without the zero page mode use the absolute mode
+        let s = "JSR $0020\nLDA $0010,Y\nLDA $10,Y\nSTA $10,Y\nLDX $10,Y\nLDA $0010\nLDA 0x010\nLDA a:$10\nLDA %1_0000_0000\nJMP $0010";
+        let mut assembler = Assembler::new(s);
         assert_eq!(
-            src,
+            assembler.assemble().unwrap(),
             vec![
-                0xA9, 0x02, // LDA #$02
-                0xC9, 0x01, // CMP #$01
-                0xD0, 0x05, // BNE FOO
-                0xA9, 0x01, // LDA #$01
-                0x85, 0x00, // STA $00
-                0x00, // BRK
-                0xA9, 0x01, // LDA #$01
-                0x85, 0x01, // STA $01
-                0x00, // BRK
+                0x20, 0x20, 0x00, 0xB9, 0x10, 0x00, 0xB9, 0x10, 0x00, 0x99, 0x10, 0x00, 0xB6, 0x10,
+                0xA5, 0x10, 0xA5, 0x10, 0xAD, 0x10, 0x00, 0xAD, 0x00, 0x01, 0x4C, 0x10, 0x00
+            ]
+        );
+        assert_eq!(assembler.warnings, vec![]);
+
+        let error = Assembler::new("LDA -1").assemble().unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::OutOfRange(-1));
+        let error = Assembler::new("LDA (-1),Y").assemble().unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::OutOfRange(-1));
+    }
+
+    #[test]
+    fn test_assemble_register_names() {
+        let s = r#"
+xpos = $10
+y_max = $0300
+    LDX #0
+yloop:
+    LDA xpos,x
+    STA y_max,Y
+    LDA (xpos),y
+    STA (xpos,X)
+    INX
+    CPX #xpos
+    BNE yloop
+x:
+    JMP x
+"#;
+        let mut assembler = Assembler::new(s);
+        assert_eq!(
+            assembler.assemble().unwrap(),
+            vec![
+                0xA2, 0x00, 0xB5, 0x10, 0x99, 0x00, 0x03, 0xB1, 0x10, 0x81, 0x10, 0xE8, 0xE0, 0x10,
+                0xD0, 0xF2, 0x4C, 0x10, 0x80
+            ]
+        );
+
+        let error = Assembler::new("LDA $10,xpos").assemble().unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::UnexpectedToken {
+                expected: "X or Y".to_string(),
+                found: "xpos".to_string()
+            }
+        );
+        let error = Assembler::new("LDA $10,z").assemble().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected token: expected \"X or Y\", found \"z\" at line 1, column 9"
+        );
+        let error = Assembler::new("LDA $1000,z").assemble().unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::UnexpectedToken {
+                expected: "X or Y".to_string(),
+                found: "z".to_string()
+            }
+        );
+        let error = Assembler::new("LDA ($10,Y)").assemble().unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::UnexpectedToken {
+                expected: "X".to_string(),
+                found: "Y".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_assemble_long_branches() {
+        let error = Assembler::new("BEQ far\n.res 200\nfar: NOP")
+            .assemble()
+            .unwrap_err();
+        assert_eq!(error.kind, AssemblerErrorKind::BranchOutOfRange(200));
+
+        let binary = Assembler::new("JEQ near\nnear: JCC $1234")
+            .assemble()
+            .unwrap();
+        assert_eq!(binary, vec![0xF0, 0x00, 0xB0, 0x03, 0x4C, 0x34, 0x12]);
+
+        let s = "start: NOP\n.res 200\nBNE start\nBNE start";
+        let mut assembler = Assembler::new(s);
+        let chunks = assembler.set_long_branches(true).assemble_chunks().unwrap();
+        // the second branch reaches the `JMP` of the first one, it is not in range of `start`
+        assert_eq!(
+            chunks[1].bytes,
+            vec![0xF0, 0x03, 0x4C, 0x00, 0x80, 0xF0, 0x03, 0x4C, 0x00, 0x80]
+        );
+
+        let error = Assembler::new("JEQ #1").assemble().unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::InvalidInstruction("JEQ".to_string(), AddressingMode::IMM)
+        );
+    }
+
+    #[test]
+    fn test_assemble_branch_range() {
+        let assemble = |s: &str| Assembler::new(s).assemble();
+        let error = |s: &str| {
+            let error = assemble(s).unwrap_err();
+            (error.kind, error.span.start.line)
+        };
+
+        // branches reach from -128 to 127 bytes after the instruction
+        assert_eq!(
+            assemble("start: NOP\n.res 125\nBNE start").unwrap()[126..],
+            [0xD0, 0x80]
+        );
+        assert_eq!(
+            error("start: NOP\n.res 126\nBNE start"),
+            (AssemblerErrorKind::BranchOutOfRange(-129), 3)
+        );
+        assert_eq!(
+            assemble("BNE end\n.res 127\nend:").unwrap()[..2],
+            [0xD0, 0x7F]
+        );
+        assert_eq!(
+            error("BNE end\n.res 128\nend:"),
+            (AssemblerErrorKind::BranchOutOfRange(128), 1)
+        );
+        // to a constant address
+        assert_eq!(
+            error("target = $9000\n.org $8000\nBCC target"),
+            (AssemblerErrorKind::BranchOutOfRange(0x9000 - 0x8002), 3)
+        );
+        // the target of `BBR`, after the zero page address
+        assert_eq!(
+            error(".setcpu \"65C02\"\nBBR0 $12,far\n.res 128\nfar:"),
+            (AssemblerErrorKind::BranchOutOfRange(128), 2)
+        );
+
+        // a long branch is short if its target is in range, the second one shrinks first
+        // and brings the target of the first one in range
+        let binary = assemble("JNE end\nJEQ end\n.res 120\nend:").unwrap();
+        assert_eq!(binary[..4], [0xD0, 0x7A, 0xF0, 0x78]);
+        let binary = assemble("JNE end\nJEQ end\n.res 130\nend:").unwrap();
+        assert_eq!(
+            binary[..10],
+            [0xF0, 0x03, 0x4C, 0x8C, 0x80, 0xD0, 0x03, 0x4C, 0x8C, 0x80]
+        );
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let s = "LDA #1\nSTA $0200\n.org $9000\n.byte 1, 2";
+        let chunks = Assembler::new(s).assemble_chunks().unwrap();
+
+        assert_eq!(
+            String::from_utf8(OutputFormat::IntelHex.write(&chunks)).unwrap(),
+            ":05800000A9018D000242\n:0290000001026B\n:00000001FF\n"
+        );
+        assert_eq!(
+            String::from_utf8(OutputFormat::SRecord.write(&chunks)).unwrap(),
+            "S0030000FC\nS1088000A9018D00023E\nS1059000010267\nS5030002FA\nS90380007C\n"
+        );
+        let prg = OutputFormat::Prg.write(&chunks);
+        assert_eq!(prg[..7], [0x00, 0x80, 0xA9, 0x01, 0x8D, 0x00, 0x02]);
+        assert_eq!(prg.len(), 2 + 0x1002);
+        assert_eq!(OutputFormat::Raw.write(&chunks), prg[2..]);
+
+        for format in [OutputFormat::IntelHex, OutputFormat::SRecord] {
+            assert_eq!(format.read(&format.write(&chunks)).unwrap(), chunks);
+        }
+        // the `S9` record starts at the reset vector
+        let s = ".org $9000\nNOP\n.org $8000\nstart: RTS\n.org $FFFC\n.word start";
+        let vectors = Assembler::new(s).assemble_chunks().unwrap();
+        let s_record = String::from_utf8(OutputFormat::SRecord.write(&vectors)).unwrap();
+        assert!(s_record.ends_with("\nS90380007C\n"), "{s_record}");
+        let image = vec![Chunk {
+            origin: 0x8000,
+            bytes: prg[2..].to_vec(),
+        }];
+        assert_eq!(OutputFormat::Prg.read(&prg).unwrap(), image);
+        assert_eq!(OutputFormat::Raw.read(&prg[2..]).unwrap(), image);
+
+        let error = OutputFormat::IntelHex
+            .read(b":00000001FF\n:0290000001026A")
+            .unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::InvalidRecord("invalid checksum".to_string())
+        );
+        assert_eq!(error.span.start.line, 2);
+        assert_eq!(OutputFormat::from_path("rom.S19"), OutputFormat::SRecord);
+    }
+
+    #[test]
+    fn test_assemble_object() {
+        let s = ".import putchar\n.export main\n.segment \"ZEROPAGE\"\nptr: .res 2\n.segment \"CODE\"\nmain: LDA #<text\nSTA ptr\nJSR putchar\nloop: BNE loop\n.segment \"RODATA\"\ntext: .word text + 1";
+        let object = Assembler::new(s).assemble_object().unwrap();
+
+        assert_eq!(object.imports, ["putchar"]);
+        assert_eq!(
+            object.exports,
+            [ObjectSymbol {
+                name: "main".to_string(),
+                value: 0,
+                segment: Some("CODE".to_string()),
+            }]
+        );
+        let segments = object
+            .segments
+            .iter()
+            .map(|segment| (segment.name.as_str(), segment.size, segment.bytes.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            [
+                (
+                    "CODE",
+                    9,
+                    vec![0xA9, 0x00, 0x85, 0x00, 0x20, 0x00, 0x00, 0xD0, 0xFE]
+                ),
+                ("ZEROPAGE", 2, vec![]),
+                ("RODATA", 2, vec![0x00, 0x00]),
+            ]
+        );
+        let relocation = |offset, kind, target: RelocationTarget, addend| Relocation {
+            offset,
+            kind,
+            target,
+            addend,
+        };
+        let segment = |name: &str| RelocationTarget::Segment(name.to_string());
+        assert_eq!(
+            object.segments[0].relocations,
+            [
+                relocation(1, RelocationKind::Low, segment("RODATA"), 0),
+                relocation(3, RelocationKind::Byte, segment("ZEROPAGE"), 0),
+                relocation(
+                    5,
+                    RelocationKind::Word,
+                    RelocationTarget::Import("putchar".to_string()),
+                    0
+                ),
+            ]
+        );
+        assert_eq!(
+            object.segments[2].relocations,
+            [relocation(0, RelocationKind::Word, segment("RODATA"), 1)]
+        );
+        assert_eq!(Object::read(&object.write()).unwrap(), object);
+
+        let error = |s| Assembler::new(s).assemble_object().unwrap_err().kind;
+        assert_eq!(
+            error(".org $1000"),
+            AssemblerErrorKind::AbsoluteOnly("org".to_string())
+        );
+        assert_eq!(
+            error(".import f\nBEQ f"),
+            AssemblerErrorKind::SegmentBranch("f".to_string())
+        );
+        assert_eq!(
+            error("a: .byte a * 2"),
+            AssemblerErrorKind::NotRelocatable("(a * 2)".to_string())
+        );
+        assert_eq!(
+            Assembler::new(".segment \"DATA\"")
+                .assemble()
+                .unwrap_err()
+                .kind,
+            AssemblerErrorKind::ObjectOnly("segment".to_string())
+        );
+    }
+
+    #[test]
+    fn test_link() {
+        let main = ".import putchar\n.export main\nmain: LDA #'A'\nJSR putchar\n.segment \"VECTORS\"\n.word main";
+        let io = ".export putchar\nputchar: STA $F001\nRTS";
+        let mut objects = [main, io]
+            .map(|s| Assembler::new(s).assemble_object().unwrap())
+            .to_vec();
+        objects[1].name = "io.o".to_string();
+
+        let config = "# ROM only\nMEMORY {\n ROM: start = $F000, size = $1000, fill = $FF;\n}\nSEGMENTS {\n CODE: load = ROM;\n VECTORS: load = ROM, start = $FFFC;\n}";
+        let config = LinkerConfig::parse(config).unwrap();
+        let linked = link(&objects, &config).unwrap();
+
+        assert_eq!(linked.chunks.len(), 1);
+        let bytes = &linked.chunks[0].bytes;
+        assert_eq!(
+            bytes[..9],
+            [0xA9, 0x41, 0x20, 0x05, 0xF0, 0x8D, 0x01, 0xF0, 0x60]
+        );
+        assert_eq!(bytes[9], 0xFF);
+        assert_eq!(bytes[0xFFC..], [0x00, 0xF0, 0xFF, 0xFF]);
+        assert_eq!(linked.symbol("putchar"), Some(0xF005));
+        assert!(linked
+            .map()
+            .contains("CODE         io.o             ROM          $F005  $F008  $0004"));
+
+        let error = |objects: &[Object], config: &str| {
+            link(objects, &LinkerConfig::parse(config).unwrap())
+                .unwrap_err()
+                .kind
+        };
+        let small = "MEMORY { ROM: start = $F000, size = 8; }\nSEGMENTS { CODE: load = ROM; VECTORS: load = ROM; }";
+        assert_eq!(
+            error(&objects, small),
+            AssemblerErrorKind::SegmentOverflow {
+                segment: "CODE".to_string(),
+                memory: "ROM".to_string(),
+                bytes: 1,
+            }
+        );
+        assert_eq!(
+            link(&objects[..1], &LinkerConfig::default())
+                .unwrap_err()
+                .kind,
+            AssemblerErrorKind::UnresolvedImport {
+                name: "putchar".to_string(),
+                object: String::new(),
+            }
+        );
+        let zero_page = Assembler::new(".import value\nLDA z:value")
+            .assemble_object()
+            .unwrap();
+        let value = Assembler::new(".export value\nvalue = $1234")
+            .assemble_object()
+            .unwrap();
+        assert_eq!(
+            link(&[zero_page, value], &LinkerConfig::default())
+                .unwrap_err()
+                .kind,
+            AssemblerErrorKind::RelocationOutOfRange {
+                target: "import value".to_string(),
+                value: 0x1234,
+            }
+        );
+
+        let error = LinkerConfig::parse("MEMORY {\n ROM: start = $8000;\n}").unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::InvalidConfig(
+                "memory area ROM needs a start and a size".to_string()
+            )
+        );
+        assert_eq!(error.span.start.line, 2);
+    }
+
+    #[test]
+    fn test_assemble_layout() {
+        let layout = "MEMORY {\n ZP: start = $0000, size = $100;\n RAM: start = $0200, size = $100;\n ROM: start = $F000, size = $10, fill = $EA;\n}\nSEGMENTS {\n ZEROPAGE: load = ZP, type = zp;\n BSS: load = RAM, type = bss;\n CODE: load = ROM;\n RODATA: load = ROM;\n}";
+        let layout = LinkerConfig::parse(layout).unwrap();
+        let source = ".segment \"ZEROPAGE\"\nptr: .res 2\n.segment \"BSS\"\nbuffer: .res 16\n.segment \"CODE\"\nLDA text\nSTA ptr\n.segment \"RODATA\"\ntext: .byte 1, 2\n.segment \"CODE\"\nSTA buffer\nRTS";
+        let chunks = Assembler::new(source)
+            .set_layout(layout.clone())
+            .assemble_chunks()
+            .unwrap();
+        assert_eq!(
+            chunks,
+            vec![Chunk {
+                origin: 0xF000,
+                bytes: vec![
+                    0xAD, 0x09, 0xF0, 0x85, 0x00, 0x8D, 0x00, 0x02, 0x60, 0x01, 0x02, 0xEA, 0xEA,
+                    0xEA, 0xEA, 0xEA
+                ],
+            }]
+        );
+
+        let error = |source: &str| {
+            let error = Assembler::new(source)
+                .set_layout(layout.clone())
+                .assemble()
+                .unwrap_err();
+            (error.kind, error.span.start.line)
+        };
+        assert_eq!(
+            error("NOP\n.segment \"RODATA\"\n.res 16"),
+            (
+                AssemblerErrorKind::SegmentOverflow {
+                    segment: "RODATA".to_string(),
+                    memory: "ROM".to_string(),
+                    bytes: 1,
+                },
+                2
+            )
+        );
+        // the implicit segment before the first `.segment`, at its first statement
+        assert_eq!(
+            error("start:\n.res 14\nNOP\nJMP start\n.segment \"RODATA\"\n.byte 1"),
+            (
+                AssemblerErrorKind::SegmentOverflow {
+                    segment: "CODE".to_string(),
+                    memory: "ROM".to_string(),
+                    bytes: 2,
+                },
+                2
+            )
+        );
+        assert_eq!(
+            error("NOP\n.segment \"RODATA\"\n.res 16").0.to_string(),
+            "Segment RODATA overflows memory area ROM by 1 byte"
+        );
+        assert_eq!(
+            error("NOP\n.segment \"RODATA\"\n.res 17").0.to_string(),
+            "Segment RODATA overflows memory area ROM by 2 bytes"
+        );
+        assert_eq!(
+            error(".segment \"BSS\"\nLDA #1"),
+            (AssemblerErrorKind::BssData("BSS".to_string()), 2)
+        );
+        assert_eq!(
+            error(".segment \"DATA\""),
+            (AssemblerErrorKind::UnplacedSegment("DATA".to_string()), 1)
+        );
+        assert_eq!(
+            error(".org $F000"),
+            (AssemblerErrorKind::AbsoluteOnly("org".to_string()), 1)
+        );
+    }
+
+    #[test]
+    fn test_assemble_cpu() {
+        let s = r#"
+.setcpu "65C02"
+start:
+    LDA ($12)
+    STA (ptr)
+    JMP (table,X)
+    JMP ($12,X)
+    STZ $10
+    STZ $1234,X
+    BRA start
+    BBR0 $12,start
+    SMB7 ptr
+    PHX
+    INC
+ptr = $20
+table = $1234
+"#;
+        let binary = Assembler::new(s).assemble().unwrap();
+        assert_eq!(
+            binary,
+            vec![
+                0xB2, 0x12, 0x92, 0x20, 0x7C, 0x34, 0x12, 0x7C, 0x12, 0x00, 0x64, 0x10, 0x9E, 0x34,
+                0x12, 0x80, 0xEF, 0x0F, 0x12, 0xEC, 0xF7, 0x20, 0xDA, 0x1A
+            ]
+        );
+
+        let s = "LAX ($12),Y\nSLO $1234,X\nNOP $12\nNOP #1\nJAM\nSAX $12,Y";
+        let binary = Assembler::new(s)
+            .set_cpu(CpuType::Nmos6502X)
+            .assemble()
+            .unwrap();
+        assert_eq!(
+            binary,
+            vec![0xB3, 0x12, 0x1F, 0x34, 0x12, 0x04, 0x12, 0x80, 0x01, 0x02, 0x97, 0x12]
+        );
+
+        let binary = Assembler::new(".pc02\nphy\n.p02\npha")
+            .set_dialect(Dialect::Ca65)
+            .assemble()
+            .unwrap();
+        assert_eq!(binary, vec![0x5A, 0x48]);
+
+        let error = |s: &str| Assembler::new(s).assemble().unwrap_err().kind;
+        assert_eq!(
+            error("BRA $00"),
+            AssemblerErrorKind::UnavailableInstruction {
+                mnemonic: "BRA".to_string(),
+                cpu: CpuType::Nmos6502,
+            }
+        );
+        assert_eq!(
+            error(".setcpu \"65SC02\"\nSMB0 $12"),
+            AssemblerErrorKind::UnavailableInstruction {
+                mnemonic: "SMB0".to_string(),
+                cpu: CpuType::Cmos65SC02,
+            }
+        );
+        assert_eq!(
+            error("INC A").to_string(),
+            "Accumulator mode of INC (INC A) is not available on the 6502, it needs a CMOS CPU (65SC02, 65C02, W65C02), select one with .setcpu"
+        );
+        assert_eq!(
+            error("LDA ($12)"),
+            AssemblerErrorKind::InvalidInstruction("LDA".to_string(), AddressingMode::IND)
+        );
+        assert!(matches!(
+            error(".setcpu \"Z80\""),
+            AssemblerErrorKind::UnexpectedToken { .. }
+        ));
+    }
+
+    #[test]
+    fn test_disassemble_cpu() {
+        let lines = |bytes: &[u8], cpu| {
+            disassemble(bytes, cpu, AccumulatorSyntax::Implied)
+                .unwrap()
+                .into_iter()
+                .map(|(_, _, line)| line)
+                .collect::<Vec<_>>()
+        };
+
+        let bytes = [0x80, 0xFE, 0xB2, 0x12, 0x0F, 0x12, 0xFD, 0x7C, 0x34, 0x12];
+        assert_eq!(
+            lines(&bytes, CpuType::Cmos65C02),
+            ["BRA $FE", "LDA ($12)", "BBR0 $12,$FD", "JMP ($1234,X)"]
+        );
+        assert_eq!(
+            lines(&[0x80, 0x01, 0xA7, 0x12, 0xEB, 0x02], CpuType::Nmos6502X),
+            ["NOP #$01", "LAX $12", "SBC #$02"]
+        );
+        let nmos = disassemble(&[0x80, 0x01], CpuType::Nmos6502, AccumulatorSyntax::Implied);
+        assert!(nmos.is_err());
+        let sc02 = disassemble(
+            &[0x0F, 0x12],
+            CpuType::Cmos65SC02,
+            AccumulatorSyntax::Implied,
+        );
+        assert!(sc02.is_err());
+    }
+
+    #[test]
+    fn test_disassemble_flow() {
+        let source = r#"
+        .org $8000
+reset:  LDX #$00
+loop:   LDA table,X
+        JSR print
+        INX
+        CPX #4
+        BNE loop
+        JMP (vector)
+table:  .byte $01, $02, $03, $04, $60
+print:  STA $0200
+        STA $12
+        STA a:$12
+        RTS
+vector: .word reset
+nmi:    RTI
+        .org $FFFA
+        .word nmi, reset, nmi
+"#;
+        let (origin, bytes) = chunks_to_image(&Assembler::new(source).assemble_chunks().unwrap());
+        let disassembly = disassemble_flow(origin, &bytes, &DisassemblyOptions::default());
+
+        let lines = disassembly
+            .lines
+            .iter()
+            .take(14)
+            .map(|line| match &line.label {
+                Some(label) => format!("{label}: {}", line.text),
+                None => line.text.clone(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "L8000: LDX #$00",
+                "L8002: LDA L8010,X",
+                "JSR L8015",
+                "INX",
+                "CPX #$04",
+                "BNE L8002",
+                "JMP (L801E)",
+                "L8010: .byte $01, $02, $03, $04, $60",
+                "L8015: STA $0200",
+                "STA $12",
+                "STA a:$0012",
+                "RTS",
+                "L801E: .byte $00, $80",
+                "L8020: RTI",
             ]
         );
+        // the table is data, even though $60 is `RTS`
+        assert_eq!(disassembly.kinds[0x13], ByteKind::Data);
+        assert_eq!(disassembly.kinds[0x14], ByteKind::Data);
+        assert_eq!(disassembly.kinds[0x15], ByteKind::Code);
+        let vectors = disassembly.lines.last().unwrap();
+        assert_eq!(vectors.address, 0xFFFA);
+        assert_eq!(vectors.text, ".word L8020, L8000, L8020");
+
+        // the source assembles to the same bytes
+        let reassembled = Assembler::new(&disassembly.source())
+            .assemble_chunks()
+            .unwrap();
+        assert_eq!(chunks_to_image(&reassembled), (origin, bytes.clone()));
+
+        // entry points instead of the vectors, a branch into the middle of an instruction
+        let bytes = [0xA9, 0xEA, 0xF0, 0xFD, 0x00, 0xFF];
+        let options = DisassemblyOptions {
+            entries: vec![0x1000],
+            ..Default::default()
+        };
+        let disassembly = disassemble_flow(0x1000, &bytes, &options);
+        let lines = disassembly
+            .lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["LDA #$EA", "BEQ *-1", "BRK", ".byte $FF"]);
+        assert_eq!(
+            disassembly.labels.iter().copied().collect::<Vec<_>>(),
+            [0x1000]
+        );
+        let reassembled = Assembler::new(&disassembly.source()).assemble().unwrap();
+        assert_eq!(reassembled, bytes);
+
+        // without the vectors, the code starts at the origin
+        let disassembly = disassemble_flow(0x1000, &bytes, &DisassemblyOptions::default());
+        assert_eq!(disassembly.lines[0].text, "LDA #$EA");
+    }
+
+    #[test]
+    fn test_disassemble_flow_round_trip() {
+        // branches out of the image and into the middle of an instruction, a bit branch
+        let source = r#"
+        .org $C000
+start:  LDX #$00
+        BEQ skip+1
+        BNE *-$10
+skip:   LDA #$EA
+        BBR0 $12,*-$20
+        STX $0200
+        JMP start
+        .org $FFFC
+        .word start, start
+"#;
+        let (origin, bytes) = chunks_to_image(
+            &Assembler::new(source)
+                .set_cpu(CpuType::Cmos65C02)
+                .assemble_chunks()
+                .unwrap(),
+        );
+        let options = DisassemblyOptions {
+            cpu: CpuType::Cmos65C02,
+            ..Default::default()
+        };
+        let disassembly = disassemble_flow(origin, &bytes, &options);
+        let source = disassembly.source();
+        assert!(source.contains("BNE *-16"), "{source}");
+        assert!(source.contains("BBR0 $12,*-32"), "{source}");
+
+        let reassembled = Assembler::new(&source)
+            .set_cpu(CpuType::Cmos65C02)
+            .assemble_chunks()
+            .unwrap();
+        assert_eq!(chunks_to_image(&reassembled), (origin, bytes));
+    }
+
+    #[test]
+    fn test_disassemble_flow_round_trip_opcodes() {
+        // every opcode of every CPU, the duplicates of the 6502X are data
+        for cpu in CpuType::ALL {
+            for opcode in opcodes::OPCODES
+                .iter()
+                .filter(|opcode| opcode.set.includes(cpu))
+            {
+                let bytes = [opcode.byte, 0x12, 0x34, 0xEA];
+                let options = DisassemblyOptions {
+                    cpu,
+                    ..Default::default()
+                };
+                let source = disassemble_flow(0x1000, &bytes, &options).source();
+                let reassembled = Assembler::new(&source)
+                    .set_cpu(cpu)
+                    .assemble_chunks()
+                    .unwrap_or_else(|error| {
+                        panic!("{cpu} ${:02X}: {error}\n{source}", opcode.byte)
+                    });
+                assert_eq!(
+                    chunks_to_image(&reassembled),
+                    (0x1000, bytes.to_vec()),
+                    "{cpu} ${:02X}\n{source}",
+                    opcode.byte
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_accumulator_syntax() {
+        let bytes = Assembler::new("ASL\nLSR A\nINC A\nROR $12\n")
+            .set_cpu(CpuType::Cmos65C02)
+            .assemble()
+            .unwrap();
+        assert_eq!(bytes, [0x0A, 0x4A, 0x1A, 0x66, 0x12]);
+
+        let lines = |accumulator| {
+            disassemble(&bytes, CpuType::Cmos65C02, accumulator)
+                .unwrap()
+                .into_iter()
+                .map(|(_, _, line)| line)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(AccumulatorSyntax::Implied),
+            ["ASL", "LSR", "INC", "ROR $12"]
+        );
+        assert_eq!(
+            lines(AccumulatorSyntax::Explicit),
+            ["ASL A", "LSR A", "INC A", "ROR $12"]
+        );
+    }
+
+    #[test]
+    fn test_format_source() {
+        let source = "; sprite routine\n\nstart: lda #10 ; load\n  sta $0200,x\nloop:   dex\n bne loop\n  ; indented\n asl a\n lda #<(table+1)*2\ntable = $1000\n .byte 1,2   ; data\n bne :-\n";
+        let formatted = format_source(source, &FormatOptions::default());
+        assert_eq!(
+            formatted,
+            "; sprite routine\n\
+             \n\
+             start:  LDA #$0A                ; load\n        \
+             STA $0200,X\n\
+             loop:   DEX\n        \
+             BNE loop\n        \
+             ; indented\n        \
+             ASL\n        \
+             LDA #<(table + $01) * $02\n\
+             table = $1000\n        \
+             .byte $01,$02           ; data\n        \
+             BNE :-\n"
+        );
+        assert_eq!(
+            format_source(&formatted, &FormatOptions::default()),
+            formatted
+        );
+
+        // the formatted source assembles to the same bytes
+        let source =
+            "start: lda #10\n sta $0200,x\n asl a\n lda #<(table+1)*2\n bne start\ntable = $1000\n .word 300,table\n";
+        let formatted = format_source(source, &FormatOptions::default());
+        assert_eq!(
+            Assembler::new(&formatted).assemble().unwrap(),
+            Assembler::new(source).assemble().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_options() {
+        let options = FormatOptions {
+            mnemonic_case: MnemonicCase::Lower,
+            mnemonic_column: 4,
+            operand_column: 10,
+            comment_column: 20,
+            radix: Radix::Binary,
+            accumulator: AccumulatorSyntax::Explicit,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_source("longlabel: LDA $80,X ; x\n ROL\n", &options),
+            "longlabel: lda %10000000,x ; x\n    rol   a\n"
+        );
+        // the data directives and the constants are written in the radix, characters are kept
+        assert_eq!(
+            format_source("SIZE = 2*$0010\n .byte 'A',3\n .WORD SIZE+1\n", &options),
+            "SIZE = %00000010*%00010000\n    .byte 'A',%00000011\n    .WORD SIZE+%00000001\n"
+        );
+        // characters and cheap local labels are kept as written
+        assert_eq!(
+            format_source("@loop: CMP #'A'\n BNE @loop\n", &options),
+            "@loop: cmp #'A'\n    bne   @loop\n"
+        );
+
+        // the registers are in the case of the mnemonics, also in the operands kept as written
+        assert_eq!(
+            format_source(
+                ":\n ldx $0010,y\n lda :-,y\n lda ('A'),y\n asl a\n",
+                &FormatOptions::default()
+            ),
+            ":\n        LDX $10,Y\n        LDA :-,Y\n        LDA ('A'),Y\n        ASL\n"
+        );
+        assert_eq!(format_source(" LDA :-,Y\n", &options), "    lda   :-,y\n");
+
+        // the CPU of `.setcpu` selects the mode of `INC A`
+        let source = ".setcpu \"65C02\"\n inc A\n";
+        assert_eq!(
+            format_source(source, &FormatOptions::default()),
+            "        .setcpu \"65C02\"\n        INC\n"
+        );
     }
 }
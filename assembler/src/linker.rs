@@ -0,0 +1,1032 @@
+use crate::{
+    AssemblerError, AssemblerErrorKind, AssemblerResult, Chunk, Object, Position, RelocationKind,
+    RelocationTarget, DEFAULT_SEGMENT,
+};
+use std::{collections::HashMap, fmt::Write};
+
+/// Memory layout of the default [`LinkerConfig`], the code starts at [`crate::DEFAULT_ORIGIN`]
+pub const DEFAULT_CONFIG: &str = r#"
+MEMORY {
+    ZP:  start = $0000, size = $0100;
+    RAM: start = $0200, size = $7E00;
+    ROM: start = $8000, size = $8000;
+}
+SEGMENTS {
+    ZEROPAGE: load = ZP, type = zp;
+    BSS:      load = RAM, type = bss;
+    CODE:     load = ROM;
+    RODATA:   load = ROM;
+    DATA:     load = ROM;
+    VECTORS:  load = ROM, start = $FFFA;
+}
+"#;
+
+/// Where the linker places the segments of the objects, see [`LinkerConfig::parse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkerConfig {
+    pub memory: Vec<MemoryArea>,
+    /// in the order they are placed in their memory areas
+    pub segments: Vec<SegmentPlacement>,
+}
+
+/// A named range of the address space, `fill` fills the unused bytes of the area in the output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryArea {
+    pub name: String,
+    pub start: u16,
+    pub size: usize,
+    pub fill: Option<u8>,
+}
+
+/// A segment in a memory area (`load`), after the previous segment of the area or at `start`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentPlacement {
+    pub name: String,
+    pub load: String,
+    pub start: Option<u16>,
+    pub kind: SegmentKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SegmentKind {
+    /// code and data (`type = ro` or `type = rw`)
+    #[default]
+    Code,
+    /// reserved bytes only (`.res`), nothing is written to the output
+    Bss,
+    /// reserved bytes in the zero page, labels in it are zero page addresses
+    ZeroPage,
+}
+
+impl Default for LinkerConfig {
+    fn default() -> Self {
+        Self::parse(DEFAULT_CONFIG).expect("the default config is valid")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ConfigToken<'a> {
+    Word(&'a str),
+    Symbol(char),
+    End,
+}
+
+/// Tokens of a config with their positions, `#` starts a comment.
+fn config_tokens(text: &str) -> Vec<(ConfigToken<'_>, Position)> {
+    let mut tokens = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut start = None;
+        for (column, c) in line.char_indices().chain([(line.len(), ' ')]) {
+            let symbol = matches!(c, '{' | '}' | ':' | '=' | ',' | ';');
+            if c.is_whitespace() || symbol {
+                if let Some(start) = start.take() {
+                    tokens.push((
+                        ConfigToken::Word(&line[start..column]),
+                        Position::new(i + 1, start + 1),
+                    ));
+                }
+                if symbol {
+                    tokens.push((ConfigToken::Symbol(c), Position::new(i + 1, column + 1)));
+                }
+            } else if start.is_none() {
+                start = Some(column);
+            }
+        }
+    }
+    tokens.push((ConfigToken::End, Position::new(text.lines().count() + 1, 1)));
+
+    tokens
+}
+
+struct ConfigParser<'a> {
+    tokens: Vec<(ConfigToken<'a>, Position)>,
+    index: usize,
+}
+
+impl<'a> ConfigParser<'a> {
+    fn token(&self) -> &ConfigToken<'a> {
+        &self.tokens[self.index].0
+    }
+
+    fn position(&self) -> Position {
+        self.tokens[self.index].1
+    }
+
+    fn error(&self, message: String) -> AssemblerError {
+        AssemblerError::new(AssemblerErrorKind::InvalidConfig(message), self.position())
+    }
+
+    fn next(&mut self) -> ConfigToken<'a> {
+        let token = self.token().clone();
+        if token != ConfigToken::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn symbol(&mut self, symbol: char) -> AssemblerResult<()> {
+        match self.token() {
+            ConfigToken::Symbol(c) if *c == symbol => {
+                self.next();
+                Ok(())
+            }
+            _ => Err(self.error(format!("expected `{symbol}`"))),
+        }
+    }
+
+    fn word(&mut self) -> AssemblerResult<&'a str> {
+        match self.token() {
+            ConfigToken::Word(word) => {
+                let word = *word;
+                self.next();
+                Ok(word)
+            }
+            _ => Err(self.error("expected a name".to_string())),
+        }
+    }
+
+    /// `$8000`, `0x8000`, `%1000`, `32768`, like the number literals of the sources
+    fn number(&mut self) -> AssemblerResult<i32> {
+        let position = self.position();
+        let word = self.word()?;
+        let (digits, radix) = match word.strip_prefix('$') {
+            Some(digits) => (digits, 16),
+            None => match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+                Some(digits) => (digits, 16),
+                None => match word.strip_prefix('%') {
+                    Some(digits) => (digits, 2),
+                    None => (word, 10),
+                },
+            },
+        };
+
+        i32::from_str_radix(&digits.replace('_', ""), radix).map_err(|_| {
+            AssemblerError::new(
+                AssemblerErrorKind::InvalidConfig(format!("invalid number `{word}`")),
+                position,
+            )
+        })
+    }
+
+    /// `NAME: attribute = value, ...;` until `}`, `attribute` is called for every attribute.
+    fn entries(
+        &mut self,
+        mut entry: impl FnMut(&mut Self, &'a str, Position) -> AssemblerResult<()>,
+    ) -> AssemblerResult<()> {
+        self.symbol('{')?;
+        while self.token() != &ConfigToken::Symbol('}') {
+            let position = self.position();
+            let name = self.word()?;
+            self.symbol(':')?;
+            entry(self, name, position)?;
+            self.symbol(';')?;
+        }
+        self.symbol('}')
+    }
+
+    /// `attribute = value, ...`, the values are read by `value`.
+    fn attributes(
+        &mut self,
+        mut value: impl FnMut(&mut Self, &'a str) -> AssemblerResult<()>,
+    ) -> AssemblerResult<()> {
+        loop {
+            let attribute = self.word()?;
+            self.symbol('=')?;
+            value(self, attribute)?;
+            if self.token() != &ConfigToken::Symbol(',') {
+                return Ok(());
+            }
+            self.next();
+        }
+    }
+}
+
+impl LinkerConfig {
+    /// Reads a config in the format of the `ld65` configs, `#` starts a comment:
+    ///
+    /// ```text
+    /// MEMORY {
+    ///     ZP:  start = $0000, size = $0100;
+    ///     ROM: start = $8000, size = $8000, fill = $FF;
+    /// }
+    /// SEGMENTS {
+    ///     ZEROPAGE: load = ZP, type = zp;
+    ///     CODE:     load = ROM;
+    ///     VECTORS:  load = ROM, start = $FFFA;
+    /// }
+    /// ```
+    ///
+    /// segment types are `ro`, `rw` (the default), `bss` and `zp`, see [`SegmentKind`].
+    /// errors are [`AssemblerErrorKind::InvalidConfig`]
+    pub fn parse(text: &str) -> AssemblerResult<Self> {
+        let mut parser = ConfigParser {
+            tokens: config_tokens(text),
+            index: 0,
+        };
+        let mut config = LinkerConfig {
+            memory: Vec::new(),
+            segments: Vec::new(),
+        };
+        let mut loads = Vec::new();
+
+        while parser.token() != &ConfigToken::End {
+            let position = parser.position();
+            match parser.word()? {
+                "MEMORY" => parser.entries(|parser, name, _| {
+                    let (mut start, mut size, mut fill) = (None, None, None);
+                    parser.attributes(|parser, attribute| {
+                        let position = parser.position();
+                        let value = parser.number()?;
+                        let (field, max) = match attribute {
+                            "start" => (&mut start, 0xFFFF),
+                            "size" => (&mut size, 0x10000),
+                            "fill" => (&mut fill, 0xFF),
+                            _ => {
+                                return Err(parser.error(format!("unknown attribute `{attribute}`")))
+                            }
+                        };
+                        if value > max {
+                            return Err(AssemblerError::new(
+                                AssemblerErrorKind::OutOfRange(value),
+                                position,
+                            ));
+                        }
+                        *field = Some(value);
+                        Ok(())
+                    })?;
+
+                    let (Some(start), Some(size)) = (start, size) else {
+                        return Err(
+                            parser.error(format!("memory area {name} needs a start and a size"))
+                        );
+                    };
+                    if start + size > 0x10000 {
+                        return Err(parser.error(format!("memory area {name} ends after $FFFF")));
+                    }
+                    config.memory.push(MemoryArea {
+                        name: name.to_string(),
+                        start: start as u16,
+                        size: size as usize,
+                        fill: fill.map(|fill| fill as u8),
+                    });
+                    Ok(())
+                })?,
+                "SEGMENTS" => parser.entries(|parser, name, position| {
+                    let mut placement = SegmentPlacement {
+                        name: name.to_string(),
+                        load: String::new(),
+                        start: None,
+                        kind: SegmentKind::Code,
+                    };
+                    parser.attributes(|parser, attribute| {
+                        match attribute {
+                            "load" => placement.load = parser.word()?.to_string(),
+                            "start" => {
+                                let position = parser.position();
+                                let start = parser.number()?;
+                                placement.start = Some(u16::try_from(start).map_err(|_| {
+                                    AssemblerError::new(
+                                        AssemblerErrorKind::OutOfRange(start),
+                                        position,
+                                    )
+                                })?);
+                            }
+                            "type" => {
+                                placement.kind = match parser.word()? {
+                                    "ro" | "rw" => SegmentKind::Code,
+                                    "bss" => SegmentKind::Bss,
+                                    "zp" => SegmentKind::ZeroPage,
+                                    kind => {
+                                        return Err(
+                                            parser.error(format!("unknown segment type `{kind}`"))
+                                        )
+                                    }
+                                }
+                            }
+                            _ => {
+                                return Err(parser.error(format!("unknown attribute `{attribute}`")))
+                            }
+                        }
+                        Ok(())
+                    })?;
+
+                    if placement.load.is_empty() {
+                        return Err(
+                            parser.error(format!("segment {name} needs a memory area (`load`)"))
+                        );
+                    }
+                    loads.push((placement.load.clone(), position));
+                    config.segments.push(placement);
+                    Ok(())
+                })?,
+                section => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::InvalidConfig(format!("unknown section `{section}`")),
+                        position,
+                    ))
+                }
+            }
+        }
+
+        for (load, position) in loads {
+            if !config.memory.iter().any(|area| area.name == load) {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::InvalidConfig(format!("unknown memory area `{load}`")),
+                    position,
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl SegmentKind {
+    /// Whether the segment only reserves bytes, nothing of it is written to the output.
+    pub fn is_reserved(&self) -> bool {
+        matches!(self, SegmentKind::Bss | SegmentKind::ZeroPage)
+    }
+}
+
+impl LinkerConfig {
+    pub fn placement(&self, segment: &str) -> Option<&SegmentPlacement> {
+        self.segments
+            .iter()
+            .find(|placement| placement.name == segment)
+    }
+
+    /// Places the segments (name and size, the parts of a segment in order), returns their start addresses.
+    /// the segments are placed in the order of the config, segments that are not in the config at `0`.
+    pub(crate) fn place(&self, segments: &[(&str, usize)]) -> AssemblerResult<Vec<usize>> {
+        let mut starts = vec![0; segments.len()];
+        for area in &self.memory {
+            let end = area.start as usize + area.size;
+            let mut pointer = area.start as usize;
+            for placement in self.segments.iter().filter(|s| s.load == area.name) {
+                if let Some(start) = placement.start {
+                    if (start as usize) < pointer || start as usize > end {
+                        return Err(link_error(AssemblerErrorKind::SegmentOverlap {
+                            segment: placement.name.clone(),
+                            address: start,
+                        }));
+                    }
+                    pointer = start as usize;
+                }
+
+                for (i, (name, size)) in segments.iter().enumerate() {
+                    if *name == placement.name {
+                        starts[i] = pointer;
+                        pointer += size;
+                    }
+                }
+
+                if pointer > end {
+                    return Err(link_error(AssemblerErrorKind::SegmentOverflow {
+                        segment: placement.name.clone(),
+                        memory: area.name.clone(),
+                        bytes: pointer - end,
+                    }));
+                }
+            }
+        }
+
+        Ok(starts)
+    }
+
+    /// The chunks with every memory area that has a `fill` value as a single chunk,
+    /// its unused bytes are the fill value. sorted by origin.
+    pub(crate) fn fill(&self, chunks: Vec<Chunk>) -> Vec<Chunk> {
+        let mut filled = self
+            .memory
+            .iter()
+            .filter_map(|area| {
+                area.fill.map(|fill| Chunk {
+                    origin: area.start,
+                    bytes: vec![fill; area.size],
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for chunk in chunks {
+            let start = chunk.origin as usize;
+            let area = filled.iter_mut().find(|area| {
+                let origin = area.origin as usize;
+                (origin..=origin + area.bytes.len()).contains(&start)
+                    && start + chunk.bytes.len() <= origin + area.bytes.len()
+            });
+            match area {
+                Some(area) => {
+                    let offset = start - area.origin as usize;
+                    area.bytes[offset..offset + chunk.bytes.len()].copy_from_slice(&chunk.bytes);
+                }
+                None => filled.push(chunk),
+            }
+        }
+        filled.sort_by_key(|chunk| chunk.origin);
+
+        filled
+    }
+}
+
+/// Segments of a source assembled to its addresses, see [`crate::Assembler::set_layout`]
+pub(crate) struct Layout {
+    pub config: LinkerConfig,
+    /// start address of every segment of the config, placed again after every pass
+    starts: HashMap<String, usize>,
+    /// segments of the source in order of their first use: name, size and position of the first `.segment`
+    segments: Vec<(String, usize, Position)>,
+    /// index of the current segment in `segments`
+    current: usize,
+}
+
+impl Layout {
+    pub fn new(config: LinkerConfig) -> Self {
+        let empty = config
+            .segments
+            .iter()
+            .map(|placement| (placement.name.as_str(), 0))
+            .collect::<Vec<_>>();
+        // a config with overlapping segments is reported by `place`
+        let starts = config.place(&empty).unwrap_or_default();
+        let starts = empty
+            .iter()
+            .zip(starts)
+            .map(|((name, _), start)| (name.to_string(), start))
+            .collect();
+
+        Self {
+            config,
+            starts,
+            segments: Vec::new(),
+            current: 0,
+        }
+    }
+
+    fn start(&self, segment: &str) -> usize {
+        self.starts.get(segment).copied().unwrap_or_default()
+    }
+
+    /// Starts a pass in [`DEFAULT_SEGMENT`], returns its start address.
+    pub fn reset(&mut self) -> usize {
+        self.segments = vec![(DEFAULT_SEGMENT.to_string(), 0, Position::default())];
+        self.current = 0;
+        self.start(DEFAULT_SEGMENT)
+    }
+
+    /// Switches to the segment `name`, `pointer` is the address in the current segment.
+    /// returns the address in the segment.
+    pub fn switch(
+        &mut self,
+        name: &str,
+        position: Position,
+        pointer: usize,
+    ) -> AssemblerResult<usize> {
+        if self.config.placement(name).is_none() {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::UnplacedSegment(name.to_string()),
+                position,
+            ));
+        }
+
+        self.end(pointer);
+        self.current = match self.segments.iter().position(|(n, ..)| n == name) {
+            Some(i) => {
+                // the implicit segment without statements
+                if self.segments[i].2 == Position::default() {
+                    self.segments[i].2 = position;
+                }
+                i
+            }
+            None => {
+                self.segments.push((name.to_string(), 0, position));
+                self.segments.len() - 1
+            }
+        };
+        let (name, size, _) = &self.segments[self.current];
+
+        Ok(self.start(name) + size)
+    }
+
+    /// Records the position of a statement of the current segment, the errors of
+    /// [`DEFAULT_SEGMENT`] before its first `.segment` are reported at its first statement.
+    pub fn statement(&mut self, position: Position) {
+        let first = &mut self.segments[self.current].2;
+        if *first == Position::default() {
+            *first = position;
+        }
+    }
+
+    /// Sets the size of the current segment, `pointer` is the address after its last byte.
+    pub fn end(&mut self, pointer: usize) {
+        let start = self.start(&self.segments[self.current].0);
+        self.segments[self.current].1 = pointer - start;
+    }
+
+    /// Whether the current segment only reserves bytes, see [`SegmentKind::is_reserved`]
+    pub fn is_reserved(&self) -> Option<&str> {
+        let name = &self.segments[self.current].0;
+        self.config
+            .placement(name)
+            .is_some_and(|placement| placement.kind.is_reserved())
+            .then_some(name.as_str())
+    }
+
+    /// Places the segments with the sizes of the last pass, returns whether a start address changed.
+    /// the errors are reported at the first `.segment` of the segment, see [`Layout::statement`]
+    pub fn place(&mut self) -> AssemblerResult<bool> {
+        let sizes = self
+            .segments
+            .iter()
+            .map(|(name, size, _)| (name.as_str(), *size))
+            .collect::<Vec<_>>();
+        let starts = self.config.place(&sizes).map_err(|mut error| {
+            if let AssemblerErrorKind::SegmentOverlap { segment, .. }
+            | AssemblerErrorKind::SegmentOverflow { segment, .. } = &error.kind
+            {
+                if let Some((.., position)) = self.segments.iter().find(|(n, ..)| n == segment) {
+                    *error.span = (*position).into();
+                }
+            }
+            error
+        })?;
+
+        let mut changed = false;
+        for ((name, ..), start) in self.segments.iter().zip(starts) {
+            changed |= self.starts.insert(name.clone(), start) != Some(start);
+        }
+
+        Ok(changed)
+    }
+}
+
+/// A segment of an object placed by the linker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedSegment {
+    pub name: String,
+    /// see [`Object::name`]
+    pub object: String,
+    pub memory: String,
+    pub start: u16,
+    pub size: usize,
+}
+
+/// Linked objects, see [`link`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Linked {
+    pub chunks: Vec<Chunk>,
+    /// see [`LinkerConfig::memory`]
+    pub memory: Vec<MemoryArea>,
+    /// every segment of every object, by address
+    pub segments: Vec<PlacedSegment>,
+    /// exported symbols and their values, sorted by name
+    pub symbols: Vec<(String, i32)>,
+}
+
+fn link_error(kind: AssemblerErrorKind) -> AssemblerError {
+    AssemblerError::new(kind, Position::default())
+}
+
+/// Links the objects into the chunks of a program, placed as configured.
+///
+/// the segments are placed in the order of the config, the parts of a segment in the order of the objects.
+/// every import has to be exported by one of the objects.
+pub fn link(objects: &[Object], config: &LinkerConfig) -> AssemblerResult<Linked> {
+    for object in objects {
+        for segment in &object.segments {
+            if !config.segments.iter().any(|s| s.name == segment.name) {
+                return Err(link_error(AssemblerErrorKind::UnknownSegment {
+                    segment: segment.name.clone(),
+                    object: object.name.clone(),
+                }));
+            }
+        }
+    }
+
+    let pieces = objects
+        .iter()
+        .enumerate()
+        .flat_map(|(i, object)| object.segments.iter().map(move |segment| (i, segment)))
+        .collect::<Vec<_>>();
+    let starts = config.place(
+        &pieces
+            .iter()
+            .map(|(_, segment)| (segment.name.as_str(), segment.size))
+            .collect::<Vec<_>>(),
+    )?;
+    // start address of every segment, by object index and name
+    let bases = pieces
+        .iter()
+        .zip(&starts)
+        .map(|((i, segment), start)| ((*i, segment.name.as_str()), *start as i32))
+        .collect::<HashMap<_, _>>();
+
+    let mut symbols = HashMap::new();
+    for (i, object) in objects.iter().enumerate() {
+        for export in &object.exports {
+            let base = match &export.segment {
+                Some(segment) => *bases.get(&(i, segment.as_str())).ok_or_else(|| {
+                    link_error(AssemblerErrorKind::UnknownSegment {
+                        segment: segment.clone(),
+                        object: object.name.clone(),
+                    })
+                })?,
+                None => 0,
+            };
+            if symbols
+                .insert(export.name.clone(), base + export.value)
+                .is_some()
+            {
+                return Err(link_error(AssemblerErrorKind::DuplicateExport(
+                    export.name.clone(),
+                )));
+            }
+        }
+    }
+    for object in objects {
+        for import in &object.imports {
+            if !symbols.contains_key(import) {
+                return Err(link_error(AssemblerErrorKind::UnresolvedImport {
+                    name: import.clone(),
+                    object: object.name.clone(),
+                }));
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let mut segments = Vec::new();
+    for ((i, segment), start) in pieces.into_iter().zip(starts) {
+        let object = &objects[i];
+        let placement = config.placement(&segment.name).unwrap();
+        if placement.kind.is_reserved() && !segment.bytes.is_empty() {
+            return Err(link_error(AssemblerErrorKind::BssData(
+                segment.name.clone(),
+            )));
+        }
+
+        let mut bytes = segment.bytes.clone();
+        for relocation in &segment.relocations {
+            let target = match &relocation.target {
+                RelocationTarget::Segment(name) => bases.get(&(i, name.as_str())),
+                RelocationTarget::Import(name) => symbols.get(name),
+            };
+            let Some(target) = target else {
+                return Err(link_error(AssemblerErrorKind::UnresolvedImport {
+                    name: relocation.target.to_string(),
+                    object: object.name.clone(),
+                }));
+            };
+
+            let value = target + relocation.addend;
+            let offset = relocation.offset;
+            let out_of_range = || {
+                link_error(AssemblerErrorKind::RelocationOutOfRange {
+                    target: relocation.target.to_string(),
+                    value,
+                })
+            };
+            match relocation.kind {
+                RelocationKind::Word => {
+                    let value = u16::try_from(value).map_err(|_| out_of_range())?;
+                    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+                }
+                RelocationKind::Byte => {
+                    bytes[offset] = u8::try_from(value).map_err(|_| out_of_range())?
+                }
+                RelocationKind::Low => bytes[offset] = value as u8,
+                RelocationKind::High => bytes[offset] = (value >> 8) as u8,
+            }
+        }
+
+        if !bytes.is_empty() {
+            chunks.push(Chunk {
+                origin: start as u16,
+                bytes,
+            });
+        }
+        segments.push(PlacedSegment {
+            name: segment.name.clone(),
+            object: object.name.clone(),
+            memory: placement.load.clone(),
+            start: start as u16,
+            size: segment.size,
+        });
+    }
+    segments.sort_by_key(|segment| segment.start);
+
+    let mut symbols = symbols.into_iter().collect::<Vec<_>>();
+    symbols.sort();
+
+    Ok(Linked {
+        chunks: config.fill(chunks),
+        memory: config.memory.clone(),
+        segments,
+        symbols,
+    })
+}
+
+impl Linked {
+    /// Value of an exported symbol.
+    pub fn symbol(&self, name: &str) -> Option<i32> {
+        self.symbols
+            .iter()
+            .find(|(symbol, _)| symbol == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// The map file: the memory areas and how much of them is used, where every segment is placed,
+    /// and the exported symbols.
+    pub fn map(&self) -> String {
+        let mut output = String::from("Memory areas:\n");
+        writeln!(
+            output,
+            "{:<12} {:<6} {:<6} {:<6} Used",
+            "Name", "Start", "End", "Size"
+        )
+        .unwrap();
+        for area in &self.memory {
+            let used = self
+                .segments
+                .iter()
+                .filter(|segment| segment.memory == area.name)
+                .map(|segment| segment.size)
+                .sum::<usize>();
+            writeln!(
+                output,
+                "{:<12} ${:04X}  ${:04X}  ${:04X}  ${used:04X}",
+                area.name,
+                area.start,
+                (area.start as usize + area.size).saturating_sub(1),
+                area.size
+            )
+            .unwrap();
+        }
+
+        output.push_str("\nSegments:\n");
+        writeln!(
+            output,
+            "{:<12} {:<16} {:<12} {:<6} {:<6} Size",
+            "Name", "Object", "Memory", "Start", "End"
+        )
+        .unwrap();
+        for segment in self.segments.iter().filter(|segment| segment.size > 0) {
+            writeln!(
+                output,
+                "{:<12} {:<16} {:<12} ${:04X}  ${:04X}  ${:04X}",
+                segment.name,
+                segment.object,
+                segment.memory,
+                segment.start,
+                segment.start as usize + segment.size - 1,
+                segment.size
+            )
+            .unwrap();
+        }
+
+        output.push_str("\nExports:\n");
+        for (name, value) in &self.symbols {
+            writeln!(output, "{name:<28} ${value:04X}").unwrap();
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObjectSegment, ObjectSymbol};
+
+    const CONFIG: &str = "MEMORY {
+    ZP:  start = 0, size = $100;
+    RAM: start = $0200, size = $10;
+    ROM: start = $F000, size = 8, fill = $FF;
+}
+SEGMENTS {
+    ZEROPAGE: load = ZP, type = zp;
+    BSS:      load = RAM, type = bss;
+    CODE:     load = ROM;
+    VECTORS:  load = ROM, start = $F006;
+}";
+
+    fn config() -> LinkerConfig {
+        LinkerConfig::parse(CONFIG).unwrap()
+    }
+
+    /// An object with one segment of `bytes`, `size` reserves the bytes after them.
+    fn object(name: &str, segment: &str, bytes: &[u8], size: usize) -> Object {
+        let mut object_segment = ObjectSegment::new(segment, false);
+        object_segment.bytes = bytes.to_vec();
+        object_segment.size = size;
+        Object {
+            name: name.to_string(),
+            segments: vec![object_segment],
+            ..Default::default()
+        }
+    }
+
+    fn error(text: &str) -> (String, usize) {
+        let error = LinkerConfig::parse(text).unwrap_err();
+        (error.kind.to_string(), error.span.start.line)
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = config();
+        assert_eq!(
+            config.memory[2],
+            MemoryArea {
+                name: "ROM".to_string(),
+                start: 0xF000,
+                size: 8,
+                fill: Some(0xFF),
+            }
+        );
+        assert_eq!(config.segments[1].kind, SegmentKind::Bss);
+        assert_eq!(config.segments[3].start, Some(0xF006));
+        assert!(config.segments[0].kind.is_reserved());
+        assert!(!config.segments[2].kind.is_reserved());
+
+        // the number literals of the sources, comments
+        let config = LinkerConfig::parse(
+            "MEMORY { A: start = 0x10, size = %1000_0000; } # comment\nSEGMENTS { S: load = A, type = ro; }",
+        )
+        .unwrap();
+        assert_eq!(
+            (config.memory[0].start, config.memory[0].size),
+            (0x10, 0x80)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("FILES {\n}", "unknown section `FILES`", 1),
+            ("MEMORY {\n ROM start = 0;\n}", "expected `:`", 2),
+            ("MEMORY {\n ROM: start = 0, size = 1\n}", "expected `;`", 3),
+            ("MEMORY {\n ROM: start = $80zz;\n}", "invalid number `$80zz`", 2),
+            ("MEMORY {\n ROM: begin = 0;\n}", "unknown attribute `begin`", 2),
+            ("MEMORY {\n ROM: start = $F000, size = $2000;\n}", "memory area ROM ends after $FFFF", 2),
+            ("MEMORY {\n ROM: start = 0, size = 1;\n}\nSEGMENTS {\n CODE: start = 0;\n}", "segment CODE needs a memory area (`load`)", 5),
+            ("MEMORY {\n ROM: start = 0, size = 1;\n}\nSEGMENTS {\n CODE: load = ROM, type = text;\n}", "unknown segment type `text`", 5),
+            ("SEGMENTS {\n CODE: load = ROM;\n}", "unknown memory area `ROM`", 2),
+            ("MEMORY {\n ROM: start = 0, size = 1;\n", "expected a name", 3),
+        ];
+        for (text, message, line) in cases {
+            assert_eq!(
+                error(text),
+                (format!("Invalid linker config: {message}"), line),
+                "{text}"
+            );
+        }
+        assert_eq!(
+            LinkerConfig::parse("MEMORY {\n ROM: start = $10000, size = 1;\n}")
+                .unwrap_err()
+                .kind,
+            AssemblerErrorKind::OutOfRange(0x10000)
+        );
+    }
+
+    #[test]
+    fn test_place() {
+        let config = config();
+        // the parts of a segment in order, then the next segment of the area
+        assert_eq!(
+            config
+                .place(&[
+                    ("CODE", 2),
+                    ("BSS", 4),
+                    ("CODE", 3),
+                    ("VECTORS", 2),
+                    ("LOST", 1)
+                ])
+                .unwrap(),
+            vec![0xF000, 0x0200, 0xF002, 0xF006, 0]
+        );
+        assert_eq!(
+            config.place(&[("CODE", 7)]).unwrap_err().kind,
+            AssemblerErrorKind::SegmentOverlap {
+                segment: "VECTORS".to_string(),
+                address: 0xF006,
+            }
+        );
+        assert_eq!(
+            config.place(&[("VECTORS", 3)]).unwrap_err().kind,
+            AssemblerErrorKind::SegmentOverflow {
+                segment: "VECTORS".to_string(),
+                memory: "ROM".to_string(),
+                bytes: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_fill() {
+        let chunk = |origin, bytes: &[u8]| Chunk {
+            origin,
+            bytes: bytes.to_vec(),
+        };
+        // chunks in an area with a fill value are merged into it, the others are kept
+        assert_eq!(
+            config().fill(vec![chunk(0xF001, &[0xEA]), chunk(0x0300, &[0x01])]),
+            vec![
+                chunk(0x0300, &[0x01]),
+                chunk(0xF000, &[0xFF, 0xEA, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let error = |objects: &[Object]| link(objects, &config()).unwrap_err().kind;
+
+        assert_eq!(
+            error(&[object("a.o", "DATA", &[], 0)]),
+            AssemblerErrorKind::UnknownSegment {
+                segment: "DATA".to_string(),
+                object: "a.o".to_string(),
+            }
+        );
+        // the layout overflows
+        assert_eq!(
+            error(&[
+                object("a.o", "CODE", &[0xEA; 4], 4),
+                object("b.o", "CODE", &[0xEA; 3], 3)
+            ]),
+            AssemblerErrorKind::SegmentOverlap {
+                segment: "VECTORS".to_string(),
+                address: 0xF006,
+            }
+        );
+        assert_eq!(
+            error(&[object("a.o", "BSS", &[], 17)]),
+            AssemblerErrorKind::SegmentOverflow {
+                segment: "BSS".to_string(),
+                memory: "RAM".to_string(),
+                bytes: 1,
+            }
+        );
+        // bss and zero page segments can only reserve bytes
+        assert_eq!(
+            error(&[object("a.o", "BSS", &[0x01], 1)]),
+            AssemblerErrorKind::BssData("BSS".to_string())
+        );
+        assert_eq!(
+            error(&[object("a.o", "ZEROPAGE", &[0x01], 2)]),
+            AssemblerErrorKind::BssData("ZEROPAGE".to_string())
+        );
+
+        let export = |name: &str| ObjectSymbol {
+            name: name.to_string(),
+            value: 0,
+            segment: None,
+        };
+        let mut a = object("a.o", "CODE", &[], 0);
+        a.exports = vec![export("main")];
+        let mut b = a.clone();
+        b.name = "b.o".to_string();
+        assert_eq!(
+            error(&[a.clone(), b]),
+            AssemblerErrorKind::DuplicateExport("main".to_string())
+        );
+        a.exports[0].segment = Some("DATA".to_string());
+        assert_eq!(
+            error(&[a]),
+            AssemblerErrorKind::UnknownSegment {
+                segment: "DATA".to_string(),
+                object: "a.o".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_map() {
+        let mut code = object("main.o", "CODE", &[0xEA, 0x60], 2);
+        code.exports = vec![ObjectSymbol {
+            name: "main".to_string(),
+            value: 1,
+            segment: Some("CODE".to_string()),
+        }];
+        let linked = link(&[code, object("main.o", "BSS", &[], 4)], &config()).unwrap();
+        assert_eq!(linked.symbol("main"), Some(0xF001));
+        assert_eq!(linked.symbol("start"), None);
+        assert_eq!(
+            linked.map(),
+            "Memory areas:
+Name         Start  End    Size   Used
+ZP           $0000  $00FF  $0100  $0000
+RAM          $0200  $020F  $0010  $0004
+ROM          $F000  $F007  $0008  $0002
+
+Segments:
+Name         Object           Memory       Start  End    Size
+BSS          main.o           RAM          $0200  $0203  $0004
+CODE         main.o           ROM          $F000  $F001  $0002
+
+Exports:
+main                         $F001
+"
+        );
+    }
+}
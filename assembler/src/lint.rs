@@ -0,0 +1,296 @@
+use crate::{
+    byte_to_opcode, AddressingMode, Assembler, AssemblerError, AssemblerErrorKind,
+    AssemblerWarning, AssemblerWarningKind, Mnemonics, Opcode, Position, SourceFile, SymbolKind,
+};
+use std::collections::HashSet;
+
+/// A group of warnings, reported as set with [`Assembler::set_lint`].
+///
+/// the warnings of a line are suppressed with a `; nowarn` comment,
+/// or only the ones of the named lints with `; nowarn unused, zero-page`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// labels and constants that are never referenced
+    Unused,
+    /// absolute addressing of an address in the zero page, unless forced with `a:`
+    ZeroPage,
+    /// `JMP ($xxFF)`, the NMOS 6502 reads the high byte of the pointer from `$xx00`
+    JmpIndirect,
+    /// instructions after `JMP`, `BRA`, `RTS`, `RTI`, `BRK`, `STP` or `JAM` that no label refers to
+    Unreachable,
+    /// branches to the branch instruction itself
+    BranchToSelf,
+    /// writes into the assembled instructions
+    SelfModifying,
+    /// deprecated syntax, like `define`
+    Deprecated,
+}
+
+/// How the warnings of a lint are reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    #[default]
+    Warn,
+    /// the warnings are errors, see [`AssemblerErrorKind::DeniedWarning`]
+    Deny,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 7] = [
+        Lint::Unused,
+        Lint::ZeroPage,
+        Lint::JmpIndirect,
+        Lint::Unreachable,
+        Lint::BranchToSelf,
+        Lint::SelfModifying,
+        Lint::Deprecated,
+    ];
+
+    /// Name of the lint in `; nowarn` comments and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::Unused => "unused",
+            Lint::ZeroPage => "zero-page",
+            Lint::JmpIndirect => "jmp-indirect",
+            Lint::Unreachable => "unreachable",
+            Lint::BranchToSelf => "branch-to-self",
+            Lint::SelfModifying => "self-modifying",
+            Lint::Deprecated => "deprecated",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+impl AssemblerWarningKind {
+    pub fn lint(&self) -> Lint {
+        match self {
+            AssemblerWarningKind::DeprecatedDefine(_)
+            | AssemblerWarningKind::DeprecatedDefineOperand(_) => Lint::Deprecated,
+            AssemblerWarningKind::UnusedLabel(_) | AssemblerWarningKind::UnusedConstant(_) => {
+                Lint::Unused
+            }
+            AssemblerWarningKind::ZeroPage(_) => Lint::ZeroPage,
+            AssemblerWarningKind::JmpIndirectPageWrap(_) => Lint::JmpIndirect,
+            AssemblerWarningKind::Unreachable => Lint::Unreachable,
+            AssemblerWarningKind::BranchToSelf => Lint::BranchToSelf,
+            AssemblerWarningKind::SelfModifying(_) => Lint::SelfModifying,
+        }
+    }
+}
+
+impl Assembler<'_> {
+    /// Checks the assembled source (its listing and symbols) for the warnings of the lints,
+    /// deprecated syntax is reported by the parser and absolute zero page addresses while assembling.
+    pub(crate) fn lint(&mut self) {
+        use AddressingMode::*;
+        use Mnemonics::*;
+
+        let used = self.used.borrow();
+        for symbol in &self.listing.symbols {
+            if !used.contains(&symbol.name) {
+                let kind = match symbol.kind {
+                    SymbolKind::Label => AssemblerWarningKind::UnusedLabel(symbol.name.clone()),
+                    SymbolKind::Constant => {
+                        AssemblerWarningKind::UnusedConstant(symbol.name.clone())
+                    }
+                };
+                self.warnings
+                    .push(AssemblerWarning::new(kind, symbol.position));
+            }
+        }
+
+        // the addresses of an object are offsets in its segments, they overlap
+        if self.object.is_some() {
+            return;
+        }
+
+        let labels = self.labels.values().copied().collect::<HashSet<_>>();
+        let code = self
+            .listing
+            .entries
+            .iter()
+            .filter(|entry| entry.cycles.is_some())
+            .map(|entry| entry.address as usize..entry.address as usize + entry.bytes.len())
+            .collect::<Vec<_>>();
+
+        // code is reachable after a label or a gap (`.org`, `.res`)
+        let mut reachable = true;
+        let mut next = None;
+        for entry in &self.listing.entries {
+            if next != Some(entry.address as usize) || labels.contains(&entry.address) {
+                reachable = true;
+            }
+            next = Some(entry.address as usize + entry.bytes.len());
+
+            let Some(&Opcode { mnemonic, mode, .. }) = entry
+                .cycles
+                .and_then(|_| byte_to_opcode(*entry.bytes.first()?, entry.cpu).ok())
+            else {
+                continue;
+            };
+            let operand = match entry.bytes[1..] {
+                [low] => low as u16,
+                [low, high] => u16::from_le_bytes([low, high]),
+                _ => 0,
+            };
+            let mut warn = |kind| {
+                self.warnings
+                    .push(AssemblerWarning::new(kind, entry.position))
+            };
+
+            if !reachable {
+                warn(AssemblerWarningKind::Unreachable);
+                reachable = true;
+            }
+
+            if mnemonic == JMP && mode == IND && operand & 0xFF == 0xFF {
+                warn(AssemblerWarningKind::JmpIndirectPageWrap(operand));
+            }
+            if mnemonic.is_branch() && entry.bytes[1] == 0xFE {
+                warn(AssemblerWarningKind::BranchToSelf);
+            }
+            if mnemonic.writes(mode)
+                && matches!(mode, ZPG | ZPX | ZPY | ABS | ABX | ABY)
+                && code.iter().any(|range| range.contains(&(operand as usize)))
+            {
+                warn(AssemblerWarningKind::SelfModifying(operand));
+            }
+
+            if matches!(mnemonic, JMP | BRA | RTS | RTI | BRK | STP | JAM) {
+                reachable = false;
+            }
+        }
+    }
+
+    /// Drops the warnings of allowed lints and suppressed lines, the warnings of denied lints become errors.
+    pub(crate) fn apply_lints(&mut self) {
+        for warning in std::mem::take(&mut self.warnings) {
+            let lint = warning.kind.lint();
+            if suppressed(&self.files, warning.position, lint) {
+                continue;
+            }
+
+            match self.lints.get(&lint).copied().unwrap_or_default() {
+                LintLevel::Allow => {}
+                LintLevel::Warn => self.warnings.push(warning),
+                LintLevel::Deny => self.errors.push(AssemblerError::new(
+                    AssemblerErrorKind::DeniedWarning(warning.kind),
+                    warning.position,
+                )),
+            }
+        }
+
+        self.warnings.sort_by_key(|warning| {
+            let position = warning.position;
+            (position.file, position.line, position.column)
+        });
+    }
+}
+
+/// Whether the line at `position` has a `; nowarn` comment for `lint`.
+fn suppressed(files: &[SourceFile], position: Position, lint: Lint) -> bool {
+    let Some(line) = files
+        .get(position.file)
+        .and_then(|file| file.source.lines().nth(position.line.wrapping_sub(1)))
+    else {
+        return false;
+    };
+
+    // `;` in strings and character literals, the escapes are not skipped
+    let mut quote = None;
+    let Some(start) = line.find(|c| {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+        c == ';' && quote.is_none()
+    }) else {
+        return false;
+    };
+
+    let mut words = line[start + 1..]
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty());
+    if !words.any(|word| word == "nowarn") {
+        return false;
+    }
+
+    // without lint names every warning of the line is suppressed
+    let mut lints = words.map_while(Lint::from_name).peekable();
+    lints.peek().is_none() || lints.any(|l| l == lint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Warnings of the source, with their lines.
+    fn warnings(source: &str) -> Vec<(AssemblerWarningKind, usize)> {
+        let mut assembler = Assembler::new(source);
+        assembler.assemble().unwrap();
+        assembler
+            .warnings
+            .iter()
+            .map(|warning| (warning.kind.clone(), warning.position.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_names() {
+        for lint in Lint::ALL {
+            assert_eq!(Lint::from_name(lint.name()), Some(lint));
+        }
+        assert_eq!(Lint::from_name("unused-label"), None);
+    }
+
+    #[test]
+    fn test_suppressed() {
+        let files = [SourceFile {
+            name: String::new(),
+            source: "NOP ; nowarn\nLDA #';' ; nowarn unreachable\nNOP ; nowarn unused, zero-page\nNOP ; no warnings\n.byte \"; nowarn\"\nNOP ; nowarn this line\n".to_string(),
+        }];
+        let suppressed = |line, lint| suppressed(&files, Position::new(line, 1), lint);
+
+        assert!(suppressed(1, Lint::Unused));
+        // `;` in a character literal does not start the comment
+        assert!(suppressed(2, Lint::Unreachable));
+        assert!(!suppressed(2, Lint::Unused));
+        assert!(suppressed(3, Lint::ZeroPage));
+        assert!(!suppressed(3, Lint::Unreachable));
+        assert!(!suppressed(4, Lint::Unused));
+        assert!(!suppressed(5, Lint::Unused));
+        // words that are not lints end the list
+        assert!(suppressed(6, Lint::Unused));
+        // lines that are not in the file
+        assert!(!suppressed(7, Lint::Unused));
+        assert!(!suppressed(0, Lint::Unused));
+    }
+
+    #[test]
+    fn test_lint() {
+        // code after a gap is reachable
+        let s = "start: JMP start\n.res 2\nNOP\nRTS\nNOP";
+        assert_eq!(warnings(s), vec![(AssemblerWarningKind::Unreachable, 5)]);
+
+        // writes into the code, with an index
+        let s = ".org $10\nstart: LDX #0\nSTA start+1,X\nJMP start";
+        assert_eq!(
+            warnings(s),
+            vec![(AssemblerWarningKind::SelfModifying(0x11), 3)]
+        );
+        // reads of the code are not
+        assert_eq!(warnings("start: LDA start\nJMP start"), vec![]);
+
+        // the unused labels of scopes are qualified
+        let s = ".scope s\nlocal: NOP\n.endscope\nJMP main\nmain: RTS";
+        assert_eq!(
+            warnings(s),
+            vec![(AssemblerWarningKind::UnusedLabel("s::local".to_string()), 2)]
+        );
+    }
+}
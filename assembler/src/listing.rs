@@ -0,0 +1,281 @@
+use crate::{CpuType, Expansion, Position, SourceFile, Symbol};
+use std::{collections::HashMap, fmt};
+
+/// Bytes an instruction or a data directive was assembled to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingEntry {
+    pub position: Position,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// cycles of an instruction, see [`crate::Opcode::cycles`] and [`crate::Opcode::page_cross`]
+    pub cycles: Option<(u8, bool)>,
+    /// CPU of an instruction, its opcode is decoded for it (see [`crate::byte_to_opcode`])
+    pub cpu: CpuType,
+}
+
+/// Listing of an assembled source, see [`crate::Assembler::listing`]
+///
+/// displayed as one row per source line: line number, address, bytes, cycles and the text of the line.
+/// lines expanded from a macro follow its invocation and are marked with `+`,
+/// included files follow their `.include` directive. the symbol table is at the end.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Listing {
+    pub files: Vec<SourceFile>,
+    /// see [`crate::Parser::included_at`]
+    pub included_at: HashMap<usize, Position>,
+    /// see [`crate::Parser::expansions`]
+    pub expansions: Vec<Expansion>,
+    pub entries: Vec<ListingEntry>,
+    /// labels and constants, sorted by name
+    pub symbols: Vec<Symbol>,
+}
+
+/// Bytes displayed in a row, longer data continues in the next rows.
+const ROW_BYTES: usize = 4;
+
+impl Listing {
+    /// Position in the source the statement at `position` was written at,
+    /// the invocation of the outermost macro for expanded statements.
+    fn call_site(&self, mut position: Position) -> Position {
+        while let Some(expansion) = position
+            .expansion
+            .checked_sub(1)
+            .and_then(|i| self.expansions.get(i))
+        {
+            position = expansion.call_site;
+        }
+
+        position
+    }
+
+    /// Whether `file` is `descendant` or (transitively) includes it.
+    fn includes(&self, file: usize, mut descendant: usize) -> bool {
+        loop {
+            if descendant == file {
+                return true;
+            }
+            match self.included_at.get(&descendant) {
+                Some(position) => descendant = position.file,
+                None => return false,
+            }
+        }
+    }
+}
+
+struct Writer<'a, 'f, 'b> {
+    f: &'f mut fmt::Formatter<'b>,
+    listing: &'a Listing,
+    lines: Vec<Vec<&'a str>>,
+    /// number of lines printed so far, by file
+    printed: Vec<usize>,
+}
+
+impl<'a> Writer<'a, '_, '_> {
+    fn row(
+        &mut self,
+        line: usize,
+        marker: char,
+        entry: Option<&ListingEntry>,
+        text: &str,
+    ) -> fmt::Result {
+        let Some(entry) = entry else {
+            return writeln!(
+                self.f,
+                "{}",
+                format!("{line:>5}{marker} {:27}{text}", "").trim_end()
+            );
+        };
+
+        let cycles = match entry.cycles {
+            Some((cycles, true)) => format!("{cycles}*"),
+            Some((cycles, false)) => cycles.to_string(),
+            None => String::new(),
+        };
+        let mut rows = entry.bytes.chunks(ROW_BYTES);
+        let row = format!(
+            "{line:>5}{marker} {:04X}  {:<11}  {cycles:<6}  {text}",
+            entry.address,
+            hex(rows.next().unwrap_or_default()),
+        );
+        writeln!(self.f, "{}", row.trim_end())?;
+
+        for (i, bytes) in rows.enumerate() {
+            let address = entry.address as usize + (i + 1) * ROW_BYTES;
+            writeln!(self.f, "       {:04X}  {}", address as u16, hex(bytes))?;
+        }
+
+        Ok(())
+    }
+
+    fn text(&self, file: usize, line: usize) -> &'a str {
+        self.lines[file]
+            .get(line.wrapping_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Prints the lines of `file` up to `line` that are not printed yet.
+    fn flush(&mut self, file: usize, line: usize) -> fmt::Result {
+        let line = line.min(self.lines[file].len());
+        while self.printed[file] < line {
+            self.printed[file] += 1;
+            let text = self.text(file, self.printed[file]);
+            self.row(self.printed[file], ' ', None, text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the including files up to the `.include` directive of `file`.
+    fn enter(&mut self, file: usize) -> fmt::Result {
+        if let Some(position) = self.listing.included_at.get(&file).copied() {
+            self.enter(position.file)?;
+            self.flush(position.file, position.line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the rest of `file` and the files that included it, until `to`.
+    fn leave(&mut self, mut file: usize, to: usize) -> fmt::Result {
+        while !self.listing.includes(file, to) {
+            self.flush(file, usize::MAX)?;
+            file = match self.listing.included_at.get(&file) {
+                Some(position) => position.file,
+                None => return Ok(()),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut writer = Writer {
+            f,
+            listing: self,
+            lines: self
+                .files
+                .iter()
+                .map(|file| file.source.lines().collect())
+                .collect(),
+            printed: vec![0; self.files.len()],
+        };
+        writeln!(writer.f, "Line   Addr  Bytes        Cycles  Source")?;
+
+        let mut file = 0;
+        for entry in &self.entries {
+            let call_site = self.call_site(entry.position);
+            writer.leave(file, call_site.file)?;
+            writer.enter(call_site.file)?;
+            file = call_site.file;
+
+            let Position { line, .. } = entry.position;
+            if entry.position.expansion == 0 {
+                writer.flush(file, line - 1)?;
+                writer.printed[file] = writer.printed[file].max(line);
+                writer.row(line, ' ', Some(entry), writer.text(file, line))?;
+            } else {
+                writer.flush(file, call_site.line)?;
+                let text = writer.text(entry.position.file, line);
+                writer.row(line, '+', Some(entry), text)?;
+            }
+        }
+        writer.leave(file, 0)?;
+        writer.flush(0, usize::MAX)?;
+
+        let width = self.symbols.iter().map(|symbol| symbol.name.len()).max();
+        if let Some(width) = width {
+            writeln!(f, "\nSymbols:")?;
+            for Symbol { name, value, .. } in &self.symbols {
+                match value {
+                    0..=0xFFFF => writeln!(f, "{name:width$}  ${value:04X}")?,
+                    _ => writeln!(f, "{name:width$}  {value}")?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Assembler;
+    use std::collections::HashMap;
+
+    fn listing(source: &str, files: &[(&str, &str)]) -> String {
+        let resolver = files
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.as_bytes().to_vec()))
+            .collect::<HashMap<_, _>>();
+        let mut assembler = Assembler::new(source);
+        assembler.set_resolver(resolver).assemble().unwrap();
+        assembler.listing.to_string()
+    }
+
+    #[test]
+    fn test_listing_include() {
+        let source = "start:\n.include \"init.asm\"\n    JMP start\n";
+        assert_eq!(
+            listing(source, &[("init.asm", "; init\n    SEI\n    CLD\n")]),
+            r#"Line   Addr  Bytes        Cycles  Source
+    1                             start:
+    2                             .include "init.asm"
+    1                             ; init
+    2  8000  78           2           SEI
+    3  8001  D8           2           CLD
+    3  8002  4C 00 80     3           JMP start
+
+Symbols:
+start  $8000
+"#
+        );
+    }
+
+    #[test]
+    fn test_listing_nested_macros() {
+        // the lines of the inner macro follow the invocation of the outer one
+        let source = ".macro inner\n    INX\n.endm\n.macro outer\n    inner\n    DEY\n.endm\n    outer\n    RTS\n";
+        assert_eq!(
+            listing(source, &[]),
+            r#"Line   Addr  Bytes        Cycles  Source
+    1                             .macro inner
+    2                                 INX
+    3                             .endm
+    4                             .macro outer
+    5                                 inner
+    6                                 DEY
+    7                             .endm
+    8                                 outer
+    2+ 8000  E8           2           INX
+    6+ 8001  88           2           DEY
+    9  8002  60           6           RTS
+"#
+        );
+    }
+
+    #[test]
+    fn test_listing_symbols() {
+        // values that are not addresses are printed in decimal
+        let source = "NEG = -1\nBIG = 300 * 300\nstart: RTS\n";
+        assert!(
+            listing(source, &[]).ends_with("\nSymbols:\nBIG    90000\nNEG    -1\nstart  $8000\n")
+        );
+
+        // a source without code lists its lines and no symbol table
+        assert_eq!(
+            listing("; comment\n\n", &[]),
+            "Line   Addr  Bytes        Cycles  Source\n    1                             ; comment\n    2\n"
+        );
+    }
+}
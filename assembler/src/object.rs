@@ -0,0 +1,615 @@
+//! Relocatable object files, see [`crate::Assembler::assemble_object`] and [`crate::link`]
+
+use crate::{AssemblerError, AssemblerErrorKind, AssemblerResult, Expression, Position};
+use crate::{BinaryOperator, UnaryOperator};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fmt::Write,
+};
+
+const HEADER: &str = "o6502 1";
+
+/// Bytes in a `bytes` record.
+const RECORD_BYTES: usize = 32;
+
+/// Segment of the statements before the first `.segment` directive.
+pub const DEFAULT_SEGMENT: &str = "CODE";
+
+/// An assembled source whose segments are not placed yet, see [`crate::link`]
+///
+/// an object is written as text, one record per line:
+///
+/// ```text
+/// o6502 1
+/// import putchar
+/// export main 0 CODE
+/// export SCREEN 1024
+/// segment CODE 8
+/// bytes A9412000004C0000
+/// reloc 3 word import putchar 0
+/// reloc 6 word segment CODE 0
+/// segment ZEROPAGE 2 zp
+/// ```
+///
+/// - `o6502 1`: the header and the version of the format
+/// - `import NAME`: a symbol defined by another object
+/// - `export NAME VALUE [SEGMENT]`: a symbol for the other objects, the value is an offset in
+///   the segment, or absolute without one
+/// - `segment NAME SIZE [zp]`: a segment, the following records belong to it. the bytes after
+///   the emitted ones are reserved (`.res`), `zp` segments are in the zero page
+/// - `bytes HEX`: bytes of the segment, appended to the previous ones
+/// - `reloc OFFSET KIND TARGET ADDEND`: the bytes at `OFFSET` are the address of `TARGET`
+///   (`segment NAME` or `import NAME`) plus `ADDEND`, see [`RelocationKind`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Object {
+    /// file the object was read from, for the errors and the map file of the linker
+    pub name: String,
+    pub segments: Vec<ObjectSegment>,
+    /// symbols defined by the object for the other objects (`.export`)
+    pub exports: Vec<ObjectSymbol>,
+    /// symbols the object uses that other objects define (`.import`)
+    pub imports: Vec<String>,
+}
+
+/// `.segment "NAME"`, the bytes of the statements in the segment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectSegment {
+    pub name: String,
+    /// bytes of the segment, the bytes after `bytes` are reserved (`.res`) and not emitted
+    pub size: usize,
+    pub bytes: Vec<u8>,
+    pub relocations: Vec<Relocation>,
+    /// labels in the segment are zero page addresses, see [`crate::Segment::zero_page`]
+    pub zero_page: bool,
+}
+
+impl ObjectSegment {
+    pub fn new(name: &str, zero_page: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            size: 0,
+            bytes: Vec::new(),
+            relocations: Vec::new(),
+            zero_page,
+        }
+    }
+}
+
+/// An exported symbol, `value` is an offset in `segment` or absolute without one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub value: i32,
+    pub segment: Option<String>,
+}
+
+/// Bytes of a segment that depend on where a segment is placed or on an imported symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relocation {
+    /// offset of the bytes in the segment
+    pub offset: usize,
+    pub kind: RelocationKind,
+    pub target: RelocationTarget,
+    pub addend: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocationKind {
+    /// an address, little endian
+    Word,
+    /// an address in the zero page
+    Byte,
+    /// `<symbol`, the low byte of an address
+    Low,
+    /// `>symbol`, the high byte of an address
+    High,
+}
+
+impl RelocationKind {
+    const ALL: [RelocationKind; 4] = [
+        RelocationKind::Word,
+        RelocationKind::Byte,
+        RelocationKind::Low,
+        RelocationKind::High,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            RelocationKind::Word => "word",
+            RelocationKind::Byte => "byte",
+            RelocationKind::Low => "low",
+            RelocationKind::High => "high",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelocationTarget {
+    /// the start of a segment of the same object
+    Segment(String),
+    /// a symbol exported by another object
+    Import(String),
+}
+
+impl fmt::Display for RelocationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelocationTarget::Segment(name) => write!(f, "segment {name}"),
+            RelocationTarget::Import(name) => write!(f, "import {name}"),
+        }
+    }
+}
+
+/// Value of an expression in an object, before the segments are placed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Relocatable {
+    Absolute(i32),
+    /// the address of the target plus the addend
+    Relative(RelocationTarget, i32),
+    /// [`RelocationKind::Low`] or [`RelocationKind::High`] of a relative value
+    Part(RelocationKind, RelocationTarget, i32),
+}
+
+impl Relocatable {
+    /// The value if the target was at `0`, for the symbol table.
+    pub fn addend(&self) -> i32 {
+        match self {
+            Relocatable::Absolute(value)
+            | Relocatable::Relative(_, value)
+            | Relocatable::Part(_, _, value) => *value,
+        }
+    }
+}
+
+impl Expression {
+    /// Evaluates the expression in an object, like [`Expression::evaluate`].
+    ///
+    /// a relative value can only be moved by a constant (`symbol + 1`, `symbol - 1`),
+    /// split into its bytes (`<symbol`, `>symbol`), or subtracted from a value relative to the same target.
+    pub fn evaluate_relocatable(
+        &self,
+        current_address: &Relocatable,
+        resolve: &dyn Fn(&str) -> Option<Relocatable>,
+    ) -> Result<Relocatable, AssemblerErrorKind> {
+        use Relocatable::*;
+
+        let absolute = |expression: Expression| expression.evaluate(None, &|_| None).map(Absolute);
+        let not_relocatable = || Err(AssemblerErrorKind::NotRelocatable(self.to_string()));
+
+        match self {
+            Expression::Number(n) => Ok(Absolute(*n)),
+            Expression::Identifier(identifier) => resolve(identifier)
+                .ok_or_else(|| AssemblerErrorKind::InvalidLabel(identifier.clone())),
+            Expression::CurrentAddress => Ok(current_address.clone()),
+            Expression::Unary(operator, expression) => {
+                match (
+                    operator,
+                    expression.evaluate_relocatable(current_address, resolve)?,
+                ) {
+                    (_, Absolute(value)) => absolute(Expression::Unary(
+                        *operator,
+                        Box::new(Expression::Number(value)),
+                    )),
+                    (UnaryOperator::Low, Relative(target, addend)) => {
+                        Ok(Part(RelocationKind::Low, target, addend))
+                    }
+                    (UnaryOperator::High, Relative(target, addend)) => {
+                        Ok(Part(RelocationKind::High, target, addend))
+                    }
+                    _ => not_relocatable(),
+                }
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate_relocatable(current_address, resolve)?;
+                let right = right.evaluate_relocatable(current_address, resolve)?;
+                match (operator, left, right) {
+                    (_, Absolute(left), Absolute(right)) => absolute(Expression::Binary(
+                        *operator,
+                        Box::new(Expression::Number(left)),
+                        Box::new(Expression::Number(right)),
+                    )),
+                    (BinaryOperator::Add, Relative(target, addend), Absolute(value))
+                    | (BinaryOperator::Add, Absolute(value), Relative(target, addend)) => {
+                        Ok(Relative(target, addend.wrapping_add(value)))
+                    }
+                    (BinaryOperator::Sub, Relative(target, addend), Absolute(value)) => {
+                        Ok(Relative(target, addend.wrapping_sub(value)))
+                    }
+                    (BinaryOperator::Sub, Relative(left, a), Relative(right, b))
+                        if left == right =>
+                    {
+                        Ok(Absolute(a.wrapping_sub(b)))
+                    }
+                    _ => not_relocatable(),
+                }
+            }
+        }
+    }
+}
+
+/// Segments, imports and exports of the object being assembled, see [`crate::Assembler::assemble_object`]
+#[derive(Debug, Default)]
+pub(crate) struct ObjectState {
+    pub segments: Vec<ObjectSegment>,
+    /// index of the current segment in `segments`
+    pub segment: usize,
+    /// segment of every label, by qualified name
+    pub labels: HashMap<String, String>,
+    /// values of the constants, by qualified name
+    pub constants: HashMap<String, Relocatable>,
+    pub imports: Vec<String>,
+    /// imports in the zero page (`.importzp`)
+    pub zero_page_imports: HashSet<String>,
+    /// `.export` names and where they are exported
+    pub exports: Vec<(String, Position)>,
+    /// values of the exports, after the last pass
+    pub symbols: Vec<ObjectSymbol>,
+}
+
+impl ObjectState {
+    /// Starts a pass over the program, in the default segment.
+    pub fn reset(&mut self) {
+        self.segments = vec![ObjectSegment::new(DEFAULT_SEGMENT, false)];
+        self.segment = 0;
+    }
+
+    pub fn current(&mut self) -> &mut ObjectSegment {
+        &mut self.segments[self.segment]
+    }
+
+    /// Switches to the segment `name` and returns its size (where it continues),
+    /// `size` is the size of the current segment.
+    pub fn switch(&mut self, name: &str, zero_page: bool, size: usize) -> usize {
+        self.current().size = size;
+        self.segment = match self.segments.iter().position(|s| s.name == name) {
+            Some(index) => index,
+            None => {
+                self.segments.push(ObjectSegment::new(name, zero_page));
+                self.segments.len() - 1
+            }
+        };
+
+        self.current().size
+    }
+
+    /// Whether the value is an address in the zero page, absolute or in a zero page segment.
+    pub fn is_zero_page(&self, value: &Relocatable) -> bool {
+        match value {
+            Relocatable::Absolute(value) => (0..=0xFF).contains(value),
+            Relocatable::Relative(RelocationTarget::Segment(name), _) => self
+                .segments
+                .iter()
+                .any(|segment| segment.name == *name && segment.zero_page),
+            Relocatable::Relative(RelocationTarget::Import(name), _) => {
+                self.zero_page_imports.contains(name)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn invalid_object(line: usize, message: &str) -> AssemblerError {
+    AssemblerError::new(
+        AssemblerErrorKind::InvalidObject(message.to_string()),
+        Position::new(line, 1),
+    )
+}
+
+impl Object {
+    /// The object in its text format, see [`Object`]
+    pub fn write(&self) -> String {
+        let mut output = format!("{HEADER}\n");
+        for import in &self.imports {
+            writeln!(output, "import {import}").unwrap();
+        }
+        for export in &self.exports {
+            write!(output, "export {} {}", export.name, export.value).unwrap();
+            match &export.segment {
+                Some(segment) => writeln!(output, " {segment}").unwrap(),
+                None => writeln!(output).unwrap(),
+            }
+        }
+
+        for segment in &self.segments {
+            write!(output, "segment {} {}", segment.name, segment.size).unwrap();
+            match segment.zero_page {
+                true => writeln!(output, " zp").unwrap(),
+                false => writeln!(output).unwrap(),
+            }
+            for bytes in segment.bytes.chunks(RECORD_BYTES) {
+                let hex = bytes.iter().map(|byte| format!("{byte:02X}"));
+                writeln!(output, "bytes {}", hex.collect::<String>()).unwrap();
+            }
+            for relocation in &segment.relocations {
+                writeln!(
+                    output,
+                    "reloc {} {} {} {}",
+                    relocation.offset,
+                    relocation.kind.name(),
+                    relocation.target,
+                    relocation.addend
+                )
+                .unwrap();
+            }
+        }
+
+        output
+    }
+
+    /// Reads an object in its text format, errors are [`AssemblerErrorKind::InvalidObject`] at the line of the record.
+    pub fn read(text: &str) -> AssemblerResult<Object> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            return Err(invalid_object(1, "expected the header `o6502 1`"));
+        }
+
+        let mut object = Object::default();
+        for (i, line) in lines {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            read_record(&mut object, &fields).map_err(|e| invalid_object(i + 1, e))?;
+        }
+
+        for segment in &object.segments {
+            if segment.bytes.len() > segment.size {
+                return Err(invalid_object(
+                    0,
+                    &format!("segment {} has more bytes than its size", segment.name),
+                ));
+            }
+        }
+
+        Ok(object)
+    }
+}
+
+fn read_record(object: &mut Object, fields: &[&str]) -> Result<(), &'static str> {
+    fn number<T: std::str::FromStr>(field: &str) -> Result<T, &'static str> {
+        field.parse().map_err(|_| "invalid number")
+    }
+
+    match *fields {
+        [] => {}
+        ["import", name] => object.imports.push(name.to_string()),
+        ["export", name, value, ref segment @ ..] if segment.len() <= 1 => {
+            object.exports.push(ObjectSymbol {
+                name: name.to_string(),
+                value: number(value)?,
+                segment: segment.first().map(|segment| segment.to_string()),
+            })
+        }
+        ["segment", name, size, ref zero_page @ ..] if matches!(zero_page, [] | ["zp"]) => {
+            let mut segment = ObjectSegment::new(name, !zero_page.is_empty());
+            segment.size = number(size)?;
+            object.segments.push(segment);
+        }
+        ["bytes", hex] => {
+            let segment = object.segments.last_mut().ok_or("bytes before a segment")?;
+            if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+                return Err("expected hexadecimal digits");
+            }
+            for i in (0..hex.len()).step_by(2) {
+                let byte = u8::from_str_radix(&hex[i..i + 2], 16)
+                    .map_err(|_| "expected hexadecimal digits")?;
+                segment.bytes.push(byte);
+            }
+        }
+        ["reloc", offset, kind, target, name, addend] => {
+            let segment = object
+                .segments
+                .last_mut()
+                .ok_or("relocation before a segment")?;
+            let kind = RelocationKind::ALL
+                .into_iter()
+                .find(|k| k.name() == kind)
+                .ok_or("unknown relocation kind")?;
+            let target = match target {
+                "segment" => RelocationTarget::Segment(name.to_string()),
+                "import" => RelocationTarget::Import(name.to_string()),
+                _ => return Err("unknown relocation target"),
+            };
+            let offset = number(offset)?;
+            let size = if kind == RelocationKind::Word { 2 } else { 1 };
+            if offset + size > segment.bytes.len() {
+                return Err("relocation outside of the bytes of the segment");
+            }
+
+            segment.relocations.push(Relocation {
+                offset,
+                kind,
+                target,
+                addend: number(addend)?,
+            });
+        }
+        _ => return Err("unknown record"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the example of the format in the documentation of [`Object`]
+    const OBJECT: &str = "o6502 1
+import putchar
+export main 0 CODE
+export SCREEN 1024
+segment CODE 8
+bytes A9412000004C0000
+reloc 3 word import putchar 0
+reloc 6 word segment CODE 0
+segment ZEROPAGE 2 zp
+";
+
+    /// Message and line of the error of reading `text`.
+    fn error(text: &str) -> (String, usize) {
+        let error = Object::read(text).unwrap_err();
+        match error.kind {
+            AssemblerErrorKind::InvalidObject(message) => (message, error.span.start.line),
+            kind => panic!("unexpected error: {kind}"),
+        }
+    }
+
+    #[test]
+    fn test_read_write() {
+        let object = Object::read(OBJECT).unwrap();
+        assert_eq!(object.imports, vec!["putchar".to_string()]);
+        assert_eq!(
+            object.exports[1],
+            ObjectSymbol {
+                name: "SCREEN".to_string(),
+                value: 1024,
+                segment: None,
+            }
+        );
+        assert_eq!(object.segments.len(), 2);
+        assert_eq!(object.segments[0].bytes.len(), 8);
+        assert_eq!(
+            object.segments[0].relocations[0],
+            Relocation {
+                offset: 3,
+                kind: RelocationKind::Word,
+                target: RelocationTarget::Import("putchar".to_string()),
+                addend: 0,
+            }
+        );
+        assert!(object.segments[1].zero_page);
+        assert_eq!(object.write(), OBJECT);
+
+        // the bytes are written in records of 32 bytes
+        let mut segment = ObjectSegment::new("DATA", false);
+        segment.bytes = vec![0xEA; 40];
+        segment.size = 40;
+        let object = Object {
+            segments: vec![segment],
+            ..Default::default()
+        };
+        let text = object.write();
+        assert_eq!(
+            text.lines()
+                .filter(|line| line.starts_with("bytes"))
+                .count(),
+            2
+        );
+        assert_eq!(Object::read(&text).unwrap(), object);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert_eq!(error(""), ("expected the header `o6502 1`".to_string(), 1));
+        assert_eq!(
+            error("o6502 2\n"),
+            ("expected the header `o6502 1`".to_string(), 1)
+        );
+
+        let cases = [
+            ("export main x", "invalid number"),
+            ("export main 0 CODE extra", "unknown record"),
+            ("segment CODE 1 rw", "unknown record"),
+            ("segment CODE -1", "invalid number"),
+            ("bytes EA", "bytes before a segment"),
+            ("reloc 0 word segment CODE 0", "relocation before a segment"),
+            ("section CODE", "unknown record"),
+        ];
+        for (record, message) in cases {
+            assert_eq!(
+                error(&format!("o6502 1\n{record}\n")),
+                (message.to_string(), 2),
+                "{record}"
+            );
+        }
+
+        let cases = [
+            ("bytes EAE", "expected hexadecimal digits"),
+            ("bytes EAXX", "expected hexadecimal digits"),
+            ("reloc 0 long segment CODE 0", "unknown relocation kind"),
+            ("reloc 0 word symbol CODE 0", "unknown relocation target"),
+            (
+                "reloc 1 word segment CODE 0",
+                "relocation outside of the bytes of the segment",
+            ),
+            ("reloc 0 word segment CODE x", "invalid number"),
+        ];
+        for (record, message) in cases {
+            assert_eq!(
+                error(&format!("o6502 1\nsegment CODE 2\nbytes EAEA\n{record}\n")),
+                (message.to_string(), 4),
+                "{record}"
+            );
+        }
+
+        // the size is checked after the records, the error is not at a line
+        assert_eq!(
+            error("o6502 1\nsegment CODE 1\nbytes EAEA\n"),
+            ("segment CODE has more bytes than its size".to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_relocatable() {
+        use Expression::{Binary, Identifier, Number, Unary};
+        use Relocatable::{Absolute, Part, Relative};
+
+        let code = || RelocationTarget::Segment("CODE".to_string());
+        let resolve = |name: &str| match name {
+            "start" => Some(Relative(code(), 2)),
+            "end" => Some(Relative(code(), 10)),
+            "putchar" => Some(Relative(RelocationTarget::Import("putchar".to_string()), 0)),
+            _ => None,
+        };
+        let evaluate = |expression: Expression| {
+            expression.evaluate_relocatable(&Relative(code(), 4), &resolve)
+        };
+        let identifier = |name: &str| Box::new(Identifier(name.to_string()));
+        let binary = |operator, left, right| Binary(operator, left, right);
+
+        assert_eq!(
+            evaluate(binary(
+                BinaryOperator::Add,
+                identifier("start"),
+                Box::new(Number(1))
+            )),
+            Ok(Relative(code(), 3))
+        );
+        assert_eq!(
+            evaluate(binary(
+                BinaryOperator::Sub,
+                identifier("end"),
+                identifier("start")
+            )),
+            Ok(Absolute(8))
+        );
+        assert_eq!(
+            evaluate(Unary(UnaryOperator::High, identifier("start"))),
+            Ok(Part(RelocationKind::High, code(), 2))
+        );
+        assert_eq!(
+            evaluate(Expression::CurrentAddress),
+            Ok(Relative(code(), 4))
+        );
+        assert_eq!(
+            evaluate(Unary(UnaryOperator::Low, Box::new(Number(0x1234)))),
+            Ok(Absolute(0x34))
+        );
+
+        // the targets differ, a relative value can not be scaled
+        let error = evaluate(binary(
+            BinaryOperator::Sub,
+            identifier("end"),
+            identifier("putchar"),
+        ));
+        assert!(matches!(error, Err(AssemblerErrorKind::NotRelocatable(_))));
+        let error = evaluate(binary(
+            BinaryOperator::Mul,
+            identifier("start"),
+            Box::new(Number(2)),
+        ));
+        assert!(matches!(error, Err(AssemblerErrorKind::NotRelocatable(_))));
+        assert_eq!(
+            evaluate(*identifier("missing")),
+            Err(AssemblerErrorKind::InvalidLabel("missing".to_string()))
+        );
+    }
+}
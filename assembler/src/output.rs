@@ -0,0 +1,466 @@
+use crate::{
+    chunks_to_image, entry_point, AssemblerError, AssemblerErrorKind, AssemblerResult, Chunk,
+    Position, DEFAULT_ORIGIN,
+};
+use std::{fmt::Write, path::Path};
+
+/// File format of an assembled program, see [`OutputFormat::write`] and [`OutputFormat::read`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// the bytes from the lowest to the highest address, gaps between the chunks are filled with `0x00`.
+    /// it has no address, it is loaded at [`DEFAULT_ORIGIN`]
+    #[default]
+    Raw,
+    /// Intel HEX, a data record (`:10800000...`) of up to 16 bytes per line
+    IntelHex,
+    /// Motorola S-record, `S1` data records of up to 16 bytes with a `S9` record of the start address
+    SRecord,
+    /// Commodore program, the load address (little endian) and the bytes as in [`OutputFormat::Raw`]
+    Prg,
+}
+
+/// Bytes in a data record of Intel HEX and S-record files.
+const RECORD_BYTES: usize = 16;
+
+/// Address and bytes of a data record.
+type Record = (u32, Vec<u8>);
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 4] = [
+        OutputFormat::Raw,
+        OutputFormat::IntelHex,
+        OutputFormat::SRecord,
+        OutputFormat::Prg,
+    ];
+
+    /// Name of the format on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Raw => "raw",
+            OutputFormat::IntelHex => "ihex",
+            OutputFormat::SRecord => "srec",
+            OutputFormat::Prg => "prg",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Raw => "bin",
+            OutputFormat::IntelHex => "hex",
+            OutputFormat::SRecord => "srec",
+            OutputFormat::Prg => "prg",
+        }
+    }
+
+    /// Format of a file by its extension (`.hex`, `.ihx`, `.srec`, `.s19`, `.prg`), [`OutputFormat::Raw`] for any other.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("hex" | "ihx" | "ihex") => OutputFormat::IntelHex,
+            Some("srec" | "s19" | "mot") => OutputFormat::SRecord,
+            Some("prg") => OutputFormat::Prg,
+            _ => OutputFormat::Raw,
+        }
+    }
+
+    /// Writes the chunks (see [`crate::Assembler::assemble_chunks`]) in the format.
+    pub fn write(&self, chunks: &[Chunk]) -> Vec<u8> {
+        match self {
+            OutputFormat::Raw => chunks_to_image(chunks).1,
+            OutputFormat::IntelHex => intel_hex(chunks).into_bytes(),
+            OutputFormat::SRecord => s_record(chunks).into_bytes(),
+            OutputFormat::Prg => {
+                let (origin, bytes) = chunks_to_image(chunks);
+                [&origin.to_le_bytes()[..], &bytes].concat()
+            }
+        }
+    }
+
+    /// Reads the chunks of a file in the format, to be loaded at their origins.
+    /// errors are [`AssemblerErrorKind::InvalidRecord`] at the line of the record.
+    pub fn read(&self, data: &[u8]) -> AssemblerResult<Vec<Chunk>> {
+        let chunks = match self {
+            OutputFormat::Raw => vec![Chunk {
+                origin: DEFAULT_ORIGIN,
+                bytes: data.to_vec(),
+            }],
+            OutputFormat::IntelHex => read_records(data, read_intel_hex)?,
+            OutputFormat::SRecord => read_records(data, read_s_record)?,
+            OutputFormat::Prg => match data {
+                [low, high, bytes @ ..] => vec![Chunk {
+                    origin: u16::from_le_bytes([*low, *high]),
+                    bytes: bytes.to_vec(),
+                }],
+                _ => {
+                    return Err(invalid_record(1, "missing load address"));
+                }
+            },
+        };
+
+        Ok(chunks
+            .into_iter()
+            .filter(|chunk| !chunk.bytes.is_empty())
+            .collect())
+    }
+}
+
+fn invalid_record(line: usize, message: &str) -> AssemblerError {
+    AssemblerError::new(
+        AssemblerErrorKind::InvalidRecord(message.to_string()),
+        Position::new(line, 1),
+    )
+}
+
+/// `bytes` as uppercase hexadecimal digits.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
+
+fn parse_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Data records of the chunks, `(address, bytes)`
+fn records(chunks: &[Chunk]) -> impl Iterator<Item = (u16, &[u8])> {
+    chunks.iter().flat_map(|chunk| {
+        chunk
+            .bytes
+            .chunks(RECORD_BYTES)
+            .enumerate()
+            .map(|(i, bytes)| (chunk.origin.wrapping_add((i * RECORD_BYTES) as u16), bytes))
+    })
+}
+
+/// ```text
+/// :03800000A9018D46
+/// :00000001FF
+/// ```
+fn intel_hex(chunks: &[Chunk]) -> String {
+    let mut output = String::new();
+    let mut record = |kind: u8, address: u16, bytes: &[u8]| {
+        let record = [
+            &[bytes.len() as u8],
+            &address.to_be_bytes()[..],
+            &[kind],
+            bytes,
+        ]
+        .concat();
+        let checksum = record
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+            .wrapping_neg();
+        writeln!(output, ":{}{checksum:02X}", hex(&record)).unwrap();
+    };
+
+    for (address, bytes) in records(chunks) {
+        record(0x00, address, bytes);
+    }
+    record(0x01, 0, &[]);
+
+    output
+}
+
+/// ```text
+/// S0030000FC
+/// S1068000A9018D42
+/// S5030001FB
+/// S90380007C
+/// ```
+/// the start address of the `S9` record is the entry point, the reset vector or the origin
+/// of the first chunk (see [`entry_point`]), like the loaders start the program.
+fn s_record(chunks: &[Chunk]) -> String {
+    let mut output = String::new();
+    let mut record = |kind: u8, address: u16, bytes: &[u8]| {
+        let record = [&[bytes.len() as u8 + 3], &address.to_be_bytes()[..], bytes].concat();
+        let checksum = !record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        writeln!(output, "S{kind}{}{checksum:02X}", hex(&record)).unwrap();
+    };
+
+    record(0, 0, &[]);
+    let mut count = 0;
+    for (address, bytes) in records(chunks) {
+        record(1, address, bytes);
+        count += 1;
+    }
+    if count <= 0xFFFF {
+        record(5, count as u16, &[]);
+    }
+    let start = entry_point(chunks).unwrap_or(DEFAULT_ORIGIN);
+    record(9, start, &[]);
+
+    output
+}
+
+/// Reads the lines of a text format with `read_line`, which returns the data of a record (`None` for other records).
+/// consecutive records are merged into a chunk.
+fn read_records(
+    data: &[u8],
+    read_line: fn(&str) -> Result<Option<Record>, &'static str>,
+) -> AssemblerResult<Vec<Chunk>> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid_record(1, "not a text file"))?;
+    let mut chunks = Vec::<Chunk>::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((address, bytes)) = read_line(line).map_err(|e| invalid_record(i + 1, e))? else {
+            continue;
+        };
+        if address as usize + bytes.len() > 0x10000 {
+            return Err(invalid_record(i + 1, "address out of range"));
+        }
+
+        match chunks.last_mut() {
+            Some(chunk) if chunk.origin as u32 + chunk.bytes.len() as u32 == address => {
+                chunk.bytes.extend(bytes)
+            }
+            _ => chunks.push(Chunk {
+                origin: address as u16,
+                bytes,
+            }),
+        }
+    }
+
+    Ok(chunks)
+}
+
+fn read_intel_hex(line: &str) -> Result<Option<Record>, &'static str> {
+    let record = line
+        .strip_prefix(':')
+        .and_then(parse_hex)
+        .ok_or("expected `:` and hexadecimal digits")?;
+    let [length, high, low, kind, ..] = record[..] else {
+        return Err("record too short");
+    };
+    if record.len() != length as usize + 5 {
+        return Err("length does not match the record");
+    }
+    if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+        return Err("invalid checksum");
+    }
+
+    let data = &record[4..record.len() - 1];
+    match kind {
+        0x00 => Ok(Some((
+            u16::from_be_bytes([high, low]) as u32,
+            data.to_vec(),
+        ))),
+        // end of file, start addresses
+        0x01 | 0x03 | 0x05 => Ok(None),
+        // extended addresses, only the first 64K are supported
+        0x02 | 0x04 if data.iter().all(|byte| *byte == 0) => Ok(None),
+        0x02 | 0x04 => Err("address out of range"),
+        _ => Err("unknown record type"),
+    }
+}
+
+fn read_s_record(line: &str) -> Result<Option<Record>, &'static str> {
+    let mut chars = line.chars();
+    let (Some('S' | 's'), Some(kind)) = (chars.next(), chars.next()) else {
+        return Err("expected `S` and the record type");
+    };
+    let record = parse_hex(chars.as_str()).ok_or("expected hexadecimal digits")?;
+    let Some((&count, _)) = record.split_first() else {
+        return Err("record too short");
+    };
+    if record.len() != count as usize + 1 {
+        return Err("length does not match the record");
+    }
+    if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xFF {
+        return Err("invalid checksum");
+    }
+
+    let address_length = match kind {
+        '1' => 2,
+        '2' => 3,
+        '3' => 4,
+        // header, count and start address records
+        '0' | '5' | '6' | '7' | '8' | '9' => return Ok(None),
+        _ => return Err("unknown record type"),
+    };
+    let data = &record[1..record.len() - 1];
+    if data.len() < address_length {
+        return Err("record too short");
+    }
+
+    let (address, bytes) = data.split_at(address_length);
+    let address = address
+        .iter()
+        .fold(0u32, |address, byte| address << 8 | *byte as u32);
+    Ok(Some((address, bytes.to_vec())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 20 bytes across a page, two data records
+    fn chunks() -> Vec<Chunk> {
+        vec![Chunk {
+            origin: 0x80F8,
+            bytes: (0..20).collect(),
+        }]
+    }
+
+    /// Message and line of the error of reading `data` in the format.
+    fn error(format: OutputFormat, data: &str) -> (String, usize) {
+        let error = format.read(data.as_bytes()).unwrap_err();
+        match error.kind {
+            AssemblerErrorKind::InvalidRecord(message) => (message, error.span.start.line),
+            kind => panic!("unexpected error: {kind}"),
+        }
+    }
+
+    #[test]
+    fn test_raw() {
+        let chunks = vec![
+            Chunk {
+                origin: 0x8000,
+                bytes: vec![0xEA],
+            },
+            Chunk {
+                origin: 0x8003,
+                bytes: vec![0x60],
+            },
+        ];
+        // the gap is filled with zeros
+        assert_eq!(OutputFormat::Raw.write(&chunks), [0xEA, 0x00, 0x00, 0x60]);
+        assert_eq!(
+            OutputFormat::Raw.read(&[0xEA]).unwrap(),
+            vec![Chunk {
+                origin: DEFAULT_ORIGIN,
+                bytes: vec![0xEA],
+            }]
+        );
+        assert_eq!(OutputFormat::Raw.read(&[]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_prg() {
+        let prg = OutputFormat::Prg.write(&chunks());
+        assert_eq!(prg[..4], [0xF8, 0x80, 0x00, 0x01]);
+        assert_eq!(prg.len(), 2 + 20);
+        assert_eq!(OutputFormat::Prg.read(&prg).unwrap(), chunks());
+
+        assert_eq!(
+            error(OutputFormat::Prg, "\x01"),
+            ("missing load address".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let hex = ":1080F800000102030405060708090A0B0C0D0E0F00\n:04810800101112132D\n:00000001FF\n";
+        assert_eq!(
+            String::from_utf8(OutputFormat::IntelHex.write(&chunks())).unwrap(),
+            hex
+        );
+        assert_eq!(
+            OutputFormat::IntelHex.read(hex.as_bytes()).unwrap(),
+            chunks()
+        );
+        assert_eq!(intel_hex(&[]), ":00000001FF\n");
+
+        // blank lines and extended addresses of the first 64K are skipped
+        let hex = "\n:020000040000FA\n:01800000423D\n\n:00000001FF\n";
+        assert_eq!(
+            OutputFormat::IntelHex.read(hex.as_bytes()).unwrap(),
+            vec![Chunk {
+                origin: 0x8000,
+                bytes: vec![0x42],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_intel_hex_errors() {
+        let cases = [
+            ("0180000042BD", "expected `:` and hexadecimal digits"),
+            (":01800000423", "expected `:` and hexadecimal digits"),
+            (":018000", "record too short"),
+            (":02800000423D", "length does not match the record"),
+            (":01800000423E", "invalid checksum"),
+            (":020000040001F9", "address out of range"),
+            (":00000006FA", "unknown record type"),
+            (":02FFFF000102FD", "address out of range"),
+        ];
+        for (hex, message) in cases {
+            let data = format!(":01800000423D\n{hex}\n");
+            assert_eq!(
+                error(OutputFormat::IntelHex, &data),
+                (message.to_string(), 2),
+                "{hex}"
+            );
+        }
+        assert_eq!(
+            OutputFormat::IntelHex.read(&[0xFF]).unwrap_err().kind,
+            AssemblerErrorKind::InvalidRecord("not a text file".to_string())
+        );
+    }
+
+    #[test]
+    fn test_s_record() {
+        let srec = "S0030000FC\nS11380F8000102030405060708090A0B0C0D0E0FFC\nS10781081011121329\nS5030002FA\nS90380F884\n";
+        assert_eq!(
+            String::from_utf8(OutputFormat::SRecord.write(&chunks())).unwrap(),
+            srec
+        );
+        assert_eq!(
+            OutputFormat::SRecord.read(srec.as_bytes()).unwrap(),
+            chunks()
+        );
+        // without chunks the program starts at the default origin
+        assert_eq!(s_record(&[]), "S0030000FC\nS5030000FC\nS90380007C\n");
+
+        // `S2` and `S3` records with longer addresses, in lowercase
+        let srec = "S2050080004238\ns306000080014335\n";
+        assert_eq!(
+            OutputFormat::SRecord.read(srec.as_bytes()).unwrap(),
+            vec![Chunk {
+                origin: 0x8000,
+                bytes: vec![0x42, 0x43],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_s_record_errors() {
+        let cases = [
+            ("X1048000427B", "expected `S` and the record type"),
+            ("S", "expected `S` and the record type"),
+            ("S1048000423Z", "expected hexadecimal digits"),
+            ("S1", "record too short"),
+            ("S10580004239", "length does not match the record"),
+            ("S1048000423A", "invalid checksum"),
+            ("S40480004239", "unknown record type"),
+            ("S10200FD", "record too short"),
+            ("S30700010000424273", "address out of range"),
+        ];
+        for (srec, message) in cases {
+            let data = format!("S10480004239\n{srec}\n");
+            assert_eq!(
+                error(OutputFormat::SRecord, &data),
+                (message.to_string(), 2),
+                "{srec}"
+            );
+        }
+    }
+}
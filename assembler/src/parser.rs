@@ -1,110 +1,2008 @@
 use crate::{
-    lexer::Lexer, AddressingMode, AssemblerError, AssemblerErrorKind, AssemblerResult, Instruction,
-    Mnemonics, NumberType, Operand, OperandData, Position, Program, Statement, Token, TokenKind,
+    lexer::Lexer, AddressingMode, AssemblerError, AssemblerErrorKind, AssemblerResult,
+    AssemblerWarning, AssemblerWarningKind, BinaryOperator, Constant, CpuType, Data, DataKind,
+    Dialect, Expansion, Expression, Instruction, Mnemonics, NumberType, Operand, OperandData,
+    OperandWidth, Position, Program, Segment, Sources, Span, Statement, Token, TokenKind,
+    UnaryOperator,
 };
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+/// Maximum nesting depth of macro expansions, guards against recursive macros.
+pub const MAX_MACRO_DEPTH: usize = 32;
+
+/// Directives of ca65 that the ca65 dialect does not support, they are reported as unsupported
+/// instead of unknown. every other directive in an expression is an unsupported function.
+const CA65_UNSUPPORTED: &[&str] = &[
+    "a16",
+    "a8",
+    "align",
+    "assert",
+    "autoimport",
+    "case",
+    "charmap",
+    "condes",
+    "constructor",
+    "delmac",
+    "delmacro",
+    "destructor",
+    "dword",
+    "end",
+    "endenum",
+    "endstruct",
+    "endunion",
+    "enum",
+    "error",
+    "exitmac",
+    "exitmacro",
+    "faraddr",
+    "fatal",
+    "feature",
+    "forceimport",
+    "global",
+    "globalzp",
+    "i16",
+    "i8",
+    "interruptor",
+    "linecont",
+    "literal",
+    "local",
+    "localchar",
+    "macpack",
+    "out",
+    "p4510",
+    "p816",
+    "pdtv",
+    "popcharmap",
+    "popcpu",
+    "popseg",
+    "pushcharmap",
+    "pushcpu",
+    "pushseg",
+    "reloc",
+    "set",
+    "smart",
+    "struct",
+    "tag",
+    "undef",
+    "undefine",
+    "union",
+    "warning",
+];
+
+/// Conditional directives of ca65 that the ca65 dialect does not support.
+const CA65_UNSUPPORTED_CONDITIONALS: &[&str] = &[
+    "ifblank", "ifconst", "ifnblank", "ifnref", "ifp02", "ifp4510", "ifp816", "ifpc02", "ifpdtv",
+    "ifpsc02", "ifref",
+];
+
+/// Directives of ca65 for its listing and debug information, they do not change the output.
+const CA65_IGNORED: &[&str] = &[
+    "debuginfo",
+    "fileopt",
+    "fopt",
+    "list",
+    "listbytes",
+    "pagelen",
+    "pagelength",
+];
+
+/// `.macro name param1, param2` ... `.endm`
+#[derive(Debug, Clone)]
+struct Macro<'a> {
+    params: Vec<&'a str>,
+    body: Vec<Token<'a>>,
+    /// labels defined in the body (as written, `name`, `.name`), they are unique per expansion
+    locals: HashSet<String>,
+}
+
+/// State of a conditional assembly (`.if` ... `.endif`) block.
+#[derive(Debug, Clone, Copy)]
+struct Condition {
+    /// the current branch is assembled
+    active: bool,
+    /// one of the branches has been taken (or the enclosing block is not assembled),
+    /// the following `.elif`/`.else` branches are skipped
+    taken: bool,
+    position: Position,
+}
+
+/// A `.proc` or `.scope` block.
+#[derive(Debug, Clone)]
+struct OpenScope {
+    /// index in [`Parser::scopes`]
+    index: usize,
+    directive: String,
+    position: Position,
+    /// last global label before the block, see [`Parser::identifier`]
+    global: String,
+}
+
+/// Whether `token` is a label definition, a label followed by `:` without whitespace (`loop:`).
+/// `BNE :-` is an instruction with an anonymous label operand.
+pub(crate) fn is_label_definition(token: &Token, next: &Token) -> bool {
+    let length = match token.kind {
+        TokenKind::Identifier(label) => label.chars().count(),
+        TokenKind::Directive(label) => label.chars().count() + 1,
+        _ => return false,
+    };
+
+    next.kind == TokenKind::Colon
+        && next.position.line == token.position.line
+        && next.position.column == token.position.column + length
+}
+
+/// Addressing mode of an instruction with an expression operand.
+/// branches are always relative, zero page modes the instruction does not have are widened to absolute.
+fn expression_addressing_mode(
+    mnemonic: Mnemonics,
+    mode: AddressingMode,
+    cpu: CpuType,
+) -> AddressingMode {
+    use AddressingMode::*;
+
+    match mode {
+        ZPG | ABS if mnemonic.is_branch() => REL,
+        ZPG if !mnemonic.supports(ZPG, cpu) => ABS,
+        ZPX if !mnemonic.supports(ZPX, cpu) => ABX,
+        ZPY if !mnemonic.supports(ZPY, cpu) => ABY,
+        mode => mode,
+    }
+}
+
+/// Addressing mode of an operand without a value or with a zero page value,
+/// the implied and accumulator modes and the zero page and relative modes are written alike.
+fn implied_addressing_mode(
+    mnemonic: Mnemonics,
+    mode: AddressingMode,
+    cpu: CpuType,
+) -> AddressingMode {
+    use AddressingMode::*;
+
+    match mode {
+        ZPG if mnemonic.is_branch() => REL,
+        IMP if !mnemonic.supports(IMP, cpu) && mnemonic.supports(ACC, cpu) => ACC,
+        mode => mode,
+    }
+}
+
+/// Addressing mode of an indirect operand, `(zp)` is written like `(abs)` and `(abs,X)` like `(zp,X)`.
+fn indirect_addressing_mode(
+    mnemonic: Mnemonics,
+    mode: AddressingMode,
+    cpu: CpuType,
+) -> AddressingMode {
+    use AddressingMode::*;
+
+    match mode {
+        IND if !mnemonic.supports(IND, cpu) && mnemonic.supports(IZP, cpu) => IZP,
+        IDX if !mnemonic.supports(IDX, cpu) && mnemonic.supports(IAX, cpu) => IAX,
+        mode => mode,
+    }
+}
+
+/// Index register of an operand (`$00,X`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Register {
+    X,
+    Y,
+}
+
+/// Branch of a long branch (`JEQ` is a long `BEQ`), see [`Instruction::long`]
+pub fn long_branch(name: &str) -> Option<Mnemonics> {
+    let condition = name.strip_prefix(['J', 'j'])?;
+    Mnemonics::from_name(&format!("B{condition}"))
+        .filter(|branch| branch.inverted_branch().is_some())
+}
+
+/// CPU of the CPU directives of ca65 (`.pc02` is `.setcpu "65C02"`).
+pub fn ca65_cpu(directive: &str) -> Option<CpuType> {
+    match directive.to_lowercase().as_str() {
+        "p02" => Some(CpuType::Nmos6502),
+        "psc02" => Some(CpuType::Cmos65SC02),
+        "pc02" => Some(CpuType::Cmos65C02),
+        _ => None,
+    }
+}
+
+/// Widens or narrows a number literal to the forced width of its operand.
+fn number_with_width(
+    number: NumberType,
+    width: OperandWidth,
+    span: Span,
+) -> AssemblerResult<NumberType> {
+    let value = match number {
+        NumberType::Decimal8(n) | NumberType::Hexadecimal8(n) => n as u16,
+        NumberType::Decimal16(n) | NumberType::Hexadecimal16(n) => n,
+    };
+    let hexadecimal = matches!(
+        number,
+        NumberType::Hexadecimal8(_) | NumberType::Hexadecimal16(_)
+    );
+
+    Ok(match (width, hexadecimal) {
+        (OperandWidth::ZeroPage, _) if value > 0xFF => {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange(value as i32),
+                span,
+            ))
+        }
+        (OperandWidth::ZeroPage, true) => NumberType::Hexadecimal8(value as u8),
+        (OperandWidth::ZeroPage, false) => NumberType::Decimal8(value as u8),
+        (_, true) => NumberType::Hexadecimal16(value),
+        (_, false) => NumberType::Decimal16(value),
+    })
+}
+
+/// Names `name` can refer to from `scope`, the innermost scope first.
+/// `foo::bar` and `baz` resolve to `foo::bar::baz`, `foo::baz` and `baz`.
+pub fn qualified_names(scope: &str, name: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut scope = scope;
+    while !scope.is_empty() {
+        names.push(format!("{scope}::{name}"));
+        scope = scope.rsplit_once("::").map_or("", |(parent, _)| parent);
+    }
+    names.push(name.to_string());
+
+    names
+}
+
+/// An including file, suspended while the included file is parsed.
+#[derive(Debug)]
+struct Include<'a> {
+    lexer: Lexer<'a>,
+    /// token after the `.include` directive
+    peek_token: Token<'a>,
+    pending: VecDeque<Token<'a>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Parser<'a> {
+    pub lexer: Lexer<'a>,
+    current_token: Token<'a>,
+    peek_token: Token<'a>,
+    position: Position,
+    /// end of the token before the current one
+    previous_end: Position,
+    /// values of the constants that could be evaluated while parsing, by qualified name
+    constants: HashMap<String, i32>,
+    /// operands of `define NAME #value` and `define NAME address,X`, see [`Parser::parse_define`]
+    defines: HashMap<String, Operand>,
+    macros: HashMap<&'a str, Macro<'a>>,
+    /// tokens of macro expansions, read before the lexer
+    pending: VecDeque<Token<'a>>,
+    /// every macro expansion, [`Position::expansion`] `n` refers to `expansions[n - 1]`
+    pub expansions: Vec<Expansion>,
+    conditions: Vec<Condition>,
+    /// labels and constants defined so far (qualified) and where, for `.ifdef` and the symbol table
+    pub labels: HashMap<String, Position>,
+    /// symbols defined outside of the source (`-D NAME=VALUE`), for `.if` and `.ifdef`
+    pub symbols: HashMap<String, i32>,
+    /// CPU of the following instructions, selected with `.setcpu`
+    pub cpu: CpuType,
+    /// qualified names of the labels and constants referenced while parsing, for the `unused` lint
+    pub used: RefCell<HashSet<String>>,
+    /// files for `.include` and `.incbin`, see [`Parser::with_sources`]
+    sources: Option<&'a Sources<'a>>,
+    includes: Vec<Include<'a>>,
+    /// position of the `.include` directive that included the file (by index),
+    /// the last one if the file is included more than once
+    pub included_at: HashMap<usize, Position>,
+    /// qualified names of the scopes, [`Position::scope`] `n` refers to `scopes[n]`.
+    /// `scopes[0]` is the global scope, its name is empty.
+    pub scopes: Vec<String>,
+    open_scopes: Vec<OpenScope>,
+    /// last global label, cheap local labels belong to it
+    global: String,
+    /// number of anonymous labels (`:`) defined so far
+    anonymous: usize,
+    pub warnings: Vec<AssemblerWarning>,
+    /// every error, the parser continues with the next line after an error
+    pub errors: Vec<AssemblerError>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Self {
+        let mut parser = Self {
+            lexer,
+            scopes: vec![String::new()],
+            ..Default::default()
+        };
+        // invalid tokens at the start are reported by `parse`, the lexer moves on after them
+        for _ in 0..2 {
+            while let Err(error) = parser.next_token() {
+                parser.errors.push(error);
+            }
+        }
+
+        parser
+    }
+
+    /// Parser for the main source of `sources` in `dialect`, included files are resolved with its resolver.
+    pub fn with_sources(sources: &'a Sources<'a>, dialect: Dialect) -> Self {
+        let mut lexer = Lexer::new(&sources.files[0].source);
+        lexer.dialect = dialect;
+        let mut parser = Self::new(lexer);
+        parser.sources = Some(sources);
+
+        parser
+    }
+
+    fn next_token(&mut self) -> AssemblerResult<()> {
+        self.previous_end = self.current_token.end;
+        self.current_token = self.peek_token;
+        self.peek_token = match self.pending.pop_front() {
+            Some(token) => token,
+            None => self.lexer_token()?,
+        };
+
+        self.position = self.current_token.position;
+        self.position.scope = self.scope();
+
+        Ok(())
+    }
+
+    fn lexer_token(&mut self) -> AssemblerResult<Token<'a>> {
+        let mut token = self.lexer.next_token()?;
+        while token.kind == TokenKind::Comment {
+            token = self.lexer.next_token()?;
+        }
+        if token.kind != TokenKind::EOF {
+            return Ok(token);
+        }
+
+        // end of an included file, continues after the `.include` directive
+        Ok(match self.includes.pop() {
+            Some(Include {
+                lexer,
+                peek_token,
+                pending,
+            }) => {
+                self.lexer = lexer;
+                self.pending = pending;
+                peek_token
+            }
+            None => token,
+        })
+    }
+
+    /// Whether the current file is in the ca65 dialect, see [`Dialect`]
+    fn ca65(&self) -> bool {
+        self.lexer.dialect == Dialect::Ca65
+    }
+
+    /// Span of the current token, the end of the line is right after the previous token.
+    fn span(&self) -> Span {
+        match self.current_token.kind {
+            TokenKind::Newline | TokenKind::EOF => Span::from(self.previous_end),
+            _ => Span::new(self.position, self.current_token.end),
+        }
+    }
+
+    fn expected(&mut self, expected: &TokenKind) -> AssemblerResult<()> {
+        Err(AssemblerError::new(
+            AssemblerErrorKind::UnexpectedToken {
+                expected: expected.to_string(),
+                found: self.current_token.kind.to_string(),
+            },
+            self.span(),
+        ))
+    }
+
+    fn expect_token(&mut self, token: &TokenKind) -> AssemblerResult<()> {
+        if self.current_token.kind == *token {
+            self.next_token()?;
+            Ok(())
+        } else {
+            self.expected(token)
+        }
+    }
+
+    /// Name of the identifier at the current position, scopes are resolved by the assembler.
+    /// - labels defined in a macro body are renamed to `name@expansion`, so that every expansion gets its own.
+    /// - cheap local labels (`.name`, `@name`) belong to the last global label, `global.name`
+    fn identifier(&self, identifier: &str) -> String {
+        let expansion = self.position.expansion;
+        let identifier = match expansion
+            .checked_sub(1)
+            .and_then(|i| self.expansions.get(i))
+        {
+            Some(Expansion { locals, .. }) if locals.contains(identifier) => {
+                format!("{identifier}@{expansion}")
+            }
+            _ => identifier.to_string(),
+        };
+
+        match identifier.strip_prefix(['.', '@']) {
+            Some(local) => format!("{}.{local}", self.global),
+            None => identifier,
+        }
+    }
+
+    /// Index of the current scope in [`Parser::scopes`]
+    fn scope(&self) -> usize {
+        self.open_scopes.last().map_or(0, |scope| scope.index)
+    }
+
+    /// Qualified name of `name` (as written) defined in the current scope.
+    fn qualified(&self, name: &str) -> String {
+        match self.scopes[self.scope()].as_str() {
+            "" => self.identifier(name),
+            scope => format!("{scope}::{}", self.identifier(name)),
+        }
+    }
+
+    /// Defines `name` (as written) at `position` in the current scope, returns its qualified name.
+    /// a label or constant can be defined once, see [`AssemblerErrorKind::DuplicateLabel`]
+    fn define(&mut self, name: &str, position: Position) -> AssemblerResult<String> {
+        let name = self.qualified(name);
+        if let Some(&previous) = self.labels.get(&name) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::DuplicateLabel { name, previous },
+                position,
+            ));
+        }
+        self.labels.insert(name.clone(), position);
+
+        Ok(name)
+    }
+
+    /// Defines the label `label` (as written), cheap local labels defined after it belong to it.
+    fn define_label(&mut self, label: &str, position: Position) -> AssemblerResult<String> {
+        if !label.starts_with(['.', '@']) {
+            self.global = self.identifier(label);
+        }
+
+        self.define(label, position)
+    }
+
+    /// `NAME = expression`, the current token is the first token of the expression.
+    fn parse_constant_definition(
+        &mut self,
+        name: &str,
+        position: Position,
+    ) -> AssemblerResult<Statement> {
+        let value = self.parse_expression()?;
+        self.define_constant(name, value, position)
+    }
+
+    /// Defines the constant `name` (as written) with the value.
+    fn define_constant(
+        &mut self,
+        name: &str,
+        value: Expression,
+        position: Position,
+    ) -> AssemblerResult<Statement> {
+        let name = self.define(name, position)?;
+
+        // constants that refer to labels or later constants are evaluated by the assembler
+        match self.evaluate_constant(&value) {
+            Ok(constant) => self.constants.insert(name.clone(), constant),
+            Err(_) => self.constants.remove(&name),
+        };
+
+        Ok(Statement::Constant(Constant::new(name, value, position)))
+    }
+
+    /// `:-`, `:--`, `:+`, `:++`, the n-th anonymous label before or after the current position.
+    /// the current token is the last `-`/`+` afterwards, like the other single token operands.
+    fn parse_anonymous_label(&mut self) -> AssemblerResult<String> {
+        let position = self.position;
+        let mut offset = 0isize;
+        loop {
+            match self.peek_token.kind {
+                TokenKind::Minus if offset <= 0 => offset -= 1,
+                TokenKind::Plus if offset >= 0 => offset += 1,
+                _ => break,
+            }
+            self.next_token()?;
+        }
+
+        // `:-` is the last defined label, `:+` the next one
+        let defined = self.anonymous as isize;
+        let index = if offset < 0 {
+            defined + offset + 1
+        } else {
+            defined + offset
+        };
+        if offset == 0 || index < 1 {
+            let reference = match offset {
+                0 => ":".to_string(),
+                _ => format!(":{}", "-".repeat(offset.unsigned_abs())),
+            };
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::InvalidLabel(reference),
+                position,
+            ));
+        }
+
+        Ok(format!(":{index}"))
+    }
+
+    /// Parses a label reference that does not start with an identifier, `.local` or `:-`
+    fn parse_label_reference(&mut self) -> AssemblerResult<String> {
+        match self.current_token.kind {
+            TokenKind::Directive(local) => Ok(self.identifier(&format!(".{local}"))),
+            _ => self.parse_anonymous_label(),
+        }
+    }
+
+    /// Parses the program, returns the first error if there are any, see [`Parser::errors`]
+    pub fn parse(&mut self) -> AssemblerResult<Program> {
+        let program = self.parse_program();
+
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(program),
+        }
+    }
+
+    /// Parses the program, the statements with errors are left out and their errors are
+    /// collected in [`Parser::errors`], so that the assembler can report the errors of the rest.
+    pub fn parse_program(&mut self) -> Program {
+        let mut program = Program::default();
+
+        while self.current_token.kind != TokenKind::EOF {
+            if let Err(error) = self.parse_statement(&mut program) {
+                self.errors.push(error.with_backtrace(&self.expansions));
+                self.recover();
+            }
+        }
+
+        if let Some(condition) = self.conditions.last() {
+            self.errors.push(AssemblerError::new(
+                AssemblerErrorKind::UnterminatedConditional,
+                condition.position,
+            ));
+        }
+
+        if let Some(scope) = self.open_scopes.last() {
+            self.errors.push(AssemblerError::new(
+                AssemblerErrorKind::UnterminatedScope(scope.directive.clone()),
+                scope.position,
+            ));
+        }
+
+        program
+    }
+
+    /// Skips the rest of the line after an error, other errors in the line are not reported.
+    fn recover(&mut self) {
+        // the lexer always moves on after an error, every line ends eventually
+        while !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            let _ = self.next_token();
+        }
+
+        if self.current_token.kind == TokenKind::Newline {
+            let _ = self.next_token();
+        }
+    }
+
+    fn parse_statement(&mut self, program: &mut Program) -> AssemblerResult<()> {
+        if let TokenKind::Directive(directive) = self.current_token.kind {
+            if self.parse_conditional(directive)? {
+                return Ok(());
+            }
+        }
+
+        if !self.is_active() {
+            // not assembled, unknown mnemonics and directives are not errors here
+            return self.skip_line();
+        }
+
+        match self.current_token.kind {
+            TokenKind::Identifier(identifier)
+                if !is_label_definition(&self.current_token, &self.peek_token)
+                    && self.macros.contains_key(identifier) =>
+            {
+                self.expand_macro(identifier)?;
+            }
+            TokenKind::Directive(local)
+                if is_label_definition(&self.current_token, &self.peek_token) =>
+            {
+                // .local:
+                let label = self.define_label(&format!(".{local}"), self.position)?;
+                program.0.push(Statement::Label(label));
+                self.next_token()?;
+                self.next_token()?;
+            }
+            TokenKind::Colon => {
+                // anonymous label
+                self.anonymous += 1;
+                program
+                    .0
+                    .push(Statement::Label(format!(":{}", self.anonymous)));
+                self.next_token()?;
+            }
+            TokenKind::Directive(directive)
+                if ["proc", "endproc", "scope", "endscope"]
+                    .iter()
+                    .any(|scope| directive.eq_ignore_ascii_case(scope)) =>
+            {
+                if let Some(statement) = self.parse_scope(directive)? {
+                    program.0.push(statement);
+                }
+
+                if self.current_token.kind == TokenKind::Newline {
+                    self.next_token()?;
+                }
+            }
+            TokenKind::Directive(directive) if directive.eq_ignore_ascii_case("macro") => {
+                self.parse_macro()?;
+            }
+            TokenKind::Directive(directive) if directive.eq_ignore_ascii_case("dialect") => {
+                self.parse_dialect()?;
+            }
+            TokenKind::Directive(directive) if directive.eq_ignore_ascii_case("setcpu") => {
+                self.parse_setcpu()?;
+            }
+            TokenKind::Directive(directive) if self.ca65() && ca65_cpu(directive).is_some() => {
+                self.cpu = ca65_cpu(directive).unwrap();
+                self.next_token()?;
+                self.end_line()?;
+            }
+            TokenKind::Directive(directive)
+                if self.ca65() && directive.eq_ignore_ascii_case("repeat") =>
+            {
+                self.parse_repeat()?;
+            }
+            TokenKind::Directive(directive)
+                if self.ca65() && CA65_IGNORED.contains(&directive.to_lowercase().as_str()) =>
+            {
+                self.skip_line()?;
+            }
+            TokenKind::Directive(directive) if directive.eq_ignore_ascii_case("include") => {
+                self.parse_include()?;
+            }
+            TokenKind::Identifier(identifier) => {
+                let statement = self.parse_identifier(identifier)?;
+                program.0.push(statement);
+
+                if self.current_token.kind == TokenKind::Newline {
+                    self.next_token()?;
+                }
+            }
+            TokenKind::Directive(directive) => {
+                let statement = self.parse_directive(directive)?;
+                program.0.push(statement);
+
+                if self.current_token.kind == TokenKind::Newline {
+                    self.next_token()?;
+                }
+            }
+            TokenKind::Asterisk => {
+                // *= $0000
+                let position = self.position;
+                self.next_token()?;
+                self.expect_token(&TokenKind::Equal)?;
+                program
+                    .0
+                    .push(Statement::Org(self.parse_address()?, position));
+
+                if self.current_token.kind == TokenKind::Newline {
+                    self.next_token()?;
+                }
+            }
+            TokenKind::Newline => self.next_token()?,
+            TokenKind::Define => {
+                if let Some(statement) = self.parse_define()? {
+                    program.0.push(statement);
+                }
+
+                if self.current_token.kind == TokenKind::Newline {
+                    self.next_token()?;
+                }
+            }
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::UnexpectedToken {
+                        expected: TokenKind::Identifier("identifier").to_string(),
+                        found: self.current_token.kind.to_string(),
+                    },
+                    self.span(),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses `.proc name`, `.scope name`, `.endproc` and `.endscope`.
+    /// `.proc` also defines `name` as a label in the enclosing scope.
+    fn parse_scope(&mut self, directive: &str) -> AssemblerResult<Option<Statement>> {
+        let position = self.position;
+        let directive = directive.to_lowercase();
+        self.next_token()?;
+
+        if let Some(opening) = directive.strip_prefix("end") {
+            return match self.open_scopes.last() {
+                Some(scope) if scope.directive == opening => {
+                    self.global = self.open_scopes.pop().unwrap().global;
+                    Ok(None)
+                }
+                _ => Err(AssemblerError::new(
+                    AssemblerErrorKind::UnmatchedScope(directive),
+                    position,
+                )),
+            };
+        }
+
+        let name = match self.current_token.kind {
+            TokenKind::Identifier(name) => name,
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::UnexpectedToken {
+                        expected: TokenKind::Identifier("identifier").to_string(),
+                        found: self.current_token.kind.to_string(),
+                    },
+                    self.span(),
+                ))
+            }
+        };
+        self.next_token()?;
+
+        // a procedure is entered from outside of it, its label is not reported as unused
+        let statement = match directive.as_str() {
+            "proc" => {
+                let label = self.define_label(name, position)?;
+                self.used.borrow_mut().insert(label.clone());
+                Some(Statement::Label(label))
+            }
+            _ => None,
+        };
+
+        let qualified = match self.scopes[self.scope()].as_str() {
+            "" => name.to_string(),
+            scope => format!("{scope}::{name}"),
+        };
+        // a scope can be reopened, its labels are shared
+        let index = match self.scopes.iter().position(|scope| *scope == qualified) {
+            Some(index) => index,
+            None => {
+                self.scopes.push(qualified);
+                self.scopes.len() - 1
+            }
+        };
+
+        self.open_scopes.push(OpenScope {
+            index,
+            directive,
+            position,
+            global: std::mem::take(&mut self.global),
+        });
+        // the position of the following tokens is in the new scope
+        self.position.scope = index;
+
+        Ok(statement)
+    }
+
+    /// Whether the current line is assembled, see [`Condition`]
+    fn is_active(&self) -> bool {
+        self.conditions
+            .last()
+            .is_none_or(|condition| condition.active)
+    }
+
+    fn skip_line(&mut self) -> AssemblerResult<()> {
+        while !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            self.next_token()?;
+        }
+
+        if self.current_token.kind == TokenKind::Newline {
+            self.next_token()?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses `.if`, `.ifdef`, `.ifndef`, `.elif`, `.else` and `.endif`.
+    /// returns `false` if the directive is not a conditional directive.
+    fn parse_conditional(&mut self, directive: &'a str) -> AssemblerResult<bool> {
+        let position = self.position;
+        let directive = match directive.to_lowercase() {
+            directive if directive == "elseif" && self.ca65() => "elif".to_string(),
+            directive => directive,
+        };
+        let enclosing = self.is_active();
+
+        let unmatched = || {
+            AssemblerError::new(
+                AssemblerErrorKind::UnmatchedConditional(directive.clone()),
+                position,
+            )
+        };
+
+        match directive.as_str() {
+            "if" | "ifdef" | "ifndef" => {
+                self.next_token()?;
+
+                let active = enclosing && self.parse_condition(&directive)?;
+                self.conditions.push(Condition {
+                    active,
+                    taken: active || !enclosing,
+                    position,
+                });
+            }
+            "elif" => {
+                let condition = *self.conditions.last().ok_or_else(unmatched)?;
+                self.next_token()?;
+
+                let active = !condition.taken && self.parse_condition("if")?;
+                if let Some(condition) = self.conditions.last_mut() {
+                    condition.active = active;
+                    condition.taken |= active;
+                }
+            }
+            "else" => {
+                let condition = self.conditions.last_mut().ok_or_else(unmatched)?;
+                condition.active = !condition.taken;
+                condition.taken = true;
+                self.next_token()?;
+            }
+            "endif" => {
+                self.conditions.pop().ok_or_else(unmatched)?;
+                self.next_token()?;
+            }
+            directive if self.ca65() && CA65_UNSUPPORTED_CONDITIONALS.contains(&directive) => {
+                if enclosing {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::Unsupported(format!(".{directive}")),
+                        position,
+                    ));
+                }
+                // not assembled, its `.endif` still ends it
+                self.next_token()?;
+                self.conditions.push(Condition {
+                    active: false,
+                    taken: true,
+                    position,
+                });
+            }
+            _ => return Ok(false),
+        }
+
+        // the rest of the line is skipped if the condition was not evaluated
+        let active = self.is_active();
+        if active && !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            self.expected(&TokenKind::Newline)?;
+        }
+        self.skip_line()?;
+
+        Ok(true)
+    }
+
+    fn parse_condition(&mut self, directive: &str) -> AssemblerResult<bool> {
+        let position = self.position;
+
+        if directive == "if" {
+            return Ok(self.parse_constant()? != 0);
+        }
+
+        let defined = match self.current_token.kind {
+            TokenKind::Identifier(identifier) => {
+                self.symbols.contains_key(identifier)
+                    || self.macros.contains_key(identifier)
+                    || qualified_names(&self.scopes[self.scope()], &self.identifier(identifier))
+                        .into_iter()
+                        .find(|label| self.labels.contains_key(label))
+                        .map(|label| self.used.borrow_mut().insert(label))
+                        .is_some()
+            }
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::UnexpectedToken {
+                        expected: TokenKind::Identifier("identifier").to_string(),
+                        found: self.current_token.kind.to_string(),
+                    },
+                    position,
+                ))
+            }
+        };
+        self.next_token()?;
+
+        Ok(if directive == "ifdef" {
+            defined
+        } else {
+            !defined
+        })
+    }
+
+    /// Parses an expression that is evaluated while parsing,
+    /// it can only refer to symbols and constants defined before.
+    fn parse_constant(&mut self) -> AssemblerResult<i32> {
+        let position = self.position;
+        let expression = self.parse_expression()?;
+
+        self.evaluate_constant(&expression)
+            .map_err(|kind| AssemblerError::new(kind, position))
+    }
+
+    fn evaluate_constant(&self, expression: &Expression) -> Result<i32, AssemblerErrorKind> {
+        let scope = &self.scopes[self.scope()];
+
+        expression.evaluate(None, &|identifier| {
+            qualified_names(scope, identifier)
+                .into_iter()
+                .find_map(|name| {
+                    let value = *self.constants.get(&name)?;
+                    self.used.borrow_mut().insert(name);
+                    Some(value)
+                })
+                .or_else(|| self.symbols.get(identifier).copied())
+        })
+    }
+
+    /// Resolves `path` relative to the file containing `position`.
+    fn resolve(&self, path: &str, position: Position) -> AssemblerResult<String> {
+        let from = self
+            .sources
+            .filter(|_| position.file > 0)
+            .map(|sources| sources.files[position.file].name.as_str());
+
+        self.sources
+            .and_then(|sources| sources.resolver)
+            .and_then(|resolver| resolver.resolve(path, from))
+            .ok_or_else(|| {
+                AssemblerError::new(AssemblerErrorKind::FileNotFound(path.to_string()), position)
+            })
+    }
+
+    fn read(&self, name: String, position: Position) -> AssemblerResult<Vec<u8>> {
+        match self.sources.and_then(|sources| sources.resolver) {
+            Some(resolver) => resolver.read(&name).map_err(|e| {
+                AssemblerError::new(
+                    AssemblerErrorKind::FileError(format!("{name:?}: {e}")),
+                    position,
+                )
+            }),
+            None => Err(AssemblerError::new(
+                AssemblerErrorKind::FileNotFound(name),
+                position,
+            )),
+        }
+    }
+
+    /// `.include "file"`, the tokens of the file are read before the rest of the source.
+    fn parse_include(&mut self) -> AssemblerResult<()> {
+        let position = self.position;
+        self.next_token()?;
+
+        let path = match self.current_token.kind {
+            TokenKind::String(path) => {
+                String::from_utf8_lossy(&self.lexer.unescape(path).unwrap_or_default()).to_string()
+            }
+            _ => return self.expected(&TokenKind::String("string")),
+        };
+        if !matches!(self.peek_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            self.next_token()?;
+            return self.expected(&TokenKind::Newline);
+        }
+
+        let name = self.resolve(&path, position)?;
+        let Some((sources, file)) = self
+            .sources
+            .and_then(|sources| Some((sources, sources.find(&name)?)))
+        else {
+            // not loaded by `Sources::load`, reading it again reports why
+            self.read(name.clone(), position)?;
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::FileError(format!("{name:?}: invalid UTF-8")),
+                position,
+            ));
+        };
+
+        // the lexer of the including file may already be resumed by the lookahead,
+        // the chain of including files is followed from the file of the directive instead
+        let mut including = Some(position.file);
+        while let Some(parent) = including {
+            if parent == file {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::IncludeRecursion(name),
+                    position,
+                ));
+            }
+            including = self.included_at.get(&parent).map(|position| position.file);
+        }
+        self.included_at.insert(file, position);
+
+        let mut included = Lexer::with_file(&sources.files[file].source, file);
+        included.dialect = self.lexer.dialect;
+        let lexer = std::mem::replace(&mut self.lexer, included);
+        self.includes.push(Include {
+            lexer,
+            peek_token: self.peek_token,
+            pending: std::mem::take(&mut self.pending),
+        });
+
+        self.peek_token = self.lexer_token()?;
+        self.next_token()
+    }
+
+    /// `.incbin "file"[, offset[, length]]`
+    fn parse_incbin(&mut self, position: Position) -> AssemblerResult<Vec<u8>> {
+        let path = String::from_utf8_lossy(&self.parse_string()?).to_string();
+        let name = self.resolve(&path, position)?;
+        let bytes = self.read(name, position)?;
+
+        let mut arguments = Vec::new();
+        while arguments.len() < 2 && self.current_token.kind == TokenKind::Comma {
+            self.next_token()?;
+            let value = self.parse_constant()?;
+            if value < 0 {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::OutOfRange(value),
+                    position,
+                ));
+            }
+            arguments.push(value as usize);
+        }
+
+        let offset = arguments.first().copied().unwrap_or(0);
+        let length = arguments
+            .get(1)
+            .copied()
+            .unwrap_or(bytes.len().saturating_sub(offset));
+
+        match bytes.get(offset..offset + length) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange((offset + length) as i32),
+                position,
+            )),
+        }
+    }
+
+    fn parse_identifier(&mut self, identifier: &'a str) -> AssemblerResult<Statement> {
+        let position = self.position;
+
+        if is_label_definition(&self.current_token, &self.peek_token) {
+            let label = self.define_label(identifier, position)?;
+            self.next_token()?;
+            self.next_token()?;
+
+            return Ok(Statement::Label(label));
+        }
+
+        let constant = match self.peek_token.kind {
+            TokenKind::Equal => true,
+            TokenKind::Directive(directive) => directive.eq_ignore_ascii_case("equ"),
+            _ => false,
+        };
+        if constant {
+            // NAME = expression, NAME .equ expression
+            self.next_token()?;
+            self.next_token()?;
+
+            return self.parse_constant_definition(identifier, position);
+        }
+
+        if identifier.eq_ignore_ascii_case("dcb") {
+            self.next_token()?;
+
+            return Ok(Statement::Data(Data::new(
+                DataKind::Byte(self.parse_expression_list()?),
+                position,
+            )));
+        }
+
+        let long = long_branch(identifier);
+        let instruction = match long {
+            Some(branch) => branch,
+            None => Mnemonics::from_name(identifier).ok_or_else(|| {
+                AssemblerError::new(
+                    AssemblerErrorKind::InvalidMnemonic(identifier.to_string()),
+                    position,
+                )
+            })?,
+        };
+        if !self.cpu.has(instruction) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::UnavailableInstruction {
+                    mnemonic: instruction.to_string(),
+                    cpu: self.cpu,
+                },
+                position,
+            ));
+        }
+        self.next_token()?;
+        let mut operand = if instruction.is_bit_branch() {
+            self.parse_bit_branch_operand()?
+        } else if self.is_accumulator_operand(instruction) {
+            if !instruction.supports(AddressingMode::ACC, self.cpu) {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::UnavailableAccumulatorMode {
+                        mnemonic: instruction.to_string(),
+                        cpu: self.cpu,
+                    },
+                    self.span(),
+                ));
+            }
+            self.next_token()?;
+            Operand::new(AddressingMode::ACC, None)
+        } else if let Some(operand) = self.define_operand() {
+            self.next_token()?;
+            operand
+        } else {
+            self.parse_operand()?
+        };
+        // the target of a long branch is an address, not an offset
+        if let (Some(_), Some(OperandData::Number(number))) = (long, &operand.value) {
+            let value = match *number {
+                NumberType::Decimal8(n) | NumberType::Hexadecimal8(n) => n as i32,
+                NumberType::Decimal16(n) | NumberType::Hexadecimal16(n) => n as i32,
+            };
+            operand.value = Some(OperandData::Expression(Expression::Number(value)));
+        }
+        // `z:` is an error for instructions without the zero page mode
+        if operand
+            .value
+            .as_ref()
+            .is_some_and(OperandData::is_expression)
+            && operand.width != OperandWidth::ZeroPage
+        {
+            operand.addressing_mode =
+                expression_addressing_mode(instruction, operand.addressing_mode, self.cpu);
+        }
+        // `JMP $10` and `LDA $10,Y` use the absolute mode like expressions in the zero page
+        if let (OperandWidth::Auto, Some(OperandData::Number(number))) =
+            (operand.width, &mut operand.value)
+        {
+            let mode = expression_addressing_mode(instruction, operand.addressing_mode, self.cpu);
+            if mode != operand.addressing_mode && mode != AddressingMode::REL {
+                operand.addressing_mode = mode;
+                *number = number_with_width(*number, OperandWidth::Absolute, Span::from(position))?;
+            }
+        }
+        operand.addressing_mode =
+            implied_addressing_mode(instruction, operand.addressing_mode, self.cpu);
+        operand.addressing_mode =
+            indirect_addressing_mode(instruction, operand.addressing_mode, self.cpu);
+        // `JMP ($12)` and `JMP ($12,X)` read the pointer at a 16-bit address
+        if let (AddressingMode::IND | AddressingMode::IAX, Some(OperandData::Number(number))) =
+            (operand.addressing_mode, &mut operand.value)
+        {
+            *number = number_with_width(*number, OperandWidth::Absolute, Span::from(position))?;
+        }
+        if long.is_some() && operand.addressing_mode != AddressingMode::REL {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::InvalidInstruction(
+                    identifier.to_uppercase(),
+                    operand.addressing_mode,
+                ),
+                position,
+            ));
+        }
+
+        Ok(Statement::Instruction(Instruction {
+            opcode: instruction,
+            operand,
+            position,
+            long: long.is_some(),
+            cpu: self.cpu,
+        }))
+    }
+
+    fn parse_macro(&mut self) -> AssemblerResult<()> {
+        let position = self.position;
+        self.next_token()?;
+
+        let name = match self.current_token.kind {
+            TokenKind::Identifier(name) => name,
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::UnexpectedToken {
+                        expected: TokenKind::Identifier("identifier").to_string(),
+                        found: self.current_token.kind.to_string(),
+                    },
+                    self.span(),
+                ))
+            }
+        };
+        self.next_token()?;
+
+        let mut params = Vec::new();
+        while let TokenKind::Identifier(param) = self.current_token.kind {
+            params.push(param);
+            self.next_token()?;
+
+            if self.current_token.kind != TokenKind::Comma {
+                break;
+            }
+            self.next_token()?;
+        }
+
+        if !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            self.expected(&TokenKind::Newline)?;
+        }
+
+        let mut body = Vec::new();
+        loop {
+            self.next_token()?;
+            match self.current_token.kind {
+                TokenKind::Directive(directive)
+                    if directive.eq_ignore_ascii_case("endm")
+                        || (self.ca65()
+                            && ["endmacro", "endmac"]
+                                .iter()
+                                .any(|end| directive.eq_ignore_ascii_case(end))) =>
+                {
+                    break
+                }
+                TokenKind::Directive(directive) if directive.eq_ignore_ascii_case("macro") => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::NestedMacro,
+                        self.span(),
+                    ))
+                }
+                TokenKind::EOF => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::UnterminatedMacro(name.to_string()),
+                        position,
+                    ))
+                }
+                _ => body.push(self.current_token),
+            }
+        }
+        self.next_token()?;
+
+        let locals = body
+            .windows(2)
+            .filter(|tokens| is_label_definition(&tokens[0], &tokens[1]))
+            .filter_map(|tokens| match tokens[0].kind {
+                TokenKind::Identifier(label) => Some(label.to_string()),
+                TokenKind::Directive(label) => Some(format!(".{label}")),
+                _ => None,
+            })
+            .collect();
+
+        self.macros.insert(
+            name,
+            Macro {
+                params,
+                body,
+                locals,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &'a str) -> AssemblerResult<()> {
+        let call_site = self.position;
+
+        let mut depth = 0;
+        let mut expansion = call_site.expansion;
+        while let Some(parent) = expansion
+            .checked_sub(1)
+            .and_then(|i| self.expansions.get(i))
+        {
+            depth += 1;
+            expansion = parent.call_site.expansion;
+        }
+        if depth >= MAX_MACRO_DEPTH {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::MacroRecursion(name.to_string()),
+                call_site,
+            ));
+        }
+
+        self.next_token()?;
+
+        // arguments are separated by commas outside of parentheses, `M ($00,X), 1`
+        let mut arguments = Vec::new();
+        let mut argument = Vec::new();
+        let mut parens = 0usize;
+        while !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            match self.current_token.kind {
+                TokenKind::LParen => parens += 1,
+                TokenKind::RParen => parens = parens.saturating_sub(1),
+                TokenKind::Comma if parens == 0 => {
+                    arguments.push(std::mem::take(&mut argument));
+                    self.next_token()?;
+                    continue;
+                }
+                _ => {}
+            }
+            argument.push(self.current_token);
+            self.next_token()?;
+        }
+        if !argument.is_empty() || !arguments.is_empty() {
+            arguments.push(argument);
+        }
+
+        let Macro {
+            params,
+            body,
+            locals,
+        } = self.macros[name].clone();
+
+        if arguments.len() != params.len() {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::MacroArguments {
+                    name: name.to_string(),
+                    expected: params.len(),
+                    found: arguments.len(),
+                },
+                call_site,
+            ));
+        }
+
+        self.expansions.push(Expansion {
+            name: name.to_string(),
+            call_site,
+            locals,
+        });
+        let expansion = self.expansions.len();
+
+        let mut tokens = Vec::new();
+        for mut token in body {
+            if let TokenKind::Identifier(identifier) = token.kind {
+                if let Some(i) = params.iter().position(|param| *param == identifier) {
+                    tokens.extend(arguments[i].iter().copied());
+                    continue;
+                }
+            }
+
+            token.position.expansion = expansion;
+            tokens.push(token);
+        }
+
+        // the expansion is read before the rest of the invocation line
+        self.pending.push_front(self.peek_token);
+        self.pending.push_front(self.current_token);
+        for token in tokens.into_iter().rev() {
+            self.pending.push_front(token);
+        }
+        self.next_token()?;
+        self.next_token()?;
+
+        Ok(())
+    }
+
+    fn parse_directive(&mut self, directive: &'a str) -> AssemblerResult<Statement> {
+        let position = self.position;
+        self.next_token()?;
+
+        if self.ca65() {
+            if let Some(statement) =
+                self.parse_ca65_directive(&directive.to_lowercase(), position)?
+            {
+                return Ok(statement);
+            }
+        }
+
+        Ok(match directive.to_lowercase().as_str() {
+            "org" => Statement::Org(self.parse_address()?, position),
+            "byte" => Statement::Data(Data::new(
+                DataKind::Byte(self.parse_expression_list()?),
+                position,
+            )),
+            "word" => Statement::Data(Data::new(
+                DataKind::Word(self.parse_expression_list()?),
+                position,
+            )),
+            "text" => Statement::Data(Data::new(DataKind::Text(self.parse_string()?), position)),
+            "asciiz" => {
+                let mut text = self.parse_string()?;
+                text.push(0);
+                Statement::Data(Data::new(DataKind::Text(text), position))
+            }
+            "fill" => {
+                let count = self.parse_expression()?;
+                let value = if self.current_token.kind == TokenKind::Comma {
+                    self.next_token()?;
+                    self.parse_expression()?
+                } else {
+                    Expression::Number(0)
+                };
+
+                Statement::Data(Data::new(DataKind::Fill(count, value), position))
+            }
+            "res" => Statement::Data(Data::new(DataKind::Res(self.parse_expression()?), position)),
+            "equ" => {
+                // .equ NAME, expression
+                let TokenKind::Identifier(name) = self.current_token.kind else {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::UnexpectedToken {
+                            expected: TokenKind::Identifier("identifier").to_string(),
+                            found: self.current_token.kind.to_string(),
+                        },
+                        self.span(),
+                    ));
+                };
+                self.next_token()?;
+                self.expect_token(&TokenKind::Comma)?;
+
+                self.parse_constant_definition(name, position)?
+            }
+            "incbin" => Statement::Data(Data::new(
+                DataKind::Text(self.parse_incbin(position)?),
+                position,
+            )),
+            "segment" => Statement::Segment(self.parse_segment(position)?),
+            "import" => Statement::Import(self.parse_identifier_list()?, false, position),
+            "export" => Statement::Export(self.parse_identifier_list()?, position),
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::InvalidDirective(directive.to_string()),
+                    position,
+                ))
+            }
+        })
+    }
+
+    /// Parses the directives of ca65 that are not native directives or differ from them,
+    /// returns `None` for the other directives.
+    fn parse_ca65_directive(
+        &mut self,
+        directive: &str,
+        position: Position,
+    ) -> AssemblerResult<Option<Statement>> {
+        let bytes = |operator| {
+            move |expressions: Vec<Expression>| {
+                expressions
+                    .into_iter()
+                    .map(|expression| Expression::Unary(operator, Box::new(expression)))
+                    .collect()
+            }
+        };
+
+        let kind = match directive {
+            "byte" | "byt" => DataKind::Byte(self.parse_ca65_byte_list()?),
+            "addr" => DataKind::Word(self.parse_expression_list()?),
+            // big endian words
+            "dbyt" => DataKind::Byte(
+                self.parse_expression_list()?
+                    .into_iter()
+                    .flat_map(|expression| {
+                        [UnaryOperator::High, UnaryOperator::Low].map(|operator| {
+                            Expression::Unary(operator, Box::new(expression.clone()))
+                        })
+                    })
+                    .collect(),
+            ),
+            "lobytes" => DataKind::Byte(bytes(UnaryOperator::Low)(self.parse_expression_list()?)),
+            "hibytes" => DataKind::Byte(bytes(UnaryOperator::High)(self.parse_expression_list()?)),
+            "bankbytes" => {
+                DataKind::Byte(bytes(UnaryOperator::Bank)(self.parse_expression_list()?))
+            }
+            // `.res count, value` fills the bytes
+            "res" => {
+                let count = self.parse_expression()?;
+                if self.current_token.kind == TokenKind::Comma {
+                    self.next_token()?;
+                    DataKind::Fill(count, self.parse_expression()?)
+                } else {
+                    DataKind::Res(count)
+                }
+            }
+            "code" | "rodata" | "data" | "bss" | "zeropage" => {
+                return Ok(Some(Statement::Segment(Segment {
+                    name: directive.to_uppercase(),
+                    zero_page: directive == "zeropage",
+                    position,
+                })))
+            }
+            "importzp" => {
+                return Ok(Some(Statement::Import(
+                    self.parse_identifier_list()?,
+                    true,
+                    position,
+                )))
+            }
+            "exportzp" => {
+                return Ok(Some(Statement::Export(
+                    self.parse_identifier_list()?,
+                    position,
+                )))
+            }
+            "define" => return self.parse_ca65_define(position).map(Some),
+            directive if CA65_UNSUPPORTED.contains(&directive) => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::Unsupported(format!(".{directive}")),
+                    position,
+                ))
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Statement::Data(Data::new(kind, position))))
+    }
+
+    /// `.byte` of ca65, strings are their bytes (`.byte "text", 0`)
+    fn parse_ca65_byte_list(&mut self) -> AssemblerResult<Vec<Expression>> {
+        let mut expressions = Vec::new();
+        loop {
+            match self.current_token.kind {
+                TokenKind::String(_) => expressions.extend(
+                    self.parse_string()?
+                        .into_iter()
+                        .map(|byte| Expression::Number(byte as i32)),
+                ),
+                _ => expressions.push(self.parse_expression()?),
+            }
+
+            if self.current_token.kind != TokenKind::Comma {
+                return Ok(expressions);
+            }
+            self.next_token()?;
+        }
+    }
+
+    /// `.define NAME value` of ca65, only values that are expressions are supported (as constants).
+    fn parse_ca65_define(&mut self, position: Position) -> AssemblerResult<Statement> {
+        let name = match self.current_token.kind {
+            TokenKind::Identifier(name) => name,
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::UnexpectedToken {
+                        expected: TokenKind::Identifier("identifier").to_string(),
+                        found: self.current_token.kind.to_string(),
+                    },
+                    self.span(),
+                ))
+            }
+        };
+        self.next_token()?;
+
+        let unsupported = || {
+            AssemblerError::new(
+                AssemblerErrorKind::Unsupported(format!(
+                    ".define {name} with a value that is not an expression"
+                )),
+                position,
+            )
+        };
+        let statement =
+            self.parse_constant_definition(name, position)
+                .map_err(|error| match error.kind {
+                    AssemblerErrorKind::DuplicateLabel { .. } => error,
+                    _ => unsupported(),
+                })?;
+        if !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            return Err(unsupported());
+        }
+
+        Ok(statement)
+    }
+
+    /// `.dialect ca65` or `.dialect native`, the dialect of the rest of the file.
+    fn parse_dialect(&mut self) -> AssemblerResult<()> {
+        self.next_token()?;
+
+        let dialect = match self.current_token.kind {
+            TokenKind::Identifier(name) => Dialect::from_name(name),
+            _ => None,
+        };
+        let Some(dialect) = dialect else {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::UnexpectedToken {
+                    expected: "native or ca65".to_string(),
+                    found: self.current_token.kind.to_string(),
+                },
+                self.span(),
+            ));
+        };
+        // the next token is read in the new dialect
+        self.lexer.dialect = dialect;
+        self.next_token()?;
+
+        self.end_line()
+    }
+
+    /// `.setcpu "65C02"`, the CPU of the following instructions, see [`CpuType::from_name`]
+    fn parse_setcpu(&mut self) -> AssemblerResult<()> {
+        self.next_token()?;
+
+        let cpu = match self.current_token.kind {
+            TokenKind::String(name) => CpuType::from_name(name),
+            _ => None,
+        };
+        let Some(cpu) = cpu else {
+            let names = CpuType::ALL.map(|cpu| format!("\"{cpu}\""));
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::UnexpectedToken {
+                    expected: names.join(", "),
+                    found: self.current_token.kind.to_string(),
+                },
+                self.span(),
+            ));
+        };
+        self.cpu = cpu;
+        self.next_token()?;
+
+        self.end_line()
+    }
+
+    /// Expects the end of a statement that is not in the program and moves to the next line.
+    fn end_line(&mut self) -> AssemblerResult<()> {
+        if !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            self.expected(&TokenKind::Newline)?;
+        }
+        if self.current_token.kind == TokenKind::Newline {
+            self.next_token()?;
+        }
+
+        Ok(())
+    }
+
+    /// `.repeat count[, name]` ... `.endrep` of ca65, the body is read `count` times.
+    /// `name` is the number of the repetition in the body, from `0`.
+    fn parse_repeat(&mut self) -> AssemblerResult<()> {
+        let position = self.position;
+        self.next_token()?;
+
+        let count = self.parse_constant()?;
+        if !(0..=0xFFFF).contains(&count) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange(count),
+                position,
+            ));
+        }
+        let mut counter = None;
+        if self.current_token.kind == TokenKind::Comma {
+            self.next_token()?;
+            match self.current_token.kind {
+                TokenKind::Identifier(name) => counter = Some(name),
+                _ => self.expected(&TokenKind::Identifier("identifier"))?,
+            }
+            self.next_token()?;
+        }
+        if !matches!(self.current_token.kind, TokenKind::Newline | TokenKind::EOF) {
+            self.expected(&TokenKind::Newline)?;
+        }
+
+        // nested `.repeat` blocks are repeated with the body
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            self.next_token()?;
+            match self.current_token.kind {
+                TokenKind::Directive(directive) if directive.eq_ignore_ascii_case("repeat") => {
+                    depth += 1
+                }
+                TokenKind::Directive(directive)
+                    if ["endrep", "endrepeat"]
+                        .iter()
+                        .any(|end| directive.eq_ignore_ascii_case(end)) =>
+                {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                TokenKind::EOF => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::UnterminatedRepeat,
+                        position,
+                    ))
+                }
+                _ => {}
+            }
+            body.push(self.current_token);
+        }
+        self.next_token()?;
+
+        let mut tokens = Vec::new();
+        for i in 0..count {
+            tokens.extend(body.iter().map(|token| match token.kind {
+                TokenKind::Identifier(name) if Some(name) == counter => Token {
+                    kind: TokenKind::Decimal(i as u16),
+                    ..*token
+                },
+                _ => *token,
+            }));
+        }
+
+        // the repetitions are read before the rest of the `.endrep` line
+        self.pending.push_front(self.peek_token);
+        self.pending.push_front(self.current_token);
+        for token in tokens.into_iter().rev() {
+            self.pending.push_front(token);
+        }
+        self.next_token()?;
+        self.next_token()
+    }
+
+    /// `"NAME"`, `"NAME": zeropage` or `"NAME": absolute`
+    fn parse_segment(&mut self, position: Position) -> AssemblerResult<Segment> {
+        let name_span = self.span();
+        let name = String::from_utf8_lossy(&self.parse_string()?).into_owned();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::InvalidOperand(format!("{name:?}")),
+                name_span,
+            ));
+        }
+
+        let mut zero_page = name.eq_ignore_ascii_case("ZEROPAGE");
+        if self.current_token.kind == TokenKind::Colon {
+            self.next_token()?;
+            let size = match self.current_token.kind {
+                TokenKind::Identifier(size) => size.to_lowercase(),
+                _ => String::new(),
+            };
+            if size != "zeropage" && size != "absolute" {
+                self.expected(&TokenKind::Identifier("zeropage"))?;
+            }
+            zero_page = size == "zeropage";
+            self.next_token()?;
+        }
+
+        Ok(Segment {
+            name,
+            zero_page,
+            position,
+        })
+    }
+
+    /// `name, ...`, the names of `.import` and `.export`
+    fn parse_identifier_list(&mut self) -> AssemblerResult<Vec<String>> {
+        let mut names = Vec::new();
+        loop {
+            match self.current_token.kind {
+                TokenKind::Identifier(name) => names.push(self.identifier(name)),
+                _ => self.expected(&TokenKind::Identifier("identifier"))?,
+            }
+            self.next_token()?;
 
-#[derive(Debug, Default)]
-pub struct Parser<'a> {
-    pub lexer: Lexer<'a>,
-    current_token: Token<'a>,
-    peek_token: Token<'a>,
-    position: Position,
-    defines: HashMap<String, Operand>,
-}
+            if self.current_token.kind != TokenKind::Comma {
+                return Ok(names);
+            }
+            self.next_token()?;
+        }
+    }
 
-impl<'a> Parser<'a> {
-    pub fn new(lexer: Lexer<'a>) -> Self {
-        let mut parser = Self {
-            lexer,
-            ..Default::default()
+    fn parse_address(&mut self) -> AssemblerResult<u16> {
+        let position = self.position;
+        let address = self.parse_constant()?;
+
+        u16::try_from(address)
+            .map_err(|_| AssemblerError::new(AssemblerErrorKind::OutOfRange(address), position))
+    }
+
+    fn parse_string(&mut self) -> AssemblerResult<Vec<u8>> {
+        let string = match self.current_token.kind {
+            TokenKind::String(string) => string,
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::UnexpectedToken {
+                        expected: TokenKind::String("string").to_string(),
+                        found: self.current_token.kind.to_string(),
+                    },
+                    self.span(),
+                ))
+            }
         };
-        parser.next_token().unwrap();
-        parser.next_token().unwrap();
+        self.next_token()?;
 
-        parser
+        // escape sequences are already validated by the lexer
+        Ok(self.lexer.unescape(string).unwrap_or_default())
     }
 
-    fn next_token(&mut self) -> AssemblerResult<()> {
-        self.current_token = self.peek_token;
-        self.peek_token = self.lexer.next_token()?;
+    fn parse_expression_list(&mut self) -> AssemblerResult<Vec<Expression>> {
+        let mut expressions = vec![self.parse_expression()?];
 
-        self.position = self.current_token.position;
+        while self.current_token.kind == TokenKind::Comma {
+            self.next_token()?;
+            expressions.push(self.parse_expression()?);
+        }
 
-        Ok(())
+        Ok(expressions)
     }
 
-    fn expected(&mut self, expected: &TokenKind) -> AssemblerResult<()> {
-        Err(AssemblerError::new(
-            AssemblerErrorKind::UnexpectedToken {
-                expected: expected.to_string(),
-                found: self.current_token.kind.to_string(),
-            },
-            self.position,
-        ))
+    pub fn parse_expression(&mut self) -> AssemblerResult<Expression> {
+        self.parse_binary_expression(0)
     }
 
-    fn expect_token(&mut self, token: &TokenKind) -> AssemblerResult<()> {
-        if self.current_token.kind == *token {
-            self.next_token()?;
-            Ok(())
-        } else {
-            self.expected(token)
+    fn binary_operator(&self) -> Option<BinaryOperator> {
+        if self.ca65() {
+            return self.ca65_binary_operator();
         }
+
+        Some(match self.current_token.kind {
+            TokenKind::DoublePipe => BinaryOperator::LogicalOr,
+            TokenKind::DoubleAmpersand => BinaryOperator::LogicalAnd,
+            TokenKind::Pipe => BinaryOperator::Or,
+            TokenKind::Caret => BinaryOperator::Xor,
+            TokenKind::Ampersand => BinaryOperator::And,
+            TokenKind::EqualEqual => BinaryOperator::Equal,
+            TokenKind::NotEqual => BinaryOperator::NotEqual,
+            TokenKind::LessThan => BinaryOperator::Less,
+            TokenKind::GreaterThan => BinaryOperator::Greater,
+            TokenKind::LessEqual => BinaryOperator::LessEqual,
+            TokenKind::GreaterEqual => BinaryOperator::GreaterEqual,
+            TokenKind::ShiftLeft => BinaryOperator::Shl,
+            TokenKind::ShiftRight => BinaryOperator::Shr,
+            TokenKind::Plus => BinaryOperator::Add,
+            TokenKind::Minus => BinaryOperator::Sub,
+            TokenKind::Asterisk => BinaryOperator::Mul,
+            TokenKind::Slash => BinaryOperator::Div,
+            _ => return None,
+        })
     }
 
-    pub fn parse(&mut self) -> AssemblerResult<Program> {
-        let mut program = Program::default();
+    /// Binary operators of ca65, their precedence is the one of ca65 (see [`BinaryOperator::precedence`])
+    fn ca65_binary_operator(&self) -> Option<BinaryOperator> {
+        use BinaryOperator::*;
 
-        while self.current_token.kind != TokenKind::EOF {
-            match self.current_token.kind {
-                TokenKind::Identifier(identifier) => {
-                    let statement = self.parse_identifier(identifier)?;
-                    program.0.push(statement);
+        Some(match self.current_token.kind {
+            TokenKind::DoublePipe => LogicalOr,
+            TokenKind::DoubleAmpersand => LogicalAnd,
+            TokenKind::Equal | TokenKind::EqualEqual => Equal,
+            TokenKind::NotEqual => NotEqual,
+            TokenKind::LessThan => Less,
+            TokenKind::GreaterThan => Greater,
+            TokenKind::LessEqual => LessEqual,
+            TokenKind::GreaterEqual => GreaterEqual,
+            TokenKind::Plus => Add,
+            TokenKind::Minus => Sub,
+            TokenKind::Pipe => Or,
+            TokenKind::Asterisk => Mul,
+            TokenKind::Slash => Div,
+            TokenKind::Ampersand => And,
+            TokenKind::Caret => Xor,
+            TokenKind::ShiftLeft => Shl,
+            TokenKind::ShiftRight => Shr,
+            TokenKind::Directive(operator) => match operator.to_lowercase().as_str() {
+                "or" => LogicalOr,
+                "and" => LogicalAnd,
+                "xor" => LogicalXor,
+                "bitor" => Or,
+                "mod" => Mod,
+                "bitand" => And,
+                "bitxor" => Xor,
+                "shl" => Shl,
+                "shr" => Shr,
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
 
-                    if self.current_token.kind == TokenKind::Newline {
-                        self.next_token()?;
-                    }
-                }
-                TokenKind::Newline => self.next_token()?,
-                TokenKind::Define => {
-                    self.parse_define()?;
+    fn parse_binary_expression(&mut self, precedence: u8) -> AssemblerResult<Expression> {
+        let mut left = self.parse_unary_expression()?;
 
-                    if self.current_token.kind == TokenKind::Newline {
-                        self.next_token()?;
-                    }
+        while let Some(operator) = self.binary_operator() {
+            let operator_precedence = operator.precedence(self.lexer.dialect);
+            if operator_precedence < precedence {
+                break;
+            }
+            self.next_token()?;
+
+            let right = self.parse_binary_expression(operator_precedence + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary_expression(&mut self) -> AssemblerResult<Expression> {
+        if self.ca65() {
+            let operator = match self.current_token.kind {
+                TokenKind::Caret => Some(UnaryOperator::Bank),
+                TokenKind::Directive(operator) if operator.eq_ignore_ascii_case("bitnot") => {
+                    Some(UnaryOperator::Not)
                 }
-                _ => {
-                    return Err(AssemblerError::new(
-                        AssemblerErrorKind::UnexpectedToken {
-                            expected: TokenKind::Identifier("identifier").to_string(),
-                            found: self.current_token.kind.to_string(),
-                        },
-                        self.position,
-                    ))
+                TokenKind::Bang => Some(UnaryOperator::LogicalNot),
+                TokenKind::Directive(operator) if operator.eq_ignore_ascii_case("not") => {
+                    Some(UnaryOperator::LogicalNot)
                 }
+                _ => None,
+            };
+            if let Some(operator) = operator {
+                self.next_token()?;
+                // `!` and `.not` have the lowest precedence in ca65, `!a && b` is `!(a && b)`
+                let operand = match operator {
+                    UnaryOperator::LogicalNot => self.parse_binary_expression(0)?,
+                    _ => self.parse_unary_expression()?,
+                };
+                return Ok(Expression::Unary(operator, Box::new(operand)));
             }
         }
 
-        Ok(program)
+        let operator = match self.current_token.kind {
+            TokenKind::Minus => UnaryOperator::Negate,
+            TokenKind::Tilde => UnaryOperator::Not,
+            TokenKind::LessThan => UnaryOperator::Low,
+            TokenKind::GreaterThan => UnaryOperator::High,
+            TokenKind::Bang => UnaryOperator::LogicalNot,
+            _ => return self.parse_primary_expression(),
+        };
+        self.next_token()?;
+
+        Ok(Expression::Unary(
+            operator,
+            Box::new(self.parse_unary_expression()?),
+        ))
     }
 
-    fn parse_identifier(&mut self, identifier: &'a str) -> AssemblerResult<Statement> {
-        Ok(if self.peek_token.kind == TokenKind::Colon {
-            self.next_token()?;
-            self.next_token()?;
-            Statement::Label(identifier.to_string())
-        } else {
-            let instruction = Mnemonics::to_mnemonics(identifier, self.position)?;
-            self.next_token()?;
-            let operand = self.parse_operand()?;
+    fn parse_primary_expression(&mut self) -> AssemblerResult<Expression> {
+        let expression = match self.current_token.kind {
+            TokenKind::Decimal(n) | TokenKind::Hexadecimal(n) | TokenKind::Binary(n) => {
+                Expression::Number(n as i32)
+            }
+            TokenKind::Char(c) => Expression::Number(c as i32),
+            TokenKind::Identifier(identifier) => {
+                Expression::Identifier(self.identifier(identifier))
+            }
+            TokenKind::Directive(function) if self.ca65() => self.parse_ca65_function(function)?,
+            TokenKind::Directive(_) | TokenKind::Colon => {
+                Expression::Identifier(self.parse_label_reference()?)
+            }
+            TokenKind::Asterisk => Expression::CurrentAddress,
+            TokenKind::LParen => {
+                self.next_token()?;
+                let expression = self.parse_expression()?;
+                if self.current_token.kind != TokenKind::RParen {
+                    self.expected(&TokenKind::RParen)?;
+                }
+                expression
+            }
+            _ => {
+                return Err(AssemblerError::new(
+                    AssemblerErrorKind::InvalidOperand(self.current_token.kind.to_string()),
+                    self.span(),
+                ))
+            }
+        };
+        self.next_token()?;
 
-            Statement::Instruction(Instruction {
-                opcode: instruction,
-                operand,
-                position: self.position,
-            })
-        })
+        Ok(expression)
+    }
+
+    /// `.lobyte(x)`, `.hibyte(x)`, `.bankbyte(x)`, `.loword(x)`, `.hiword(x)` and `.defined(name)` of ca65.
+    /// the current token is the `)` afterwards, like the other primary expressions.
+    fn parse_ca65_function(&mut self, function: &str) -> AssemblerResult<Expression> {
+        let function = function.to_lowercase();
+        if ![
+            "lobyte", "hibyte", "bankbyte", "loword", "hiword", "defined", "def",
+        ]
+        .contains(&function.as_str())
+        {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::Unsupported(format!(".{function}")),
+                self.span(),
+            ));
+        }
+        self.next_token()?;
+        self.expect_token(&TokenKind::LParen)?;
+
+        let expression = match function.as_str() {
+            "defined" | "def" => Expression::Number(self.parse_condition("ifdef")? as i32),
+            function => {
+                let value = Box::new(self.parse_expression()?);
+                let mask = |value, mask| {
+                    Expression::Binary(
+                        BinaryOperator::And,
+                        Box::new(value),
+                        Box::new(Expression::Number(mask)),
+                    )
+                };
+                match function {
+                    "lobyte" => Expression::Unary(UnaryOperator::Low, value),
+                    "hibyte" => Expression::Unary(UnaryOperator::High, value),
+                    "bankbyte" => Expression::Unary(UnaryOperator::Bank, value),
+                    "loword" => mask(*value, 0xFFFF),
+                    _ => mask(
+                        Expression::Binary(
+                            BinaryOperator::Shr,
+                            value,
+                            Box::new(Expression::Number(16)),
+                        ),
+                        0xFFFF,
+                    ),
+                }
+            }
+        };
+        if self.current_token.kind != TokenKind::RParen {
+            self.expected(&TokenKind::RParen)?;
+        }
+
+        Ok(expression)
     }
 
     /*
@@ -118,10 +2016,10 @@ impl<'a> Parser<'a> {
     ZPX LDA $00,X ($00 is the operand)
     ZPY LDA $00,Y ($00 is the operand)
 
-    IMP ACC --> IMPACC
+    IMP ACC --> IMP, ACC if the instruction has no implied mode
 
     ZPG LDA $00 ($00 is the operand) -+
-    REL BNE $00 ($00 is the operand) -+--> RELZPG
+    REL BNE $00 ($00 is the operand) -+--> ZPG, REL if the instruction is a branch
     */
     fn parse_operand(&mut self) -> AssemblerResult<Operand> {
         Ok(match self.current_token.kind {
@@ -129,160 +2027,188 @@ impl<'a> Parser<'a> {
                 self.next_token()?;
                 let operand_data = self.parse_operand_data()?;
 
-                if !operand_data.is_8() {
+                if operand_data.is_number() && !operand_data.is_8() {
                     return Err(AssemblerError::new(
                         AssemblerErrorKind::InvalidOperand(operand_data.to_string()),
-                        self.position,
+                        self.span(),
                     ));
                 }
 
-                self.next_token()?;
-
                 Operand::new(AddressingMode::IMM, Some(operand_data))
             }
-            TokenKind::Hexadecimal8Bit(number) => {
-                // $00
-                self.next_token()?;
-                let operand_data = OperandData::Number(NumberType::Hexadecimal8(number));
-
-                self.parse_8bit_operand_comma(operand_data)?
-            }
-            TokenKind::Hexadecimal16Bit(number) => {
-                // $0000
-                self.next_token()?;
-                let operand_data = OperandData::Number(NumberType::Hexadecimal16(number));
-
-                self.parse_16bit_operand_comma(operand_data)?
-            }
             TokenKind::LParen => {
                 // (
                 self.next_token()?;
                 let operand_data = self.parse_operand_data()?; // ($0000
-                self.next_token()?;
 
                 self.parse_operand_lparen(operand_data)?
             }
-            TokenKind::Decimal(number) => {
-                // 00
-                self.next_token()?;
-                if number > 255 {
-                    let operand_data = OperandData::Number(NumberType::Decimal16(number));
-                    self.parse_16bit_operand_comma(operand_data)?
-                } else {
-                    let operand_data = OperandData::Number(NumberType::Decimal8(number as u8));
-                    self.parse_8bit_operand_comma(operand_data)?
-                }
-            }
-            TokenKind::Identifier(identifier) => {
-                self.next_token()?;
-                if let Some(operand) = self.defines.get(identifier) {
-                    match operand.addressing_mode {
-                        AddressingMode::ZPX
-                        | AddressingMode::ZPY
-                        | AddressingMode::ABX
-                        | AddressingMode::ABY => operand.clone(),
-                        _ => match operand.value.clone().unwrap() {
-                            OperandData::Number(number_type) => match number_type {
-                                NumberType::Decimal8(_) | NumberType::Hexadecimal8(_) => {
-                                    self.parse_8bit_operand_comma(operand.value.clone().unwrap())?
-                                }
-                                NumberType::Decimal16(_) | NumberType::Hexadecimal16(_) => {
-                                    self.parse_16bit_operand_comma(operand.value.clone().unwrap())?
-                                }
-                            },
-                            _ => operand.clone(),
-                        },
+            TokenKind::EOF | TokenKind::Newline => Operand::new(AddressingMode::IMP, None),
+            _ => {
+                // $00, $0000, LABEL, a:LABEL, z:LABEL
+                let width = self.parse_operand_width()?;
+                let start = self.position;
+                let operand_data = match (width, self.parse_operand_data()?) {
+                    (OperandWidth::Auto, operand_data) => operand_data,
+                    (width, OperandData::Number(number)) => {
+                        let span = Span::new(start, self.previous_end);
+                        OperandData::Number(number_with_width(number, width, span)?)
                     }
+                    (_, operand_data) => operand_data,
+                };
+
+                // expressions are absolute until the assembler knows their value
+                let zero_page = match width {
+                    OperandWidth::Auto => operand_data.is_8(),
+                    width => width == OperandWidth::ZeroPage,
+                };
+                let mut operand = if zero_page {
+                    self.parse_8bit_operand_comma(operand_data)?
                 } else {
-                    let operand_data = OperandData::Label(identifier.to_string());
-                    Operand::new(AddressingMode::RELZPG, Some(operand_data))
-                }
-            }
-            TokenKind::EOF | TokenKind::Newline => Operand::new(AddressingMode::IMPACC, None),
-            _ => {
-                return Err(AssemblerError::new(
-                    AssemblerErrorKind::InvalidOperand(self.current_token.kind.to_string()),
-                    self.position,
-                ))
+                    self.parse_16bit_operand_comma(operand_data)?
+                };
+                operand.width = width;
+
+                operand
             }
         })
     }
 
-    fn parse_operand_data(&mut self) -> AssemblerResult<OperandData> {
-        Ok(match self.current_token.kind {
-            TokenKind::Decimal(number) => {
-                if number > 255 {
-                    OperandData::Number(NumberType::Decimal16(number))
-                } else {
-                    OperandData::Number(NumberType::Decimal8(number as u8))
-                }
-            }
-            TokenKind::Hexadecimal8Bit(number) => {
-                OperandData::Number(NumberType::Hexadecimal8(number))
-            }
-            TokenKind::Hexadecimal16Bit(number) => {
-                OperandData::Number(NumberType::Hexadecimal16(number))
-            }
-            TokenKind::Identifier(identifier) => {
-                if let Some(operand) = self.defines.get(identifier) {
-                    operand.value.clone().unwrap()
-                } else {
-                    OperandData::Label(identifier.to_string())
-                }
-            }
-            _ => {
-                return Err(AssemblerError::new(
-                    AssemblerErrorKind::InvalidOperand(self.current_token.kind.to_string()),
-                    self.position,
-                ))
+    /// Operand of a `define` with an addressing mode if the current token is its name alone
+    /// (`STA STA_ADDR`), see [`Parser::parse_define`]
+    fn define_operand(&self) -> Option<Operand> {
+        match self.current_token.kind {
+            TokenKind::Identifier(name)
+                if matches!(self.peek_token.kind, TokenKind::EOF | TokenKind::Newline) =>
+            {
+                self.defines.get(name).cloned()
             }
+            _ => None,
+        }
+    }
+
+    /// Whether the current token is the `A` of `ASL A` (`INC A` of the CMOS CPUs, on every CPU),
+    /// `A` is a label for the instructions without the accumulator mode on any CPU.
+    fn is_accumulator_operand(&self, mnemonic: Mnemonics) -> bool {
+        matches!(self.current_token.kind, TokenKind::Identifier("a" | "A"))
+            && matches!(self.peek_token.kind, TokenKind::EOF | TokenKind::Newline)
+            && CpuType::ALL
+                .into_iter()
+                .any(|cpu| mnemonic.supports(AddressingMode::ACC, cpu))
+    }
+
+    /// `zp,target` of `BBR0` and `BBS0`, the target is an address like the target of a long branch.
+    fn parse_bit_branch_operand(&mut self) -> AssemblerResult<Operand> {
+        let start = self.position;
+        let value = self.parse_operand_data()?;
+        if value.is_number() && !value.is_8() {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::InvalidOperand(value.to_string()),
+                Span::new(start, self.previous_end),
+            ));
+        }
+        self.expect_token(&TokenKind::Comma)?;
+
+        Ok(Operand {
+            target: Some(self.parse_expression()?),
+            ..Operand::new(AddressingMode::ZPR, Some(value))
         })
     }
 
+    /// Parses the `a:` (absolute) or `z:` (zero page) prefix of an address operand.
+    fn parse_operand_width(&mut self) -> AssemblerResult<OperandWidth> {
+        let width = match self.current_token.kind {
+            TokenKind::Identifier("a" | "A") => OperandWidth::Absolute,
+            TokenKind::Identifier("z" | "Z") => OperandWidth::ZeroPage,
+            _ => return Ok(OperandWidth::Auto),
+        };
+        if self.peek_token.kind != TokenKind::Colon {
+            return Ok(OperandWidth::Auto);
+        }
+        self.next_token()?;
+        self.next_token()?;
+
+        Ok(width)
+    }
+
+    /// Parses the value of an operand.
+    /// a single number literal is 8-bit if its value is (`$0F` and `$000F` alike), other expressions are 16-bit,
+    /// the assembler selects zero page addressing for them (see [`OperandWidth::Auto`]).
+    fn parse_operand_data(&mut self) -> AssemblerResult<OperandData> {
+        let token = self.current_token.kind;
+        let expression = self.parse_expression()?;
+
+        // the parser does not fold constants, a number expression is a single literal
+        let Expression::Number(number) = expression else {
+            return Ok(OperandData::Expression(expression));
+        };
+        let number = number as u16;
+        Ok(OperandData::Number(match (token, u8::try_from(number)) {
+            (TokenKind::Hexadecimal(_), Ok(number)) => NumberType::Hexadecimal8(number),
+            (TokenKind::Hexadecimal(_), Err(_)) => NumberType::Hexadecimal16(number),
+            (_, Ok(number)) => NumberType::Decimal8(number),
+            (_, Err(_)) => NumberType::Decimal16(number),
+        }))
+    }
+
+    /// Index register at the current token, `X` or `Y` in any case.
+    /// they are identifiers outside of the index position of an operand (`xpos: LDA x,X`).
+    fn register(&self) -> Option<Register> {
+        match self.current_token.kind {
+            TokenKind::Identifier("x" | "X") => Some(Register::X),
+            TokenKind::Identifier("y" | "Y") => Some(Register::Y),
+            _ => None,
+        }
+    }
+
+    /// Parses the index register after the `,` of an operand, see [`Parser::register`]
+    fn parse_register(&mut self) -> AssemblerResult<Option<Register>> {
+        let register = self.register();
+        if register.is_some() {
+            self.next_token()?;
+        }
+
+        Ok(register)
+    }
+
     fn parse_8bit_operand_comma(&mut self, operand_data: OperandData) -> AssemblerResult<Operand> {
         Ok(if let TokenKind::Comma = self.current_token.kind {
             self.next_token()?;
-            match self.current_token.kind {
-                TokenKind::X => {
-                    self.next_token()?;
-                    // $00,X
-                    Operand::new(AddressingMode::ZPX, Some(operand_data))
-                }
-                TokenKind::Y => {
-                    self.next_token()?;
-                    // $00,Y
-                    Operand::new(AddressingMode::ZPY, Some(operand_data))
-                }
-                _ => {
+            match self.parse_register()? {
+                // $00,X
+                Some(Register::X) => Operand::new(AddressingMode::ZPX, Some(operand_data)),
+                // $00,Y
+                Some(Register::Y) => Operand::new(AddressingMode::ZPY, Some(operand_data)),
+                None => {
                     return Err(AssemblerError::new(
-                        AssemblerErrorKind::InvalidOperand(operand_data.to_string()),
-                        self.position,
+                        AssemblerErrorKind::UnexpectedToken {
+                            expected: "X or Y".to_string(),
+                            found: self.current_token.kind.to_string(),
+                        },
+                        self.span(),
                     ))
                 }
             }
         } else {
-            Operand::new(AddressingMode::RELZPG, Some(operand_data))
+            Operand::new(AddressingMode::ZPG, Some(operand_data))
         })
     }
 
     fn parse_16bit_operand_comma(&mut self, operand_data: OperandData) -> AssemblerResult<Operand> {
         Ok(if let TokenKind::Comma = self.current_token.kind {
             self.next_token()?;
-            match self.current_token.kind {
-                TokenKind::X => {
-                    self.next_token()?;
-                    // $0000,X
-                    Operand::new(AddressingMode::ABX, Some(operand_data))
-                }
-                TokenKind::Y => {
-                    self.next_token()?;
-                    // $0000,Y
-                    Operand::new(AddressingMode::ABY, Some(operand_data))
-                }
-                _ => {
+            match self.parse_register()? {
+                // $0000,X
+                Some(Register::X) => Operand::new(AddressingMode::ABX, Some(operand_data)),
+                // $0000,Y
+                Some(Register::Y) => Operand::new(AddressingMode::ABY, Some(operand_data)),
+                None => {
                     return Err(AssemblerError::new(
-                        AssemblerErrorKind::InvalidOperand(operand_data.to_string()),
-                        self.position,
+                        AssemblerErrorKind::UnexpectedToken {
+                            expected: "X or Y".to_string(),
+                            found: self.current_token.kind.to_string(),
+                        },
+                        self.span(),
                     ))
                 }
             }
@@ -297,7 +2223,11 @@ impl<'a> Parser<'a> {
                 // ($0000,
                 self.next_token()?;
 
-                self.expect_token(&TokenKind::X)?; // ($00,X
+                // ($00,X
+                if self.register() != Some(Register::X) {
+                    self.expected(&TokenKind::Identifier("X"))?;
+                }
+                self.next_token()?;
 
                 if let TokenKind::RParen = self.current_token.kind {
                     self.next_token()?;
@@ -309,7 +2239,7 @@ impl<'a> Parser<'a> {
                             expected: TokenKind::RParen.to_string(),
                             found: self.current_token.kind.to_string(),
                         },
-                        self.position,
+                        self.span(),
                     ));
                 }
             }
@@ -319,7 +2249,7 @@ impl<'a> Parser<'a> {
                 // ($0000),
                 if let TokenKind::Comma = self.current_token.kind {
                     self.next_token()?;
-                    if let TokenKind::Y = self.current_token.kind {
+                    if self.register() == Some(Register::Y) {
                         self.next_token()?;
                         // ($00),Y
                         Operand::new(AddressingMode::IDY, Some(operand_data))
@@ -327,10 +2257,10 @@ impl<'a> Parser<'a> {
                     } else {
                         return Err(AssemblerError::new(
                             AssemblerErrorKind::UnexpectedToken {
-                                expected: TokenKind::Y.to_string(),
+                                expected: TokenKind::Identifier("Y").to_string(),
                                 found: self.current_token.kind.to_string(),
                             },
-                            self.position,
+                            self.span(),
                         ));
                     }
                 } else {
@@ -340,13 +2270,19 @@ impl<'a> Parser<'a> {
             _ => {
                 return Err(AssemblerError::new(
                     AssemblerErrorKind::InvalidOperand(operand_data.to_string()),
-                    self.position,
+                    self.span(),
                 ))
             }
         })
     }
 
-    fn parse_define(&mut self) -> AssemblerResult<()> {
+    /// `define NAME value`, deprecated alias of `NAME = value`
+    /// `define NAME value`, a constant like `NAME = value`.
+    /// the old forms with an addressing mode (`define V #$05`, `define ADDR $0200,Y`) are kept as operands,
+    /// an instruction with the name alone as its operand uses it.
+    /// returns `None` for them, they are no statement
+    fn parse_define(&mut self) -> AssemblerResult<Option<Statement>> {
+        let position = self.position;
         self.expect_token(&TokenKind::Define)?;
         let identifier = match self.current_token.kind {
             TokenKind::Identifier(identifier) => identifier,
@@ -356,16 +2292,42 @@ impl<'a> Parser<'a> {
                         expected: TokenKind::Identifier("identifier").to_string(),
                         found: self.current_token.kind.to_string(),
                     },
-                    self.position,
+                    self.span(),
                 ))
             }
         };
         self.next_token()?;
 
         let operand = self.parse_operand()?;
-        self.defines.insert(identifier.to_string(), operand);
+        let value = match (operand.addressing_mode, operand.value) {
+            (AddressingMode::ZPG | AddressingMode::ABS, Some(OperandData::Number(number))) => {
+                Some(Expression::Number(match number {
+                    NumberType::Decimal8(n) | NumberType::Hexadecimal8(n) => n as i32,
+                    NumberType::Decimal16(n) | NumberType::Hexadecimal16(n) => n as i32,
+                }))
+            }
+            (AddressingMode::ZPG | AddressingMode::ABS, Some(OperandData::Expression(value))) => {
+                Some(value)
+            }
+            (_, value) => {
+                self.defines
+                    .insert(identifier.to_string(), Operand { value, ..operand });
+                None
+            }
+        };
+        let (statement, warning) = match value {
+            Some(value) => (
+                Some(self.define_constant(identifier, value, position)?),
+                AssemblerWarningKind::DeprecatedDefine(identifier.to_string()),
+            ),
+            None => (
+                None,
+                AssemblerWarningKind::DeprecatedDefineOperand(identifier.to_string()),
+            ),
+        };
+        self.warnings.push(AssemblerWarning::new(warning, position));
 
-        Ok(())
+        Ok(statement)
     }
 }
 
@@ -374,9 +2336,11 @@ mod tests {
     use crate::{
         lexer::Lexer,
         AddressingMode::{self, *},
+        AssemblerErrorKind, BinaryOperator, CpuType, Data, DataKind, Dialect,
+        Expression::*,
         Instruction,
         Mnemonics::{self, *},
-        NumberType, Operand, OperandData, Parser, Position, Statement,
+        NumberType, Operand, OperandData, Parser, Position, Statement, UnaryOperator,
     };
 
     fn test_parse_instruction(input: &str, expected: Instruction) {
@@ -394,14 +2358,8 @@ mod tests {
     ) -> Instruction {
         Instruction::new(
             mnemonic,
-            Operand::new(
-                mode,
-                match data {
-                    Some(data) => Some(OperandData::Number(data)),
-                    None => None,
-                },
-            ),
-            Position::default(),
+            Operand::new(mode, data.map(OperandData::Number)),
+            Position::new(1, 1),
         )
     }
 
@@ -465,7 +2423,7 @@ mod tests {
     fn test_parse_lda_zeropage() {
         test_parse_instruction(
             "LDA $FF",
-            instruction(LDA, RELZPG, Some(NumberType::Hexadecimal8(255))),
+            instruction(LDA, ZPG, Some(NumberType::Hexadecimal8(255))),
         );
     }
 
@@ -487,6 +2445,132 @@ mod tests {
 
     #[test]
     fn test_parse_clc_implied_accumulator() {
-        test_parse_instruction("CLC", instruction(CLC, IMPACC, None));
+        test_parse_instruction("CLC", instruction(CLC, IMP, None));
+    }
+
+    #[test]
+    fn test_parse_accumulator() {
+        test_parse_instruction("ASL", instruction(ASL, ACC, None));
+        test_parse_instruction("ASL A", instruction(ASL, ACC, None));
+        test_parse_instruction("ror a", instruction(ROR, ACC, None));
+
+        let parse = |input, cpu| {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.cpu = cpu;
+            parser.parse().unwrap().0.remove(0)
+        };
+        assert_eq!(
+            parse("INC A", CpuType::Cmos65C02),
+            Statement::Instruction(Instruction {
+                cpu: CpuType::Cmos65C02,
+                ..instruction(INC, ACC, None)
+            })
+        );
+        // the register, not a label, on the NMOS 6502
+        let mut parser = Parser::new(Lexer::new("INC A"));
+        let error = parser.parse().unwrap_err();
+        assert_eq!(
+            error.kind,
+            AssemblerErrorKind::UnavailableAccumulatorMode {
+                mnemonic: "INC".to_string(),
+                cpu: CpuType::Nmos6502,
+            }
+        );
+        assert_eq!((error.span.start.column, error.span.end.column), (5, 6));
+    }
+
+    #[test]
+    fn test_parse_org() {
+        let lexer = Lexer::new(".org $E000\n*= 512");
+        let mut parser = Parser::new(lexer);
+
+        assert_eq!(
+            parser.parse().unwrap().0,
+            vec![
+                Statement::Org(0xE000, Position::new(1, 1)),
+                Statement::Org(0x0200, Position::new(2, 1))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let lexer = Lexer::new("1 + 2 * 3 | <LABEL");
+        let mut parser = Parser::new(lexer);
+
+        assert_eq!(
+            parser.parse_expression().unwrap(),
+            Binary(
+                BinaryOperator::Or,
+                Box::new(Binary(
+                    BinaryOperator::Add,
+                    Box::new(Number(1)),
+                    Box::new(Binary(
+                        BinaryOperator::Mul,
+                        Box::new(Number(2)),
+                        Box::new(Number(3))
+                    ))
+                )),
+                Box::new(Unary(
+                    UnaryOperator::Low,
+                    Box::new(Identifier("LABEL".to_string()))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_ca65_expression() {
+        let mut lexer = Lexer::new("1 + 2 & 3 .mod .lobyte(LABEL) = 1");
+        lexer.dialect = Dialect::Ca65;
+        let mut parser = Parser::new(lexer);
+
+        assert_eq!(
+            parser.parse_expression().unwrap(),
+            Binary(
+                BinaryOperator::Equal,
+                Box::new(Binary(
+                    BinaryOperator::Add,
+                    Box::new(Number(1)),
+                    Box::new(Binary(
+                        BinaryOperator::Mod,
+                        Box::new(Binary(
+                            BinaryOperator::And,
+                            Box::new(Number(2)),
+                            Box::new(Number(3))
+                        )),
+                        Box::new(Unary(
+                            UnaryOperator::Low,
+                            Box::new(Identifier("LABEL".to_string()))
+                        ))
+                    ))
+                )),
+                Box::new(Number(1))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_data() {
+        let lexer = Lexer::new(".byte 1, 2\n.asciiz \"A\"\n.fill 3");
+        let mut parser = Parser::new(lexer);
+
+        assert_eq!(
+            parser.parse().unwrap().0,
+            vec![
+                Statement::Data(Data::new(
+                    DataKind::Byte(vec![Number(1), Number(2)]),
+                    Position::new(1, 1)
+                )),
+                Statement::Data(Data::new(
+                    DataKind::Text(vec![b'A', 0]),
+                    Position::new(2, 1)
+                )),
+                Statement::Data(Data::new(
+                    DataKind::Fill(Number(3), Number(0)),
+                    Position::new(3, 1)
+                )),
+            ]
+        );
     }
 }
@@ -0,0 +1,148 @@
+use crate::{lexer::Lexer, unescape, TokenKind};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Resolves the files referenced by `.include` and `.incbin`.
+pub trait FileResolver: fmt::Debug {
+    /// Resolves `path` referenced from the file `from` (`None` for the main source) to a unique name,
+    /// returns `None` if the file does not exist.
+    fn resolve(&self, path: &str, from: Option<&str>) -> Option<String>;
+
+    /// Reads the contents of a resolved file.
+    fn read(&self, name: &str) -> Result<Vec<u8>, String>;
+}
+
+/// Resolves files from an in-memory map of file names to contents, paths are matched exactly.
+impl FileResolver for HashMap<String, Vec<u8>> {
+    fn resolve(&self, path: &str, _: Option<&str>) -> Option<String> {
+        self.contains_key(path).then(|| path.to_string())
+    }
+
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        self.get(name)
+            .cloned()
+            .ok_or_else(|| "file not found".to_string())
+    }
+}
+
+/// Resolves files from the file system.
+/// paths are searched relative to the including file, then in `include_paths`, then in the working directory.
+#[derive(Debug, Clone, Default)]
+pub struct FsResolver {
+    pub include_paths: Vec<PathBuf>,
+}
+
+impl FsResolver {
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        Self { include_paths }
+    }
+}
+
+impl FileResolver for FsResolver {
+    fn resolve(&self, path: &str, from: Option<&str>) -> Option<String> {
+        let parent = from.and_then(|from| Path::new(from).parent());
+
+        parent
+            .into_iter()
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(path))
+            .chain([PathBuf::from(path)])
+            .find(|path| path.is_file())
+            .map(|path| path.to_string_lossy().to_string())
+    }
+
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        fs::read(name).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+/// Source files of a program, [`crate::Position::file`] `n` refers to `files[n]`.
+/// `files[0]` is the main source, its name is empty.
+#[derive(Debug, Default)]
+pub struct Sources<'a> {
+    pub files: Vec<SourceFile>,
+    pub resolver: Option<&'a dyn FileResolver>,
+}
+
+impl<'a> Sources<'a> {
+    /// Loads the main source and every file it includes (transitively).
+    ///
+    /// tokens borrow the source they were read from, so the included files are loaded before parsing.
+    /// files that can not be loaded are skipped here, the parser reports them where they are included.
+    pub fn load(source: &str, resolver: Option<&'a dyn FileResolver>) -> Self {
+        let mut sources = Self {
+            files: vec![SourceFile {
+                name: String::new(),
+                source: source.to_string(),
+            }],
+            resolver,
+        };
+
+        let Some(resolver) = resolver else {
+            return sources;
+        };
+
+        let mut file = 0;
+        while file < sources.files.len() {
+            let from = (file > 0).then(|| sources.files[file].name.clone());
+
+            for path in includes(&sources.files[file].source) {
+                let Some(name) = resolver.resolve(&path, from.as_deref()) else {
+                    continue;
+                };
+                if sources.find(&name).is_some() {
+                    continue;
+                }
+
+                if let Some(source) = resolver
+                    .read(&name)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                {
+                    sources.files.push(SourceFile { name, source });
+                }
+            }
+
+            file += 1;
+        }
+
+        sources
+    }
+
+    /// Index of the loaded file with the resolved `name`.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.files.iter().position(|file| file.name == name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.files.iter().map(|file| file.name.clone()).collect()
+    }
+}
+
+/// Paths of the `.include` directives in the source, until the first lexer error.
+fn includes(source: &str) -> Vec<String> {
+    let mut lexer = Lexer::new(source);
+    let mut includes = Vec::new();
+    let mut include = false;
+
+    while let Ok(token) = lexer.next_token() {
+        match token.kind {
+            TokenKind::EOF => break,
+            TokenKind::String(path) if include => includes
+                .push(String::from_utf8_lossy(&unescape(path).unwrap_or_default()).to_string()),
+            _ => {}
+        }
+        include = matches!(token.kind, TokenKind::Directive(directive) if directive.eq_ignore_ascii_case("include"));
+    }
+
+    includes
+}
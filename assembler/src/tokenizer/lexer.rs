@@ -1,19 +1,25 @@
-use crate::{AssemblerError, AssemblerErrorKind, AssemblerResult, Position, Token};
+use crate::{
+    unescape, AssemblerError, AssemblerErrorKind, AssemblerResult, Dialect, Position, Span, Token,
+};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Lexer<'a> {
     pub input: &'a str,
+    /// byte offset of the current character
     pub position: usize,
+    /// byte offset of the next character
     pub read_position: usize,
     pub current_char: char,
     pub current_position: Position,
+    /// of the rest of the file, the parser switches it with `.dialect`
+    pub dialect: Dialect,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         let mut lexer = Lexer {
             input,
-            current_position: Position(1, 0),
+            current_position: Position::new(1, 0),
             ..Default::default()
         };
 
@@ -21,64 +27,157 @@ impl<'a> Lexer<'a> {
         lexer
     }
 
+    /// Lexer for an included file, see [`Position::file`]
+    pub fn with_file(input: &'a str, file: usize) -> Self {
+        let mut lexer = Self::new(input);
+        lexer.current_position.file = file;
+        lexer
+    }
+
     fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.current_char = '\0';
-        } else {
-            self.current_char = self.input.chars().nth(self.read_position).unwrap();
-        }
+        self.current_char = self.char_at(self.read_position);
 
         self.position = self.read_position;
-        self.read_position += 1;
+        self.read_position += self.current_char.len_utf8();
 
-        self.current_position.1 += 1;
+        self.current_position.column += 1;
     }
 
     fn peek_char(&self) -> char {
-        if self.read_position >= self.input.len() {
-            '\0'
-        } else {
-            self.input.chars().nth(self.read_position).unwrap()
-        }
+        self.char_at(self.read_position)
+    }
+
+    /// Character at the byte offset `position`, `'\0'` after the end of the input.
+    fn char_at(&self, position: usize) -> char {
+        self.input
+            .get(position..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or('\0')
     }
 
     fn skip_whitespace(&mut self) {
         while self.current_char.is_whitespace() {
             if self.current_char == '\n' {
-                self.current_position.0 += 1;
-                self.current_position.1 = 0;
+                self.current_position.line += 1;
+                self.current_position.column = 0;
                 break;
             }
             self.read_char();
         }
     }
 
+    /// `name`, `scope::name`, `global.local`, `x_pos2`.
+    /// the registers `X` and `Y` are identifiers too, the parser reads them after the `,` of an operand.
     fn read_identifier(&mut self) -> &'a str {
         let position = self.position;
-        while self.current_char.is_alphanumeric() || self.current_char == '_' {
-            self.read_char();
+        loop {
+            if is_identifier_char(self.current_char) {
+                self.read_char();
+            } else if self.current_char == ':' && self.peek_char() == ':' {
+                self.read_char();
+                self.read_char();
+            } else if self.current_char == '.' && is_identifier_char(self.peek_char()) {
+                self.read_char();
+            } else {
+                break;
+            }
         }
 
         &self.input[position..self.position]
     }
 
-    fn read_number(&mut self) -> AssemblerResult<usize> /* Negative number not supported yet */ {
+    /// Digits of `radix` with `_` separators (`1_000`, `%0101_0101`), the width of the number
+    /// does not depend on the number of digits (`$0F` and `$000F` are the same number).
+    /// negative numbers are expressions, see [`crate::UnaryOperator::Negate`]
+    fn read_number(&mut self, radix: u32) -> AssemblerResult<u16> {
         let position = self.position;
-        while self.current_char.is_ascii_digit() {
+        while self.current_char.is_digit(radix)
+            || (self.current_char == '_'
+                && self.position != position
+                && self.peek_char().is_digit(radix))
+        {
             self.read_char();
         }
 
-        Ok(match self.input[position..self.position].parse() {
-            Ok(number) => number,
-            Err(_) => {
-                return Err(AssemblerError::new(
-                    AssemblerErrorKind::InvalidNumber,
-                    self.current_position,
-                ))
+        let digits = self.input[position..self.position].replace('_', "");
+        match i32::from_str_radix(&digits, radix) {
+            Ok(number @ 0..=0xFFFF) => Ok(number as u16),
+            Ok(number) => Err(AssemblerError::new(
+                AssemblerErrorKind::OutOfRange(number),
+                self.current_position,
+            )),
+            Err(_) => Err(AssemblerError::new(
+                AssemblerErrorKind::InvalidNumber,
+                self.current_position,
+            )),
+        }
+    }
+
+    /// `'A'`, `'\n'`, the escapes of strings are supported (see [`unescape`])
+    fn read_char_literal(&mut self) -> AssemblerResult<u8> {
+        let position = self.current_position;
+        let literal = self.read_quoted('\'')?;
+
+        match self.unescape(literal).as_deref() {
+            Ok([byte]) => Ok(*byte),
+            _ => Err(AssemblerError::new(
+                AssemblerErrorKind::InvalidCharLiteral(literal.to_string()),
+                position,
+            )),
+        }
+    }
+
+    fn read_string(&mut self) -> AssemblerResult<&'a str> {
+        let position = self.current_position;
+        let string = self.read_quoted('"')?;
+
+        if let Err(c) = self.unescape(string) {
+            return Err(AssemblerError::new(
+                AssemblerErrorKind::InvalidEscape(c),
+                position,
+            ));
+        }
+
+        Ok(string)
+    }
+
+    /// Text between the `quote` at the current character and the closing one, escapes are skipped.
+    fn read_quoted(&mut self, quote: char) -> AssemblerResult<&'a str> {
+        let position = self.current_position;
+        self.read_char();
+
+        let start = self.position;
+        while self.current_char != quote {
+            match self.current_char {
+                '\0' | '\n' => {
+                    return Err(AssemblerError::new(
+                        AssemblerErrorKind::UnterminatedString,
+                        position,
+                    ))
+                }
+                '\\' if self.dialect == Dialect::Native => {
+                    self.read_char();
+                    self.read_char();
+                }
+                _ => self.read_char(),
             }
-        })
+        }
+
+        let string = &self.input[start..self.position];
+        self.read_char();
+
+        Ok(string)
     }
 
+    /// Bytes of a string literal, ca65 strings have no escapes.
+    pub fn unescape(&self, string: &str) -> Result<Vec<u8>, char> {
+        match self.dialect {
+            Dialect::Native => unescape(string),
+            Dialect::Ca65 => Ok(string.as_bytes().to_vec()),
+        }
+    }
+
+    /// `; comment` until the end of the line, the parser skips it, the formatter keeps it.
     fn read_comment(&mut self) {
         self.read_char();
 
@@ -87,25 +186,51 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Reads the next token, after an error the lexer continues after the invalid token.
     pub fn next_token(&mut self) -> AssemblerResult<Token<'a>> {
-        use super::TokenKind::*;
-
         self.skip_whitespace();
+        let start = self.current_position;
+
+        match self.read_token() {
+            Ok(mut token) => {
+                token.end = self.current_position;
+                Ok(token)
+            }
+            Err(mut error) => {
+                if error.span.start.line == self.current_position.line {
+                    *error.span = Span::new(start, self.current_position);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    fn read_token(&mut self) -> AssemblerResult<Token<'a>> {
+        use super::TokenKind::*;
 
         let position = self.current_position;
 
         let token = match self.current_char {
+            // `name := value` is a constant like `name = value`
+            ':' if self.dialect == Dialect::Ca65 && self.peek_char() == '=' => {
+                self.read_char();
+                Some(Equal)
+            }
             '\n' => Some(Newline),
             '(' => Some(LParen),
             ')' => Some(RParen),
             ',' => Some(Comma),
             ':' => Some(Colon),
-            'x' | 'X' => Some(X),
-            'y' | 'Y' => Some(Y),
             '#' => Some(Hash),
+            '*' => Some(Asterisk),
+            '+' => Some(Plus),
+            '-' => Some(Minus),
+            '/' => Some(Slash),
+            '^' => Some(Caret),
+            '~' => Some(Tilde),
             ';' => {
                 self.read_comment();
-                return self.next_token();
+                return Ok(Token::new(Comment, position));
             }
             '\0' => Some(EOF),
             _ => None,
@@ -117,70 +242,94 @@ impl<'a> Lexer<'a> {
                 Ok(Token::new(token, position))
             }
             None => match self.current_char {
-                '$' => {
+                '<' | '>' | '=' | '!' | '&' | '|' => {
+                    let c = self.current_char;
                     self.read_char();
 
-                    let _position = self.position;
-                    while self.current_char.is_ascii_hexdigit() {
-                        self.read_char();
-                    }
-
-                    let number = &self.input[_position..self.position];
-                    match number.len() {
-                        1 | 2 => Ok(Token::new(
-                            Hexadecimal8Bit(u8::from_str_radix(number, 16).unwrap()),
-                            position,
-                        )),
-                        3 | 4 => Ok(Token::new(
-                            Hexadecimal16Bit(u16::from_str_radix(number, 16).unwrap()),
-                            position,
-                        )),
-                        _ => Err(AssemblerError::new(
-                            AssemblerErrorKind::IllegalCharacter(self.current_char),
-                            self.current_position,
-                        )),
-                    }
+                    let token = match (c, self.current_char) {
+                        ('<', '<') => Some(ShiftLeft),
+                        ('>', '>') => Some(ShiftRight),
+                        ('<', '=') => Some(LessEqual),
+                        ('>', '=') => Some(GreaterEqual),
+                        ('=', '=') => Some(EqualEqual),
+                        ('!', '=') => Some(NotEqual),
+                        ('<', '>') if self.dialect == Dialect::Ca65 => Some(NotEqual),
+                        ('&', '&') => Some(DoubleAmpersand),
+                        ('|', '|') => Some(DoublePipe),
+                        _ => None,
+                    };
+
+                    Ok(Token::new(
+                        match token {
+                            Some(token) => {
+                                self.read_char();
+                                token
+                            }
+                            None => match c {
+                                '<' => LessThan,
+                                '>' => GreaterThan,
+                                '=' => Equal,
+                                '!' => Bang,
+                                '&' => Ampersand,
+                                _ => Pipe,
+                            },
+                        },
+                        position,
+                    ))
                 }
-                '0' if self.peek_char() == 'x' => {
+                '"' => Ok(Token::new(String(self.read_string()?), position)),
+                '\'' => Ok(Token::new(Char(self.read_char_literal()?), position)),
+                '$' => {
                     self.read_char();
+                    Ok(Token::new(Hexadecimal(self.read_number(16)?), position))
+                }
+                '%' => {
                     self.read_char();
-
-                    let _position = self.position;
-                    while self.current_char.is_ascii_hexdigit() {
-                        self.read_char();
-                    }
-
-                    let number = &self.input[_position..self.position];
-                    match number.len() {
-                        2 => Ok(Token::new(
-                            Hexadecimal8Bit(u8::from_str_radix(number, 16).unwrap()),
-                            position,
-                        )),
-                        4 => Ok(Token::new(
-                            Hexadecimal16Bit(u16::from_str_radix(number, 16).unwrap()),
-                            position,
-                        )),
-                        _ => Err(AssemblerError::new(
-                            AssemblerErrorKind::IllegalCharacter(self.current_char),
-                            self.current_position,
-                        )),
-                    }
+                    Ok(Token::new(Binary(self.read_number(2)?), position))
                 }
-                c if c.is_alphabetic() => {
+                '0' if matches!(self.peek_char(), 'x' | 'X') => {
+                    self.read_char();
+                    self.read_char();
+                    Ok(Token::new(Hexadecimal(self.read_number(16)?), position))
+                }
+                '.' if self.peek_char().is_alphabetic() => {
+                    self.read_char();
+                    Ok(Token::new(Directive(self.read_identifier()), position))
+                }
+                '@' if is_identifier_start(self.peek_char()) => {
+                    // cheap local label, `@name`
+                    let start = self.position;
+                    self.read_char();
+                    self.read_identifier();
+                    Ok(Token::new(
+                        Identifier(&self.input[start..self.position]),
+                        position,
+                    ))
+                }
+                c if is_identifier_start(c) => {
                     let identifier = self.read_identifier();
                     Ok(match identifier {
                         "define" => Token::new(Define, position),
                         identifier => Token::new(Identifier(identifier), position),
                     })
                 }
-                c if c.is_numeric() => {
-                    Ok(Token::new(Decimal(self.read_number()? as u16), position))
+                c if c.is_numeric() => Ok(Token::new(Decimal(self.read_number(10)?), position)),
+                c => {
+                    self.read_char();
+                    Err(AssemblerError::new(
+                        AssemblerErrorKind::IllegalCharacter(c),
+                        position,
+                    ))
                 }
-                _ => Err(AssemblerError::new(
-                    AssemblerErrorKind::IllegalCharacter(self.current_char),
-                    self.current_position,
-                )),
             },
         }
     }
 }
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -1,7 +1,7 @@
 pub mod lexer;
 use std::fmt;
 
-use crate::Position;
+use crate::{Position, Span};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 #[rustfmt::skip]
@@ -11,13 +11,34 @@ pub enum TokenKind<'a> {
     Comma,
     Colon,
     Hash,
+    Asterisk,
+    Equal,
+    Plus,
+    Minus,
+    Slash,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    LessThan,
+    GreaterThan,
+    ShiftLeft,
+    ShiftRight,
+    LessEqual,
+    GreaterEqual,
+    EqualEqual,
+    NotEqual,
+    Bang,
+    DoubleAmpersand,
+    DoublePipe,
     Newline,
-    X,
-    Y,
     Decimal(u16),
-    Hexadecimal8Bit(u8),
-    Hexadecimal16Bit(u16),
+    Hexadecimal(u16),
+    Binary(u16),
+    Char(u8),
     Identifier(&'a str),
+    Directive(&'a str),
+    String(&'a str),
     Comment,
     Define,
     EOF,
@@ -33,13 +54,34 @@ impl fmt::Display for TokenKind<'_> {
             Comma => write!(f, ","),
             Colon => write!(f, ":"),
             Hash => write!(f, "#"),
+            Asterisk => write!(f, "*"),
+            Equal => write!(f, "="),
+            Plus => write!(f, "+"),
+            Minus => write!(f, "-"),
+            Slash => write!(f, "/"),
+            Ampersand => write!(f, "&"),
+            Pipe => write!(f, "|"),
+            Caret => write!(f, "^"),
+            Tilde => write!(f, "~"),
+            LessThan => write!(f, "<"),
+            GreaterThan => write!(f, ">"),
+            ShiftLeft => write!(f, "<<"),
+            ShiftRight => write!(f, ">>"),
+            LessEqual => write!(f, "<="),
+            GreaterEqual => write!(f, ">="),
+            EqualEqual => write!(f, "=="),
+            NotEqual => write!(f, "!="),
+            Bang => write!(f, "!"),
+            DoubleAmpersand => write!(f, "&&"),
+            DoublePipe => write!(f, "||"),
             Newline => write!(f, "\\n"),
-            X => write!(f, "X"),
-            Y => write!(f, "Y"),
             Decimal(n) => write!(f, "{}", n),
-            Hexadecimal8Bit(n) => write!(f, "${:02X}", n),
-            Hexadecimal16Bit(n) => write!(f, "${:04X}", n),
+            Hexadecimal(n) => write!(f, "${:02X}", n),
+            Binary(n) => write!(f, "%{:08b}", n),
+            Char(c) => write!(f, "'{}'", (*c as char).escape_default()),
             Identifier(s) => write!(f, "{}", s),
+            Directive(s) => write!(f, ".{}", s),
+            String(s) => write!(f, "\"{}\"", s),
             Comment => write!(f, ";"),
             Define => write!(f, "define"),
             EOF => write!(f, "EOF"),
@@ -47,10 +89,70 @@ impl fmt::Display for TokenKind<'_> {
     }
 }
 
+/// Syntax of a source file, see [`crate::Assembler::set_dialect`] and the `.dialect` directive.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    #[default]
+    Native,
+    /// the syntax of ca65 (cc65), strings have no escapes, `<>` is `!=`, `:=` is `=`,
+    /// and directives in expressions are its functions and operators (`.lobyte(x)`, `.mod`)
+    Ca65,
+}
+
+impl Dialect {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "native" => Some(Self::Native),
+            "ca65" => Some(Self::Ca65),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves the escape sequences of a string literal.
+///
+/// `\n`, `\r`, `\t`, `\0`, `\\`, `\"`, `\'` and `\xHH` are supported,
+/// the offending character is returned for any other escape.
+pub fn unescape(s: &str) -> Result<Vec<u8>, char> {
+    let mut bytes = Vec::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('"') => bytes.push(b'"'),
+            Some('\'') => bytes.push(b'\''),
+            Some('x') => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 => bytes.push(byte),
+                    _ => return Err('x'),
+                }
+            }
+            Some(c) => return Err(c),
+            None => return Err('\\'),
+        }
+    }
+
+    Ok(bytes)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Token<'a> {
     pub kind: TokenKind<'a>,
     pub position: Position,
+    /// position after the token, see [`Token::span`]
+    pub end: Position,
 }
 
 impl Default for Token<'_> {
@@ -58,20 +160,29 @@ impl Default for Token<'_> {
         Self {
             kind: TokenKind::EOF,
             position: Position::default(),
+            end: Position::default(),
         }
     }
 }
 
 impl<'a> Token<'a> {
     pub fn new(kind: TokenKind<'a>, position: Position) -> Self {
-        Self { kind, position }
+        Self {
+            kind,
+            position,
+            end: position,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.position, self.end)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lexer::Lexer;
+    use crate::{lexer::Lexer, AssemblerErrorKind};
 
     struct IteratorLexer<'a>(Lexer<'a>);
 
@@ -90,7 +201,7 @@ mod tests {
 
     fn test_tokenizer(input: &str, expected: &[TokenKind]) {
         let mut iterator_lexer = IteratorLexer(Lexer::new(input)).enumerate();
-        while let Some((i, token)) = iterator_lexer.next() {
+        for (i, token) in iterator_lexer.by_ref() {
             // println!("{}: {:?}", i, token);
             assert_eq!(token.kind, expected[i]);
         }
@@ -112,12 +223,44 @@ mod tests {
     #[test]
     fn test_tokenizer_hexadecimal() {
         test_tokenizer(
-            "$1234 0x56",
+            "$1234 0x56 $F $00FF 0XaBc",
+            &[
+                TokenKind::Hexadecimal(0x1234),
+                TokenKind::Hexadecimal(0x56),
+                TokenKind::Hexadecimal(0xF),
+                TokenKind::Hexadecimal(0xFF),
+                TokenKind::Hexadecimal(0xABC),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_number_literals() {
+        test_tokenizer(
+            "%0101_0101 %1 1_000 $FF_FF 'A' '\\n' '\\'' '\"' -1",
             &[
-                TokenKind::Hexadecimal16Bit(0x1234),
-                TokenKind::Hexadecimal8Bit(0x56),
+                TokenKind::Binary(0x55),
+                TokenKind::Binary(1),
+                TokenKind::Decimal(1000),
+                TokenKind::Hexadecimal(0xFFFF),
+                TokenKind::Char(b'A'),
+                TokenKind::Char(b'\n'),
+                TokenKind::Char(b'\''),
+                TokenKind::Char(b'"'),
+                TokenKind::Minus,
+                TokenKind::Decimal(1),
             ],
         );
+
+        let error = |input| Lexer::new(input).next_token().unwrap_err().kind;
+        assert_eq!(error("$10000"), AssemblerErrorKind::OutOfRange(0x10000));
+        assert_eq!(error("$"), AssemblerErrorKind::InvalidNumber);
+        assert_eq!(error("%2"), AssemblerErrorKind::InvalidNumber);
+        assert_eq!(
+            error("'AB'"),
+            AssemblerErrorKind::InvalidCharLiteral("AB".to_string())
+        );
+        assert_eq!(error("'A"), AssemblerErrorKind::UnterminatedString);
     }
 
     #[test]
@@ -128,6 +271,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tokenizer_register_identifier() {
+        test_tokenizer(
+            "x X y xpos yloop _tmp x_1 main.x2 y::x @x ,X",
+            &[
+                TokenKind::Identifier("x"),
+                TokenKind::Identifier("X"),
+                TokenKind::Identifier("y"),
+                TokenKind::Identifier("xpos"),
+                TokenKind::Identifier("yloop"),
+                TokenKind::Identifier("_tmp"),
+                TokenKind::Identifier("x_1"),
+                TokenKind::Identifier("main.x2"),
+                TokenKind::Identifier("y::x"),
+                TokenKind::Identifier("@x"),
+                TokenKind::Comma,
+                TokenKind::Identifier("X"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_label_identifier() {
+        test_tokenizer(
+            "@loop main.loop sound::init :-",
+            &[
+                TokenKind::Identifier("@loop"),
+                TokenKind::Identifier("main.loop"),
+                TokenKind::Identifier("sound::init"),
+                TokenKind::Colon,
+                TokenKind::Minus,
+            ],
+        );
+    }
+
     #[test]
     fn test_tokenizer_symbols() {
         test_tokenizer(
@@ -144,4 +322,111 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn test_tokenizer_directive() {
+        test_tokenizer(
+            ".org $E000\n*= $0200",
+            &[
+                TokenKind::Directive("org"),
+                TokenKind::Hexadecimal(0xE000),
+                TokenKind::Newline,
+                TokenKind::Asterisk,
+                TokenKind::Equal,
+                TokenKind::Hexadecimal(0x0200),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_operators() {
+        test_tokenizer(
+            "+ - * / & | ^ ~ < > << >>",
+            &[
+                TokenKind::Plus,
+                TokenKind::Minus,
+                TokenKind::Asterisk,
+                TokenKind::Slash,
+                TokenKind::Ampersand,
+                TokenKind::Pipe,
+                TokenKind::Caret,
+                TokenKind::Tilde,
+                TokenKind::LessThan,
+                TokenKind::GreaterThan,
+                TokenKind::ShiftLeft,
+                TokenKind::ShiftRight,
+            ],
+        );
+        test_tokenizer(
+            "= == != ! <= >= && ||",
+            &[
+                TokenKind::Equal,
+                TokenKind::EqualEqual,
+                TokenKind::NotEqual,
+                TokenKind::Bang,
+                TokenKind::LessEqual,
+                TokenKind::GreaterEqual,
+                TokenKind::DoubleAmpersand,
+                TokenKind::DoublePipe,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_comment() {
+        test_tokenizer(
+            "LDA ; load ; it\n; line",
+            &[
+                TokenKind::Identifier("LDA"),
+                TokenKind::Comment,
+                TokenKind::Newline,
+                TokenKind::Comment,
+            ],
+        );
+        let token = IteratorLexer(Lexer::new("NOP ; it")).nth(1).unwrap();
+        assert_eq!((token.position.column, token.end.column), (5, 9));
+
+        // columns count characters, not bytes
+        let tokens = IteratorLexer(Lexer::new("; déjà\n.byte \"é\", x")).collect::<Vec<_>>();
+        assert_eq!(tokens[3].kind, TokenKind::String("é"));
+        assert_eq!(
+            (tokens[5].position.line, tokens[5].position.column),
+            (2, 12)
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_string() {
+        test_tokenizer(
+            r#""abc" "a\"b\x41""#,
+            &[TokenKind::String("abc"), TokenKind::String(r#"a\"b\x41"#)],
+        );
+        assert_eq!(unescape(r#"a\"b\x41\n"#), Ok(b"a\"bA\n".to_vec()));
+        assert_eq!(unescape(r"\q"), Err('q'));
+    }
+
+    #[test]
+    fn test_tokenizer_ca65() {
+        let mut lexer = Lexer::new(r#"a := 1 <> "C:\" '\'"#);
+        lexer.dialect = Dialect::Ca65;
+        let mut tokens = Vec::new();
+        while let Ok(token) = lexer.next_token() {
+            if token.kind == TokenKind::EOF {
+                break;
+            }
+            tokens.push(token.kind);
+        }
+
+        assert_eq!(
+            tokens,
+            [
+                TokenKind::Identifier("a"),
+                TokenKind::Equal,
+                TokenKind::Decimal(1),
+                TokenKind::NotEqual,
+                TokenKind::String("C:\\"),
+                TokenKind::Char(b'\\'),
+            ]
+        );
+    }
 }
@@ -1,9 +1,12 @@
 use crate::{ui::*, View, DEBUG_OUTPUT, DEBUG_UPDATE, IS_RUNNING};
-use assembler::Assembler;
+use assembler::{
+    entry_point, AccumulatorSyntax, Assembler, Assembly, Chunk, CpuType, DisassemblyLine,
+    FsResolver,
+};
 use chrono::prelude::*;
 use eframe::egui::*;
 use emulator::{memory::Memory, Cpu6502, CpuDebugger, DebugKind, Debugger};
-use std::{sync::atomic::Ordering, thread, time::Duration};
+use std::{path::PathBuf, sync::atomic::Ordering, thread, time::Duration};
 
 #[derive(Default)]
 pub struct AppDebugger;
@@ -31,6 +34,10 @@ pub struct Settings {
     pub reactive_mode: bool,
     pub step_delay_input: String,
     pub step_delay: u64,
+    /// CPU the source is assembled and the memory disassembled for
+    pub cpu: CpuType,
+    /// syntax of the accumulator mode in disassembled instructions (`ASL` or `ASL A`)
+    pub accumulator: AccumulatorSyntax,
 }
 
 impl Default for Settings {
@@ -40,6 +47,8 @@ impl Default for Settings {
             reactive_mode: true,
             step_delay_input: "0".to_string(),
             step_delay: 0,
+            cpu: CpuType::default(),
+            accumulator: AccumulatorSyntax::default(),
         }
     }
 }
@@ -68,25 +77,30 @@ impl Default for WindowVisibility {
 pub struct App {
     pub emulator: Cpu6502<AppDebugger>,
     pub source_input: String,
+    /// file the source was loaded from or saved to, `.include` is resolved next to it
+    pub source_path: Option<PathBuf>,
     pub memory_dump_range: (u16, u16),
     pub memory_dump_range_input: (String, String),
     pub error: Option<String>,
     pub key_input: String,
     pub settings: Settings,
     pub window_visibility: WindowVisibility,
-    pub disassembled: Vec<(usize, String, String)>,
+    /// lines of the disassembler window, see [`assembler::disassemble_flow`]
+    pub disassembled: Vec<DisassemblyLine>,
+    /// the loaded program with its symbols, `None` if a binary was loaded
+    pub assembly: Option<Assembly>,
 }
 
 impl App {
     pub fn new(program: &str) -> App {
-        let src = Assembler::new(program).assemble().unwrap();
+        let assembly = Assembler::new(program).build().unwrap();
 
         let memory = Memory::new();
 
         let mut emulator = Cpu6502::<AppDebugger>::new(memory);
 
         emulator.reset();
-        emulator.load(&src);
+        load_chunks(&mut emulator, &assembly.chunks);
 
         Self {
             emulator,
@@ -95,9 +109,37 @@ impl App {
             memory_dump_range_input: ("0000".to_string(), "00FF".to_string()),
             error: None,
             key_input: String::new(),
+            assembly: Some(assembly),
             ..Default::default()
         }
     }
+
+    pub fn assembler(&self) -> Assembler<'_> {
+        let include_paths = self
+            .source_path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .map(PathBuf::from)
+            .into_iter()
+            .collect();
+
+        let mut assembler = Assembler::new(&self.source_input);
+        assembler
+            .set_resolver(FsResolver::new(include_paths))
+            .set_cpu(self.settings.cpu);
+        assembler
+    }
+}
+
+/// Loads every chunk at its origin and points the PC at the program entry, see [`entry_point`]
+pub fn load_chunks(emulator: &mut Cpu6502<AppDebugger>, chunks: &[Chunk]) {
+    for chunk in chunks {
+        emulator.load_at(chunk.origin, &chunk.bytes);
+    }
+
+    if let Some(entry) = entry_point(chunks) {
+        emulator.registers.pc = entry;
+    }
 }
 
 impl eframe::App for App {
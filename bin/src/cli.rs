@@ -0,0 +1,860 @@
+use assembler::{
+    chunks_to_image, disassemble_flow, format_source, lexer::Lexer, link, AccumulatorSyntax,
+    Assembler, CpuType, Dialect, DisassemblyOptions, FormatOptions, FsResolver, LinkerConfig, Lint,
+    LintLevel, MnemonicCase, Object, OutputFormat, Parser, Radix,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const USAGE: &str = r#"Usage: bin [COMMAND]
+
+Without a command, the emulator window is opened.
+
+Commands:
+    assemble <INPUT> [OPTIONS]    Assemble a source file
+    link <OBJECTS>... [OPTIONS]   Link object files (assembled with --object) into a program
+    format <INPUT> [OPTIONS]      Format a source file, it is printed unless -o or -w is given
+    disassemble <INPUT> [OPTIONS] Disassemble a program by following its code from the vectors,
+                                  it is printed unless -o is given
+
+Options:
+    -o <OUTPUT>                   Output file (default: INPUT with the extension of the format)
+    -c, --object                  Assemble a relocatable object file (.o) for the linker
+    -C <CONFIG>                   Place the segments (.segment) with a layout, like -C of link
+    -f <FORMAT>                   Output format (default: by the extension of OUTPUT, or raw)
+    -l <LISTING>                  Write a listing (addresses, bytes, cycles and source) to LISTING
+    --labels <FILE>               Write the labels in the VICE monitor format (load with `ll`)
+    --debug <FILE>                Write the symbols and line information as JSON
+    --dbg <FILE>                  Write the debug information in the cc65 .dbg format
+    -D <NAME>[=<VALUE>]           Define a symbol for .if/.ifdef (VALUE defaults to 1)
+    -I <DIR>                      Search DIR for .include/.incbin files
+    --dialect <DIALECT>           Syntax of INPUT: native (default) or ca65, files can switch with .dialect
+    --cpu <CPU>                   Instruction set: 6502 (default), 6502X (undocumented opcodes), 65SC02,
+                                  65C02 or W65C02, the source can switch with .setcpu
+    --long-branches               Assemble branches to targets out of range as the inverted branch over a JMP
+    --allow <LINT>                Do not report the warnings of LINT
+    --warn <LINT>                 Report the warnings of LINT (the default)
+    --deny <LINT>                 Report the warnings of LINT as errors
+
+Link options:
+    -o <OUTPUT>                   Output file (default: the first object with the extension of the format)
+    -f <FORMAT>                   Output format (default: by the extension of OUTPUT, or raw)
+    -C <CONFIG>                   Memory layout and segment placement in the ld65 format
+                                  (default: ZP, RAM at $0200, ROM at $8000 with CODE, RODATA, DATA
+                                  and VECTORS at $FFFA)
+    -m <MAP>                      Write a map file (memory areas, segments and exports) to MAP
+
+Format options:
+    -o <OUTPUT>                   Output file
+    -w, --write                   Rewrite INPUT
+    --case <CASE>                 Case of the mnemonics and registers: upper (default) or lower
+    --radix <RADIX>               Radix of the numbers in operands and data: hex (default), dec or bin
+    --label-column <N>            Column of the labels and constants (default: 0)
+    --mnemonic-column <N>         Column of the instructions and directives (default: 8)
+    --operand-column <N>          Column of the operands (default: 12)
+    --comment-column <N>          Column of the comments after a statement (default: 32)
+    --explicit-accumulator        Write the accumulator mode as `ASL A` instead of `ASL`
+    --dialect <DIALECT>           Syntax of INPUT, like assemble
+    --cpu <CPU>                   Instruction set, like assemble
+
+Disassemble options:
+    -o <OUTPUT>                   Output file
+    -f <FORMAT>                   Format of INPUT (default: by the extension of INPUT, or raw)
+    --origin <ADDRESS>            Address of a raw INPUT (default: $8000)
+    -e, --entry <ADDRESS>         Follow the code from ADDRESS instead of the NMI, reset and IRQ vectors
+                                  (or the start of INPUT without them), can be given more than once
+    --explicit-accumulator        Write the accumulator mode as `ASL A` instead of `ASL`
+    --cpu <CPU>                   Instruction set, like assemble
+
+Formats:
+    raw     the bytes from the lowest to the highest address, gaps are filled with zeros
+    ihex    Intel HEX (.hex)
+    srec    Motorola S-record (.srec, .s19)
+    prg     the load address (2 bytes) and the bytes as in raw (.prg)
+
+Lints (`warnings` is every lint):
+    unused, zero-page, jmp-indirect, unreachable, branch-to-self, self-modifying, deprecated
+"#;
+
+/// Runs the command line interface, `args` does not include the program name.
+pub fn run(args: &[String]) -> Result<(), String> {
+    match args.first().map(String::as_str) {
+        Some("assemble") => assemble(&args[1..]),
+        Some("link") => link_objects(&args[1..]),
+        Some("format") => format_file(&args[1..]),
+        Some("disassemble") => disassemble_file(&args[1..]),
+        Some("help" | "-h" | "--help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(command) => Err(format!("Unknown command: {command}\n\n{USAGE}")),
+        None => Ok(()),
+    }
+}
+
+#[derive(Default)]
+struct AssembleOptions {
+    input: Option<String>,
+    output: Option<String>,
+    format: Option<OutputFormat>,
+    listing: Option<String>,
+    labels: Option<String>,
+    debug: Option<String>,
+    dbg: Option<String>,
+    symbols: Vec<(String, i32)>,
+    include_paths: Vec<PathBuf>,
+    /// in order, later options override earlier ones. `None` is every lint
+    lints: Vec<(Option<Lint>, LintLevel)>,
+    long_branches: bool,
+    object: bool,
+    layout: Option<String>,
+    dialect: Dialect,
+    cpu: CpuType,
+}
+
+impl AssembleOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" => options.output = Some(value(&mut args, arg)?),
+                "-f" => options.format = Some(parse_format(&value(&mut args, arg)?)?),
+                "-l" => options.listing = Some(value(&mut args, arg)?),
+                "--labels" => options.labels = Some(value(&mut args, arg)?),
+                "--debug" => options.debug = Some(value(&mut args, arg)?),
+                "--dbg" => options.dbg = Some(value(&mut args, arg)?),
+                "-D" => options.symbols.push(parse_symbol(&value(&mut args, arg)?)?),
+                arg if arg.starts_with("-D") => options.symbols.push(parse_symbol(&arg[2..])?),
+                "-I" => options.include_paths.push(value(&mut args, arg)?.into()),
+                arg if arg.starts_with("-I") => options.include_paths.push(arg[2..].into()),
+                "--long-branches" => options.long_branches = true,
+                "--dialect" => options.dialect = parse_dialect(&value(&mut args, arg)?)?,
+                "--cpu" => options.cpu = parse_cpu(&value(&mut args, arg)?)?,
+                "-c" | "--object" => options.object = true,
+                "-C" => options.layout = Some(value(&mut args, arg)?),
+                "--allow" | "--warn" | "--deny" => {
+                    let level = match arg.as_str() {
+                        "--allow" => LintLevel::Allow,
+                        "--warn" => LintLevel::Warn,
+                        _ => LintLevel::Deny,
+                    };
+                    options
+                        .lints
+                        .push((parse_lint(&value(&mut args, arg)?)?, level));
+                }
+                arg if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+                _ if options.input.is_none() => options.input = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument: {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn value(args: &mut std::slice::Iter<String>, option: &str) -> Result<String, String> {
+    args.next()
+        .cloned()
+        .ok_or_else(|| format!("Missing value for {option}"))
+}
+
+/// `NAME=VALUE` or `NAME`, the value can be any constant expression (`$10`, `1 << 4`)
+fn parse_symbol(symbol: &str) -> Result<(String, i32), String> {
+    let (name, value) = symbol.split_once('=').unwrap_or((symbol, "1"));
+    if name.is_empty() {
+        return Err(format!("Invalid symbol: {symbol}"));
+    }
+
+    let value = Parser::new(Lexer::new(value))
+        .parse_expression()
+        .map_err(|e| e.to_string())?
+        .evaluate(None, &|_| None)
+        .map_err(|e| format!("Invalid value for {name}: {e}"))?;
+
+    Ok((name.to_string(), value))
+}
+
+/// A lint by name, `warnings` is every lint.
+fn parse_lint(name: &str) -> Result<Option<Lint>, String> {
+    match name {
+        "warnings" => Ok(None),
+        name => Lint::from_name(name)
+            .map(Some)
+            .ok_or_else(|| format!("Unknown lint: {name}")),
+    }
+}
+
+fn parse_format(name: &str) -> Result<OutputFormat, String> {
+    OutputFormat::from_name(name).ok_or_else(|| format!("Unknown format: {name}"))
+}
+
+fn parse_dialect(name: &str) -> Result<Dialect, String> {
+    Dialect::from_name(name).ok_or_else(|| format!("Unknown dialect: {name}"))
+}
+
+fn parse_cpu(name: &str) -> Result<CpuType, String> {
+    CpuType::from_name(name).ok_or_else(|| format!("Unknown CPU: {name}"))
+}
+
+/// An address, any constant expression like the value of `-D`
+fn parse_address(address: &str) -> Result<u16, String> {
+    Parser::new(Lexer::new(address))
+        .parse_expression()
+        .ok()
+        .and_then(|expression| expression.evaluate(None, &|_| None).ok())
+        .and_then(|value| u16::try_from(value).ok())
+        .ok_or_else(|| format!("Invalid address: {address}"))
+}
+
+fn parse_column(column: &str) -> Result<usize, String> {
+    column
+        .parse()
+        .map_err(|_| format!("Invalid column: {column}"))
+}
+
+fn assemble(args: &[String]) -> Result<(), String> {
+    let options = AssembleOptions::parse(args)?;
+    let input = options
+        .input
+        .ok_or_else(|| format!("Missing input\n\n{USAGE}"))?;
+    let format = options
+        .format
+        .or_else(|| options.output.as_ref().map(OutputFormat::from_path))
+        .unwrap_or_default();
+    let extension = match options.object {
+        true => "o",
+        false => format.extension(),
+    };
+    let output = options.output.unwrap_or_else(|| {
+        Path::new(&input)
+            .with_extension(extension)
+            .to_string_lossy()
+            .to_string()
+    });
+
+    let source = fs::read_to_string(&input).map_err(|e| format!("{input}: {e}"))?;
+
+    // files included by the input are searched next to it first
+    let mut include_paths = Path::new(&input)
+        .parent()
+        .map(Path::to_path_buf)
+        .into_iter()
+        .collect::<Vec<_>>();
+    include_paths.extend(options.include_paths);
+
+    let mut assembler = Assembler::new(&source);
+    assembler
+        .set_resolver(FsResolver::new(include_paths))
+        .set_long_branches(options.long_branches)
+        .set_dialect(options.dialect)
+        .set_cpu(options.cpu);
+    for (name, value) in &options.symbols {
+        assembler.define_symbol(name, *value);
+    }
+    if let Some(path) = &options.layout {
+        assembler.set_layout(read_config(path)?);
+    }
+    for (lint, level) in options.lints {
+        match lint {
+            Some(lint) => assembler.set_lint(lint, level),
+            None => assembler.set_lints(level),
+        };
+    }
+    if options.object {
+        let result = assembler.assemble_object();
+        if let Some(file) = assembler.files.first_mut() {
+            file.name = input.clone();
+        }
+        let object = result.map_err(|_| assembler.diagnostics())?;
+        for warning in &assembler.warnings {
+            eprintln!("{}", warning.render(&assembler.files));
+        }
+
+        let outputs = [
+            (Some(output), object.write()),
+            (options.listing, assembler.listing.to_string()),
+        ];
+        for (path, contents) in outputs {
+            if let Some(path) = path {
+                fs::write(&path, contents).map_err(|e| format!("{path}: {e}"))?;
+            }
+        }
+        return Ok(());
+    }
+
+    let result = assembler.build();
+    // every error and warning is printed with its source line
+    if let Some(file) = assembler.files.first_mut() {
+        file.name = input.clone();
+    }
+    let mut assembly = result.map_err(|_| assembler.diagnostics())?;
+    assembly.files[0] = input.clone();
+    for warning in &assembler.warnings {
+        eprintln!("{}", warning.render(&assembler.files));
+    }
+
+    let outputs = [
+        (Some(output), format.write(&assembly.chunks)),
+        (options.listing, assembler.listing.to_string().into_bytes()),
+        (options.labels, assembly.vice_labels().into_bytes()),
+        (options.debug, assembly.json().into_bytes()),
+        (options.dbg, assembly.ca65_dbg().into_bytes()),
+    ];
+    for (path, contents) in outputs {
+        if let Some(path) = path {
+            fs::write(&path, contents).map_err(|e| format!("{path}: {e}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct LinkOptions {
+    objects: Vec<String>,
+    output: Option<String>,
+    format: Option<OutputFormat>,
+    config: Option<String>,
+    map: Option<String>,
+}
+
+impl LinkOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" => options.output = Some(value(&mut args, arg)?),
+                "-f" => options.format = Some(parse_format(&value(&mut args, arg)?)?),
+                "-C" => options.config = Some(value(&mut args, arg)?),
+                "-m" => options.map = Some(value(&mut args, arg)?),
+                arg if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+                _ => options.objects.push(arg.clone()),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn link_objects(args: &[String]) -> Result<(), String> {
+    let options = LinkOptions::parse(args)?;
+    let Some(first) = options.objects.first() else {
+        return Err(format!("Missing objects\n\n{USAGE}"));
+    };
+    let format = options
+        .format
+        .or_else(|| options.output.as_ref().map(OutputFormat::from_path))
+        .unwrap_or_default();
+    let output = options.output.clone().unwrap_or_else(|| {
+        Path::new(first)
+            .with_extension(format.extension())
+            .to_string_lossy()
+            .to_string()
+    });
+
+    let config = match &options.config {
+        Some(path) => read_config(path)?,
+        None => LinkerConfig::default(),
+    };
+
+    let mut objects = Vec::new();
+    for path in &options.objects {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let mut object = Object::read(&text).map_err(|e| format!("{path}: {e}"))?;
+        object.name = path.clone();
+        objects.push(object);
+    }
+
+    let linked = link(&objects, &config).map_err(|e| e.to_string())?;
+
+    let outputs = [
+        (Some(output), format.write(&linked.chunks)),
+        (options.map, linked.map().into_bytes()),
+    ];
+    for (path, contents) in outputs {
+        if let Some(path) = path {
+            fs::write(&path, contents).map_err(|e| format!("{path}: {e}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct FormatFileOptions {
+    input: Option<String>,
+    output: Option<String>,
+    write: bool,
+    format: FormatOptions,
+}
+
+impl FormatFileOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let format = &mut options.format;
+            match arg.as_str() {
+                "-o" => options.output = Some(value(&mut args, arg)?),
+                "-w" | "--write" => options.write = true,
+                "--case" => {
+                    let name = value(&mut args, arg)?;
+                    format.mnemonic_case = MnemonicCase::from_name(&name)
+                        .ok_or_else(|| format!("Unknown case: {name}"))?;
+                }
+                "--radix" => {
+                    let name = value(&mut args, arg)?;
+                    format.radix =
+                        Radix::from_name(&name).ok_or_else(|| format!("Unknown radix: {name}"))?;
+                }
+                "--label-column" => format.label_column = parse_column(&value(&mut args, arg)?)?,
+                "--mnemonic-column" => {
+                    format.mnemonic_column = parse_column(&value(&mut args, arg)?)?
+                }
+                "--operand-column" => {
+                    format.operand_column = parse_column(&value(&mut args, arg)?)?
+                }
+                "--comment-column" => {
+                    format.comment_column = parse_column(&value(&mut args, arg)?)?
+                }
+                "--explicit-accumulator" => format.accumulator = AccumulatorSyntax::Explicit,
+                "--dialect" => format.dialect = parse_dialect(&value(&mut args, arg)?)?,
+                "--cpu" => format.cpu = parse_cpu(&value(&mut args, arg)?)?,
+                arg if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+                _ if options.input.is_none() => options.input = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument: {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn format_file(args: &[String]) -> Result<(), String> {
+    let options = FormatFileOptions::parse(args)?;
+    let input = options
+        .input
+        .ok_or_else(|| format!("Missing input\n\n{USAGE}"))?;
+
+    let source = fs::read_to_string(&input).map_err(|e| format!("{input}: {e}"))?;
+    let formatted = format_source(&source, &options.format);
+
+    match options.output.or(options.write.then_some(input)) {
+        Some(path) => fs::write(&path, formatted).map_err(|e| format!("{path}: {e}")),
+        None => {
+            print!("{formatted}");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Default)]
+struct DisassembleOptions {
+    input: Option<String>,
+    output: Option<String>,
+    format: Option<OutputFormat>,
+    origin: Option<u16>,
+    disassembly: DisassemblyOptions,
+}
+
+impl DisassembleOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let disassembly = &mut options.disassembly;
+            match arg.as_str() {
+                "-o" => options.output = Some(value(&mut args, arg)?),
+                "-f" => options.format = Some(parse_format(&value(&mut args, arg)?)?),
+                "--origin" => options.origin = Some(parse_address(&value(&mut args, arg)?)?),
+                "-e" | "--entry" => disassembly
+                    .entries
+                    .push(parse_address(&value(&mut args, arg)?)?),
+                "--explicit-accumulator" => disassembly.accumulator = AccumulatorSyntax::Explicit,
+                "--cpu" => disassembly.cpu = parse_cpu(&value(&mut args, arg)?)?,
+                arg if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+                _ if options.input.is_none() => options.input = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument: {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn disassemble_file(args: &[String]) -> Result<(), String> {
+    let options = DisassembleOptions::parse(args)?;
+    let input = options
+        .input
+        .ok_or_else(|| format!("Missing input\n\n{USAGE}"))?;
+    let format = options
+        .format
+        .unwrap_or_else(|| OutputFormat::from_path(&input));
+
+    let data = fs::read(&input).map_err(|e| format!("{input}: {e}"))?;
+    let mut chunks = format
+        .read(&data)
+        .map_err(|e| format!("{input}:{}: {}", e.span.start.line, e.kind))?;
+    if let (OutputFormat::Raw, Some(origin), [chunk]) = (format, options.origin, &mut chunks[..]) {
+        chunk.origin = origin;
+    }
+
+    let (origin, bytes) = chunks_to_image(&chunks);
+    let source = disassemble_flow(origin, &bytes, &options.disassembly).source();
+
+    match options.output {
+        Some(path) => fs::write(&path, source).map_err(|e| format!("{path}: {e}")),
+        None => {
+            print!("{source}");
+            Ok(())
+        }
+    }
+}
+
+fn read_config(path: &str) -> Result<LinkerConfig, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    LinkerConfig::parse(&text).map_err(|e| format!("{path}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Empty directory for the files of a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cli-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file(dir: &Path, name: &str) -> String {
+        dir.join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_run() {
+        assert_eq!(run(&[]), Ok(()));
+        assert_eq!(
+            run(&args(&["build"])),
+            Err(format!("Unknown command: build\n\n{USAGE}"))
+        );
+        for command in ["assemble", "format", "disassemble"] {
+            assert_eq!(
+                run(&args(&[command])),
+                Err(format!("Missing input\n\n{USAGE}"))
+            );
+        }
+        assert_eq!(
+            run(&args(&["link"])),
+            Err(format!("Missing objects\n\n{USAGE}"))
+        );
+    }
+
+    #[test]
+    fn test_assemble_options() {
+        let options = AssembleOptions::parse(&args(&[
+            "main.asm",
+            "-DDEBUG",
+            "-D",
+            "SIZE=$10 * 2",
+            "-Ilib",
+            "-I",
+            "inc",
+            "--allow",
+            "warnings",
+            "--deny",
+            "unused",
+            "-f",
+            "ihex",
+            "--cpu",
+            "65C02",
+            "--dialect",
+            "ca65",
+            "--long-branches",
+        ]))
+        .unwrap();
+        assert_eq!(options.input.as_deref(), Some("main.asm"));
+        assert_eq!(
+            options.symbols,
+            [("DEBUG".to_string(), 1), ("SIZE".to_string(), 32)]
+        );
+        assert_eq!(options.include_paths, [PathBuf::from("lib"), "inc".into()]);
+        assert_eq!(
+            options.lints,
+            [
+                (None, LintLevel::Allow),
+                (Some(Lint::Unused), LintLevel::Deny)
+            ]
+        );
+        assert_eq!(options.format, Some(OutputFormat::IntelHex));
+        assert_eq!(options.cpu, CpuType::Cmos65C02);
+        assert_eq!(options.dialect, Dialect::Ca65);
+        assert!(options.long_branches);
+
+        let error = |list: &[&str]| AssembleOptions::parse(&args(list)).err().unwrap();
+        assert_eq!(error(&["a.asm", "b.asm"]), "Unexpected argument: b.asm");
+        assert_eq!(error(&["--listing", "a.lst"]), "Unknown option: --listing");
+        assert_eq!(error(&["a.asm", "-o"]), "Missing value for -o");
+        assert_eq!(error(&["-f", "bin"]), "Unknown format: bin");
+        assert_eq!(
+            error(&["--warn", "unused-label"]),
+            "Unknown lint: unused-label"
+        );
+        assert_eq!(error(&["--cpu", "65816"]), "Unknown CPU: 65816");
+        assert_eq!(error(&["--dialect", "acme"]), "Unknown dialect: acme");
+        assert_eq!(error(&["-D=1"]), "Invalid symbol: =1");
+        assert_eq!(
+            error(&["-DSIZE=WIDTH"]),
+            "Invalid value for SIZE: Invalid label: WIDTH"
+        );
+    }
+
+    #[test]
+    fn test_assemble() {
+        let dir = temp_dir("assemble");
+        let input = file(&dir, "main.asm");
+        fs::write(&input, ".include \"init.asm\"\nstart: JMP start\n").unwrap();
+        fs::write(dir.join("init.asm"), "    SEI\n").unwrap();
+
+        // the output is next to the input, with the extension of the format
+        assert_eq!(run(&args(&["assemble", &input])), Ok(()));
+        assert_eq!(
+            fs::read(dir.join("main.bin")).unwrap(),
+            [0x78, 0x4C, 0x01, 0x80]
+        );
+        assert_eq!(run(&args(&["assemble", &input, "-f", "prg"])), Ok(()));
+        assert_eq!(
+            fs::read(dir.join("main.prg")).unwrap(),
+            [0x00, 0x80, 0x78, 0x4C, 0x01, 0x80]
+        );
+
+        // the format of -o is chosen by its extension
+        let output = file(&dir, "out.hex");
+        let labels = file(&dir, "main.lbl");
+        let listing = file(&dir, "main.lst");
+        let debug = file(&dir, "main.json");
+        let dbg = file(&dir, "main.dbg");
+        let result = run(&args(&[
+            "assemble", &input, "-o", &output, "-l", &listing, "--labels", &labels, "--debug",
+            &debug, "--dbg", &dbg,
+        ]));
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            ":04800000784C018037\n:00000001FF\n"
+        );
+        assert_eq!(fs::read_to_string(&labels).unwrap(), "al C:8001 .start\n");
+        assert!(fs::read_to_string(&listing).unwrap().contains("SEI"));
+        assert!(fs::read_to_string(&debug).unwrap().contains(&input));
+        assert!(fs::read_to_string(&dbg).unwrap().contains(&input));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let dir = temp_dir("assemble-errors");
+        let input = file(&dir, "main.asm");
+        fs::write(&input, "    LDA #$100\n").unwrap();
+
+        // the diagnostics name the input file
+        let error = run(&args(&["assemble", &input])).unwrap_err();
+        assert!(error.contains(&format!("{input}:1:")), "{error}");
+        assert!(!dir.join("main.bin").exists());
+
+        let missing = file(&dir, "missing.asm");
+        let error = run(&args(&["assemble", &missing])).unwrap_err();
+        assert!(error.starts_with(&format!("{missing}: ")), "{error}");
+    }
+
+    #[test]
+    fn test_link() {
+        let dir = temp_dir("link");
+        let (main, data) = (file(&dir, "main.asm"), file(&dir, "data.asm"));
+        fs::write(&main, ".import value\n    LDA value\n    RTS\n").unwrap();
+        fs::write(
+            &data,
+            ".export value\n.segment \"RODATA\"\nvalue: .byte $2A\n",
+        )
+        .unwrap();
+        for input in [&main, &data] {
+            assert_eq!(run(&args(&["assemble", input, "-c"])), Ok(()));
+        }
+
+        // the output is named after the first object
+        let (main, data) = (file(&dir, "main.o"), file(&dir, "data.o"));
+        assert_eq!(run(&args(&["link", &main, &data])), Ok(()));
+        assert_eq!(
+            fs::read(dir.join("main.bin")).unwrap(),
+            [0xAD, 0x04, 0x80, 0x60, 0x2A]
+        );
+
+        let config = file(&dir, "layout.cfg");
+        fs::write(
+            &config,
+            "MEMORY { ROM: start = $C000, size = $100; }\nSEGMENTS { CODE: load = ROM; RODATA: load = ROM; }\n",
+        )
+        .unwrap();
+        let (output, map) = (file(&dir, "out.srec"), file(&dir, "out.map"));
+        let result = run(&args(&[
+            "link", &main, &data, "-C", &config, "-o", &output, "-m", &map,
+        ]));
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "S0030000FC\nS107C000AD04C06067\nS104C0042A0D\nS5030002FA\nS903C0003C\n"
+        );
+        let map = fs::read_to_string(&map).unwrap();
+        assert!(
+            map.contains("\nROM          $C000  $C0FF  $0100  $0005\n"),
+            "{map}"
+        );
+        assert!(
+            map.ends_with("\nvalue                        $C004\n"),
+            "{map}"
+        );
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let dir = temp_dir("link-errors");
+        let main = file(&dir, "main.asm");
+        fs::write(&main, ".import value\n    LDA value\n").unwrap();
+        assert_eq!(run(&args(&["assemble", &main, "-c"])), Ok(()));
+        let object = file(&dir, "main.o");
+
+        assert_eq!(
+            run(&args(&["link", &object])),
+            Err(format!("Unresolved import value of \"{object}\""))
+        );
+
+        let invalid = file(&dir, "invalid.o");
+        fs::write(&invalid, "segment CODE\n").unwrap();
+        assert_eq!(
+            run(&args(&["link", &invalid])),
+            Err(format!(
+                "{invalid}: Invalid object file: expected the header `o6502 1` at line 1, column 1"
+            ))
+        );
+
+        let config = file(&dir, "layout.cfg");
+        fs::write(&config, "MEMORY { ROM: start = $C000; }\n").unwrap();
+        assert_eq!(
+            run(&args(&["link", &object, "-C", &config])),
+            Err(format!(
+                "{config}: Invalid linker config: memory area ROM needs a start and a size at line 1, column 28"
+            ))
+        );
+
+        let error = |list: &[&str]| LinkOptions::parse(&args(list)).err().unwrap();
+        assert_eq!(error(&["a.o", "-m"]), "Missing value for -m");
+        assert_eq!(error(&["a.o", "-c"]), "Unknown option: -c");
+    }
+
+    #[test]
+    fn test_format() {
+        let dir = temp_dir("format");
+        let input = file(&dir, "main.asm");
+        fs::write(&input, "start:  lda $0F,x ; load\n  rts\n").unwrap();
+
+        let output = file(&dir, "out.asm");
+        let result = run(&args(&[
+            "format",
+            &input,
+            "-o",
+            &output,
+            "--case",
+            "lower",
+            "--radix",
+            "dec",
+            "--mnemonic-column",
+            "4",
+            "--operand-column",
+            "8",
+            "--comment-column",
+            "16",
+        ]));
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "start: lda 15,x ; load\n    rts\n"
+        );
+
+        // -w rewrites the input
+        assert_eq!(run(&args(&["format", &input, "-w"])), Ok(()));
+        assert_eq!(
+            fs::read_to_string(&input).unwrap(),
+            "start:  LDA $0F,X               ; load\n        RTS\n"
+        );
+
+        let error = |list: &[&str]| FormatFileOptions::parse(&args(list)).err().unwrap();
+        assert_eq!(error(&["--case", "title"]), "Unknown case: title");
+        assert_eq!(error(&["--radix", "oct"]), "Unknown radix: oct");
+        assert_eq!(error(&["--label-column", "-1"]), "Invalid column: -1");
+        assert_eq!(error(&["a.asm", "b.asm"]), "Unexpected argument: b.asm");
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let dir = temp_dir("disassemble");
+        let input = file(&dir, "main.bin");
+        fs::write(&input, [0xA9, 0x01, 0x0A, 0x4C, 0x02, 0xC0]).unwrap();
+
+        // a raw input is loaded at --origin
+        let output = file(&dir, "main.asm");
+        let result = run(&args(&[
+            "disassemble",
+            &input,
+            "-o",
+            &output,
+            "--origin",
+            "$C000",
+            "-e",
+            "$C000",
+            "--explicit-accumulator",
+        ]));
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "        .org $C000\nLC000:  LDA #$01\nLC002:  ASL A\n        JMP LC002\n"
+        );
+
+        // the format of the input is chosen by its extension,
+        // without vectors the code is followed from its start
+        let input = file(&dir, "main.hex");
+        fs::write(&input, ":03C00000A9010A89\n:00000001FF\n").unwrap();
+        assert_eq!(run(&args(&["disassemble", &input, "-o", &output])), Ok(()));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "        .org $C000\nLC000:  LDA #$01\n        ASL\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_errors() {
+        let dir = temp_dir("disassemble-errors");
+        let input = file(&dir, "main.hex");
+        fs::write(&input, ":03C00000A9010A00\n").unwrap();
+        assert_eq!(
+            run(&args(&["disassemble", &input])),
+            Err(format!("{input}:1: Invalid record: invalid checksum"))
+        );
+
+        let error = |list: &[&str]| DisassembleOptions::parse(&args(list)).err().unwrap();
+        assert_eq!(error(&["--origin", "$10000"]), "Invalid address: $10000");
+        assert_eq!(error(&["-e", "start"]), "Invalid address: start");
+        assert_eq!(error(&["--entry"]), "Missing value for --entry");
+        assert_eq!(error(&["-f", "elf"]), "Unknown format: elf");
+    }
+}
@@ -3,6 +3,7 @@ use emulator::DebugKind;
 use std::sync::atomic::AtomicBool;
 
 pub mod app;
+pub mod cli;
 pub mod ui;
 
 /// (time, message, kind)
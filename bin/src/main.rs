@@ -1,8 +1,18 @@
-use bin::app::App;
+use bin::{app::App, cli};
 use eframe::egui;
 use egui::*;
+use std::{env, process};
 
 fn main() -> Result<(), eframe::Error> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        if let Err(error) = cli::run(&args) {
+            eprintln!("{error}");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         initial_window_size: Some(Vec2::new(1000., 550.)),
         ..Default::default()
@@ -15,11 +25,11 @@ fn main() -> Result<(), eframe::Error> {
 LDX #$00
 LDY #$00
 
-define STA_ADDR $0200,Y
+STA_ADDR = $0200
 
 firstloop:
     TXA
-    STA STA_ADDR
+    STA STA_ADDR,Y
     PHA
     INX
     INY
@@ -28,7 +38,7 @@ firstloop:
 
 secondloop:
     PLA
-    STA STA_ADDR
+    STA STA_ADDR,Y
     INY
     CPY #$20
     BNE secondloop
@@ -1,5 +1,5 @@
 use crate::{app::App, View};
-use assembler::disassemble;
+use assembler::{disassemble_flow, DisassemblyOptions};
 use eframe::egui::*;
 use emulator::memory::MemoryBus;
 
@@ -8,14 +8,14 @@ pub struct DisassemblerUi;
 impl View for DisassemblerUi {
     fn ui(&mut self, ui: &mut Ui, app: &mut App) {
         if ui.button("disassemble").clicked() {
-            let sliced = app.emulator.memory.slice(0x8000..0xFFFF);
-            app.disassembled = match disassemble(sliced) {
-                Ok(disassembled) => disassembled,
-                Err(e) => {
-                    app.error = Some(e.to_string());
-                    return;
-                }
+            // the ROM, the code is followed from the vectors at its end
+            let rom = app.emulator.memory.slice(0x8000..=0xFFFF);
+            let options = DisassemblyOptions {
+                cpu: app.settings.cpu,
+                accumulator: app.settings.accumulator,
+                ..Default::default()
             };
+            app.disassembled = disassemble_flow(0x8000, rom, &options).lines;
         }
 
         ui.separator();
@@ -23,16 +23,37 @@ impl View for DisassemblerUi {
         ScrollArea::both()
             .auto_shrink([false, true])
             .show(ui, |ui| {
-                for (pointer, bytes, instruction) in app.disassembled.iter() {
+                for line in app.disassembled.iter() {
+                    let address = line.address;
+                    // the labels of the program, or the generated ones
+                    if let Some(label) = app
+                        .assembly
+                        .as_ref()
+                        .and_then(|assembly| assembly.label(address))
+                        .or(line.label.as_deref())
+                    {
+                        ui.label(
+                            RichText::new(format!("{label}:"))
+                                .monospace()
+                                .color(Color32::from_rgb(220, 180, 60)),
+                        );
+                    }
+
                     // ui.label(format!("0x{:04X}: {}", i + 0x8000, line));
                     let pointer = Label::new(
-                        RichText::new(format!("0x{:04X}", pointer + 0x8000))
+                        RichText::new(format!("0x{:04X}", address))
                             .monospace()
                             .color(Color32::from_rgb(50, 180, 80)),
                     );
+                    let bytes = line
+                        .bytes
+                        .iter()
+                        .map(|byte| format!("{byte:02X}"))
+                        .collect::<Vec<_>>()
+                        .join(" ");
                     let bytes = Label::new(RichText::new(bytes).monospace());
                     let instruction =
-                        Label::new(RichText::new(instruction).monospace().color(Color32::WHITE));
+                        Label::new(RichText::new(&line.text).monospace().color(Color32::WHITE));
 
                     ui.horizontal(|ui| {
                         ui.add(pointer);
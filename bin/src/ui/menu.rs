@@ -1,6 +1,10 @@
-use crate::{app::App, View, IS_RUNNING};
-use assembler::{disassemble, Assembler};
+use crate::{
+    app::{load_chunks, App},
+    View, IS_RUNNING,
+};
+use assembler::{chunks_to_image, disassemble_flow, DisassemblyOptions, OutputFormat};
 use eframe::egui::{menu::menu_button, *};
+use emulator::{DebugKind, Debugger};
 use std::{fs, sync::atomic::Ordering};
 
 pub struct MenuBar;
@@ -17,12 +21,19 @@ impl View for MenuBar {
 
             menu_button(ui, "File", |ui| {
                 if ui.button("save as binary").clicked() {
-                    let dialog = tinyfiledialogs::save_file_dialog("Save as binary", "binary.bin");
+                    // the format is chosen by the extension, see `OutputFormat::from_path`
+                    let dialog = tinyfiledialogs::save_file_dialog_with_filter(
+                        "Save as binary",
+                        "binary.bin",
+                        &["*.bin", "*.hex", "*.srec", "*.s19", "*.prg"],
+                        "Binary, Intel HEX, S-record or PRG",
+                    );
 
                     if let Some(path) = dialog {
-                        let binary = Assembler::new(&app.source_input).assemble();
-                        match binary {
-                            Ok(binary) => {
+                        let chunks = app.assembler().assemble_chunks();
+                        match chunks {
+                            Ok(chunks) => {
+                                let binary = OutputFormat::from_path(&path).write(&chunks);
                                 if let Err(e) = fs::write(path, binary) {
                                     app.error = Some(e.to_string());
                                 }
@@ -42,8 +53,32 @@ impl View for MenuBar {
                     );
 
                     if let Some(path) = dialog {
-                        if let Err(e) = fs::write(path, source) {
-                            app.error = Some(e.to_string());
+                        match fs::write(&path, source) {
+                            Ok(_) => app.source_path = Some(path.into()),
+                            Err(e) => app.error = Some(e.to_string()),
+                        }
+                    }
+                }
+                if ui.button("save as listing").clicked() {
+                    let dialog = tinyfiledialogs::save_file_dialog_with_filter(
+                        "Save as listing",
+                        "listing.lst",
+                        &["*.lst"],
+                        "Assembly listing (*.lst)",
+                    );
+
+                    if let Some(path) = dialog {
+                        let listing = {
+                            let mut assembler = app.assembler();
+                            assembler.assemble().map(|_| assembler.listing.to_string())
+                        };
+                        match listing {
+                            Ok(listing) => {
+                                if let Err(e) = fs::write(path, listing) {
+                                    app.error = Some(e.to_string());
+                                }
+                            }
+                            Err(e) => app.error = Some(e.to_string()),
                         }
                     }
                 }
@@ -52,25 +87,27 @@ impl View for MenuBar {
                     let dialog = tinyfiledialogs::open_file_dialog("Load binary", "", None);
 
                     if let Some(path) = dialog {
-                        let binary = fs::read(path);
-                        match binary {
-                            Ok(binary) => {
+                        let chunks = fs::read(&path).map_err(|e| e.to_string()).and_then(|data| {
+                            OutputFormat::from_path(&path)
+                                .read(&data)
+                                .map_err(|e| format!("{path}:{}: {}", e.span.start.line, e.kind))
+                        });
+                        match chunks {
+                            Ok(chunks) => {
                                 app.emulator.reset();
-                                app.emulator.load(&binary);
-
-                                app.source_input = match disassemble(&binary) {
-                                    Ok(disassembled) => disassembled
-                                        .iter()
-                                        .map(|(_, _, i)| i.clone())
-                                        .collect::<Vec<_>>()
-                                        .join("\n"),
-                                    Err(e) => {
-                                        app.error = Some(e.to_string());
-                                        return;
-                                    }
+                                load_chunks(&mut app.emulator, &chunks);
+                                app.assembly = None;
+
+                                let (origin, binary) = chunks_to_image(&chunks);
+                                let options = DisassemblyOptions {
+                                    cpu: app.settings.cpu,
+                                    accumulator: app.settings.accumulator,
+                                    ..Default::default()
                                 };
+                                app.source_input =
+                                    disassemble_flow(origin, &binary, &options).source();
                             }
-                            Err(e) => app.error = Some(e.to_string()),
+                            Err(e) => app.error = Some(e),
                         }
                     }
                 }
@@ -82,10 +119,11 @@ impl View for MenuBar {
                     );
 
                     if let Some(path) = dialog {
-                        let source = fs::read_to_string(path);
+                        let source = fs::read_to_string(&path);
                         match source {
                             Ok(source) => {
                                 app.source_input = source;
+                                app.source_path = Some(path.into());
                                 app.emulator.reset();
                             }
                             Err(e) => app.error = Some(e.to_string()),
@@ -128,16 +166,27 @@ impl View for MenuBar {
             }
 
             if ui.button("Load").clicked() {
-                let src = match Assembler::new(&app.source_input).assemble() {
-                    Ok(src) => src,
-                    Err(e) => {
-                        app.error = Some(e.to_string());
+                let (assembly, warnings) = {
+                    let mut assembler = app.assembler();
+                    let assembly = assembler.build().map_err(|_| assembler.diagnostics());
+                    (assembly, std::mem::take(&mut assembler.warnings))
+                };
+                let assembly = match assembly {
+                    Ok(assembly) => assembly,
+                    Err(diagnostics) => {
+                        app.error = Some(diagnostics);
                         return;
                     }
                 };
 
                 app.emulator.reset();
-                app.emulator.load(&src);
+                load_chunks(&mut app.emulator, &assembly.chunks);
+                app.assembly = Some(assembly);
+                for warning in warnings {
+                    app.emulator
+                        .debugger
+                        .debug(&warning.to_string(), DebugKind::Warn);
+                }
                 app.error = None;
             }
 
@@ -1,4 +1,5 @@
 use crate::{app::App, View};
+use assembler::{AccumulatorSyntax, CpuType};
 use eframe::egui::*;
 
 pub struct SettingsUi;
@@ -14,6 +15,22 @@ impl View for SettingsUi {
                 ui.checkbox(&mut settings.reactive_mode, "Reactive Mode")
                     .on_hover_text("If unchecked (Continuous mode), CPU usage may increase.");
                 ui.checkbox(&mut settings.panel_ui, "Panel UI");
+                ComboBox::from_label("CPU")
+                    .selected_text(settings.cpu.name())
+                    .show_ui(ui, |ui| {
+                        for cpu in CpuType::ALL {
+                            ui.selectable_value(&mut settings.cpu, cpu, cpu.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Instructions the assembler and the disassembler accept, the source can select another CPU with .setcpu");
+                ui.horizontal(|ui| {
+                    ui.label("Accumulator");
+                    ui.radio_value(&mut settings.accumulator, AccumulatorSyntax::Implied, "ASL");
+                    ui.radio_value(&mut settings.accumulator, AccumulatorSyntax::Explicit, "ASL A");
+                })
+                .response
+                .on_hover_text("How the disassembler and Format write the accumulator mode, the assembler accepts both");
                 ui.separator();
                 ui.checkbox(&mut visibility.display, "Show Display");
                 ui.checkbox(&mut visibility.source, "Show Source Input");
@@ -1,4 +1,5 @@
 use crate::{app::App, View};
+use assembler::{format_source, FormatOptions};
 use eframe::egui::*;
 
 pub struct SourceInput;
@@ -6,6 +7,19 @@ pub struct SourceInput;
 impl View for SourceInput {
     fn ui(&mut self, ui: &mut Ui, app: &mut App) {
         ui.vertical_centered(|ui| {
+            if ui
+                .button("Format")
+                .on_hover_text("Aligns the labels, instructions and comments in columns")
+                .clicked()
+            {
+                let options = FormatOptions {
+                    cpu: app.settings.cpu,
+                    accumulator: app.settings.accumulator,
+                    ..Default::default()
+                };
+                app.source_input = format_source(&app.source_input, &options);
+            }
+
             ScrollArea::vertical().show(ui, |ui| {
                 ui.style_mut().visuals.extreme_bg_color = Color32::default();
                 ui.vertical_centered(|ui| {
@@ -1,17 +1,2 @@
-#[doc = include_str!("../../../docs/addressing_mode.md")]
-#[derive(Clone, Copy, Debug)]
-pub enum AddressingMode {
-    // Implicit,
-    // Accumulator,
-    Immediate,
-    Absolute,
-    AbsoluteX,
-    AbsoluteY,
-    Indirect,
-    IndirectX,
-    IndirectY,
-    ZeroPage,
-    ZeroPageX,
-    ZeroPageY,
-    // Relative,
-}
+//! The addressing modes of the opcode table the CPU dispatches on, see [`opcodes::OPCODES`].
+pub use opcodes::AddressingMode;
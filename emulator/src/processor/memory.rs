@@ -13,6 +13,7 @@ pub trait MemoryBus {
     type Data;
     type Addr;
     fn rom(&mut self, data: &[Self::Data]);
+    fn load(&mut self, addr: Self::Addr, data: &[Self::Data]);
     fn reset(&mut self);
     fn write(&mut self, addr: Self::Addr, data: Self::Data);
     fn read(&mut self, addr: Self::Addr) -> Self::Data;
@@ -34,15 +35,17 @@ pub trait MemoryBus {
 ///
 /// The actual ROM memory map of the MOS 6502 ranges from `0x8000` - `0xFFF9`, and interrupt vectors are stored in `0xFFFA` - `0xFFFF`.
 /// however, since it does not implement interrupts, it is currently not used.
+///
+/// Programs are not limited to the ROM area, [`MemoryBus::load`] can place data at any address.
 pub struct Memory<T: Debugger> {
-    pub mem: [u8; 0xFFFF],
+    pub mem: [u8; 0x10000],
     pub debugger: T,
 }
 
 impl<T: Debugger> Memory<T> {
     pub fn new() -> Memory<T> {
         Memory {
-            mem: [0; 0xFFFF],
+            mem: [0; 0x10000],
             debugger: T::default(),
         }
     }
@@ -55,7 +58,7 @@ impl<T: Debugger> Memory<T> {
 impl<T: Debugger> Default for Memory<T> {
     fn default() -> Memory<T> {
         Memory {
-            mem: [0; 0xFFFF],
+            mem: [0; 0x10000],
             debugger: T::default(),
         }
     }
@@ -81,14 +84,30 @@ impl<T: Debugger> MemoryBus for Memory<T> {
 
     /// `rom` function loads the program from address `0x8000`.
     fn rom(&mut self, program: &[Self::Data]) {
-        self.debug(format!("Load ROM ({} bytes)", program.len()).as_str());
-        self.mem[ORG as usize..ORG as usize + program.len()].copy_from_slice(program);
+        self.load(ORG, program);
+    }
+
+    /// `load` function loads the data from the given address.
+    /// data past `0xFFFF` is truncated.
+    fn load(&mut self, address: Self::Addr, data: &[Self::Data]) {
+        self.debug(&format!("Load 0x{:04X} ({} bytes)", address, data.len()));
+
+        let start = address as usize;
+        let end = (start + data.len()).min(self.mem.len());
+        if end - start < data.len() {
+            self.debugger.debug(
+                &format!("Truncated {} bytes", data.len() - (end - start)),
+                DebugKind::Warn,
+            );
+        }
+
+        self.mem[start..end].copy_from_slice(&data[..end - start]);
     }
 
     /// Resets the memory.
     fn reset(&mut self) {
         self.debug("Reset Memory");
-        self.mem = [0; 0xFFFF];
+        self.mem = [0; 0x10000];
     }
 
     /// Write data to memory address
@@ -130,37 +149,10 @@ impl<T: Debugger> MemoryBus for Memory<T> {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_read_write() {
-        let mut memory = Memory::<NoneDebugger>::default();
-
-        memory.write(0x0000, 0x12);
-        memory.write(0x0001, 0x34);
-
-        assert_eq!(memory.read(0x0000), 0x12);
-        assert_eq!(memory.read(0x0001), 0x34);
-    }
-
-    #[test]
-    fn test_read_write_addr() {
-        let mut memory = Memory::<NoneDebugger>::default();
-
-        memory.write_addr(0x0000, 0x1234);
-
-        assert_eq!(memory.read(0x0000), 0x34);
-        assert_eq!(memory.read(0x0001), 0x12);
-        assert_eq!(memory.read_addr(0x0000), 0x1234);
-    }
-}
-
 /// | 0x0000 | 00 00 .. 00 00 | ................ |
 pub type MemoryDumpResult = Vec<(u16, [u8; 16], [char; 16])>;
 
-pub fn memory_hexdump(memory: [u8; 0xFFFF], start: u16, end: u16) -> MemoryDumpResult {
+pub fn memory_hexdump(memory: [u8; 0x10000], start: u16, end: u16) -> MemoryDumpResult {
     let mut memory: Memory<NoneDebugger> = Memory {
         mem: memory,
         ..Default::default()
@@ -171,10 +163,10 @@ pub fn memory_hexdump(memory: [u8; 0xFFFF], start: u16, end: u16) -> MemoryDumpR
         let mut line = ([0; 16], [' '; 16]);
 
         for i in 0..16 {
-            if addr + i > u16::MAX - 1 {
+            let Some(addr) = addr.checked_add(i) else {
                 break;
-            }
-            let data = memory.read(addr + i);
+            };
+            let data = memory.read(addr);
 
             line.0[i as usize] = data;
 
@@ -191,7 +183,7 @@ pub fn memory_hexdump(memory: [u8; 0xFFFF], start: u16, end: u16) -> MemoryDumpR
     result
 }
 
-pub fn memory_hexdump_string(memory: [u8; 0xFFFF], start: u16, end: u16) -> String {
+pub fn memory_hexdump_string(memory: [u8; 0x10000], start: u16, end: u16) -> String {
     let mut memory: Memory<NoneDebugger> = Memory {
         mem: memory,
         ..Default::default()
@@ -202,22 +194,22 @@ pub fn memory_hexdump_string(memory: [u8; 0xFFFF], start: u16, end: u16) -> Stri
         let mut line = format!("[0x{:04X}] ", addr);
 
         for i in 0..16 {
-            if addr + i > u16::MAX - 1 {
+            let Some(addr) = addr.checked_add(i) else {
                 line.push_str("   ");
                 break;
-            }
-            let data = memory.read(addr + i);
+            };
+            let data = memory.read(addr);
             line.push_str(&format!("{:02X} ", data));
         }
 
         line.push_str("| ");
 
         for i in 0..16 {
-            if addr + i > u16::MAX - 1 {
+            let Some(addr) = addr.checked_add(i) else {
                 line.push(' ');
                 break;
-            }
-            let data = memory.read(addr + i);
+            };
+            let data = memory.read(addr);
             if data.is_ascii_control() {
                 line.push('.');
             } else {
@@ -232,3 +224,42 @@ pub fn memory_hexdump_string(memory: [u8; 0xFFFF], start: u16, end: u16) -> Stri
 
     result.join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write() {
+        let mut memory = Memory::<NoneDebugger>::default();
+
+        memory.write(0x0000, 0x12);
+        memory.write(0x0001, 0x34);
+
+        assert_eq!(memory.read(0x0000), 0x12);
+        assert_eq!(memory.read(0x0001), 0x34);
+    }
+
+    #[test]
+    fn test_read_write_addr() {
+        let mut memory = Memory::<NoneDebugger>::default();
+
+        memory.write_addr(0x0000, 0x1234);
+
+        assert_eq!(memory.read(0x0000), 0x34);
+        assert_eq!(memory.read(0x0001), 0x12);
+        assert_eq!(memory.read_addr(0x0000), 0x1234);
+    }
+
+    #[test]
+    fn test_load() {
+        let mut memory = Memory::<NoneDebugger>::default();
+
+        memory.load(0xE000, &[0x01, 0x02]);
+        memory.load(0xFFFE, &[0x03, 0x04, 0x05]);
+
+        assert_eq!(memory.read(0xE000), 0x01);
+        assert_eq!(memory.read(0xE001), 0x02);
+        assert_eq!(memory.read_addr(0xFFFE), 0x0403);
+    }
+}
@@ -76,7 +76,7 @@ FOO:
                 update_output()
                 output.value = `Error: ${assembled.error()}`
             } else {
-                evaluator.load(assembled.value())
+                evaluator.load_assembled(assembled)
                 update_output()
             }
         } catch (e) {
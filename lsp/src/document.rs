@@ -0,0 +1,337 @@
+use crate::{protocol, Json};
+use assembler::{
+    ca65_cpu, lexer::Lexer, Assembler, CpuType, Dialect, FsResolver, Position, Span, SymbolKind,
+    Token, TokenKind,
+};
+use std::{collections::HashMap, path::PathBuf};
+
+/// A label or constant defined in a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    /// name as written, `.loop` for a cheap local label
+    pub name: String,
+    /// see [`Occurrence::key`]
+    pub key: String,
+    pub span: Span,
+    pub kind: SymbolKind,
+    /// value of the last assembly, `None` if the document has errors
+    pub value: Option<i32>,
+}
+
+/// A label or constant written in a document, its definition or a reference to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    /// name the occurrences of a symbol share, cheap local labels are qualified
+    /// with their global label (`main.loop`) like the parser does
+    pub key: String,
+    pub span: Span,
+    pub definition: bool,
+}
+
+/// An open source file, analysed when it is opened or changed.
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    pub uri: String,
+    pub text: String,
+    pub definitions: Vec<Definition>,
+    pub occurrences: Vec<Occurrence>,
+    /// `Diagnostic`s of the protocol, the errors and warnings of the last assembly
+    pub diagnostics: Vec<Json>,
+    /// CPU of every line (from `.setcpu`), the first line is at index 0
+    cpus: Vec<CpuType>,
+    /// cycles of the instruction of a line, see [`assembler::ListingEntry::cycles`]
+    cycles: HashMap<usize, (u8, bool)>,
+}
+
+impl Document {
+    pub fn new(uri: &str, text: String) -> Self {
+        let mut document = Document {
+            uri: uri.to_string(),
+            text,
+            ..Default::default()
+        };
+        document.analyse_symbols();
+        document.assemble();
+        document
+    }
+
+    /// Tokens of every line without comments, the first line is at index 0.
+    /// the lexer switches its dialect after `.dialect ca65` like the parser, invalid tokens are skipped.
+    pub fn lines(&self) -> Vec<Vec<Token<'_>>> {
+        let mut lexer = Lexer::new(&self.text);
+        let mut lines = vec![Vec::new()];
+        loop {
+            match lexer.next_token() {
+                Ok(token) if token.kind == TokenKind::EOF => return lines,
+                Ok(token) if token.kind == TokenKind::Newline => {
+                    let line = lines.last().unwrap();
+                    if let [Token {
+                        kind: TokenKind::Directive(directive),
+                        ..
+                    }, Token {
+                        kind: TokenKind::Identifier(name),
+                        ..
+                    }] = line.as_slice()
+                    {
+                        if directive.eq_ignore_ascii_case("dialect") {
+                            lexer.dialect = Dialect::from_name(name).unwrap_or(lexer.dialect);
+                        }
+                    }
+                    lines.push(Vec::new());
+                }
+                Ok(token) if token.kind == TokenKind::Comment => {}
+                Ok(token) => lines.last_mut().unwrap().push(token),
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Token at the position, the position can also be right after the token.
+    pub fn token_at(&self, position: Position) -> Option<Token<'_>> {
+        let lines = self.lines();
+        let line = lines.get(position.line.checked_sub(1)?)?;
+        at(line, |token| token.span(), position).copied()
+    }
+
+    /// Occurrence of a label or constant at the position.
+    pub fn occurrence_at(&self, position: Position) -> Option<&Occurrence> {
+        at(&self.occurrences, |occurrence| occurrence.span, position)
+    }
+
+    pub fn definition(&self, key: &str) -> Option<&Definition> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.key == key)
+    }
+
+    /// CPU the line is assembled for, see [`CpuType::default`]
+    pub fn cpu(&self, line: usize) -> CpuType {
+        line.checked_sub(1)
+            .and_then(|i| self.cpus.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Cycles of the instruction at the line, if the last assembly had no errors.
+    pub fn cycles(&self, line: usize) -> Option<(u8, bool)> {
+        self.cycles.get(&line).copied()
+    }
+
+    /// Finds the definitions and references of the labels and constants, and the CPU of every line.
+    /// the document does not have to assemble, only the lines are read.
+    fn analyse_symbols(&mut self) {
+        let mut definitions = Vec::new();
+        let mut occurrences = Vec::new();
+        let mut cpus = Vec::new();
+        let mut cpu = CpuType::default();
+        // cheap local labels belong to it
+        let mut global = String::new();
+
+        for line in self.lines() {
+            cpus.push(cpu);
+
+            let kind = |i: usize| line.get(i).map(|token: &Token| token.kind);
+            let definition = match (kind(0), kind(1)) {
+                (Some(TokenKind::Directive(directive)), Some(TokenKind::String(name)))
+                    if directive.eq_ignore_ascii_case("setcpu") =>
+                {
+                    cpu = CpuType::from_name(name).unwrap_or(cpu);
+                    None
+                }
+                (Some(TokenKind::Directive(directive)), _) if ca65_cpu(directive).is_some() => {
+                    cpu = ca65_cpu(directive).unwrap();
+                    None
+                }
+                // the name and the parameters of a macro are not symbols
+                (Some(TokenKind::Directive(directive)), _)
+                    if directive.eq_ignore_ascii_case("macro") =>
+                {
+                    continue;
+                }
+                (
+                    Some(TokenKind::Identifier(_) | TokenKind::Directive(_)),
+                    Some(TokenKind::Colon),
+                ) if line[0].end == line[1].position => Some((0, SymbolKind::Label)),
+                (Some(TokenKind::Identifier(_)), Some(TokenKind::Equal)) => {
+                    Some((0, SymbolKind::Constant))
+                }
+                (Some(TokenKind::Identifier(_)), Some(TokenKind::Directive(directive)))
+                    if directive.eq_ignore_ascii_case("equ") =>
+                {
+                    Some((0, SymbolKind::Constant))
+                }
+                (Some(TokenKind::Directive(directive)), Some(TokenKind::Identifier(_)))
+                    if directive.eq_ignore_ascii_case("equ") =>
+                {
+                    Some((1, SymbolKind::Constant))
+                }
+                (Some(TokenKind::Define), Some(TokenKind::Identifier(_))) => {
+                    Some((1, SymbolKind::Constant))
+                }
+                (Some(TokenKind::Directive(directive)), Some(TokenKind::Identifier(_)))
+                    if directive.eq_ignore_ascii_case("proc") =>
+                {
+                    Some((1, SymbolKind::Label))
+                }
+                _ => None,
+            };
+
+            for (i, token) in line.iter().enumerate() {
+                let name = match token.kind {
+                    TokenKind::Identifier(name) => name.to_string(),
+                    // `.local` is a cheap local label, the directives start the lines
+                    TokenKind::Directive(name) if i > 0 || definition.is_some() => {
+                        format!(".{name}")
+                    }
+                    _ => continue,
+                };
+
+                let definition = definition.filter(|(index, _)| *index == i);
+                if definition.is_some_and(|(_, kind)| kind == SymbolKind::Label)
+                    && !name.starts_with(['.', '@'])
+                {
+                    global = name.clone();
+                }
+
+                let key = match name.strip_prefix(['.', '@']) {
+                    Some(local) => format!("{global}.{local}"),
+                    None => name.clone(),
+                };
+                if let Some((_, kind)) = definition {
+                    definitions.push(Definition {
+                        name,
+                        key: key.clone(),
+                        span: token.span(),
+                        kind,
+                        value: None,
+                    });
+                }
+                occurrences.push(Occurrence {
+                    key,
+                    span: token.span(),
+                    definition: definition.is_some(),
+                });
+            }
+        }
+
+        // only the names of the labels and constants are references,
+        // `scope::name` refers to the `name` of the scope
+        for occurrence in &mut occurrences {
+            if let Some((_, name)) = occurrence.key.rsplit_once("::") {
+                if !definitions.iter().any(|d| d.key == occurrence.key) {
+                    occurrence.key = name.to_string();
+                }
+            }
+        }
+        occurrences.retain(|occurrence| definitions.iter().any(|d| d.key == occurrence.key));
+
+        self.definitions = definitions;
+        self.occurrences = occurrences;
+        self.cpus = cpus;
+    }
+
+    /// Assembles the document for its diagnostics, the values of its symbols and the cycles of its lines.
+    /// included files are searched next to the document.
+    fn assemble(&mut self) {
+        let text = self.text.clone();
+        let mut assembler = Assembler::new(&text);
+        let include_paths = uri_path(&self.uri)
+            .and_then(|path| path.parent().map(PathBuf::from))
+            .into_iter()
+            .collect();
+        assembler.set_resolver(FsResolver::new(include_paths));
+        let _ = assembler.build();
+
+        for error in &assembler.errors {
+            // errors of included files and of the linker are reported at the start of the document
+            let (span, message) = match error.span.start {
+                Position { file: 0, line, .. } if line > 0 => (*error.span, error.kind.to_string()),
+                _ => (Span::default(), error.to_string()),
+            };
+            self.diagnostics.push(diagnostic(span, 1, message));
+        }
+        for warning in &assembler.warnings {
+            if warning.position.file == 0 {
+                let span = warning.span(&assembler.files);
+                self.diagnostics
+                    .push(diagnostic(span, 2, warning.kind.to_string()));
+            }
+        }
+
+        for symbol in &assembler.listing.symbols {
+            let Position {
+                line,
+                file: 0,
+                expansion: 0,
+                ..
+            } = symbol.position
+            else {
+                continue;
+            };
+            let name = symbol.name.rsplit("::").next().unwrap_or_default();
+            if let Some(definition) = self
+                .definitions
+                .iter_mut()
+                .find(|d| d.span.start.line == line && d.key == name)
+            {
+                definition.value = Some(symbol.value);
+            }
+        }
+        for entry in &assembler.listing.entries {
+            if let (Some(cycles), 0, 0) =
+                (entry.cycles, entry.position.file, entry.position.expansion)
+            {
+                self.cycles.insert(entry.position.line, cycles);
+            }
+        }
+    }
+}
+
+/// Item whose span contains the position, or else the item the position is right after
+/// (the cursor at the end of a word).
+fn at<T>(items: &[T], span: impl Fn(&T) -> Span, position: Position) -> Option<&T> {
+    let on_line = || {
+        items
+            .iter()
+            .filter(|item| span(item).start.line == position.line)
+    };
+
+    on_line()
+        .find(|item| (span(item).start.column..span(item).end.column).contains(&position.column))
+        .or_else(|| on_line().find(|item| span(item).end.column == position.column))
+}
+
+/// `Diagnostic` of the protocol, `severity` 1 for errors and 2 for warnings.
+fn diagnostic(span: Span, severity: usize, message: String) -> Json {
+    Json::object([
+        ("range", protocol::range(span)),
+        ("severity", severity.into()),
+        ("source", "6502".into()),
+        ("message", message.into()),
+    ])
+}
+
+/// Path of a `file://` URI, `None` for other schemes.
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+
+    // %20 and the other escapes, decoded as UTF-8
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail.get(..2)) {
+            (b'%', Some(hex)) => match u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16) {
+                Ok(decoded) => {
+                    bytes.push(decoded);
+                    rest = &tail[2..];
+                    continue;
+                }
+                Err(_) => bytes.push(byte),
+            },
+            _ => bytes.push(byte),
+        }
+        rest = tail;
+    }
+
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
@@ -0,0 +1,350 @@
+//! A language server for the assembler, over stdio.
+//! the documents are read with the lexer of the assembler and assembled on every change:
+//! diagnostics, definitions and references of labels and constants, hovers with the opcodes
+//! and the cycles of instructions and the values of symbols, completion, symbols and formatting.
+
+mod document;
+mod json;
+mod protocol;
+mod server;
+
+pub use document::*;
+pub use json::*;
+pub use protocol::*;
+pub use server::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a session with the messages, returns the messages of the server.
+    fn session(messages: &[Json]) -> Vec<Json> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+
+        let mut output = Vec::new();
+        run(input.as_slice(), &mut output).unwrap();
+
+        let mut output = output.as_slice();
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn request(id: usize, method: &str, params: Json) -> Json {
+        Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id.into()),
+            ("method", method.into()),
+            ("params", params),
+        ])
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ])
+    }
+
+    fn open(text: &str) -> Json {
+        notification(
+            "textDocument/didOpen",
+            Json::object([(
+                "textDocument",
+                Json::object([
+                    ("uri", "file:///test.asm".into()),
+                    ("languageId", "asm".into()),
+                    ("version", 1.into()),
+                    ("text", text.into()),
+                ]),
+            )]),
+        )
+    }
+
+    /// Parameters of a request at a position of the document.
+    fn at(line: usize, character: usize) -> Json {
+        Json::object([
+            (
+                "textDocument",
+                Json::object([("uri", "file:///test.asm".into())]),
+            ),
+            (
+                "position",
+                Json::object([("line", line.into()), ("character", character.into())]),
+            ),
+        ])
+    }
+
+    /// Result of the request on the document.
+    fn result(text: &str, method: &str, params: Json) -> Json {
+        let messages = session(&[open(text), request(1, method, params)]);
+        messages[1].get("result").clone()
+    }
+
+    fn range(json: &Json) -> (usize, usize, usize, usize) {
+        let position = |key| {
+            let position = json.get(key);
+            (
+                position.get("line").as_usize().unwrap(),
+                position.get("character").as_usize().unwrap(),
+            )
+        };
+        let ((start_line, start), (end_line, end)) = (position("start"), position("end"));
+        (start_line, start, end_line, end)
+    }
+
+    #[test]
+    fn test_json() {
+        let json =
+            Json::parse(r#" {"a": [1, -2.5, true, null], "b": "x\"\né😀", "c": {}} "#).unwrap();
+        assert_eq!(json.get("a").as_array().unwrap().len(), 4);
+        assert_eq!(json.get("b").as_str(), Some("x\"\né😀"));
+        assert_eq!(json.get("missing"), &Json::Null);
+        assert_eq!(
+            json.to_string(),
+            r#"{"a":[1,-2.5,true,null],"b":"x\"\né😀","c":{}}"#
+        );
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse(r#"{"a" 1}"#).is_err());
+        assert!(Json::parse("1 2").is_err());
+    }
+
+    #[test]
+    fn test_session() {
+        let messages = session(&[
+            request(1, "initialize", Json::object([])),
+            notification("initialized", Json::object([])),
+            request(2, "unknown", Json::object([])),
+            request(3, "shutdown", Json::Null),
+            notification("exit", Json::Null),
+            request(4, "shutdown", Json::Null),
+        ]);
+
+        assert_eq!(messages.len(), 3);
+        let capabilities = messages[0].get("result").get("capabilities");
+        assert_eq!(capabilities.get("textDocumentSync").as_usize(), Some(1));
+        assert_eq!(capabilities.get("hoverProvider").as_bool(), Some(true));
+        assert_eq!(messages[1].get("id").as_usize(), Some(2));
+        assert_eq!(messages[1].get("error").get("code"), &Json::Number(-32601.));
+        assert_eq!(messages[2].get("id").as_usize(), Some(3));
+        assert_eq!(messages[2].get("result"), &Json::Null);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let change = notification(
+            "textDocument/didChange",
+            Json::object([
+                (
+                    "textDocument",
+                    Json::object([("uri", "file:///test.asm".into()), ("version", 2.into())]),
+                ),
+                (
+                    "contentChanges",
+                    vec![Json::object([("text", "start: JMP start\n".into())])].into(),
+                ),
+            ]),
+        );
+        let messages = session(&[open("LDA #$00\nJMP missing\nunused:\n"), change]);
+
+        assert_eq!(messages.len(), 2);
+        let params = messages[0].get("params");
+        assert_eq!(
+            messages[0].get("method").as_str(),
+            Some("textDocument/publishDiagnostics")
+        );
+        assert_eq!(params.get("uri").as_str(), Some("file:///test.asm"));
+        let diagnostics = params.get("diagnostics").as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("severity").as_usize(), Some(1));
+        assert_eq!(range(diagnostics[0].get("range")), (1, 4, 1, 11));
+
+        // the error is fixed, the label is used
+        let diagnostics = messages[1].get("params").get("diagnostics");
+        assert_eq!(diagnostics.as_array(), Some(&[][..]));
+
+        let messages = session(&[open("unused:\nNOP\n")]);
+        let diagnostics = messages[0].get("params").get("diagnostics");
+        let warning = &diagnostics.as_array().unwrap()[0];
+        assert_eq!(warning.get("severity").as_usize(), Some(2));
+        assert_eq!(range(warning.get("range")), (0, 0, 0, 6));
+    }
+
+    const SOURCE: &str = "\
+SCREEN = $0200
+main:
+    LDX #0
+.loop:
+    STA SCREEN,X ; fill
+    INX
+    BNE .loop
+    JMP main
+other:
+.loop:
+    JMP .loop
+";
+
+    #[test]
+    fn test_definition_and_references() {
+        let definition = result(SOURCE, "textDocument/definition", at(6, 10));
+        assert_eq!(definition.get("uri").as_str(), Some("file:///test.asm"));
+        assert_eq!(range(definition.get("range")), (3, 0, 3, 5));
+
+        // cheap local labels belong to their global label
+        let definition = result(SOURCE, "textDocument/definition", at(10, 9));
+        assert_eq!(range(definition.get("range")), (9, 0, 9, 5));
+
+        let references = result(SOURCE, "textDocument/references", at(0, 2));
+        let ranges = references
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| range(location.get("range")))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(0, 0, 0, 6), (4, 8, 4, 14)]);
+
+        let mut params = at(1, 0);
+        if let Json::Object(members) = &mut params {
+            members.push((
+                "context".to_string(),
+                Json::object([("includeDeclaration", false.into())]),
+            ));
+        }
+        let references = result(SOURCE, "textDocument/references", params);
+        assert_eq!(references.as_array().unwrap().len(), 1);
+        assert_eq!(
+            range(references.as_array().unwrap()[0].get("range")),
+            (7, 8, 7, 12)
+        );
+
+        // not a symbol
+        assert_eq!(
+            result(SOURCE, "textDocument/definition", at(5, 5)),
+            Json::Null
+        );
+    }
+
+    #[test]
+    fn test_hover() {
+        let hover = |line, character| {
+            result(SOURCE, "textDocument/hover", at(line, character))
+                .get("contents")
+                .get("value")
+                .as_str()
+                .map(str::to_string)
+        };
+
+        assert_eq!(
+            hover(4, 10).as_deref(),
+            Some("constant `SCREEN`\n\n`$0200` (512)")
+        );
+        assert_eq!(
+            hover(7, 9).as_deref(),
+            Some("label `main`\n\n`$8000` (32768)")
+        );
+
+        let sta = hover(4, 5).unwrap();
+        assert!(sta.starts_with("**STA** Store Accumulator"), "{sta}");
+        assert!(sta.contains("| ABX | $9D | 3 | 5 |"), "{sta}");
+        assert!(sta.ends_with("this line: 5 cycles"), "{sta}");
+        let bne = hover(6, 4).unwrap();
+        assert!(bne.contains("| REL | $D0 | 2 | 2+ |"), "{bne}");
+        assert!(bne.ends_with("+1 if taken, +2 to another page"), "{bne}");
+
+        assert_eq!(hover(2, 9).as_deref(), Some("`$00` (0)\n\n`%0`"));
+        assert_eq!(
+            hover(0, 0).as_deref(),
+            Some("constant `SCREEN`\n\n`$0200` (512)")
+        );
+        assert_eq!(hover(4, 20), None);
+    }
+
+    #[test]
+    fn test_hover_cpu() {
+        let hover = |text, line| {
+            result(text, "textDocument/hover", at(line, 1))
+                .get("contents")
+                .get("value")
+                .as_str()
+                .map(str::to_string)
+                .unwrap()
+        };
+
+        assert!(hover("STZ $00\n", 0).contains("not an instruction of the 6502"));
+        assert!(hover(".setcpu \"65C02\"\nSTZ $00\n", 1).contains("| ZPG | $64 | 2 | 3 |"));
+        assert!(hover("JEQ $1234\n", 0).contains("long `BEQ`"));
+    }
+
+    #[test]
+    fn test_completion() {
+        let labels = |text, line| {
+            result(text, "textDocument/completion", at(line, 0))
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item.get("label").as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let items = labels(SOURCE, 0);
+        assert!(items.contains(&"LDA".to_string()));
+        assert!(!items.contains(&"STZ".to_string()));
+        assert!(items.contains(&"SCREEN".to_string()));
+        assert_eq!(items.iter().filter(|item| *item == ".loop").count(), 1);
+
+        let items = labels(".setcpu \"65C02\"\n\n", 1);
+        assert!(items.contains(&"STZ".to_string()));
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let symbols = result(SOURCE, "textDocument/documentSymbol", at(0, 0));
+        let symbols = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.get("name").as_str().unwrap(),
+                    symbol.get("kind").as_usize().unwrap(),
+                    symbol.get("detail").as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            symbols,
+            [
+                ("SCREEN", 14, "`$0200` (512)"),
+                ("main", 12, "`$8000` (32768)"),
+                (".loop", 12, "`$8002` (32770)"),
+                ("other", 12, "`$800B` (32779)"),
+                (".loop", 12, "`$800B` (32779)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_formatting() {
+        let edits = result("lda #1 ; one\nx: nop", "textDocument/formatting", at(0, 0));
+        let edits = edits.as_array().unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(range(edits[0].get("range")), (0, 0, 1, 6));
+        assert_eq!(
+            edits[0].get("newText").as_str(),
+            Some("        LDA #$01                ; one\nx:      NOP")
+        );
+
+        let formatted = edits[0].get("newText").as_str().unwrap();
+        let edits = result(formatted, "textDocument/formatting", at(0, 0));
+        assert_eq!(edits.as_array(), Some(&[][..]));
+    }
+}
@@ -0,0 +1,8 @@
+use std::{io, process};
+
+fn main() {
+    if let Err(error) = lsp::run(io::stdin().lock(), io::stdout().lock()) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::Json;
+use assembler::{Position, Span};
+use std::io::{self, BufRead, Write};
+
+/// Reads the next message, a `Content-Length` header and the JSON content.
+/// `None` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+
+    let content = String::from_utf8(content).map_err(|_| invalid_data("content is not UTF-8"))?;
+    Json::parse(&content).map(Some).map_err(invalid_data)
+}
+
+/// Writes a message with its `Content-Length` header.
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    output.flush()
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// `{ line, character }` of a source position, lines and columns of the protocol start at 0.
+pub fn position(position: Position) -> Json {
+    Json::object([
+        ("line", position.line.saturating_sub(1).into()),
+        ("character", position.column.saturating_sub(1).into()),
+    ])
+}
+
+/// `{ start, end }` of a source span.
+pub fn range(span: Span) -> Json {
+    Json::object([("start", position(span.start)), ("end", position(span.end))])
+}
+
+/// Source position (line and column start at 1) of a `{ line, character }` of the protocol.
+pub fn source_position(position: &Json) -> Option<Position> {
+    Some(Position::new(
+        position.get("line").as_usize()? + 1,
+        position.get("character").as_usize()? + 1,
+    ))
+}
@@ -0,0 +1,422 @@
+use crate::{protocol, read_message, write_message, Document, Json};
+use assembler::{
+    format_source, long_branch, opcodes::OPCODES, CpuType, FormatOptions, Mnemonics, SymbolKind,
+    TokenKind,
+};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+/// `MethodNotFound` of JSON-RPC
+const METHOD_NOT_FOUND: i32 = -32601;
+/// `InvalidParams` of JSON-RPC
+const INVALID_PARAMS: i32 = -32602;
+
+/// State of a session, the open documents.
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    /// whether the client sent `exit`
+    exited: bool,
+}
+
+/// Runs a session until the client exits or the input ends, see [`Server::handle`]
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut input)? {
+        for message in server.handle(&message) {
+            write_message(&mut output, &message)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+impl Server {
+    /// Handles a request or a notification of the client, returns the messages to send:
+    /// the response of a request, or the diagnostics of a document that was opened or changed.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or_default();
+        let params = message.get("params");
+
+        let id = match message.get("id") {
+            Json::Null => {
+                return self.notification(method, params);
+            }
+            id => id.clone(),
+        };
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => Ok(Json::Null),
+            "textDocument/hover" => self.request(params, hover),
+            "textDocument/definition" => self.request(params, definition),
+            "textDocument/references" => self.request(params, references),
+            "textDocument/completion" => self.request(params, completion),
+            "textDocument/documentSymbol" => self.request(params, document_symbols),
+            "textDocument/formatting" => self.request(params, formatting),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {method:?}"))),
+        };
+
+        let response = match result {
+            Ok(result) => ("result", result),
+            Err((code, message)) => (
+                "error",
+                Json::object([("code", code.into()), ("message", message.into())]),
+            ),
+        };
+        vec![Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            response,
+        ])]
+    }
+
+    /// Handles a notification, unknown notifications are ignored.
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let text = match method {
+            "exit" => {
+                self.exited = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" => params.get("textDocument").get("text").as_str(),
+            // the changes are the whole text, see `textDocumentSync` of the capabilities
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text").as_str()),
+            "textDocument/didClose" => {
+                let Some(uri) = uri else {
+                    return Vec::new();
+                };
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            _ => return Vec::new(),
+        };
+
+        let (Some(uri), Some(text)) = (uri, text) else {
+            return Vec::new();
+        };
+        let document = Document::new(uri, text.to_string());
+        let diagnostics = publish_diagnostics(uri, document.diagnostics.clone());
+        self.documents.insert(uri.to_string(), document);
+
+        vec![diagnostics]
+    }
+
+    /// Result of a request on an open document, `handler` gets the document and the parameters.
+    fn request(
+        &self,
+        params: &Json,
+        handler: fn(&Document, &Json) -> Json,
+    ) -> Result<Json, (i32, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        match uri.and_then(|uri| self.documents.get(uri)) {
+            Some(document) => Ok(handler(document, params)),
+            None => Err((INVALID_PARAMS, format!("Unknown document {uri:?}"))),
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                // the whole text on every change
+                ("textDocumentSync", 1.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("completionProvider", Json::object([])),
+                ("documentSymbolProvider", true.into()),
+                ("documentFormattingProvider", true.into()),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", env!("CARGO_PKG_NAME").into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        ),
+    ])
+}
+
+fn location(document: &Document, span: assembler::Span) -> Json {
+    Json::object([
+        ("uri", document.uri.as_str().into()),
+        ("range", protocol::range(span)),
+    ])
+}
+
+/// `$0A` or `$1234`, and the decimal value.
+fn value(value: i32) -> String {
+    match value {
+        0..=0xFF => format!("`${value:02X}` ({value})"),
+        _ => format!("`${:04X}` ({value})", value as u16),
+    }
+}
+
+/// The value of a label or constant, the documentation of a mnemonic or the value of a number.
+fn hover(document: &Document, params: &Json) -> Json {
+    let Some(position) = protocol::source_position(params.get("position")) else {
+        return Json::Null;
+    };
+
+    let (contents, span) = if let Some(occurrence) = document.occurrence_at(position) {
+        let Some(definition) = document.definition(&occurrence.key) else {
+            return Json::Null;
+        };
+        let kind = match definition.kind {
+            SymbolKind::Label => "label",
+            SymbolKind::Constant => "constant",
+        };
+        let mut contents = format!("{kind} `{}`", definition.name);
+        if let Some(number) = definition.value {
+            contents += &format!("\n\n{}", value(number));
+        }
+        (contents, occurrence.span)
+    } else {
+        let Some(token) = document.token_at(position) else {
+            return Json::Null;
+        };
+        let contents = match token.kind {
+            TokenKind::Identifier(name) => {
+                match instruction_documentation(name, document.cpu(position.line)) {
+                    Some(contents) => match document.cycles(position.line) {
+                        Some(cycles) => format!("{contents}\n\n{}", cycles_on_line(name, cycles)),
+                        None => contents,
+                    },
+                    None => return Json::Null,
+                }
+            }
+            TokenKind::Decimal(number)
+            | TokenKind::Hexadecimal(number)
+            | TokenKind::Binary(number) => {
+                format!("{}\n\n`%{number:b}`", value(number as i32))
+            }
+            TokenKind::Char(byte) => value(byte as i32),
+            _ => return Json::Null,
+        };
+        (contents, token.span())
+    };
+
+    Json::object([
+        (
+            "contents",
+            Json::object([("kind", "markdown".into()), ("value", contents.into())]),
+        ),
+        ("range", protocol::range(span)),
+    ])
+}
+
+/// Name, flags and opcodes of the instruction `name` on the CPU, long branches (`JEQ`) included.
+fn instruction_documentation(name: &str, cpu: CpuType) -> Option<String> {
+    let (mnemonic, long) = match Mnemonics::from_name(name) {
+        Some(mnemonic) => (mnemonic, false),
+        None => (long_branch(name)?, true),
+    };
+
+    let opcodes = OPCODES
+        .iter()
+        .filter(|opcode| opcode.mnemonic == mnemonic && opcode.set.includes(cpu))
+        .collect::<Vec<_>>();
+    let flags = opcodes
+        .first()
+        .map(|opcode| opcode.flags)
+        .unwrap_or_default();
+
+    let mut contents = format!("**{}** {}", name.to_uppercase(), mnemonic.description());
+    if long {
+        contents += &format!(
+            "\n\nlong `{mnemonic:?}`, the inverted branch over a `JMP` if the target is out of range"
+        );
+    }
+    if opcodes.is_empty() {
+        contents += &format!("\n\nnot an instruction of the {cpu}");
+        return Some(contents);
+    }
+
+    contents +=
+        &format!("\n\nflags: {flags}\n\n| mode | opcode | bytes | cycles |\n|---|---|---|---|");
+    for opcode in opcodes {
+        contents += &format!(
+            "\n| {:?} | ${:02X} | {} | {}{} |",
+            opcode.mode,
+            opcode.byte,
+            opcode.size(),
+            opcode.cycles,
+            if opcode.page_cross { "+" } else { "" }
+        );
+    }
+
+    Some(contents)
+}
+
+/// Cycles of the instruction assembled on the line, see [`assembler::Opcode::page_cross`]
+fn cycles_on_line(name: &str, (cycles, page_cross): (u8, bool)) -> String {
+    let extra = match Mnemonics::from_name(name) {
+        _ if !page_cross => "",
+        Some(mnemonic) if !mnemonic.is_branch() && !mnemonic.is_bit_branch() => {
+            ", +1 if a page is crossed"
+        }
+        _ => ", +1 if taken, +2 to another page",
+    };
+
+    format!("this line: {cycles} cycles{extra}")
+}
+
+fn definition(document: &Document, params: &Json) -> Json {
+    protocol::source_position(params.get("position"))
+        .and_then(|position| document.occurrence_at(position))
+        .and_then(|occurrence| document.definition(&occurrence.key))
+        .map_or(Json::Null, |definition| location(document, definition.span))
+}
+
+fn references(document: &Document, params: &Json) -> Json {
+    let Some(occurrence) = protocol::source_position(params.get("position"))
+        .and_then(|position| document.occurrence_at(position))
+    else {
+        return Json::Null;
+    };
+    let declaration = params
+        .get("context")
+        .get("includeDeclaration")
+        .as_bool()
+        .unwrap_or(true);
+
+    document
+        .occurrences
+        .iter()
+        .filter(|other| other.key == occurrence.key && (declaration || !other.definition))
+        .map(|other| location(document, other.span))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// The instructions of the CPU of the line, and the labels and constants of the document.
+fn completion(document: &Document, params: &Json) -> Json {
+    let line = protocol::source_position(params.get("position")).map_or(1, |p| p.line);
+    let cpu = document.cpu(line);
+
+    let mnemonics = Mnemonics::ALL
+        .iter()
+        .filter(|mnemonic| cpu.has(**mnemonic))
+        .map(|mnemonic| {
+            Json::object([
+                ("label", format!("{mnemonic:?}").into()),
+                // `Keyword`
+                ("kind", 14.into()),
+                ("detail", mnemonic.description().into()),
+            ])
+        });
+
+    let mut names = Vec::new();
+    let symbols = document
+        .definitions
+        .iter()
+        .filter(|definition| {
+            let new = !names.contains(&&definition.name);
+            names.push(&definition.name);
+            new
+        })
+        .map(|definition| {
+            let mut item = vec![
+                ("label", definition.name.as_str().into()),
+                // `Function` and `Constant`
+                (
+                    "kind",
+                    match definition.kind {
+                        SymbolKind::Label => 3,
+                        SymbolKind::Constant => 21,
+                    }
+                    .into(),
+                ),
+            ];
+            item.extend(
+                definition
+                    .value
+                    .map(|number| ("detail", value(number).into())),
+            );
+            Json::object(item)
+        })
+        .collect::<Vec<_>>();
+
+    mnemonics.chain(symbols).collect::<Vec<_>>().into()
+}
+
+fn document_symbols(document: &Document, _: &Json) -> Json {
+    document
+        .definitions
+        .iter()
+        .map(|definition| {
+            let mut symbol = vec![
+                ("name", definition.name.as_str().into()),
+                // `Function` and `Constant`
+                (
+                    "kind",
+                    match definition.kind {
+                        SymbolKind::Label => 12,
+                        SymbolKind::Constant => 14,
+                    }
+                    .into(),
+                ),
+                ("range", protocol::range(definition.span)),
+                ("selectionRange", protocol::range(definition.span)),
+            ];
+            symbol.extend(
+                definition
+                    .value
+                    .map(|number| ("detail", value(number).into())),
+            );
+            Json::object(symbol)
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// One edit that replaces the whole text, none if it is formatted already.
+fn formatting(document: &Document, _: &Json) -> Json {
+    let formatted = format_source(&document.text, &FormatOptions::default());
+    if formatted == document.text {
+        return Json::Array(Vec::new());
+    }
+
+    let last_line = document.text.split('\n').next_back().unwrap_or_default();
+    let end = Json::object([
+        ("line", document.text.matches('\n').count().into()),
+        ("character", last_line.chars().count().into()),
+    ]);
+    let range = Json::object([
+        (
+            "start",
+            Json::object([("line", 0.into()), ("character", 0.into())]),
+        ),
+        ("end", end),
+    ]);
+
+    vec![Json::object([
+        ("range", range),
+        ("newText", formatted.into()),
+    ])]
+    .into()
+}
@@ -0,0 +1,36 @@
+#[doc = include_str!("../../docs/addressing_mode.md")]
+#[rustfmt::skip]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AddressingMode {
+    IMP /* Implied */,  ACC /* Accumulator */, IMM /* Immediate */,
+    ABS /* Absolute */, ABX /* AbsoluteX */,   ABY /* AbsoluteY */,
+    IND /* Indirect */, IDX /* IndirectX */,   IDY /* IndirectY */,
+    ZPG /* ZeroPage */, ZPX /* ZeroPageX */,   ZPY /* ZeroPageY */,
+    REL /* Relative */,
+    /* 65C02 */         IZP /* ZeroPageIndirect `(zp)` */,
+                        IAX /* AbsoluteIndexedIndirect `(abs,X)` */,
+                        ZPR /* ZeroPage and Relative `BBR0 zp,target` */
+}
+
+impl AddressingMode {
+    /// Bytes of the operand after the opcode.
+    pub fn operand_size(&self) -> usize {
+        use AddressingMode::*;
+
+        match self {
+            IMP | ACC => 0,
+            IMM | ZPG | ZPX | ZPY | IDX | IDY | IZP | REL => 1,
+            ABS | ABX | ABY | IND | IAX | ZPR => 2,
+        }
+    }
+
+    /// Zero page mode of an absolute mode (`ABS`, `ABX`, `ABY`).
+    pub fn zero_page(&self) -> Option<AddressingMode> {
+        match self {
+            AddressingMode::ABS => Some(AddressingMode::ZPG),
+            AddressingMode::ABX => Some(AddressingMode::ZPX),
+            AddressingMode::ABY => Some(AddressingMode::ZPY),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! The opcodes of the 6502 family, one entry per opcode in [`OPCODES`].
+//! the emulator dispatches on it, the assembler encodes, decodes and counts cycles with it.
+
+mod addressing_mode;
+mod mnemonics;
+mod table;
+
+pub use addressing_mode::*;
+pub use mnemonics::*;
+pub use table::OPCODES;
+
+use std::{fmt, sync::LazyLock};
+
+/// An opcode of the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub byte: u8,
+    pub mnemonic: Mnemonics,
+    pub mode: AddressingMode,
+    /// cycles without the extra cycles, see `page_cross`
+    pub cycles: u8,
+    /// whether the instruction takes one more cycle if a page boundary is crossed,
+    /// branches: one more if taken, two if it crosses a page
+    pub page_cross: bool,
+    pub flags: Flags,
+    /// the CPUs that decode the opcode to the instruction
+    pub set: InstructionSet,
+}
+
+impl Opcode {
+    /// Bytes of the instruction, the opcode and its operand.
+    pub fn size(&self) -> usize {
+        1 + self.mode.operand_size()
+    }
+}
+
+/// The CPUs an opcode exists on, every CPU decodes an opcode of at most one set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionSet {
+    /// the documented opcodes of the NMOS 6502, on every CPU
+    Nmos,
+    /// the undocumented opcodes of the NMOS 6502, on the 6502X
+    Undocumented,
+    /// the opcodes the CMOS CPUs add
+    Cmos,
+    /// the bit instructions of Rockwell, on the 65C02 and the W65C02
+    Rockwell,
+    /// `WAI` and `STP`, on the W65C02
+    Wdc,
+}
+
+impl InstructionSet {
+    pub fn includes(&self, cpu: CpuType) -> bool {
+        match self {
+            InstructionSet::Nmos => true,
+            InstructionSet::Undocumented => cpu == CpuType::Nmos6502X,
+            InstructionSet::Cmos => cpu.is_cmos(),
+            InstructionSet::Rockwell => matches!(cpu, CpuType::Cmos65C02 | CpuType::W65C02),
+            InstructionSet::Wdc => cpu == CpuType::W65C02,
+        }
+    }
+}
+
+/// Flags of the processor status register an instruction affects, its bits (`NV-BDIZC`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags(pub u8);
+
+impl Flags {
+    pub const NONE: Flags = Flags(0);
+    pub const N: Flags = Flags(0x80);
+    pub const V: Flags = Flags(0x40);
+    pub const B: Flags = Flags(0x10);
+    pub const D: Flags = Flags(0x08);
+    pub const I: Flags = Flags(0x04);
+    pub const Z: Flags = Flags(0x02);
+    pub const C: Flags = Flags(0x01);
+
+    pub const fn union(self, other: Flags) -> Flags {
+        Flags(self.0 | other.0)
+    }
+
+    pub fn contains(&self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Flags the instruction affects with the addressing mode.
+    pub const fn of(mnemonic: Mnemonics, mode: AddressingMode) -> Flags {
+        use Mnemonics::*;
+
+        const NZ: Flags = Flags::N.union(Flags::Z);
+        const NZC: Flags = NZ.union(Flags::C);
+        const NVZC: Flags = NZC.union(Flags::V);
+
+        match mnemonic {
+            ADC | SBC | ARR | ISC | RRA => NVZC,
+            ASL | LSR | ROL | ROR | CMP | CPX | CPY => NZC,
+            ALR | ANC | AXS | DCP | RLA | SLO | SRE => NZC,
+            AND | EOR | ORA | LDA | LDX | LDY | INC | DEC => NZ,
+            INX | INY | DEX | DEY | TAX | TAY | TSX | TXA | TYA => NZ,
+            PLA | PLX | PLY | ANE | LAS | LAX => NZ,
+            // `BIT #` of the CMOS CPUs only sets `Z`
+            BIT if matches!(mode, AddressingMode::IMM) => Flags::Z,
+            BIT => NZ.union(Flags::V),
+            TRB | TSB => Flags::Z,
+            BRK => Flags::B.union(Flags::I),
+            CLC | SEC => Flags::C,
+            CLD | SED => Flags::D,
+            CLI | SEI => Flags::I,
+            CLV => Flags::V,
+            // every flag but the unused bit
+            PLP | RTI => Flags(0xDF),
+            _ => Flags::NONE,
+        }
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Flags::NONE {
+            return write!(f, "-");
+        }
+        for (bit, name) in (0..8).rev().zip("NV-BDIZC".chars()) {
+            if name != '-' && self.0 & (1 << bit) != 0 {
+                write!(f, "{name}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Opcode of every byte, one table per CPU in the order of [`CpuType::ALL`], built on the first decode.
+static DECODE: LazyLock<[[Option<&'static Opcode>; 256]; CpuType::ALL.len()]> =
+    LazyLock::new(|| {
+        CpuType::ALL.map(|cpu| {
+            let mut table = [None; 256];
+            for opcode in OPCODES.iter().filter(|opcode| opcode.set.includes(cpu)) {
+                table[opcode.byte as usize].get_or_insert(opcode);
+            }
+            table
+        })
+    });
+
+/// Opcode of the byte on the CPU, a lookup in a table (the emulator decodes every instruction).
+pub fn decode(byte: u8, cpu: CpuType) -> Option<&'static Opcode> {
+    DECODE[cpu as usize][byte as usize]
+}
+
+/// Opcode of the instruction with the addressing mode on the CPU,
+/// the first one of the table if the CPU has several (`NOP`, `JAM`, `ANC` of the 6502X).
+pub fn encode(mnemonic: Mnemonics, mode: AddressingMode, cpu: CpuType) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|opcode| {
+        opcode.mnemonic == mnemonic && opcode.mode == mode && opcode.set.includes(cpu)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_single_opcode() {
+        for cpu in CpuType::ALL {
+            for byte in 0..=0xFF {
+                let mut opcodes = OPCODES
+                    .iter()
+                    .filter(|opcode| opcode.byte == byte && opcode.set.includes(cpu));
+                assert_eq!(
+                    decode(byte, cpu),
+                    opcodes.next(),
+                    "${byte:02X} on the {cpu}"
+                );
+                assert!(opcodes.next().is_none(), "${byte:02X} on the {cpu}");
+            }
+        }
+        assert_eq!(
+            CpuType::ALL.map(|cpu| decode(0x80, cpu).map(|o| o.mnemonic)),
+            [
+                None,
+                Some(Mnemonics::NOP),
+                Some(Mnemonics::BRA),
+                Some(Mnemonics::BRA),
+                Some(Mnemonics::BRA),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        for cpu in CpuType::ALL {
+            for opcode in OPCODES.iter().filter(|opcode| opcode.set.includes(cpu)) {
+                let encoded = encode(opcode.mnemonic, opcode.mode, cpu).unwrap();
+                assert_eq!(decode(encoded.byte, cpu), Some(encoded));
+            }
+        }
+        let lda = encode(Mnemonics::LDA, AddressingMode::ABX, CpuType::Nmos6502).unwrap();
+        assert_eq!(
+            (lda.byte, lda.size(), lda.cycles, lda.page_cross),
+            (0xBD, 3, 4, true)
+        );
+        assert_eq!(lda.flags.to_string(), "NZ");
+        assert!(encode(Mnemonics::STZ, AddressingMode::ZPG, CpuType::Nmos6502).is_none());
+    }
+}
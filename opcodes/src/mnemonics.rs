@@ -0,0 +1,284 @@
+use crate::{encode, AddressingMode};
+use std::fmt;
+
+macro_rules! enum_mnemonics {
+    ($($ident:ident),*) => {
+        #[repr(u8)]
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum Mnemonics {
+            $($ident,)*
+        }
+
+        impl Mnemonics {
+            pub const ALL: &'static [Mnemonics] = &[$(Mnemonics::$ident,)*];
+
+            /// Mnemonic of the name in any case (`lda`, `LDA`).
+            pub fn from_name(s: &str) -> Option<Self> {
+                Some(match s.to_uppercase().as_str() {
+                    $(stringify!($ident) => Mnemonics::$ident,)*
+                    _ => return None,
+                })
+            }
+        }
+
+        impl fmt::Display for Mnemonics {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(Mnemonics::$ident => write!(f, "{}", stringify!($ident)),)*
+                }
+            }
+        }
+    };
+}
+
+enum_mnemonics! {
+    ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI,
+    BNE, BPL, BRK, BVC, BVS, CLC, CLD, CLI,
+    CLV, CMP, CPX, CPY, DEC, DEX, DEY, EOR,
+    INC, INX, INY, JMP, JSR, LDA, LDX, LDY,
+    LSR, NOP, ORA, PHA, PHP, PLA, PLP, ROL,
+    ROR, RTI, RTS, SBC, SEC, SED, SEI, STA,
+    STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+    // 65SC02
+    BRA, PHX, PHY, PLX, PLY, STZ, TRB, TSB,
+    // 65C02, the bit instructions of Rockwell
+    BBR0, BBR1, BBR2, BBR3, BBR4, BBR5, BBR6, BBR7,
+    BBS0, BBS1, BBS2, BBS3, BBS4, BBS5, BBS6, BBS7,
+    RMB0, RMB1, RMB2, RMB3, RMB4, RMB5, RMB6, RMB7,
+    SMB0, SMB1, SMB2, SMB3, SMB4, SMB5, SMB6, SMB7,
+    // W65C02
+    STP, WAI,
+    // undocumented opcodes of the NMOS 6502 (6502X), with the names of ca65
+    ALR, ANC, ANE, ARR, AXS, DCP, ISC, JAM,
+    LAS, LAX, RLA, RRA, SAX, SHA, SHX, SHY,
+    SLO, SRE, TAS
+}
+
+/// The CPU a program is assembled for or decoded for,
+/// the instructions of the other CPUs are not available.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CpuType {
+    /// the documented instructions of the NMOS 6502
+    #[default]
+    Nmos6502,
+    /// the NMOS 6502 with its undocumented opcodes (`LAX`, `SLO`, `NOP $00`, ...)
+    Nmos6502X,
+    /// the CMOS 6502: `BRA`, `PHX`, `STZ`, `TSB`, `(zp)`, `JMP (abs,X)`, `INC` of the accumulator, ...
+    Cmos65SC02,
+    /// the 65SC02 with the bit instructions of Rockwell (`RMB0`, `SMB0`, `BBR0`, `BBS0`)
+    Cmos65C02,
+    /// the 65C02 of WDC, with `WAI` and `STP`
+    W65C02,
+}
+
+impl CpuType {
+    pub const ALL: [CpuType; 5] = [
+        CpuType::Nmos6502,
+        CpuType::Nmos6502X,
+        CpuType::Cmos65SC02,
+        CpuType::Cmos65C02,
+        CpuType::W65C02,
+    ];
+
+    /// Name of the CPU in `.setcpu` and on the command line, like ca65.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CpuType::Nmos6502 => "6502",
+            CpuType::Nmos6502X => "6502X",
+            CpuType::Cmos65SC02 => "65SC02",
+            CpuType::Cmos65C02 => "65C02",
+            CpuType::W65C02 => "W65C02",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|cpu| cpu.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn is_cmos(&self) -> bool {
+        matches!(
+            self,
+            CpuType::Cmos65SC02 | CpuType::Cmos65C02 | CpuType::W65C02
+        )
+    }
+
+    /// Whether the CPU has an opcode of the instruction.
+    pub fn has(&self, mnemonic: Mnemonics) -> bool {
+        crate::OPCODES
+            .iter()
+            .any(|opcode| opcode.mnemonic == mnemonic && opcode.set.includes(*self))
+    }
+}
+
+impl fmt::Display for CpuType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Mnemonics {
+    /// Whether the instruction is a relative branch, its operand is the target address.
+    pub fn is_branch(&self) -> bool {
+        use Mnemonics::*;
+        matches!(self, BCC | BCS | BEQ | BMI | BNE | BPL | BVC | BVS | BRA)
+    }
+
+    /// Whether the instruction tests a bit of a zero page address and branches (`BBR0 zp,target`).
+    pub fn is_bit_branch(&self) -> bool {
+        // declared in order
+        (Mnemonics::BBR0 as u8..=Mnemonics::BBS7 as u8).contains(&(*self as u8))
+    }
+
+    /// Whether the instruction clears or sets a bit of a zero page address (`RMB0 zp`, `SMB0 zp`).
+    fn is_bit_write(&self) -> bool {
+        (Mnemonics::RMB0 as u8..=Mnemonics::SMB7 as u8).contains(&(*self as u8))
+    }
+
+    /// Branch on the opposite condition (`BNE` for `BEQ`).
+    pub fn inverted_branch(&self) -> Option<Mnemonics> {
+        use Mnemonics::*;
+        Some(match self {
+            BCC => BCS,
+            BCS => BCC,
+            BEQ => BNE,
+            BNE => BEQ,
+            BMI => BPL,
+            BPL => BMI,
+            BVC => BVS,
+            BVS => BVC,
+            _ => return None,
+        })
+    }
+
+    /// What the instruction does, like the data sheets name it.
+    pub fn description(&self) -> &'static str {
+        use Mnemonics::*;
+        match self {
+            ADC => "Add Memory to Accumulator with Carry",
+            AND => "AND Memory with Accumulator",
+            ASL => "Shift One Bit Left (Memory or Accumulator)",
+            BCC => "Branch on Carry Clear",
+            BCS => "Branch on Carry Set",
+            BEQ => "Branch on Result Zero",
+            BIT => "Test Bits in Memory with Accumulator",
+            BMI => "Branch on Result Minus",
+            BNE => "Branch on Result not Zero",
+            BPL => "Branch on Result Plus",
+            BRK => "Force Break",
+            BVC => "Branch on Overflow Clear",
+            BVS => "Branch on Overflow Set",
+            CLC => "Clear Carry Flag",
+            CLD => "Clear Decimal Mode",
+            CLI => "Clear Interrupt Disable Bit",
+            CLV => "Clear Overflow Flag",
+            CMP => "Compare Memory with Accumulator",
+            CPX => "Compare Memory and Index X",
+            CPY => "Compare Memory and Index Y",
+            DEC => "Decrement by One (Memory or Accumulator)",
+            DEX => "Decrement Index X by One",
+            DEY => "Decrement Index Y by One",
+            EOR => "Exclusive-OR Memory with Accumulator",
+            INC => "Increment by One (Memory or Accumulator)",
+            INX => "Increment Index X by One",
+            INY => "Increment Index Y by One",
+            JMP => "Jump to New Location",
+            JSR => "Jump to New Location Saving Return Address",
+            LDA => "Load Accumulator with Memory",
+            LDX => "Load Index X with Memory",
+            LDY => "Load Index Y with Memory",
+            LSR => "Shift One Bit Right (Memory or Accumulator)",
+            NOP => "No Operation",
+            ORA => "OR Memory with Accumulator",
+            PHA => "Push Accumulator on Stack",
+            PHP => "Push Processor Status on Stack",
+            PLA => "Pull Accumulator from Stack",
+            PLP => "Pull Processor Status from Stack",
+            ROL => "Rotate One Bit Left (Memory or Accumulator)",
+            ROR => "Rotate One Bit Right (Memory or Accumulator)",
+            RTI => "Return from Interrupt",
+            RTS => "Return from Subroutine",
+            SBC => "Subtract Memory from Accumulator with Borrow",
+            SEC => "Set Carry Flag",
+            SED => "Set Decimal Flag",
+            SEI => "Set Interrupt Disable Status",
+            STA => "Store Accumulator in Memory",
+            STX => "Store Index X in Memory",
+            STY => "Store Index Y in Memory",
+            TAX => "Transfer Accumulator to Index X",
+            TAY => "Transfer Accumulator to Index Y",
+            TSX => "Transfer Stack Pointer to Index X",
+            TXA => "Transfer Index X to Accumulator",
+            TXS => "Transfer Index X to Stack Pointer",
+            TYA => "Transfer Index Y to Accumulator",
+            BRA => "Branch Always",
+            PHX => "Push Index X on Stack",
+            PHY => "Push Index Y on Stack",
+            PLX => "Pull Index X from Stack",
+            PLY => "Pull Index Y from Stack",
+            STZ => "Store Zero in Memory",
+            TRB => "Test and Reset Memory Bits with Accumulator",
+            TSB => "Test and Set Memory Bits with Accumulator",
+            BBR0 | BBR1 | BBR2 | BBR3 | BBR4 | BBR5 | BBR6 | BBR7 => {
+                "Branch on Bit Reset (the bit of the mnemonic, of a zero page address)"
+            }
+            BBS0 | BBS1 | BBS2 | BBS3 | BBS4 | BBS5 | BBS6 | BBS7 => {
+                "Branch on Bit Set (the bit of the mnemonic, of a zero page address)"
+            }
+            RMB0 | RMB1 | RMB2 | RMB3 | RMB4 | RMB5 | RMB6 | RMB7 => {
+                "Reset Memory Bit (the bit of the mnemonic, of a zero page address)"
+            }
+            SMB0 | SMB1 | SMB2 | SMB3 | SMB4 | SMB5 | SMB6 | SMB7 => {
+                "Set Memory Bit (the bit of the mnemonic, of a zero page address)"
+            }
+            STP => "Stop the Processor",
+            WAI => "Wait for Interrupt",
+            ALR => "AND Memory with Accumulator then Shift Right",
+            ANC => "AND Memory with Accumulator, Carry from Bit 7",
+            ANE => "Transfer Index X AND Memory AND a Constant to Accumulator (unstable)",
+            ARR => "AND Memory with Accumulator then Rotate Right",
+            AXS => "AND Accumulator with Index X, Subtract Memory into Index X",
+            DCP => "Decrement Memory then Compare with Accumulator",
+            ISC => "Increment Memory then Subtract from Accumulator with Borrow",
+            JAM => "Halt the Processor",
+            LAS => "AND Memory with Stack Pointer into Accumulator, Index X and Stack Pointer",
+            LAX => "Load Accumulator and Index X with Memory",
+            RLA => "Rotate Memory Left then AND with Accumulator",
+            RRA => "Rotate Memory Right then Add to Accumulator with Carry",
+            SAX => "Store Accumulator AND Index X in Memory",
+            SHA => "Store Accumulator AND Index X AND the High Byte of the Address + 1 in Memory",
+            SHX => "Store Index X AND the High Byte of the Address + 1 in Memory",
+            SHY => "Store Index Y AND the High Byte of the Address + 1 in Memory",
+            SLO => "Shift Memory Left then OR with Accumulator",
+            SRE => "Shift Memory Right then Exclusive-OR with Accumulator",
+            TAS => {
+                "Transfer Accumulator AND Index X to Stack Pointer, Store it AND the High Byte + 1"
+            }
+        }
+    }
+
+    /// Whether the instruction has an opcode for the addressing mode on the CPU.
+    pub fn supports(&self, addressing_mode: AddressingMode, cpu: CpuType) -> bool {
+        encode(*self, addressing_mode, cpu).is_some()
+    }
+
+    /// Whether the instruction reads, modifies and writes back the memory its operand addresses.
+    fn is_read_modify_write(&self) -> bool {
+        use Mnemonics::*;
+        matches!(
+            self,
+            ASL | LSR | ROL | ROR | INC | DEC | TRB | TSB | SLO | RLA | SRE | RRA | DCP | ISC
+        ) || self.is_bit_write()
+    }
+
+    /// Whether the instruction writes to the memory its operand addresses.
+    pub fn writes(&self, addressing_mode: AddressingMode) -> bool {
+        use Mnemonics::*;
+        match self {
+            STA | STX | STY | STZ | SAX | SHA | SHX | SHY | TAS => true,
+            _ if self.is_read_modify_write() => addressing_mode != AddressingMode::ACC,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use crate::{AddressingMode, Flags, InstructionSet, Mnemonics, Opcode};
+
+/// `byte MNEMONIC MODE cycles`, `+ 1` if the instruction can take one more cycle (see [`Opcode::page_cross`]).
+macro_rules! opcodes {
+    ($($set:ident { $($byte:literal $mnemonic:ident $mode:ident $cycles:literal $(+ $extra:literal)?,)* })*) => {
+        /// Every opcode of the CPUs, the documented opcodes of the NMOS 6502 first.
+        pub static OPCODES: &[Opcode] = &[$($(
+            Opcode {
+                byte: $byte,
+                mnemonic: Mnemonics::$mnemonic,
+                mode: AddressingMode::$mode,
+                cycles: $cycles,
+                page_cross: opcodes!(@page $($extra)?),
+                flags: Flags::of(Mnemonics::$mnemonic, AddressingMode::$mode),
+                set: InstructionSet::$set,
+            },
+        )*)*];
+    };
+    (@page $extra:literal) => { true };
+    (@page) => { false };
+}
+
+opcodes! {
+    Nmos {
+        0x69 ADC IMM 2,
+        0x65 ADC ZPG 3,
+        0x75 ADC ZPX 4,
+        0x6D ADC ABS 4,
+        0x7D ADC ABX 4 + 1,
+        0x79 ADC ABY 4 + 1,
+        0x61 ADC IDX 6,
+        0x71 ADC IDY 5 + 1,
+        0x29 AND IMM 2,
+        0x25 AND ZPG 3,
+        0x35 AND ZPX 4,
+        0x2D AND ABS 4,
+        0x3D AND ABX 4 + 1,
+        0x39 AND ABY 4 + 1,
+        0x21 AND IDX 6,
+        0x31 AND IDY 5 + 1,
+        0x0A ASL ACC 2,
+        0x06 ASL ZPG 5,
+        0x16 ASL ZPX 6,
+        0x0E ASL ABS 6,
+        0x1E ASL ABX 7,
+        0x90 BCC REL 2 + 1,
+        0xB0 BCS REL 2 + 1,
+        0xF0 BEQ REL 2 + 1,
+        0x24 BIT ZPG 3,
+        0x2C BIT ABS 4,
+        0x30 BMI REL 2 + 1,
+        0xD0 BNE REL 2 + 1,
+        0x10 BPL REL 2 + 1,
+        0x00 BRK IMP 7,
+        0x50 BVC REL 2 + 1,
+        0x70 BVS REL 2 + 1,
+        0x18 CLC IMP 2,
+        0xD8 CLD IMP 2,
+        0x58 CLI IMP 2,
+        0xB8 CLV IMP 2,
+        0xC9 CMP IMM 2,
+        0xC5 CMP ZPG 3,
+        0xD5 CMP ZPX 4,
+        0xCD CMP ABS 4,
+        0xDD CMP ABX 4 + 1,
+        0xD9 CMP ABY 4 + 1,
+        0xC1 CMP IDX 6,
+        0xD1 CMP IDY 5 + 1,
+        0xE0 CPX IMM 2,
+        0xE4 CPX ZPG 3,
+        0xEC CPX ABS 4,
+        0xC0 CPY IMM 2,
+        0xC4 CPY ZPG 3,
+        0xCC CPY ABS 4,
+        0xC6 DEC ZPG 5,
+        0xD6 DEC ZPX 6,
+        0xCE DEC ABS 6,
+        0xDE DEC ABX 7,
+        0xCA DEX IMP 2,
+        0x88 DEY IMP 2,
+        0x49 EOR IMM 2,
+        0x45 EOR ZPG 3,
+        0x55 EOR ZPX 4,
+        0x4D EOR ABS 4,
+        0x5D EOR ABX 4 + 1,
+        0x59 EOR ABY 4 + 1,
+        0x41 EOR IDX 6,
+        0x51 EOR IDY 5 + 1,
+        0xE6 INC ZPG 5,
+        0xF6 INC ZPX 6,
+        0xEE INC ABS 6,
+        0xFE INC ABX 7,
+        0xE8 INX IMP 2,
+        0xC8 INY IMP 2,
+        0x4C JMP ABS 3,
+        0x6C JMP IND 5,
+        0x20 JSR ABS 6,
+        0xA9 LDA IMM 2,
+        0xA5 LDA ZPG 3,
+        0xB5 LDA ZPX 4,
+        0xAD LDA ABS 4,
+        0xBD LDA ABX 4 + 1,
+        0xB9 LDA ABY 4 + 1,
+        0xA1 LDA IDX 6,
+        0xB1 LDA IDY 5 + 1,
+        0xA2 LDX IMM 2,
+        0xA6 LDX ZPG 3,
+        0xB6 LDX ZPY 4,
+        0xAE LDX ABS 4,
+        0xBE LDX ABY 4 + 1,
+        0xA0 LDY IMM 2,
+        0xA4 LDY ZPG 3,
+        0xB4 LDY ZPX 4,
+        0xAC LDY ABS 4,
+        0xBC LDY ABX 4 + 1,
+        0x4A LSR ACC 2,
+        0x46 LSR ZPG 5,
+        0x56 LSR ZPX 6,
+        0x4E LSR ABS 6,
+        0x5E LSR ABX 7,
+        0xEA NOP IMP 2,
+        0x09 ORA IMM 2,
+        0x05 ORA ZPG 3,
+        0x15 ORA ZPX 4,
+        0x0D ORA ABS 4,
+        0x1D ORA ABX 4 + 1,
+        0x19 ORA ABY 4 + 1,
+        0x01 ORA IDX 6,
+        0x11 ORA IDY 5 + 1,
+        0x48 PHA IMP 3,
+        0x08 PHP IMP 3,
+        0x68 PLA IMP 4,
+        0x28 PLP IMP 4,
+        0x2A ROL ACC 2,
+        0x26 ROL ZPG 5,
+        0x36 ROL ZPX 6,
+        0x2E ROL ABS 6,
+        0x3E ROL ABX 7,
+        0x6A ROR ACC 2,
+        0x66 ROR ZPG 5,
+        0x76 ROR ZPX 6,
+        0x6E ROR ABS 6,
+        0x7E ROR ABX 7,
+        0x40 RTI IMP 6,
+        0x60 RTS IMP 6,
+        0xE9 SBC IMM 2,
+        0xE5 SBC ZPG 3,
+        0xF5 SBC ZPX 4,
+        0xED SBC ABS 4,
+        0xFD SBC ABX 4 + 1,
+        0xF9 SBC ABY 4 + 1,
+        0xE1 SBC IDX 6,
+        0xF1 SBC IDY 5 + 1,
+        0x38 SEC IMP 2,
+        0xF8 SED IMP 2,
+        0x78 SEI IMP 2,
+        0x85 STA ZPG 3,
+        0x95 STA ZPX 4,
+        0x8D STA ABS 4,
+        0x9D STA ABX 5,
+        0x99 STA ABY 5,
+        0x81 STA IDX 6,
+        0x91 STA IDY 6,
+        0x86 STX ZPG 3,
+        0x96 STX ZPY 4,
+        0x8E STX ABS 4,
+        0x84 STY ZPG 3,
+        0x94 STY ZPX 4,
+        0x8C STY ABS 4,
+        0xAA TAX IMP 2,
+        0xA8 TAY IMP 2,
+        0xBA TSX IMP 2,
+        0x8A TXA IMP 2,
+        0x9A TXS IMP 2,
+        0x98 TYA IMP 2,
+    }
+    Cmos {
+        0x72 ADC IZP 5,
+        0x32 AND IZP 5,
+        0xD2 CMP IZP 5,
+        0x52 EOR IZP 5,
+        0xB2 LDA IZP 5,
+        0x12 ORA IZP 5,
+        0xF2 SBC IZP 5,
+        0x92 STA IZP 5,
+        0x89 BIT IMM 2,
+        0x34 BIT ZPX 4,
+        0x3C BIT ABX 4 + 1,
+        0x3A DEC ACC 2,
+        0x1A INC ACC 2,
+        0x7C JMP IAX 6,
+        0x80 BRA REL 3 + 1,
+        0xDA PHX IMP 3,
+        0x5A PHY IMP 3,
+        0xFA PLX IMP 4,
+        0x7A PLY IMP 4,
+        0x64 STZ ZPG 3,
+        0x74 STZ ZPX 4,
+        0x9C STZ ABS 4,
+        0x9E STZ ABX 5,
+        0x14 TRB ZPG 5,
+        0x1C TRB ABS 6,
+        0x04 TSB ZPG 5,
+        0x0C TSB ABS 6,
+    }
+    Rockwell {
+        0x07 RMB0 ZPG 5,
+        0x17 RMB1 ZPG 5,
+        0x27 RMB2 ZPG 5,
+        0x37 RMB3 ZPG 5,
+        0x47 RMB4 ZPG 5,
+        0x57 RMB5 ZPG 5,
+        0x67 RMB6 ZPG 5,
+        0x77 RMB7 ZPG 5,
+        0x87 SMB0 ZPG 5,
+        0x97 SMB1 ZPG 5,
+        0xA7 SMB2 ZPG 5,
+        0xB7 SMB3 ZPG 5,
+        0xC7 SMB4 ZPG 5,
+        0xD7 SMB5 ZPG 5,
+        0xE7 SMB6 ZPG 5,
+        0xF7 SMB7 ZPG 5,
+        0x0F BBR0 ZPR 5 + 1,
+        0x1F BBR1 ZPR 5 + 1,
+        0x2F BBR2 ZPR 5 + 1,
+        0x3F BBR3 ZPR 5 + 1,
+        0x4F BBR4 ZPR 5 + 1,
+        0x5F BBR5 ZPR 5 + 1,
+        0x6F BBR6 ZPR 5 + 1,
+        0x7F BBR7 ZPR 5 + 1,
+        0x8F BBS0 ZPR 5 + 1,
+        0x9F BBS1 ZPR 5 + 1,
+        0xAF BBS2 ZPR 5 + 1,
+        0xBF BBS3 ZPR 5 + 1,
+        0xCF BBS4 ZPR 5 + 1,
+        0xDF BBS5 ZPR 5 + 1,
+        0xEF BBS6 ZPR 5 + 1,
+        0xFF BBS7 ZPR 5 + 1,
+    }
+    Wdc {
+        0xDB STP IMP 3,
+        0xCB WAI IMP 3,
+    }
+    Undocumented {
+        0x4B ALR IMM 2,
+        0x8B ANE IMM 2,
+        0x6B ARR IMM 2,
+        0xCB AXS IMM 2,
+        0xC7 DCP ZPG 5,
+        0xD7 DCP ZPX 6,
+        0xCF DCP ABS 6,
+        0xDF DCP ABX 7,
+        0xDB DCP ABY 7,
+        0xC3 DCP IDX 8,
+        0xD3 DCP IDY 8,
+        0xE7 ISC ZPG 5,
+        0xF7 ISC ZPX 6,
+        0xEF ISC ABS 6,
+        0xFF ISC ABX 7,
+        0xFB ISC ABY 7,
+        0xE3 ISC IDX 8,
+        0xF3 ISC IDY 8,
+        0xBB LAS ABY 4 + 1,
+        0xAB LAX IMM 2,
+        0xA7 LAX ZPG 3,
+        0xB7 LAX ZPY 4,
+        0xAF LAX ABS 4,
+        0xBF LAX ABY 4 + 1,
+        0xA3 LAX IDX 6,
+        0xB3 LAX IDY 5 + 1,
+        0x27 RLA ZPG 5,
+        0x37 RLA ZPX 6,
+        0x2F RLA ABS 6,
+        0x3F RLA ABX 7,
+        0x3B RLA ABY 7,
+        0x23 RLA IDX 8,
+        0x33 RLA IDY 8,
+        0x67 RRA ZPG 5,
+        0x77 RRA ZPX 6,
+        0x6F RRA ABS 6,
+        0x7F RRA ABX 7,
+        0x7B RRA ABY 7,
+        0x63 RRA IDX 8,
+        0x73 RRA IDY 8,
+        0x87 SAX ZPG 3,
+        0x97 SAX ZPY 4,
+        0x8F SAX ABS 4,
+        0x83 SAX IDX 6,
+        0x9F SHA ABY 5,
+        0x93 SHA IDY 6,
+        0x9E SHX ABY 5,
+        0x9C SHY ABX 5,
+        0x07 SLO ZPG 5,
+        0x17 SLO ZPX 6,
+        0x0F SLO ABS 6,
+        0x1F SLO ABX 7,
+        0x1B SLO ABY 7,
+        0x03 SLO IDX 8,
+        0x13 SLO IDY 8,
+        0x47 SRE ZPG 5,
+        0x57 SRE ZPX 6,
+        0x4F SRE ABS 6,
+        0x5F SRE ABX 7,
+        0x5B SRE ABY 7,
+        0x43 SRE IDX 8,
+        0x53 SRE IDY 8,
+        0x9B TAS ABY 5,
+        0x0B ANC IMM 2,
+        0x2B ANC IMM 2,
+        0xEB SBC IMM 2,
+        0x1A NOP IMP 2,
+        0x3A NOP IMP 2,
+        0x5A NOP IMP 2,
+        0x7A NOP IMP 2,
+        0xDA NOP IMP 2,
+        0xFA NOP IMP 2,
+        0x80 NOP IMM 2,
+        0x82 NOP IMM 2,
+        0x89 NOP IMM 2,
+        0xC2 NOP IMM 2,
+        0xE2 NOP IMM 2,
+        0x04 NOP ZPG 3,
+        0x44 NOP ZPG 3,
+        0x64 NOP ZPG 3,
+        0x14 NOP ZPX 4,
+        0x34 NOP ZPX 4,
+        0x54 NOP ZPX 4,
+        0x74 NOP ZPX 4,
+        0xD4 NOP ZPX 4,
+        0xF4 NOP ZPX 4,
+        0x0C NOP ABS 4,
+        0x1C NOP ABX 4 + 1,
+        0x3C NOP ABX 4 + 1,
+        0x5C NOP ABX 4 + 1,
+        0x7C NOP ABX 4 + 1,
+        0xDC NOP ABX 4 + 1,
+        0xFC NOP ABX 4 + 1,
+        0x02 JAM IMP 2,
+        0x12 JAM IMP 2,
+        0x22 JAM IMP 2,
+        0x32 JAM IMP 2,
+        0x42 JAM IMP 2,
+        0x52 JAM IMP 2,
+        0x62 JAM IMP 2,
+        0x72 JAM IMP 2,
+        0x92 JAM IMP 2,
+        0xB2 JAM IMP 2,
+        0xD2 JAM IMP 2,
+        0xF2 JAM IMP 2,
+    }
+}
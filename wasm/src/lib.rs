@@ -1,10 +1,11 @@
-use assembler::Assembler;
+use assembler::{entry_point, Assembler, Chunk, OutputFormat};
 use emulator::{
     cpu::Cpu,
     memory::{memory_hexdump_string, Memory},
     Cpu6502, CpuDebugger, DebugKind, Debugger,
 };
 use js_sys::Function;
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
 // #[wasm_bindgen]
@@ -18,6 +19,8 @@ type DebugCallBack = Box<dyn Fn(&str)>;
 #[wasm_bindgen]
 pub struct Emulator {
     cpu: Cpu6502<WasmDebugger>,
+    /// files for `.include` and `.incbin`, by name
+    files: HashMap<String, Vec<u8>>,
 }
 
 // #[wasm_bindgen]
@@ -45,8 +48,12 @@ pub enum AssemblerResultKind {
 #[derive(Clone)]
 pub struct AssemblerResult {
     kind: AssemblerResultKind,
+    origin: Option<u16>,
     value: Option<Vec<u8>>,
     error: Option<String>,
+    warnings: Vec<String>,
+    debug: Option<String>,
+    chunks: Vec<Chunk>,
 }
 
 #[wasm_bindgen]
@@ -55,6 +62,10 @@ impl AssemblerResult {
         self.kind.clone()
     }
 
+    pub fn origin(&self) -> Option<u16> {
+        self.origin
+    }
+
     pub fn value(&self) -> Option<Vec<u8>> {
         self.value.clone()
     }
@@ -62,6 +73,35 @@ impl AssemblerResult {
     pub fn error(&self) -> Option<String> {
         self.error.clone()
     }
+
+    /// warnings separated by newlines
+    pub fn warnings(&self) -> String {
+        self.warnings.join("\n")
+    }
+
+    /// symbols and line information as JSON, see `Assembly::json`
+    pub fn debug(&self) -> Option<String> {
+        self.debug.clone()
+    }
+
+    /// the program in a file format (`raw`, `ihex`, `srec`, `prg`), see `OutputFormat`
+    pub fn output(&self, format: &str) -> Option<Vec<u8>> {
+        let format = OutputFormat::from_name(format)?;
+        self.value.as_ref().map(|_| format.write(&self.chunks))
+    }
+}
+
+impl Emulator {
+    /// Loads every chunk at its origin and points the PC at the program entry like the desktop app,
+    /// see [`entry_point`]
+    fn load_chunks(&mut self, chunks: &[Chunk]) {
+        for chunk in chunks {
+            self.cpu.load_at(chunk.origin, &chunk.bytes);
+        }
+        if let Some(entry) = entry_point(chunks) {
+            self.cpu.registers.pc = entry;
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -74,6 +114,7 @@ impl Emulator {
 
         Self {
             cpu: Cpu::default(),
+            files: HashMap::new(),
         }
     }
 
@@ -105,6 +146,27 @@ impl Emulator {
         self.cpu.load(&data);
     }
 
+    pub fn load_at(&mut self, address: u16, data: Vec<u8>) {
+        self.cpu.load_at(address, &data);
+    }
+
+    /// Loads an assembled program at the addresses of its `.org` blocks, see [`Emulator::load_chunks`]
+    pub fn load_assembled(&mut self, assembled: &AssemblerResult) {
+        self.load_chunks(&assembled.chunks);
+    }
+
+    /// Loads a program file at the addresses it specifies, the format is chosen by the extension of `name`.
+    /// returns the error if the file is invalid, see [`Emulator::load_chunks`]
+    pub fn load_file(&mut self, name: &str, data: Vec<u8>) -> Option<String> {
+        let chunks = match OutputFormat::from_path(name).read(&data) {
+            Ok(chunks) => chunks,
+            Err(e) => return Some(format!("{name}:{}: {}", e.span.start.line, e.kind)),
+        };
+        self.load_chunks(&chunks);
+
+        None
+    }
+
     pub fn reset(&mut self) {
         self.cpu.reset();
     }
@@ -125,19 +187,41 @@ impl Emulator {
         format!("{}", self.cpu)
     }
 
-    pub fn assemble(&self, source: &str) -> AssemblerResult {
-        let src = Assembler::new(source).assemble();
+    pub fn add_file(&mut self, name: String, data: Vec<u8>) {
+        self.files.insert(name, data);
+    }
 
-        match src {
-            Ok(src) => AssemblerResult {
+    pub fn clear_files(&mut self) {
+        self.files.clear();
+    }
+
+    pub fn assemble(&self, source: &str) -> AssemblerResult {
+        let mut assembler = Assembler::new(source);
+        let assembly = assembler.set_resolver(self.files.clone()).build();
+        let warnings = assembler
+            .warnings
+            .iter()
+            .map(|w| w.render(&assembler.files))
+            .collect();
+
+        match assembly {
+            Ok(assembly) => AssemblerResult {
                 kind: AssemblerResultKind::Ok,
-                value: Some(src),
+                origin: Some(assembly.origin),
+                debug: Some(assembly.json()),
+                value: Some(assembly.bytes),
+                chunks: assembly.chunks,
                 error: None,
+                warnings,
             },
-            Err(err) => AssemblerResult {
+            Err(_) => AssemblerResult {
                 kind: AssemblerResultKind::Err,
+                origin: None,
                 value: None,
-                error: Some(err.to_string()),
+                error: Some(assembler.diagnostics()),
+                warnings,
+                debug: None,
+                chunks: Vec::new(),
             },
         }
     }